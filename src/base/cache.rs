@@ -0,0 +1,103 @@
+//! A small in-process cache with per-entry expiry.
+
+use std::{
+    collections::HashMap,
+    hash::Hash,
+    sync::{Arc, RwLock},
+    time::{Duration, Instant},
+};
+
+/// An in-process cache whose entries expire after a fixed time-to-live.
+///
+/// It is designed to be trivially cloneable: clones share the same entries.
+#[derive(Debug, Clone)]
+pub struct TtlCache<K, V> {
+    ttl: Duration,
+    entries: Arc<RwLock<HashMap<K, (Instant, V)>>>,
+}
+
+impl<K, V> TtlCache<K, V>
+where
+    K: Eq + Hash,
+    V: Clone,
+{
+    /// Create a new cache with the given time-to-live.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Arc::new(RwLock::new(HashMap::new())),
+        }
+    }
+
+    /// Get an entry, if it exists and has not expired.
+    pub fn get(&self, key: &K) -> Option<V> {
+        self.get_at(key, Instant::now())
+    }
+
+    /// Insert (or replace) an entry, and evict any expired entries.
+    pub fn insert(&self, key: K, value: V) {
+        self.insert_at(key, value, Instant::now());
+    }
+
+    /// Get an entry, if it exists and has not expired by `now`.
+    pub fn get_at(&self, key: &K, now: Instant) -> Option<V> {
+        let entries = self.entries.read().unwrap_or_else(|e| e.into_inner());
+
+        entries
+            .get(key)
+            .filter(|(inserted, _)| now.saturating_duration_since(*inserted) < self.ttl)
+            .map(|(_, value)| value.clone())
+    }
+
+    /// Insert (or replace) an entry as of `now`, and evict any entries that have expired by then.
+    pub fn insert_at(&self, key: K, value: V, now: Instant) {
+        let mut entries = self.entries.write().unwrap_or_else(|e| e.into_inner());
+
+        entries.retain(|_, (inserted, _)| now.saturating_duration_since(*inserted) < self.ttl);
+        entries.insert(key, (now, value));
+    }
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ttl_cache_get_and_insert() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let clone = cache.clone();
+
+        assert_eq!(cache.get(&"a"), None);
+
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), Some(1));
+
+        // Clones share entries.
+        assert_eq!(clone.get(&"a"), Some(1));
+    }
+
+    #[test]
+    fn test_ttl_cache_expiry() {
+        let cache = TtlCache::new(Duration::ZERO);
+
+        cache.insert("a", 1);
+        assert_eq!(cache.get(&"a"), None);
+    }
+
+    #[test]
+    fn test_ttl_cache_expiry_at() {
+        let cache = TtlCache::new(Duration::from_secs(60));
+        let start = Instant::now();
+
+        cache.insert_at("a", 1, start);
+        assert_eq!(cache.get_at(&"a", start + Duration::from_secs(59)), Some(1));
+        assert_eq!(cache.get_at(&"a", start + Duration::from_secs(60)), None);
+
+        // Expired entries are evicted by later inserts.
+        cache.insert_at("b", 2, start + Duration::from_secs(61));
+        assert_eq!(cache.get_at(&"a", start), None);
+        assert_eq!(cache.get_at(&"b", start + Duration::from_secs(61)), Some(2));
+    }
+}
@@ -1,10 +1,10 @@
 //! Load configuration via `config` crate with env-override support.
 
-use std::{ops::Deref, sync::Arc};
+use std::{collections::HashMap, ops::Deref, sync::Arc};
 
 use serde::Deserialize;
 
-use crate::base::prompts;
+use crate::base::{prompts, types::AssistantClassification};
 
 use super::types::Res;
 
@@ -18,6 +18,31 @@ fn default_openai_assistant_agent_model() -> String {
     "o3".to_string()
 }
 
+/// Default for whether the web search agent searches the web
+fn default_web_search_enabled() -> bool {
+    true
+}
+
+/// Default number of seconds that web search results are reused for the same message in a channel (zero disables the cache)
+fn default_web_search_cache_ttl_seconds() -> u64 {
+    900
+}
+
+/// Default for whether health checks call the OpenAI API
+fn default_openai_health_check_enabled() -> bool {
+    true
+}
+
+/// Default for whether reasoning models are asked to summarize their reasoning
+fn default_openai_reasoning_summaries_enabled() -> bool {
+    true
+}
+
+/// Default OpenAI embedding model to use
+fn default_openai_embedding_model() -> String {
+    "text-embedding-3-small".to_string()
+}
+
 /// Default sampling temperature for OpenAI search agent
 fn default_openai_search_agent_temperature() -> f32 {
     0.0
@@ -43,6 +68,191 @@ fn default_openai_max_tokens() -> u32 {
     16384
 }
 
+/// Default OpenAI API type
+fn default_openai_api_type() -> String {
+    "openai".to_string()
+}
+
+/// Default timeout of each OpenAI API call (in seconds)
+fn default_openai_request_timeout_secs() -> u64 {
+    120
+}
+
+/// Default number of retries of a failed OpenAI API call
+fn default_openai_max_retries() -> u32 {
+    3
+}
+
+/// Default delay before the first retry of a failed OpenAI API call (in milliseconds)
+fn default_openai_retry_base_delay_ms() -> u64 {
+    1000
+}
+
+/// Default number of OpenAI API calls in flight at once
+fn default_openai_max_concurrent_requests() -> usize {
+    8
+}
+
+/// Default number of consecutive failed OpenAI API calls that open the circuit breaker
+fn default_openai_circuit_breaker_failures() -> u32 {
+    5
+}
+
+/// Default time that the circuit breaker stays open (in seconds)
+fn default_openai_circuit_breaker_cooldown_secs() -> u64 {
+    60
+}
+
+/// Default number of rounds of tool calls that the assistant may make for a single message
+fn default_max_tool_iterations() -> usize {
+    6
+}
+
+/// Default reply to a message that the assistant is not confident enough to answer
+fn default_low_confidence_reply() -> String {
+    "tag".to_string()
+}
+
+/// Default reply to a message that the model refused to respond to
+fn default_refusal_reply() -> String {
+    "Sorry, I can't help with that here.".to_string()
+}
+
+/// Default SurrealDB authentication mode
+fn default_db_auth() -> String {
+    "root".to_string()
+}
+
+/// Default SurrealDB namespace
+fn default_db_namespace() -> String {
+    "triage".to_string()
+}
+
+/// Default SurrealDB database
+fn default_db_database() -> String {
+    "bot".to_string()
+}
+
+/// Default Slack listener mode
+fn default_slack_mode() -> String {
+    "socket".to_string()
+}
+
+/// Default bind address for the Slack Events API listener
+fn default_slack_events_bind_address() -> String {
+    "0.0.0.0:3000".to_string()
+}
+
+/// Default max retries for rate limited Slack API calls
+fn default_slack_max_retries() -> u32 {
+    3
+}
+
+/// Default TTL for cached Slack user info
+fn default_slack_user_cache_ttl_seconds() -> u64 {
+    3600
+}
+
+/// Default number of days of history to backfill when joining a channel
+fn default_slack_history_backfill_days() -> u32 {
+    30
+}
+
+/// Default maximum number of characters of an attached file to read
+fn default_slack_file_max_chars() -> usize {
+    10_000
+}
+
+/// Default maximum number of images attached to a message that are given to the assistant
+fn default_slack_image_max_count() -> usize {
+    4
+}
+
+/// Default maximum size (in bytes) of an image attached to a message that is given to the assistant
+fn default_slack_image_max_bytes() -> usize {
+    5_000_000
+}
+
+/// Default for whether a placeholder is posted while the assistant is working
+fn default_slack_thinking_placeholder_enabled() -> bool {
+    true
+}
+
+/// Default for whether direct messages to the bot are handled
+fn default_slack_direct_messages_enabled() -> bool {
+    true
+}
+
+/// Default for how long a failed outbound message is retried before it is dropped
+fn default_slack_outbox_max_age_seconds() -> u64 {
+    3600
+}
+
+/// Default maximum length of a single message (Block Kit's section text limit); longer replies are split
+fn default_slack_message_max_chars() -> usize {
+    3000
+}
+
+/// Default window (after posting) in which an edited message is re-evaluated
+fn default_slack_edit_reevaluation_window_seconds() -> u64 {
+    300
+}
+
+/// Default for whether the channel directive is published to the channel's canvas
+fn default_slack_directive_canvas_enabled() -> bool {
+    false
+}
+
+/// Default number of consecutive socket mode reconnects before giving up
+fn default_slack_socket_max_reconnects() -> u32 {
+    10
+}
+
+/// Default number of days of messages to keep (zero keeps messages forever)
+fn default_message_retention_days() -> u64 {
+    0
+}
+
+/// Default maximum number of channel contexts given to the assistant
+fn default_channel_context_limit() -> usize {
+    20
+}
+
+/// Default maximum number of characters of channel context given to the assistant
+fn default_channel_context_max_chars() -> usize {
+    8000
+}
+
+/// Default number of recent channel messages given to the assistant (zero disables them)
+fn default_channel_recent_messages_limit() -> usize {
+    20
+}
+
+/// Default maximum number of characters of each recent channel message given to the assistant
+fn default_channel_recent_message_max_chars() -> usize {
+    500
+}
+
+/// Default number of characters of thread context above which the thread is summarized (zero disables summaries)
+fn default_thread_summary_threshold_chars() -> usize {
+    40000
+}
+
+/// Default number of a summarized thread's most recent messages given to the assistant as they are
+fn default_thread_summary_recent_messages() -> usize {
+    10
+}
+
+/// Default maximum number of messages returned by a message search
+fn default_message_search_limit() -> usize {
+    20
+}
+
+/// Default number of messages that are stored per transaction by batch inserts
+fn default_message_batch_size() -> usize {
+    500
+}
+
 /// Default MCP configuration file path
 fn default_mcp_config_path() -> String {
     let home = std::env::var("HOME").unwrap_or_else(|_| ".".to_string());
@@ -59,6 +269,16 @@ fn default_assistant_agent_mention_directive() -> String {
     prompts::ASSISTANT_AGENT_MENTION_DIRECTIVE.to_string()
 }
 
+/// Default direct message addendum directive for the assistant agent.
+fn default_assistant_agent_direct_message_directive() -> String {
+    prompts::ASSISTANT_AGENT_DIRECT_MESSAGE_DIRECTIVE.to_string()
+}
+
+/// Default classify directive for the assistant agent (in channels that are only classified).
+fn default_assistant_agent_classify_directive() -> String {
+    prompts::ASSISTANT_AGENT_CLASSIFY_DIRECTIVE.to_string()
+}
+
 /// Default search agent directive for the assistant agent.
 fn default_search_agent_directive() -> String {
     prompts::SEARCH_AGENT_SYSTEM_DIRECTIVE.to_string()
@@ -69,6 +289,119 @@ fn default_message_search_agent_directive() -> String {
     prompts::MESSAGE_SEARCH_AGENT_SYSTEM_DIRECTIVE.to_string()
 }
 
+/// Default link summary agent directive for link unfurls.
+fn default_link_summary_agent_directive() -> String {
+    prompts::LINK_SUMMARY_AGENT_SYSTEM_DIRECTIVE.to_string()
+}
+
+/// Default thread summary agent directive for very long threads.
+fn default_thread_summary_agent_directive() -> String {
+    prompts::THREAD_SUMMARY_AGENT_SYSTEM_DIRECTIVE.to_string()
+}
+
+/// Default intent agent directive for deciding which tools the assistant is offered.
+fn default_intent_agent_directive() -> String {
+    prompts::INTENT_AGENT_SYSTEM_DIRECTIVE.to_string()
+}
+
+/// Credentials (and listener settings) for a single Slack workspace.
+#[derive(Debug, Deserialize, Clone, Default, PartialEq, Eq)]
+pub struct SlackWorkspaceConfig {
+    /// Name of the workspace (only used for logging).
+    #[serde(default)]
+    pub name: String,
+    /// Slack app token.
+    /// Only required when `slack_mode` is "socket".
+    #[serde(default)]
+    pub app_token: String,
+    /// Slack bot token.
+    pub bot_token: String,
+    /// Slack signing secret.
+    /// Used to verify incoming requests when `slack_mode` is "events".
+    #[serde(default)]
+    pub signing_secret: String,
+    /// Bind address for the Slack Events API listener (each workspace needs its own).
+    /// Only applies when `slack_mode` is "events".
+    #[serde(default = "default_slack_events_bind_address")]
+    pub events_bind_address: String,
+}
+
+/// The price of a model, in US dollars per million tokens (see `openai_prices`).
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+pub struct ModelPrice {
+    /// The price of a million input tokens.
+    pub input: f64,
+    /// The price of a million output tokens (including reasoning tokens).
+    pub output: f64,
+}
+
+/// Overrides of what a model supports (see `openai_model_capabilities`); unset fields keep the built-in capabilities.
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ModelCapabilitiesOverride {
+    /// Whether the model accepts a `temperature`.
+    #[serde(default)]
+    pub supports_temperature: Option<bool>,
+    /// Whether the model accepts a reasoning effort.
+    #[serde(default)]
+    pub supports_reasoning_effort: Option<bool>,
+    /// The model's context window, in tokens.
+    #[serde(default)]
+    pub max_context_tokens: Option<usize>,
+    /// Whether the model supports structured outputs (a strict JSON schema), rather than only JSON mode.
+    #[serde(default)]
+    pub supports_json_schema: Option<bool>,
+    /// Whether the model can see images.
+    #[serde(default)]
+    pub supports_vision: Option<bool>,
+}
+
+/// How the assistant's input token budget is shared among the sections of its input (see `context_budget`), in percent
+/// of the budget that is left after the parts that are never trimmed (e.g., the system directive, and the user's
+/// message).
+///
+/// Each section is trimmed to its share, but the shares that sections do not use (e.g., when there are no web search
+/// results) go to the sections that need more, in order of priority: the thread, the channel directive, the channel
+/// context, the message search results, the web search results, and the recent channel activity.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq)]
+#[serde(default)]
+pub struct ContextBudget {
+    /// The share of the channel directive.
+    pub directive: u8,
+    /// The share of the channel context.
+    pub channel_context: u8,
+    /// The share of the thread context.
+    pub thread: u8,
+    /// The share of the web search results.
+    pub web_results: u8,
+    /// The share of the message search results.
+    pub message_results: u8,
+    /// The share of the recent channel activity.
+    pub recent_messages: u8,
+}
+
+impl Default for ContextBudget {
+    fn default() -> Self {
+        Self {
+            directive: 10,
+            channel_context: 15,
+            thread: 35,
+            web_results: 15,
+            message_results: 15,
+            recent_messages: 10,
+        }
+    }
+}
+
+impl ContextBudget {
+    /// The sum of the shares, in percent (at most 100).
+    pub fn total(&self) -> u32 {
+        [self.directive, self.channel_context, self.thread, self.web_results, self.message_results, self.recent_messages]
+            .iter()
+            .map(|share| *share as u32)
+            .sum()
+    }
+}
+
 /// Configuration for the triage-bot application.
 #[derive(Debug, Deserialize, Clone)]
 pub struct Config {
@@ -89,24 +422,119 @@ impl Deref for Config {
 pub struct ConfigInner {
     /// OpenAI API key (`OPENAI_API_KEY`).
     pub openai_api_key: String,
+    /// OpenAI API type (`OPENAI_API_TYPE`).
+    /// Valid values are "openai" and "azure".  With "azure", the `openai_*_model` settings are deployment names.
+    #[serde(default = "default_openai_api_type")]
+    pub openai_api_type: String,
+    /// OpenAI API base URL (`OPENAI_API_BASE`).
+    /// Defaults to OpenAI's, and is required when `openai_api_type` is "azure" (e.g., `https://my-resource.openai.azure.com`).
+    #[serde(default)]
+    pub openai_api_base: Option<String>,
+    /// Azure OpenAI API version (`OPENAI_API_VERSION`).
+    /// Only used (and required) when `openai_api_type` is "azure".
+    #[serde(default)]
+    pub openai_api_version: Option<String>,
+    /// OpenAI organization, sent as the `OpenAI-Organization` header of every call (`OPENAI_ORGANIZATION`).
+    /// Not used when `openai_api_type` is "azure".
+    #[serde(default)]
+    pub openai_organization: Option<String>,
+    /// OpenAI project, sent as the `OpenAI-Project` header of every call (`OPENAI_PROJECT`).
+    /// Requires `openai_organization`.
+    #[serde(default)]
+    pub openai_project: Option<String>,
     /// OpenAI search agent model to use (`OPENAI_SEARCH_AGENT_MODEL`).
     #[serde(default = "default_openai_search_agent_model")]
     pub openai_search_agent_model: String,
     /// OpenAI assistant agent model to use (`OPENAI_ASSISTANT_AGENT_MODEL`).
     #[serde(default = "default_openai_assistant_agent_model")]
     pub openai_assistant_agent_model: String,
+    /// OpenAI embedding model to use for semantic message search (`OPENAI_EMBEDDING_MODEL`).
+    /// The model must support shortened embeddings (e.g., the `text-embedding-3` models), since every embedding is
+    /// requested at the dimensions of the database's vector index.
+    #[serde(default = "default_openai_embedding_model")]
+    pub openai_embedding_model: String,
+    /// Whether health checks call the OpenAI API (listing the models), rather than skipping it (`OPENAI_HEALTH_CHECK_ENABLED`).
+    #[serde(default = "default_openai_health_check_enabled")]
+    pub openai_health_check_enabled: bool,
+    /// Whether the assistant's reasoning models are asked to summarize their reasoning (`OPENAI_REASONING_SUMMARIES_ENABLED`).
+    /// The summaries are logged, and attached to the traces, but never posted.  Some organizations must be verified to get them.
+    #[serde(default = "default_openai_reasoning_summaries_enabled")]
+    pub openai_reasoning_summaries_enabled: bool,
+    /// Whether the reasoning summaries are also stored with the assistant's responses, for debugging (`STORE_REASONING_SUMMARIES`).
+    #[serde(default)]
+    pub store_reasoning_summaries: bool,
+    /// Whether the payloads of LLM calls (the rendered input, and the raw output) and MCP tool calls are logged at the
+    /// `TRACE` level, for debugging prompts (`LOG_LLM_PAYLOADS`).  Secrets are redacted (see `log_redaction_patterns`).
+    #[serde(default)]
+    pub log_llm_payloads: bool,
+    /// Patterns (regular expressions) of secrets that are redacted from the logged payloads, on top of the config's own
+    /// secrets, common token formats, and the moderation denylist (config file only).
+    #[serde(default)]
+    pub log_redaction_patterns: Vec<String>,
+    /// Whether the web search agent searches the web (`WEB_SEARCH_ENABLED`).
+    /// Disable it for OpenAI-compatible endpoints (e.g., local models) that do not implement the web search tool, or when
+    /// support content must never be sent to web search.  Channels can override it (see `/triage websearch`).
+    #[serde(default = "default_web_search_enabled")]
+    pub web_search_enabled: bool,
+    /// How long web search results are reused for the same message in a channel (`WEB_SEARCH_CACHE_TTL_SECONDS`).
+    /// This saves a search when several people report the same problem; messages that ask to "search again" always
+    /// search.  Zero disables the cache.
+    #[serde(default = "default_web_search_cache_ttl_seconds")]
+    pub web_search_cache_ttl_seconds: u64,
     /// Optional custom system directive to override the default (`SYSTEM_DIRECTIVE`).
     #[serde(default = "default_assistant_agent_system_directive")]
     pub assistant_agent_system_directive: String,
     /// Optional custom mention addendum directive to override the default (`MENTION_ADDENDUM_DIRECTIVE`).
     #[serde(default = "default_assistant_agent_mention_directive")]
     pub assistant_agent_mention_directive: String,
+    /// Optional custom direct message addendum directive to override the default (`DIRECT_MESSAGE_ADDENDUM_DIRECTIVE`).
+    #[serde(default = "default_assistant_agent_direct_message_directive")]
+    pub assistant_agent_direct_message_directive: String,
+    /// Optional custom classify directive, which replaces the system directive in channels that are only classified (`ASSISTANT_AGENT_CLASSIFY_DIRECTIVE`).
+    #[serde(default = "default_assistant_agent_classify_directive")]
+    pub assistant_agent_classify_directive: String,
     /// Optional custom search agent directive to override the default (`SEARCH_AGENT_DIRECTIVE`).
     #[serde(default = "default_search_agent_directive")]
     pub search_agent_system_directive: String,
     /// Optional custom message search agent directive to override the default (`MESSAGE_SEARCH_AGENT_DIRECTIVE`).
     #[serde(default = "default_message_search_agent_directive")]
     pub message_search_agent_system_directive: String,
+    /// Optional custom link summary agent directive to override the default (`LINK_SUMMARY_AGENT_DIRECTIVE`).
+    #[serde(default = "default_link_summary_agent_directive")]
+    pub link_summary_agent_system_directive: String,
+    /// Optional custom thread summary agent directive to override the default (`THREAD_SUMMARY_AGENT_DIRECTIVE`).
+    #[serde(default = "default_thread_summary_agent_directive")]
+    pub thread_summary_agent_system_directive: String,
+    /// Optional custom intent agent directive to override the default (`INTENT_AGENT_DIRECTIVE`).
+    #[serde(default = "default_intent_agent_directive")]
+    pub intent_agent_system_directive: String,
+    /// Optional file to load the system directive from, which takes precedence over `assistant_agent_system_directive` (`ASSISTANT_AGENT_SYSTEM_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub assistant_agent_system_directive_path: Option<String>,
+    /// Optional file to load the mention addendum directive from, which takes precedence over `assistant_agent_mention_directive` (`ASSISTANT_AGENT_MENTION_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub assistant_agent_mention_directive_path: Option<String>,
+    /// Optional file to load the direct message addendum directive from, which takes precedence over `assistant_agent_direct_message_directive` (`ASSISTANT_AGENT_DIRECT_MESSAGE_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub assistant_agent_direct_message_directive_path: Option<String>,
+    /// Optional file to load the classify directive from, which takes precedence over `assistant_agent_classify_directive` (`ASSISTANT_AGENT_CLASSIFY_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub assistant_agent_classify_directive_path: Option<String>,
+    /// Optional file to load the search agent directive from, which takes precedence over `search_agent_system_directive` (`SEARCH_AGENT_SYSTEM_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub search_agent_system_directive_path: Option<String>,
+    /// Optional file to load the message search agent directive from, which takes precedence over `message_search_agent_system_directive` (`MESSAGE_SEARCH_AGENT_SYSTEM_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub message_search_agent_system_directive_path: Option<String>,
+    /// Optional file to load the link summary agent directive from, which takes precedence over `link_summary_agent_system_directive` (`LINK_SUMMARY_AGENT_SYSTEM_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub link_summary_agent_system_directive_path: Option<String>,
+    /// Optional file to load the thread summary agent directive from, which takes precedence over `thread_summary_agent_system_directive` (`THREAD_SUMMARY_AGENT_SYSTEM_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub thread_summary_agent_system_directive_path: Option<String>,
+    /// Optional file to load the intent agent directive from, which takes precedence over `intent_agent_system_directive` (`INTENT_AGENT_SYSTEM_DIRECTIVE_PATH`).
+    #[serde(default)]
+    pub intent_agent_system_directive_path: Option<String>,
     /// Sampling temperature to use for OpenAI search agent model (`OPENAI_SEARCH_AGENT_TEMPERATURE`).
     /// Value between 0 and 2. Higher values like 0.8 make output more random,
     /// while lower values like 0.2 make it more focused and deterministic.
@@ -116,6 +544,18 @@ pub struct ConfigInner {
     /// Valid values are "low", "medium", and "high". Only applies to reasoning models (o-series).
     #[serde(default = "default_openai_search_agent_reasoning_effort")]
     pub openai_search_agent_reasoning_effort: String,
+    /// OpenAI model for the message search agent, which only extracts search terms (`OPENAI_MESSAGE_SEARCH_AGENT_MODEL`).
+    /// Defaults to `openai_search_agent_model` (see `message_search_agent_model`).
+    #[serde(default)]
+    pub openai_message_search_agent_model: Option<String>,
+    /// Sampling temperature to use for the message search agent (`OPENAI_MESSAGE_SEARCH_AGENT_TEMPERATURE`).
+    /// Defaults to `openai_search_agent_temperature`.
+    #[serde(default)]
+    pub openai_message_search_agent_temperature: Option<f32>,
+    /// Reasoning effort to use for the message search agent (`OPENAI_MESSAGE_SEARCH_AGENT_REASONING_EFFORT`).
+    /// Defaults to `openai_search_agent_reasoning_effort`.
+    #[serde(default)]
+    pub openai_message_search_agent_reasoning_effort: Option<String>,
     /// Sampling temperature to use for OpenAI assistant agent model (`OPENAI_ASSISTANT_AGENT_TEMPERATURE`).
     /// Value between 0 and 2. Higher values like 0.8 make output more random,
     /// while lower values like 0.2 make it more focused and deterministic.
@@ -129,18 +569,231 @@ pub struct ConfigInner {
     /// Maximum number of tokens that can be generated in the response.
     #[serde(default = "default_openai_max_tokens")]
     pub openai_max_tokens: u32,
+    /// How long each OpenAI API call may take, in seconds (`OPENAI_REQUEST_TIMEOUT_SECS`).
+    /// Reasoning models can be slow, so this is generous by default.
+    #[serde(default = "default_openai_request_timeout_secs")]
+    pub openai_request_timeout_secs: u64,
+    /// How many times a failed (or timed out) OpenAI API call is retried (`OPENAI_MAX_RETRIES`).
+    #[serde(default = "default_openai_max_retries")]
+    pub openai_max_retries: u32,
+    /// The delay before the first retry of a failed OpenAI API call, in milliseconds (`OPENAI_RETRY_BASE_DELAY_MS`).
+    /// The delay doubles with every retry, plus some random jitter.
+    #[serde(default = "default_openai_retry_base_delay_ms")]
+    pub openai_retry_base_delay_ms: u64,
+    /// How many OpenAI API calls may be in flight at once (`OPENAI_MAX_CONCURRENT_REQUESTS`).
+    /// Other calls wait their turn, so that a flood of messages does not trip the rate limits.  Zero is unlimited.
+    #[serde(default = "default_openai_max_concurrent_requests")]
+    pub openai_max_concurrent_requests: usize,
+    /// How many consecutive OpenAI API calls (after their retries) must fail to open the circuit breaker
+    /// (`OPENAI_CIRCUIT_BREAKER_FAILURES`).  While it is open, calls fail fast, and messages are not answered.  Zero never opens it.
+    #[serde(default = "default_openai_circuit_breaker_failures")]
+    pub openai_circuit_breaker_failures: u32,
+    /// How long the circuit breaker stays open before a trial call, in seconds (`OPENAI_CIRCUIT_BREAKER_COOLDOWN_SECS`).
+    #[serde(default = "default_openai_circuit_breaker_cooldown_secs")]
+    pub openai_circuit_breaker_cooldown_secs: u64,
+    /// How many rounds of tool calls the assistant may make for a single message (`MAX_TOOL_ITERATIONS`).
+    /// Once they run out, the assistant is made to give a final response (with tools disabled).
+    #[serde(default = "default_max_tool_iterations")]
+    pub max_tool_iterations: usize,
+    /// Input token budgets by model, e.g., `"gpt-4.1" = 200000` (config file only, under `[openai_token_budgets]`).
+    /// Models that are not listed get their context window, less `openai_max_tokens`.  Inputs over budget are truncated
+    /// (message search results first, then web search results, and then the oldest thread messages).
+    #[serde(default)]
+    pub openai_token_budgets: HashMap<String, usize>,
+    /// How the assistant's input token budget is shared among the sections of its input, in percent, e.g.,
+    /// `{ thread = 50, web_results = 5, message_results = 5 }` (config file only, under `[context_budget]`).  Unset sections keep their
+    /// defaults, and the shares must add up to at most 100.
+    #[serde(default)]
+    pub context_budget: ContextBudget,
+    /// Model prices, in US dollars per million tokens, e.g., `"gpt-4.1" = { input = 2.0, output = 8.0 }` (config file
+    /// only, under `[openai_prices]`), for estimating what each response costs.  A model matches the longest name that it
+    /// starts with (so `gpt-4.1-mini` is not priced as `gpt-4.1`, and dated snapshots match their model).
+    #[serde(default)]
+    pub openai_prices: HashMap<String, ModelPrice>,
+    /// Overrides of what models support, e.g., `"my-model" = { supports_temperature = true, max_context_tokens = 32768 }`
+    /// (config file only, under `[openai_model_capabilities]`), for models that the built-in table gets wrong, or does not
+    /// know.  Models are matched like `openai_prices`.
+    #[serde(default)]
+    pub openai_model_capabilities: HashMap<String, ModelCapabilitiesOverride>,
     /// Slack app token (`SLACK_APP_TOKEN`).
+    /// Only required when `slack_mode` is "socket".
+    #[serde(default)]
     pub slack_app_token: String,
     /// Slack bot token (`SLACK_BOT_TOKEN`).
+    /// Only optional when `slack_workspaces` is set.
+    #[serde(default)]
     pub slack_bot_token: String,
     /// Slack signing secret (`SLACK_SIGNING_SECRET`).
+    /// Used to verify incoming requests when `slack_mode` is "events".
+    #[serde(default)]
     pub slack_signing_secret: String,
+    /// Credentials for multiple Slack workspaces, served from one process (config file only).
+    /// When set, the single-workspace `slack_*_token`/`slack_signing_secret` settings are ignored,
+    /// and channels are namespaced by workspace (team ID) in the database.
+    #[serde(default)]
+    pub slack_workspaces: Vec<SlackWorkspaceConfig>,
+    /// Slack listener mode (`SLACK_MODE`).
+    /// Valid values are "socket" (Socket Mode, requires an app token) and "events" (HTTP Events API).
+    #[serde(default = "default_slack_mode")]
+    pub slack_mode: String,
+    /// Bind address for the Slack Events API listener (`SLACK_EVENTS_BIND_ADDRESS`).
+    /// Only applies when `slack_mode` is "events".
+    #[serde(default = "default_slack_events_bind_address")]
+    pub slack_events_bind_address: String,
+    /// Max retries for Slack API calls that are rate limited (`SLACK_MAX_RETRIES`).
+    /// Each retry waits for the `Retry-After` duration that Slack returns.
+    #[serde(default = "default_slack_max_retries")]
+    pub slack_max_retries: u32,
+    /// How long resolved Slack user info (e.g., display names) is cached, in seconds (`SLACK_USER_CACHE_TTL_SECONDS`).
+    #[serde(default = "default_slack_user_cache_ttl_seconds")]
+    pub slack_user_cache_ttl_seconds: u64,
+    /// Number of days of channel history to backfill when the bot joins a channel (`SLACK_HISTORY_BACKFILL_DAYS`).
+    /// Set to 0 to disable backfilling.
+    #[serde(default = "default_slack_history_backfill_days")]
+    pub slack_history_backfill_days: u32,
+    /// Maximum number of characters read from each text file (or snippet) attached to a message (`SLACK_FILE_MAX_CHARS`).
+    #[serde(default = "default_slack_file_max_chars")]
+    pub slack_file_max_chars: usize,
+    /// Maximum number of images attached to a message that are downloaded for the assistant to see (`SLACK_IMAGE_MAX_COUNT`).
+    /// Set to 0 to never download images.
+    #[serde(default = "default_slack_image_max_count")]
+    pub slack_image_max_count: usize,
+    /// Maximum size (in bytes) of each image that is downloaded for the assistant to see (`SLACK_IMAGE_MAX_BYTES`); larger images are skipped.
+    #[serde(default = "default_slack_image_max_bytes")]
+    pub slack_image_max_bytes: usize,
+    /// Whether a "looking into this…" placeholder is posted (and then edited into the answer) while the assistant works (`SLACK_THINKING_PLACEHOLDER_ENABLED`).
+    #[serde(default = "default_slack_thinking_placeholder_enabled")]
+    pub slack_thinking_placeholder_enabled: bool,
+    /// Whether replies are streamed into the thread (edited into the placeholder as they are generated) (`SLACK_STREAMING_ENABLED`).
+    #[serde(default)]
+    pub slack_streaming_enabled: bool,
+    /// Whether the bot responds to (and stores) direct messages (`SLACK_DIRECT_MESSAGES_ENABLED`).
+    #[serde(default = "default_slack_direct_messages_enabled")]
+    pub slack_direct_messages_enabled: bool,
+    /// How long after posting an edit to a message that the bot already replied to is re-evaluated, in seconds (`SLACK_EDIT_REEVALUATION_WINDOW_SECONDS`).
+    /// Zero disables re-evaluation, and channels can override this with `/triage edits`.
+    #[serde(default = "default_slack_edit_reevaluation_window_seconds")]
+    pub slack_edit_reevaluation_window_seconds: u64,
+    /// Whether the channel directive is published to the channel's canvas when it is updated (`SLACK_DIRECTIVE_CANVAS_ENABLED`).
+    /// This is best-effort, since not every workspace has canvases enabled.
+    #[serde(default = "default_slack_directive_canvas_enabled")]
+    pub slack_directive_canvas_enabled: bool,
+    /// How long a reply that failed to send is retried (with backoff) before it is dropped, in seconds (`SLACK_OUTBOX_MAX_AGE_SECONDS`).
+    #[serde(default = "default_slack_outbox_max_age_seconds")]
+    pub slack_outbox_max_age_seconds: u64,
+    /// The maximum number of characters in a single message (`SLACK_MESSAGE_MAX_CHARS`).
+    /// Longer replies are split (at paragraph and code block boundaries) into chunks that are posted to the thread in order.
+    #[serde(default = "default_slack_message_max_chars")]
+    pub slack_message_max_chars: usize,
+    /// The number of consecutive socket mode reconnects (with backoff) before the bot gives up and exits (`SLACK_SOCKET_MAX_RECONNECTS`).
+    /// Zero retries forever.
+    #[serde(default = "default_slack_socket_max_reconnects")]
+    pub slack_socket_max_reconnects: u32,
+    /// Comma-separated allowlist of domains to unfurl shared links for (`SLACK_UNFURL_DOMAINS`).
+    /// Subdomains of an allowed domain are also unfurled.  Empty (the default) disables unfurling.
+    #[serde(default)]
+    pub slack_unfurl_domains: String,
+    /// Emoji to react with for each classification, e.g., `Bug = "triage-bug"` (config file only, under `[emoji_map]`).
+    /// Classifications that are not listed use the default emoji.
+    #[serde(default)]
+    pub emoji_map: HashMap<String, String>,
     /// Database endpoint URL (`DB_ENDPOINT`).
     pub db_endpoint: String,
     /// Database username (`DB_USERNAME`).
+    #[serde(default)]
     pub db_username: String,
     /// Database password (`DB_PASSWORD`).
+    #[serde(default)]
     pub db_password: String,
+    /// How the bot signs in to the SurrealDB server (`DB_AUTH`).
+    /// Valid values are "root", "namespace" and "database" (a user defined at that level, with `db_username` and
+    /// `db_password`), and "token" (a pre-issued token, with `db_token`).
+    #[serde(default = "default_db_auth")]
+    pub db_auth: String,
+    /// A pre-issued token to authenticate to the SurrealDB server with, when `db_auth` is "token" (`DB_TOKEN`).
+    #[serde(default)]
+    pub db_token: String,
+    /// The namespace to use on the SurrealDB server (`DB_NAMESPACE`).
+    #[serde(default = "default_db_namespace")]
+    pub db_namespace: String,
+    /// The database to use on the SurrealDB server (`DB_DATABASE`).
+    #[serde(default = "default_db_database")]
+    pub db_database: String,
+    /// Whether the connection to the SurrealDB server uses TLS (`DB_TLS`).
+    /// A `wss://` endpoint always does, so this is for endpoints without a scheme.
+    #[serde(default)]
+    pub db_tls: bool,
+    /// Path to a PEM file of CA certificates to trust for the TLS connection to the SurrealDB server (`DB_TLS_CA_PATH`).
+    /// Used for servers with certificates from an internal PKI; by default, the public roots are trusted.
+    #[serde(default)]
+    pub db_tls_ca_path: Option<String>,
+    /// How many days of messages are kept for each channel (`MESSAGE_RETENTION_DAYS`).
+    /// Older messages are pruned in the background (unless a stored context was created from them), and channels can
+    /// override this.  Zero keeps messages forever.
+    #[serde(default = "default_message_retention_days")]
+    pub message_retention_days: u64,
+    /// Whether messages are also searched by meaning, rather than just by keywords (`SEMANTIC_SEARCH_ENABLED`).
+    /// This embeds every stored message (and every question), so it adds a small API cost per message.
+    #[serde(default)]
+    pub semantic_search_enabled: bool,
+    /// Whether the assistant's replies are checked before they are posted (`MODERATION_ENABLED`).
+    /// Replies are checked with OpenAI's moderation model, and against `moderation_denylist`; a reply that fails the
+    /// check is replaced by a note that it was flagged for a human.  Channels can override this (`/triage moderation`).
+    #[serde(default)]
+    pub moderation_enabled: bool,
+    /// Patterns (regular expressions) that replies must not match, e.g., `['\.corp\.example\.com', 'AKIA[0-9A-Z]{16}']`
+    /// for internal hostnames and credentials (config file only).  Matches are also redacted from the logs.
+    #[serde(default)]
+    pub moderation_denylist: Vec<String>,
+    /// The confidence (0-100) below which the assistant's answers are withheld (`REPLY_CONFIDENCE_THRESHOLD`).
+    /// Defaults to 0 (every answer is posted), and channels can override it (`/triage confidence`).
+    #[serde(default)]
+    pub reply_confidence_threshold: u8,
+    /// What is posted instead of an answer below the confidence threshold (`LOW_CONFIDENCE_REPLY`).
+    /// Either "tag" (only the classification, and a tag of the channel's oncall) or "silent" (nothing).
+    #[serde(default = "default_low_confidence_reply")]
+    pub low_confidence_reply: String,
+    /// What is posted when the model refuses to respond to a message (`REFUSAL_REPLY`), after reacting to it.
+    /// Empty posts nothing (only the reaction), and channels can override it (`/triage refusal`).
+    #[serde(default = "default_refusal_reply")]
+    pub refusal_reply: String,
+    /// How many of a channel's stored contexts are given to the assistant (`CHANNEL_CONTEXT_LIMIT`).
+    /// The newest contexts are kept, and the assistant is told when older context was left out.
+    #[serde(default = "default_channel_context_limit")]
+    pub channel_context_limit: usize,
+    /// How many characters of a channel's stored contexts are given to the assistant (`CHANNEL_CONTEXT_MAX_CHARS`).
+    #[serde(default = "default_channel_context_max_chars")]
+    pub channel_context_max_chars: usize,
+    /// How many of a channel's most recent messages are given to the assistant (`CHANNEL_RECENT_MESSAGES_LIMIT`).
+    /// Zero disables the recent channel activity.
+    #[serde(default = "default_channel_recent_messages_limit")]
+    pub channel_recent_messages_limit: usize,
+    /// How many characters of each recent channel message are given to the assistant (`CHANNEL_RECENT_MESSAGE_MAX_CHARS`).
+    #[serde(default = "default_channel_recent_message_max_chars")]
+    pub channel_recent_message_max_chars: usize,
+    /// How many characters of thread context there can be before the thread is summarized (`THREAD_SUMMARY_THRESHOLD_CHARS`).
+    /// Longer threads are given to the assistant as a summary of their older messages, followed by their most recent
+    /// messages.  Zero disables thread summaries.
+    #[serde(default = "default_thread_summary_threshold_chars")]
+    pub thread_summary_threshold_chars: usize,
+    /// How many of a summarized thread's most recent messages are given to the assistant as they are (`THREAD_SUMMARY_RECENT_MESSAGES`).
+    #[serde(default = "default_thread_summary_recent_messages")]
+    pub thread_summary_recent_messages: usize,
+    /// How many messages a message search returns (`MESSAGE_SEARCH_LIMIT`).
+    #[serde(default = "default_message_search_limit")]
+    pub message_search_limit: usize,
+    /// How much newer messages are boosted in message search results (`MESSAGE_SEARCH_RECENCY_BOOST`).
+    /// A message's relevance is scaled by `1 + boost / (1 + age in days)`, so zero ranks by relevance alone.
+    #[serde(default)]
+    pub message_search_recency_boost: f32,
+    /// Whether message searches also match likely typos of the search terms (`MESSAGE_SEARCH_FUZZY`).
+    /// Only a channel's most recent messages are looked through for these, after the exact matches.
+    #[serde(default)]
+    pub message_search_fuzzy: bool,
+    /// How many messages are stored per transaction when messages are added in bulk (`MESSAGE_BATCH_SIZE`).
+    /// Used by history backfills and channel imports.
+    #[serde(default = "default_message_batch_size")]
+    pub message_batch_size: usize,
     /// MCP configuration file path (`MCP_CONFIG_PATH`).
     /// Path to the MCP JSON configuration file that defines available MCP servers.
     #[serde(default = "default_mcp_config_path")]
@@ -158,14 +811,24 @@ impl Config {
             cfg = cfg.add_source(config::File::with_name(".hidden/config.toml"));
         }
 
-        let result = Config {
-            inner: Arc::new(cfg.build()?.try_deserialize()?),
-        };
+        let mut inner: ConfigInner = cfg.build()?.try_deserialize()?;
+
+        // Directives can be loaded from files (which take precedence over the inline directives, and the built-in ones).
+        inner.load_directive_files()?;
+
+        // An empty organization or project (e.g., `TRIAGE_BOT_OPENAI_PROJECT=`) is unset, rather than sent as an empty header.
+        inner.unset_empty_openai_ids();
+
+        let result = Config { inner: Arc::new(inner) };
 
         if result.openai_search_agent_temperature < 0.0 || result.openai_search_agent_temperature > 2.0 {
             return Err(anyhow::anyhow!("OpenAI search agent temperature must be between 0 and 2."));
         }
 
+        if result.message_search_agent_temperature() < 0.0 || result.message_search_agent_temperature() > 2.0 {
+            return Err(anyhow::anyhow!("OpenAI message search agent temperature must be between 0 and 2."));
+        }
+
         if result.openai_assistant_agent_temperature < 0.0 || result.openai_assistant_agent_temperature > 2.0 {
             return Err(anyhow::anyhow!("OpenAI assistant agent temperature must be between 0 and 2."));
         }
@@ -174,6 +837,37 @@ impl Config {
             return Err(anyhow::anyhow!("OpenAI max tokens must be between 1 and 128000."));
         }
 
+        if result.openai_request_timeout_secs < 1 || result.openai_request_timeout_secs > 3600 {
+            return Err(anyhow::anyhow!("OpenAI request timeout must be between 1 and 3600 seconds."));
+        }
+
+        if result.openai_max_retries > 10 {
+            return Err(anyhow::anyhow!("OpenAI max retries must be at most 10."));
+        }
+
+        if result.openai_retry_base_delay_ms > 60000 {
+            return Err(anyhow::anyhow!("OpenAI retry base delay must be at most 60000 milliseconds."));
+        }
+
+        if result.openai_max_concurrent_requests > 1000 {
+            return Err(anyhow::anyhow!("OpenAI max concurrent requests must be at most 1000."));
+        }
+
+        if result.openai_circuit_breaker_cooldown_secs > 3600 {
+            return Err(anyhow::anyhow!("OpenAI circuit breaker cool-down must be at most 3600 seconds."));
+        }
+
+        if result.max_tool_iterations > 50 {
+            return Err(anyhow::anyhow!("Max tool iterations must be at most 50."));
+        }
+
+        if result.context_budget.total() > 100 {
+            return Err(anyhow::anyhow!(
+                "The context budget's shares must add up to at most 100 (percent), not {}.",
+                result.context_budget.total()
+            ));
+        }
+
         // Validate reasoning effort
         if !["low", "medium", "high"].contains(&result.openai_assistant_agent_reasoning_effort.as_str()) {
             return Err(anyhow::anyhow!("OpenAI assistant agent reasoning effort must be one of: low, medium, high."));
@@ -183,6 +877,621 @@ impl Config {
             return Err(anyhow::anyhow!("OpenAI search agent reasoning effort must be one of: low, medium, high."));
         }
 
+        if !["low", "medium", "high"].contains(&result.message_search_agent_reasoning_effort()) {
+            return Err(anyhow::anyhow!("OpenAI message search agent reasoning effort must be one of: low, medium, high."));
+        }
+
+        // Validate the OpenAI API settings.
+        result.validate_openai_api()?;
+
+        // Validate the slack mode.
+        if !["socket", "events"].contains(&result.slack_mode.as_str()) {
+            return Err(anyhow::anyhow!("Slack mode must be one of: socket, events."));
+        }
+
+        // Validate the confidence settings.
+        if result.reply_confidence_threshold > 100 {
+            return Err(anyhow::anyhow!("Reply confidence threshold must be at most 100."));
+        }
+
+        if !["tag", "silent"].contains(&result.low_confidence_reply.as_str()) {
+            return Err(anyhow::anyhow!("Low confidence reply must be one of: tag, silent."));
+        }
+
+        // Validate the moderation denylist, and the log redaction patterns.
+        result.moderation_denylist_regexes()?;
+        result.log_redaction_regexes()?;
+
+        // Validate the emoji map.
+        if let Some(name) = result
+            .emoji_map
+            .keys()
+            .find(|name| !AssistantClassification::ALL.iter().any(|c| format!("{c:?}").eq_ignore_ascii_case(name)))
+        {
+            return Err(anyhow::anyhow!("Emoji map key `{}` must be one of: Bug, Feature, Question, Incident, Other.", name));
+        }
+
+        // Validate the database authentication and TLS settings.
+        result.validate_db_auth()?;
+        result.validate_db_tls()?;
+
+        // Validate the slack workspaces.
+        let workspaces = result.workspaces();
+
+        if workspaces.iter().any(|w| w.bot_token.is_empty()) {
+            return Err(anyhow::anyhow!("Slack bot token is required (for every workspace)."));
+        }
+
+        if result.slack_mode == "socket" && workspaces.iter().any(|w| w.app_token.is_empty()) {
+            return Err(anyhow::anyhow!("Slack app token is required when slack mode is `socket`."));
+        }
+
+        if result.slack_mode == "events" {
+            if workspaces.iter().any(|w| w.events_bind_address.parse::<std::net::SocketAddr>().is_err()) {
+                return Err(anyhow::anyhow!("Slack events bind address must be a valid socket address (e.g., `0.0.0.0:3000`)."));
+            }
+
+            let mut addresses = workspaces.iter().map(|w| w.events_bind_address.as_str()).collect::<Vec<_>>();
+            addresses.sort();
+            addresses.dedup();
+
+            if addresses.len() != workspaces.len() {
+                return Err(anyhow::anyhow!("Each Slack workspace needs its own events bind address."));
+            }
+        }
+
         Ok(result)
     }
 }
+
+impl ConfigInner {
+    /// Unset the OpenAI organization and project, if they are empty (or whitespace).
+    fn unset_empty_openai_ids(&mut self) {
+        for id in [&mut self.openai_organization, &mut self.openai_project] {
+            *id = id.take().filter(|id| !id.trim().is_empty());
+        }
+    }
+
+    /// Replace each directive whose file is set (e.g., `assistant_agent_system_directive_path`) with the file's contents.
+    fn load_directive_files(&mut self) -> Res<()> {
+        let directives = [
+            (
+                "assistant_agent_system_directive_path",
+                &self.assistant_agent_system_directive_path,
+                &mut self.assistant_agent_system_directive,
+            ),
+            (
+                "assistant_agent_mention_directive_path",
+                &self.assistant_agent_mention_directive_path,
+                &mut self.assistant_agent_mention_directive,
+            ),
+            (
+                "assistant_agent_direct_message_directive_path",
+                &self.assistant_agent_direct_message_directive_path,
+                &mut self.assistant_agent_direct_message_directive,
+            ),
+            (
+                "assistant_agent_classify_directive_path",
+                &self.assistant_agent_classify_directive_path,
+                &mut self.assistant_agent_classify_directive,
+            ),
+            ("search_agent_system_directive_path", &self.search_agent_system_directive_path, &mut self.search_agent_system_directive),
+            (
+                "message_search_agent_system_directive_path",
+                &self.message_search_agent_system_directive_path,
+                &mut self.message_search_agent_system_directive,
+            ),
+            (
+                "link_summary_agent_system_directive_path",
+                &self.link_summary_agent_system_directive_path,
+                &mut self.link_summary_agent_system_directive,
+            ),
+            (
+                "thread_summary_agent_system_directive_path",
+                &self.thread_summary_agent_system_directive_path,
+                &mut self.thread_summary_agent_system_directive,
+            ),
+            ("intent_agent_system_directive_path", &self.intent_agent_system_directive_path, &mut self.intent_agent_system_directive),
+        ];
+
+        for (name, path, directive) in directives {
+            let Some(path) = path.as_deref().filter(|path| !path.trim().is_empty()) else {
+                continue;
+            };
+
+            *directive = std::fs::read_to_string(path).map_err(|err| anyhow::anyhow!("Failed to read the directive file (`{}`) at `{}`: {}", name, path, err))?;
+        }
+
+        Ok(())
+    }
+
+    /// Whether the database endpoint is a SurrealDB server (rather than an embedded database).
+    fn is_db_server(&self) -> bool {
+        !["memory", "mem://", "rocksdb://", "file://", "sqlite://"].iter().any(|prefix| self.db_endpoint.starts_with(prefix))
+    }
+
+    /// Validate the OpenAI API settings.
+    fn validate_openai_api(&self) -> Res<()> {
+        if !["openai", "azure"].contains(&self.openai_api_type.as_str()) {
+            return Err(anyhow::anyhow!("OpenAI API type (`openai_api_type`) must be one of: openai, azure."));
+        }
+
+        let is_set = |value: &Option<String>| value.as_deref().is_some_and(|v| !v.trim().is_empty());
+
+        if self.openai_api_type == "azure" {
+            if !is_set(&self.openai_api_base) {
+                return Err(anyhow::anyhow!("OpenAI API base (`openai_api_base`) is required when `openai_api_type` is `azure`."));
+            }
+
+            if !is_set(&self.openai_api_version) {
+                return Err(anyhow::anyhow!("OpenAI API version (`openai_api_version`) is required when `openai_api_type` is `azure`."));
+            }
+        } else if self.openai_api_version.is_some() {
+            return Err(anyhow::anyhow!("OpenAI API version (`openai_api_version`) is only used when `openai_api_type` is `azure`."));
+        }
+
+        if self.openai_api_type == "azure" && (self.openai_organization.is_some() || self.openai_project.is_some()) {
+            return Err(anyhow::anyhow!(
+                "OpenAI organization and project (`openai_organization`, `openai_project`) are not used when `openai_api_type` is `azure`."
+            ));
+        }
+
+        if is_set(&self.openai_project) && !is_set(&self.openai_organization) {
+            return Err(anyhow::anyhow!("OpenAI project (`openai_project`) requires an organization (`openai_organization`)."));
+        }
+
+        // They are sent as headers, so they must be valid header values.
+        let is_header_value = |value: &Option<String>| value.as_deref().is_none_or(|v| v.chars().all(|c| c.is_ascii_graphic()));
+        if !is_header_value(&self.openai_organization) || !is_header_value(&self.openai_project) {
+            return Err(anyhow::anyhow!(
+                "OpenAI organization and project (`openai_organization`, `openai_project`) must be IDs (e.g., `org-...`, `proj_...`)."
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Validate the database authentication settings.
+    fn validate_db_auth(&self) -> Res<()> {
+        if !["root", "namespace", "database", "token"].contains(&self.db_auth.as_str()) {
+            return Err(anyhow::anyhow!("Database auth (`db_auth`) must be one of: root, namespace, database, token."));
+        }
+
+        if self.db_auth != "root" && !self.is_db_server() {
+            return Err(anyhow::anyhow!("Database auth `{}` only applies to SurrealDB servers.", self.db_auth));
+        }
+
+        if self.db_namespace.is_empty() || self.db_database.is_empty() {
+            return Err(anyhow::anyhow!("Database namespace and database (`db_namespace` and `db_database`) cannot be empty."));
+        }
+
+        if self.db_auth == "token" {
+            if self.db_token.is_empty() {
+                return Err(anyhow::anyhow!("Database token (`db_token`) is required when `db_auth` is `token`."));
+            }
+        } else if !self.db_token.is_empty() {
+            return Err(anyhow::anyhow!("Database token (`db_token`) is only used when `db_auth` is `token`."));
+        } else if self.is_db_server() && self.db_username.is_empty() {
+            return Err(anyhow::anyhow!("Database username (`db_username`) is required when `db_auth` is `{}`.", self.db_auth));
+        }
+
+        Ok(())
+    }
+
+    /// Validate the database TLS settings against the database endpoint.
+    fn validate_db_tls(&self) -> Res<()> {
+        let uses_tls = self.db_tls || self.db_endpoint.starts_with("wss://") || self.db_endpoint.starts_with("https://");
+
+        if self.db_tls && (self.db_endpoint.starts_with("ws://") || self.db_endpoint.starts_with("http://")) {
+            return Err(anyhow::anyhow!("Database TLS (`db_tls`) cannot be used with a plain `{}` endpoint (use `wss://`).", self.db_endpoint));
+        }
+
+        if self.db_tls && !self.is_db_server() {
+            return Err(anyhow::anyhow!("Database TLS (`db_tls`) only applies to SurrealDB servers."));
+        }
+
+        if let Some(path) = &self.db_tls_ca_path {
+            if !uses_tls {
+                return Err(anyhow::anyhow!("The database CA certificates (`db_tls_ca_path`) need a TLS endpoint (`wss://`, or `db_tls`)."));
+            }
+
+            if !std::path::Path::new(path).is_file() {
+                return Err(anyhow::anyhow!("The database CA certificates file `{}` does not exist.", path));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Get the Slack workspaces to serve.
+    ///
+    /// This is `slack_workspaces` when set, or else a single workspace from the `slack_*` credentials.
+    pub fn workspaces(&self) -> Vec<SlackWorkspaceConfig> {
+        if !self.slack_workspaces.is_empty() {
+            return self.slack_workspaces.clone();
+        }
+
+        vec![SlackWorkspaceConfig {
+            name: "default".to_string(),
+            app_token: self.slack_app_token.clone(),
+            bot_token: self.slack_bot_token.clone(),
+            signing_secret: self.slack_signing_secret.clone(),
+            events_bind_address: self.slack_events_bind_address.clone(),
+        }]
+    }
+
+    /// Get the message search agent's model (`openai_message_search_agent_model`, or else the search agent's).
+    pub fn message_search_agent_model(&self) -> &str {
+        self.openai_message_search_agent_model.as_deref().unwrap_or(&self.openai_search_agent_model)
+    }
+
+    /// Get the message search agent's temperature (`openai_message_search_agent_temperature`, or else the search agent's).
+    pub fn message_search_agent_temperature(&self) -> f32 {
+        self.openai_message_search_agent_temperature.unwrap_or(self.openai_search_agent_temperature)
+    }
+
+    /// Get the message search agent's reasoning effort (`openai_message_search_agent_reasoning_effort`, or else the search
+    /// agent's).
+    pub fn message_search_agent_reasoning_effort(&self) -> &str {
+        self.openai_message_search_agent_reasoning_effort.as_deref().unwrap_or(&self.openai_search_agent_reasoning_effort)
+    }
+
+    /// Get the emoji (name) to react with for a classification, according to `emoji_map`.
+    pub fn classification_emoji(&self, classification: &AssistantClassification) -> String {
+        let name = format!("{classification:?}");
+
+        self.emoji_map
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(&name))
+            .map(|(_, emoji)| emoji.trim().trim_matches(':').to_string())
+            .filter(|emoji| !emoji.is_empty())
+            .unwrap_or_else(|| classification.default_emoji().to_string())
+    }
+
+    /// Get the price of a model, according to `openai_prices` (ignoring any provider prefix, e.g., `openai/gpt-4.1`).
+    pub fn model_price(&self, model: &str) -> Option<ModelPrice> {
+        let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+
+        self.openai_prices
+            .iter()
+            .filter(|(key, _)| name.starts_with(&key.to_lowercase()))
+            .max_by_key(|(key, _)| key.len())
+            .map(|(_, price)| *price)
+    }
+
+    /// Compile the moderation denylist's patterns.
+    pub fn moderation_denylist_regexes(&self) -> Res<Vec<regex::Regex>> {
+        self.moderation_denylist
+            .iter()
+            .map(|pattern| regex::Regex::new(pattern).map_err(|err| anyhow::anyhow!("Moderation denylist pattern `{}` is invalid: {}", pattern, err)))
+            .collect()
+    }
+
+    /// Compile the log redaction patterns.
+    pub fn log_redaction_regexes(&self) -> Res<Vec<regex::Regex>> {
+        self.log_redaction_patterns
+            .iter()
+            .map(|pattern| regex::Regex::new(pattern).map_err(|err| anyhow::anyhow!("Log redaction pattern `{}` is invalid: {}", pattern, err)))
+            .collect()
+    }
+
+    /// Whether channels are namespaced by workspace in the database (i.e., whether `slack_workspaces` is set).
+    pub fn is_multi_workspace(&self) -> bool {
+        !self.slack_workspaces.is_empty()
+    }
+
+    /// Check whether links on the given domain may be unfurled, according to `slack_unfurl_domains`.
+    pub fn is_unfurl_domain_allowed(&self, domain: &str) -> bool {
+        let domain = domain.trim().trim_end_matches('.').to_lowercase();
+
+        self.slack_unfurl_domains
+            .split(',')
+            .map(|allowed| allowed.trim().trim_start_matches("*.").to_lowercase())
+            .filter(|allowed| !allowed.is_empty())
+            .any(|allowed| domain == allowed || domain.ends_with(&format!(".{allowed}")))
+    }
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_unfurl_domain_allowed() {
+        let config = ConfigInner {
+            slack_unfurl_domains: "docs.example.com, github.com".to_string(),
+            ..Default::default()
+        };
+
+        assert!(config.is_unfurl_domain_allowed("github.com"));
+        assert!(config.is_unfurl_domain_allowed("gist.github.com"));
+        assert!(config.is_unfurl_domain_allowed("DOCS.example.com"));
+        assert!(!config.is_unfurl_domain_allowed("example.com"));
+        assert!(!config.is_unfurl_domain_allowed("notgithub.com"));
+        assert!(!ConfigInner::default().is_unfurl_domain_allowed("github.com"));
+    }
+
+    #[test]
+    fn test_load_directive_files() {
+        let path = std::env::temp_dir().join(format!("triage-bot-test-{}-{}.md", std::process::id(), chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()));
+        std::fs::write(&path, "# File Directive").unwrap();
+        let path = path.to_string_lossy().to_string();
+
+        let config = |value: serde_json::Value| serde_json::from_value::<ConfigInner>(value).unwrap();
+
+        // A file takes precedence over an inline directive, which takes precedence over the built-in one.
+        let mut from_file = config(serde_json::json!({
+            "openai_api_key": "sk-test",
+            "assistant_agent_system_directive": "# Inline Directive",
+            "assistant_agent_system_directive_path": path,
+            "search_agent_system_directive": "# Inline Search Directive",
+        }));
+        from_file.load_directive_files().unwrap();
+        assert_eq!(from_file.assistant_agent_system_directive, "# File Directive");
+        assert_eq!(from_file.search_agent_system_directive, "# Inline Search Directive");
+        assert_eq!(from_file.intent_agent_system_directive, prompts::INTENT_AGENT_SYSTEM_DIRECTIVE);
+
+        // A missing file is an error (naming the setting, and the path), rather than falling back.
+        let mut missing = config(serde_json::json!({
+            "openai_api_key": "sk-test",
+            "intent_agent_system_directive_path": "/nonexistent/intent.md",
+        }));
+        let err = missing.load_directive_files().unwrap_err().to_string();
+        assert!(err.contains("`intent_agent_system_directive_path`") && err.contains("/nonexistent/intent.md"), "{err}");
+
+        std::fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn test_validate_openai_api() {
+        let config = |value: serde_json::Value| serde_json::from_value::<ConfigInner>(value).unwrap();
+
+        // OpenAI is the default, with or without a custom base.
+        let openai = config(serde_json::json!({ "openai_api_key": "sk-test" }));
+        assert_eq!(openai.openai_api_type, "openai");
+        assert!(openai.validate_openai_api().is_ok());
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "sk-test", "openai_api_base": "http://localhost:11434/v1" }))
+                .validate_openai_api()
+                .is_ok()
+        );
+
+        let azure = config(serde_json::json!({
+            "openai_api_key": "key",
+            "openai_api_type": "azure",
+            "openai_api_base": "https://triage.openai.azure.com",
+            "openai_api_version": "2025-04-01-preview",
+        }));
+        assert!(azure.validate_openai_api().is_ok());
+
+        // Azure needs a base and a version, which only Azure uses.
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "key", "openai_api_type": "azure", "openai_api_version": "2025-04-01-preview" }))
+                .validate_openai_api()
+                .is_err()
+        );
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "key", "openai_api_type": "azure", "openai_api_base": "https://triage.openai.azure.com" }))
+                .validate_openai_api()
+                .is_err()
+        );
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "sk-test", "openai_api_version": "2025-04-01-preview" }))
+                .validate_openai_api()
+                .is_err()
+        );
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "sk-test", "openai_api_type": "anthropic" }))
+                .validate_openai_api()
+                .is_err()
+        );
+
+        // A project needs an organization, and both are sent as headers (to OpenAI only).
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "sk-test", "openai_organization": "org-123", "openai_project": "proj_456" }))
+                .validate_openai_api()
+                .is_ok()
+        );
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "sk-test", "openai_organization": "org-123" }))
+                .validate_openai_api()
+                .is_ok()
+        );
+        assert!(config(serde_json::json!({ "openai_api_key": "sk-test", "openai_project": "proj_456" })).validate_openai_api().is_err());
+        assert!(
+            config(serde_json::json!({ "openai_api_key": "sk-test", "openai_organization": "org 123\n" }))
+                .validate_openai_api()
+                .is_err()
+        );
+
+        // An empty organization or project is unset (so it is not sent as a header).
+        let mut empty = config(serde_json::json!({ "openai_api_key": "sk-test", "openai_organization": "", "openai_project": " " }));
+        empty.unset_empty_openai_ids();
+        assert_eq!((empty.openai_organization.as_deref(), empty.openai_project.as_deref()), (None, None));
+        assert!(empty.validate_openai_api().is_ok());
+
+        let mut organization = config(serde_json::json!({ "openai_api_key": "sk-test", "openai_organization": "org-123", "openai_project": "" }));
+        organization.unset_empty_openai_ids();
+        assert_eq!((organization.openai_organization.as_deref(), organization.openai_project.as_deref()), (Some("org-123"), None));
+        assert!(organization.validate_openai_api().is_ok());
+        assert!(
+            config(serde_json::json!({
+                "openai_api_key": "key",
+                "openai_api_type": "azure",
+                "openai_api_base": "https://triage.openai.azure.com",
+                "openai_api_version": "2025-04-01-preview",
+                "openai_organization": "org-123",
+            }))
+            .validate_openai_api()
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn test_db_auth_config() {
+        let config = |value: serde_json::Value| serde_json::from_value::<ConfigInner>(value).unwrap();
+
+        // Root auth, in the default namespace and database, is the default.
+        let root = config(serde_json::json!({ "openai_api_key": "sk-test", "db_endpoint": "db.internal:8000", "db_username": "root", "db_password": "root" }));
+        assert_eq!((root.db_auth.as_str(), root.db_namespace.as_str(), root.db_database.as_str()), ("root", "triage", "bot"));
+        assert!(root.validate_db_auth().is_ok());
+
+        let scoped = config(serde_json::json!({
+            "openai_api_key": "sk-test",
+            "db_endpoint": "wss://db.internal",
+            "db_auth": "database",
+            "db_namespace": "support",
+            "db_database": "triage-bot-2",
+            "db_username": "triage",
+            "db_password": "secret",
+        }));
+        assert_eq!((scoped.db_namespace.as_str(), scoped.db_database.as_str()), ("support", "triage-bot-2"));
+        assert!(scoped.validate_db_auth().is_ok());
+
+        // A token needs no username or password.
+        let token = config(serde_json::json!({ "openai_api_key": "sk-test", "db_endpoint": "db.internal:8000", "db_auth": "token", "db_token": "eyJ..." }));
+        assert!(token.validate_db_auth().is_ok());
+
+        // Incompatible combinations are rejected.
+        let invalid = |overrides: serde_json::Value| {
+            let mut value = serde_json::json!({ "openai_api_key": "sk-test", "db_endpoint": "db.internal:8000", "db_username": "triage", "db_password": "secret" });
+            value.as_object_mut().unwrap().extend(overrides.as_object().unwrap().clone());
+
+            config(value).validate_db_auth().is_err()
+        };
+
+        assert!(invalid(serde_json::json!({ "db_auth": "scope" })));
+        assert!(invalid(serde_json::json!({ "db_auth": "token" })));
+        assert!(invalid(serde_json::json!({ "db_auth": "namespace", "db_token": "eyJ..." })));
+        assert!(invalid(serde_json::json!({ "db_auth": "namespace", "db_username": "" })));
+        assert!(invalid(serde_json::json!({ "db_auth": "database", "db_endpoint": "sqlite://triage.db" })));
+        assert!(invalid(serde_json::json!({ "db_namespace": "" })));
+    }
+
+    #[test]
+    fn test_validate_db_tls() {
+        let config = |endpoint: &str, db_tls: bool, db_tls_ca_path: Option<&str>| ConfigInner {
+            db_endpoint: endpoint.to_string(),
+            db_tls,
+            db_tls_ca_path: db_tls_ca_path.map(str::to_string),
+            ..Default::default()
+        };
+
+        assert!(config("db.internal:8000", false, None).validate_db_tls().is_ok());
+        assert!(config("db.internal:8000", true, None).validate_db_tls().is_ok());
+        assert!(config("wss://db.internal", false, None).validate_db_tls().is_ok());
+        assert!(config("wss://db.internal", false, Some("Cargo.toml")).validate_db_tls().is_ok());
+
+        // TLS conflicts with plain and embedded endpoints, and CA certificates need TLS (and to exist).
+        assert!(config("ws://db.internal:8000", true, None).validate_db_tls().is_err());
+        assert!(config("sqlite://triage.db", true, None).validate_db_tls().is_err());
+        assert!(config("db.internal:8000", false, Some("Cargo.toml")).validate_db_tls().is_err());
+        assert!(config("wss://db.internal", false, Some("does/not/exist.pem")).validate_db_tls().is_err());
+    }
+
+    #[test]
+    fn test_classification_emoji() {
+        let config = ConfigInner {
+            emoji_map: HashMap::from([("Bug".to_string(), ":triage-bug:".to_string()), ("incident".to_string(), "rotating_light".to_string())]),
+            ..Default::default()
+        };
+
+        assert_eq!(config.classification_emoji(&AssistantClassification::Bug), "triage-bug");
+        assert_eq!(config.classification_emoji(&AssistantClassification::Incident), "rotating_light");
+        assert_eq!(config.classification_emoji(&AssistantClassification::Question), "question");
+        assert_eq!(ConfigInner::default().classification_emoji(&AssistantClassification::Other), "grey_question");
+    }
+
+    #[test]
+    fn test_message_search_agent_settings() {
+        let config = |value: serde_json::Value| serde_json::from_value::<ConfigInner>(value).unwrap();
+
+        // Without their own settings, they are the search agent's (as they were before they had their own).
+        let fallback = config(serde_json::json!({ "openai_api_key": "sk-test", "openai_search_agent_model": "gpt-4.1", "openai_search_agent_temperature": 0.3 }));
+        assert_eq!(fallback.message_search_agent_model(), "gpt-4.1");
+        assert_eq!(fallback.message_search_agent_temperature(), 0.3);
+        assert_eq!(fallback.message_search_agent_reasoning_effort(), "medium");
+
+        let own = config(serde_json::json!({
+            "openai_api_key": "sk-test",
+            "openai_search_agent_model": "gpt-4.1",
+            "openai_message_search_agent_model": "gpt-4.1-nano",
+            "openai_message_search_agent_temperature": 0.0,
+            "openai_message_search_agent_reasoning_effort": "low",
+        }));
+        assert_eq!(own.message_search_agent_model(), "gpt-4.1-nano");
+        assert_eq!(own.message_search_agent_temperature(), 0.0);
+        assert_eq!(own.message_search_agent_reasoning_effort(), "low");
+        assert_eq!(own.openai_search_agent_model, "gpt-4.1");
+    }
+
+    #[test]
+    fn test_model_price() {
+        let price = |input, output| ModelPrice { input, output };
+        let config = ConfigInner {
+            openai_prices: HashMap::from([("gpt-4.1".to_string(), price(2.0, 8.0)), ("gpt-4.1-mini".to_string(), price(0.4, 1.6))]),
+            ..Default::default()
+        };
+
+        assert_eq!(config.model_price("gpt-4.1"), Some(price(2.0, 8.0)));
+        assert_eq!(config.model_price("gpt-4.1-2025-04-14"), Some(price(2.0, 8.0)));
+        assert_eq!(config.model_price("gpt-4.1-mini-2025-04-14"), Some(price(0.4, 1.6)));
+        assert_eq!(config.model_price("openai/GPT-4.1-mini"), Some(price(0.4, 1.6)));
+        assert_eq!(config.model_price("o3"), None);
+    }
+
+    #[test]
+    fn test_moderation_denylist_regexes() {
+        let config = ConfigInner {
+            moderation_denylist: vec![r"\.corp\.example\.com".to_string(), "AKIA[0-9A-Z]{16}".to_string()],
+            ..Default::default()
+        };
+
+        let regexes = config.moderation_denylist_regexes().unwrap();
+        assert_eq!(regexes.len(), 2);
+        assert!(regexes[0].is_match("ssh build01.corp.example.com"));
+
+        let invalid = ConfigInner {
+            moderation_denylist: vec!["(unclosed".to_string()],
+            ..Default::default()
+        };
+        assert!(invalid.moderation_denylist_regexes().is_err());
+    }
+
+    #[test]
+    fn test_workspaces() {
+        let config = ConfigInner {
+            slack_app_token: "xapp-1".to_string(),
+            slack_bot_token: "xoxb-1".to_string(),
+            slack_events_bind_address: "0.0.0.0:3000".to_string(),
+            ..Default::default()
+        };
+
+        let workspaces = config.workspaces();
+        assert!(!config.is_multi_workspace());
+        assert_eq!(workspaces.len(), 1);
+        assert_eq!(workspaces[0].app_token, "xapp-1");
+        assert_eq!(workspaces[0].bot_token, "xoxb-1");
+
+        let internal = SlackWorkspaceConfig {
+            name: "internal".to_string(),
+            bot_token: "xoxb-internal".to_string(),
+            ..Default::default()
+        };
+        let customer = SlackWorkspaceConfig {
+            name: "customer".to_string(),
+            bot_token: "xoxb-customer".to_string(),
+            ..Default::default()
+        };
+        let config = ConfigInner {
+            slack_bot_token: "xoxb-ignored".to_string(),
+            slack_workspaces: vec![internal.clone(), customer.clone()],
+            ..Default::default()
+        };
+
+        assert!(config.is_multi_workspace());
+        assert_eq!(config.workspaces(), vec![internal, customer]);
+    }
+}
@@ -0,0 +1,65 @@
+//! Detecting the language of a message (e.g., German), so that the assistant can reply in it.
+//!
+//! Detection is local (no LLM call), and only the user's own words are considered: mentions, links, code, and the notes
+//! about attached files are left out, since they are usually English (e.g., stack traces).
+
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+// Statics.
+
+/// What is not the user's own words: code (blocks, and inline), Slack mentions and links, and attached file notes.
+static NOT_PROSE: OnceLock<Regex> = OnceLock::new();
+
+fn not_prose() -> &'static Regex {
+    NOT_PROSE.get_or_init(|| Regex::new(r"(?s)```.*?```|`[^`]*`|<[^>]*>|\[User attached [^\]]*\]").expect("The regex is valid."))
+}
+
+// Functions.
+
+/// Detect the language of a message's text (by its English name, e.g., `German`), or `None` if it is not clear (e.g.,
+/// the message is too short).
+pub fn detect_language(text: &str) -> Option<&'static str> {
+    let info = whatlang::detect(&prose(text))?;
+
+    info.is_reliable().then(|| info.lang().eng_name())
+}
+
+// Helpers.
+
+/// The user's own words in a message's text, without code, Slack mentions and links, or attached file notes.
+fn prose(text: &str) -> String {
+    not_prose().replace_all(text, " ").split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_detect_language() {
+        assert_eq!(
+            detect_language("Hallo zusammen, seit dem letzten Deployment startet der Dienst nicht mehr. Kann sich das bitte jemand ansehen?"),
+            Some("German")
+        );
+        assert_eq!(detect_language("デプロイ後にサービスが起動しなくなりました。誰か確認していただけますか？"), Some("Japanese"));
+        assert_eq!(
+            detect_language("Since the last deploy, the service no longer starts. Could someone please take a look?"),
+            Some("English")
+        );
+
+        // Too little to go on.
+        assert_eq!(detect_language("ok"), None);
+        assert_eq!(detect_language(""), None);
+    }
+
+    #[test]
+    fn test_prose() {
+        let text = "<@U12345> der Dienst stürzt ab, siehe <https://example.com|hier> und `cargo run`:\n\n[User attached a file named `trace.txt`:]\n```\nthread 'main' panicked\n```";
+
+        assert_eq!(prose(text), "der Dienst stürzt ab, siehe und :");
+    }
+}
@@ -4,7 +4,17 @@
 //! - Configuration handling and environment variables.
 //! - System prompts and directives for LLM interactions.
 //! - Common types and result handling.
+//! - Template variables in system directives and prompts.
+//! - Detecting the language of a message.
+//! - Hardening the LLM input against prompt injection.
+//! - Redacting secrets from the logs.
+//! - Small shared utilities (e.g., caching).
 
+pub mod cache;
 pub mod config;
+pub mod injection;
+pub mod language;
 pub mod prompts;
+pub mod redact;
+pub mod template;
 pub mod types;
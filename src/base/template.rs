@@ -0,0 +1,129 @@
+//! Template variables in system directives and prompts (e.g., `{date}`).
+//!
+//! Directives (built-in, or overridden in the config) can refer to a few variables that are only known when a message is
+//! handled, such as the channel name and the current date (which keeps the model from assuming a stale year).
+
+use serde::Serialize;
+use tracing::warn;
+
+// Types.
+
+/// The values of the template variables, for a single handled message.
+#[derive(Debug, Clone, Default, Serialize, PartialEq, Eq)]
+pub struct TemplateVariables {
+    /// The channel's name (`{channel_name}`), or its ID, if the name is not known.
+    pub channel_name: String,
+    /// The current date, as `YYYY-MM-DD` (`{date}`).
+    pub date: String,
+    /// The bot's user ID (`{bot_user_id}`).
+    pub bot_user_id: String,
+    /// The channel's oncall (`{oncall}`), as mentioned in the channel directive.
+    pub oncall: String,
+    /// The language to reply in (`{language}`, e.g., `German`), as detected in the message, or set for the channel.
+    pub language: String,
+}
+
+impl TemplateVariables {
+    /// The value of a variable, by its name, or `None` if there is no such variable.
+    ///
+    /// Variables without a value (e.g., a channel without an oncall) render as `unknown`.
+    fn get(&self, name: &str) -> Option<&str> {
+        let value = match name {
+            "channel_name" => &self.channel_name,
+            "date" => &self.date,
+            "bot_user_id" => &self.bot_user_id,
+            "oncall" => &self.oncall,
+            "language" => &self.language,
+            _ => return None,
+        };
+
+        Some(if value.is_empty() { "unknown" } else { value })
+    }
+}
+
+// Functions.
+
+/// Render a template, replacing each `{name}` placeholder with the value of its variable.
+///
+/// Only lowercase identifiers in braces are placeholders, so that other braces (e.g., JSON examples) are left alone.
+/// Unknown placeholders are rendered as they are (with a warning), rather than failing.
+pub fn render_template(template: &str, variables: &TemplateVariables) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find('{') {
+        rendered.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let name = rest[1..]
+            .find('}')
+            .map(|end| &rest[1..end + 1])
+            .filter(|name| !name.is_empty() && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '_'));
+
+        match name {
+            Some(name) => {
+                match variables.get(name) {
+                    Some(value) => rendered.push_str(value),
+                    None => {
+                        warn!("Unknown template variable `{{{name}}}`, so it is left as it is.");
+                        rendered.push_str(&rest[..name.len() + 2]);
+                    }
+                }
+
+                rest = &rest[name.len() + 2..];
+            }
+            None => {
+                rendered.push('{');
+                rest = &rest[1..];
+            }
+        }
+    }
+
+    rendered.push_str(rest);
+    rendered
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn variables() -> TemplateVariables {
+        TemplateVariables {
+            channel_name: "help-horses".to_string(),
+            date: "2025-06-01".to_string(),
+            bot_user_id: "U12345".to_string(),
+            oncall: String::new(),
+            language: "German".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_template() {
+        let variables = variables();
+
+        assert_eq!(
+            render_template("Today is {date}.  You are <@{bot_user_id}> in #{channel_name}.", &variables),
+            "Today is 2025-06-01.  You are <@U12345> in #help-horses."
+        );
+
+        // Variables without a value render as `unknown`.
+        assert_eq!(render_template("Tag {oncall}.", &variables), "Tag unknown.");
+
+        // Unknown placeholders, and other braces, are left as they are.
+        assert_eq!(render_template("Hi {name}, {date}!", &variables), "Hi {name}, 2025-06-01!");
+        assert_eq!(render_template(r#"Reply like {"type": "NoAction"} or {}."#, &variables), r#"Reply like {"type": "NoAction"} or {}."#);
+        assert_eq!(render_template("{{date}} {date", &variables), "{2025-06-01} {date");
+        assert_eq!(render_template("No placeholders: ünïcode {Date}.", &variables), "No placeholders: ünïcode {Date}.");
+    }
+
+    #[test]
+    fn test_render_assistant_directive_language() {
+        let directive = render_template(crate::base::prompts::ASSISTANT_AGENT_SYSTEM_DIRECTIVE, &variables());
+
+        assert!(directive.contains("Write the `message` of your replies in German"));
+        assert!(directive.contains("Keep everything else in English"));
+        assert!(!directive.contains("{language}"));
+    }
+}
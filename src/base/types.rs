@@ -4,9 +4,13 @@
 //! including error handling types, context structures for LLM interactions, and response
 //! types from the assistant.
 
+use std::sync::{Arc, Mutex};
+
 use serde::{Deserialize, Serialize};
 use serde_json::Value;
 
+use crate::base::template::TemplateVariables;
+
 /// Standard error type used throughout the application.
 pub type Err = anyhow::Error;
 /// Standard result type with unified error handling.
@@ -16,7 +20,7 @@ pub type Void = Res<()>;
 
 /// The classification of the assistant's response.
 /// This is used to determine the type of action to take based on the assistant's response.
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub enum AssistantClassification {
     /// Bug classification indicates that the issue is a bug in the system.
     Bug,
@@ -30,6 +34,99 @@ pub enum AssistantClassification {
     Other,
 }
 
+impl AssistantClassification {
+    /// All of the classifications.
+    pub const ALL: [AssistantClassification; 5] = [Self::Bug, Self::Feature, Self::Question, Self::Incident, Self::Other];
+
+    /// The default emoji (name) used to react to a message with this classification.
+    pub fn default_emoji(&self) -> &'static str {
+        match self {
+            Self::Question => "question",
+            Self::Feature => "bulb",
+            Self::Bug => "bug",
+            Self::Incident => "warning",
+            Self::Other => "grey_question",
+        }
+    }
+}
+
+/// How much the assistant does in a channel (set per channel).
+///
+/// Only ordinary messages are affected: @-mentions and direct messages are always answered in full, so that the
+/// assistant can still be asked to change its directive (or answer a question directly).
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelMode {
+    /// Answer messages (the default).
+    #[default]
+    Full,
+    /// Only classify messages (reacting with the classification's emoji), and tag the oncall, without an answer.  The
+    /// web and message searches are skipped, so this costs much less.
+    ClassifyOnly,
+    /// Only classify messages (reacting with the classification's emoji), without posting anything.
+    Silent,
+}
+
+impl ChannelMode {
+    /// All of the modes.
+    pub const ALL: [ChannelMode; 3] = [Self::Full, Self::ClassifyOnly, Self::Silent];
+
+    /// The mode's label, as it is stored (and written in commands).
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Full => "full",
+            Self::ClassifyOnly => "classify_only",
+            Self::Silent => "silent",
+        }
+    }
+
+    /// Parse a mode from its label (case-insensitively, and with `-` or ` ` for `_`, e.g., `classify-only`).
+    pub fn parse(text: &str) -> Option<Self> {
+        let label = text.trim().replace(['-', ' '], "_");
+
+        Self::ALL.into_iter().find(|mode| mode.label().eq_ignore_ascii_case(&label))
+    }
+}
+
+/// What a message to the assistant asks for, as labeled by the intent agent.
+///
+/// The intent decides which of the assistant's tools it is offered, so that it only updates its directive or context
+/// (or calls an external tool) when a message asks for that.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AssistantIntent {
+    /// The message asks to change how the bot behaves in the channel (its directive).
+    UpdateDirective,
+    /// The message asks the bot to remember something about the channel, or one of its users.
+    UpdateContext,
+    /// The message asks for something that one of the external (MCP) tools does.
+    McpRequest,
+    /// Anything else (e.g., a question, or a report), which needs none of those tools.
+    #[default]
+    Ordinary,
+}
+
+impl AssistantIntent {
+    /// All of the intents.
+    pub const ALL: [AssistantIntent; 4] = [Self::UpdateDirective, Self::UpdateContext, Self::McpRequest, Self::Ordinary];
+
+    /// The intent's label, as the intent agent writes it.
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::UpdateDirective => "UpdateDirective",
+            Self::UpdateContext => "UpdateContext",
+            Self::McpRequest => "McpRequest",
+            Self::Ordinary => "Ordinary",
+        }
+    }
+
+    /// Parse the intent agent's response, which should be a single label (possibly quoted, or with punctuation).
+    pub fn parse(text: &str) -> Option<Self> {
+        let label = text.trim().trim_matches(|c: char| !c.is_alphanumeric());
+
+        Self::ALL.into_iter().find(|intent| intent.label().eq_ignore_ascii_case(label))
+    }
+}
+
 /// An enum representing the different types of responses from the LLM.
 ///
 /// This includes both direct responses (like replies or taking no action)
@@ -46,9 +143,29 @@ pub enum AssistantResponse {
         thread_ts: String,
         /// The classification of the response, used to determine the type of action.
         classification: AssistantClassification,
+        /// The message to send in the thread (empty, or `null` when only classifying, to only react).
+        #[serde(default, deserialize_with = "null_as_default")]
+        message: String,
+        /// How confident (0-100) the assistant is in its answer (unknown for older responses, or models that leave it out).
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        confidence: Option<u8>,
+        /// The web pages that the message is based on (attached from the LLM's citations, rather than written by it).
+        #[serde(default, skip_serializing_if = "Vec::is_empty")]
+        sources: Vec<Source>,
+    },
+    /// An ephemeral reply to a thread in Slack, visible only to the user who sent the message.
+    EphemeralReplyToThread {
+        /// The timestamp of the thread to reply to.
+        thread_ts: String,
         /// The message to send in the thread.
         message: String,
     },
+    /// The model refused to respond (e.g., for safety reasons).  This is never written by the assistant, but parsed from
+    /// the model's refusal, so that it is handled (and stored) rather than failing the event.
+    Refused {
+        /// The model's explanation of the refusal.
+        reason: String,
+    },
 
     // Built-in Tool calls.
     /// Update the channel directive with a message.
@@ -57,6 +174,15 @@ pub enum AssistantResponse {
         call_id: String,
         /// The message that represents what the bot "thinks about" the directive update.
         message: String,
+        /// Whether replies to incidents should also be broadcast to the channel (unchanged if `None`).
+        #[serde(default)]
+        broadcast_incidents: Option<bool>,
+        /// The channels whose message history may also be searched (unchanged if `None`).
+        #[serde(default)]
+        linked_channels: Option<Vec<String>>,
+        /// How much the assistant does in the channel (unchanged if `None`).
+        #[serde(default)]
+        mode: Option<ChannelMode>,
     },
     /// Update the channel context with a message.
     UpdateContext {
@@ -64,6 +190,52 @@ pub enum AssistantResponse {
         call_id: String,
         /// The message that represents what the bot "thinks about" the context update.
         message: String,
+        /// How many days the context is remembered for (forever if `None`).
+        #[serde(default)]
+        expires_in_days: Option<u64>,
+    },
+    /// Remember something about a user (e.g., what they own) in the channel.
+    UpdateUserContext {
+        /// The unique identifier for the call, used to track the response.
+        call_id: String,
+        /// The ID of the user that the context is about.
+        user_id: String,
+        /// The message that represents what the bot "thinks about" the user.
+        message: String,
+    },
+    /// Forget (remove) stored channel contexts, or list them when no IDs are given.
+    ForgetContext {
+        /// The unique identifier for the call, used to track the response.
+        call_id: String,
+        /// The IDs of the contexts to forget (an empty list asks for the stored contexts instead).
+        context_ids: Vec<String>,
+    },
+    /// Restore an earlier channel directive, or list the directive history when no version is given.
+    RevertChannelDirective {
+        /// The unique identifier for the call, used to track the response.
+        call_id: String,
+        /// The ID of the directive version to restore (`None` asks for the directive history instead).
+        #[serde(default)]
+        version_id: Option<String>,
+    },
+    /// Pin a message (e.g., a thread's resolution) to the channel.
+    PinMessage {
+        /// The unique identifier for the call, used to track the response.
+        call_id: String,
+        /// The timestamp of the message to pin.
+        ts: String,
+    },
+    /// Fetch the messages of a thread (e.g., an earlier thread in the channel that the thread refers to).
+    FetchThreadContext {
+        /// The unique identifier for the call, used to track the response.
+        call_id: String,
+        /// The ID of the channel that the thread is in (which must be the current channel).
+        channel_id: String,
+        /// The timestamp of the thread's root message.
+        thread_ts: String,
+        /// How many of the thread's most recent messages to fetch (a default number if `None`).
+        #[serde(default)]
+        limit: Option<usize>,
     },
 
     // MCP Tool calls.
@@ -79,9 +251,21 @@ pub enum AssistantResponse {
 }
 
 impl AssistantResponse {
+    /// The types of the responses that the assistant writes as (JSON) text, rather than as a tool call.
+    pub const TEXT_TYPES: [&'static str; 3] = ["NoAction", "ReplyToThread", "EphemeralReplyToThread"];
+
     /// Check if the response is a tool call.
     pub fn is_tool_call(&self) -> bool {
-        matches!(self, AssistantResponse::UpdateChannelDirective { .. } | AssistantResponse::UpdateContext { .. })
+        matches!(
+            self,
+            AssistantResponse::UpdateChannelDirective { .. }
+                | AssistantResponse::UpdateContext { .. }
+                | AssistantResponse::UpdateUserContext { .. }
+                | AssistantResponse::ForgetContext { .. }
+                | AssistantResponse::RevertChannelDirective { .. }
+                | AssistantResponse::PinMessage { .. }
+                | AssistantResponse::FetchThreadContext { .. }
+        )
     }
 }
 
@@ -92,17 +276,229 @@ impl AssistantResponse {
 /// responses that may include tool calls or other structured data.
 #[derive(Debug, Serialize, Deserialize)]
 pub enum TextOrResponse {
-    /// A raw text message.
-    Text(String),
+    /// A raw text message, and the web pages that it cites.
+    Text {
+        /// The text of the message.
+        text: String,
+        /// The web pages that the text cites (e.g., from a web search).
+        sources: Vec<Source>,
+    },
     /// A response from the LLM.
     AssistantResponse(AssistantResponse),
+    /// A summary of a reasoning model's reasoning (only for the logs and traces, and never posted).
+    Reasoning {
+        /// The summary's text.
+        summary: String,
+    },
+}
+
+/// A web page that a response cites (e.g., a web search result).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Source {
+    /// The title of the page.
+    pub title: String,
+    /// The URL of the page.
+    pub url: String,
+}
+
+/// An image attached to a message (e.g., a screenshot of an error dialog).
+#[derive(Debug, Clone, Serialize, PartialEq, Eq)]
+pub struct AssistantImage {
+    /// The file's name.
+    pub name: String,
+    /// The image's MIME type (e.g., `image/png`).
+    pub mime_type: String,
+    /// The image's bytes.
+    #[serde(skip)]
+    pub data: Vec<u8>,
+}
+
+/// The web search agent's response.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WebSearchResponse {
+    /// What the search found, for the assistant agent.
+    pub text: String,
+    /// The web pages that the findings cite.
+    pub sources: Vec<Source>,
+}
+
+/// The verdict of a moderation check on some text.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Moderation {
+    /// Whether the text was flagged.
+    pub flagged: bool,
+    /// The categories that the text was flagged for (e.g., `harassment`).
+    pub categories: Vec<String>,
+}
+
+/// The token usage (and estimated cost) of one or more LLM calls.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TokenUsage {
+    /// The number of LLM calls.
+    pub requests: u64,
+    /// The number of input tokens.
+    pub input_tokens: u64,
+    /// The number of output tokens (including reasoning tokens).
+    pub output_tokens: u64,
+    /// The number of (hidden) reasoning tokens.
+    pub reasoning_tokens: u64,
+    /// The estimated cost, in US dollars (zero for models without a price, see `Config::openai_prices`).
+    pub cost_usd: f64,
+}
+
+impl TokenUsage {
+    /// Add the usage of other calls to this one.
+    pub fn add(&mut self, other: &TokenUsage) {
+        self.requests += other.requests;
+        self.input_tokens += other.input_tokens;
+        self.output_tokens += other.output_tokens;
+        self.reasoning_tokens += other.reasoning_tokens;
+        self.cost_usd += other.cost_usd;
+    }
+
+    /// Whether no calls were made.
+    pub fn is_empty(&self) -> bool {
+        self.requests == 0
+    }
+}
+
+/// Adds up the token usage of the LLM calls made while handling an event (it is shared by all of the event's agents).
+#[derive(Debug, Clone, Default)]
+pub struct UsageTracker {
+    inner: Arc<Mutex<UsageTrackerInner>>,
+}
+
+#[derive(Debug, Default)]
+struct UsageTrackerInner {
+    total: TokenUsage,
+    unreported: TokenUsage,
+}
+
+impl UsageTracker {
+    /// Record the usage of an LLM call.
+    pub fn record(&self, usage: &TokenUsage) {
+        let mut inner = self.inner.lock().unwrap();
+
+        inner.total.add(usage);
+        inner.unreported.add(usage);
+    }
+
+    /// The usage of all of the calls so far.
+    pub fn total(&self) -> TokenUsage {
+        self.inner.lock().unwrap().total.clone()
+    }
+
+    /// Take the usage of the calls since this was last called (e.g., to store it with a response, without counting any call twice).
+    pub fn take_unreported(&self) -> TokenUsage {
+        std::mem::take(&mut self.inner.lock().unwrap().unreported)
+    }
+}
+
+/// Trackers are equal when they are the same tracker (so that contexts can be compared).
+impl PartialEq for UsageTracker {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
 }
 
+impl Eq for UsageTracker {}
+
+/// Collects the reasoning summaries of the assistant's calls while handling an event (so that they can be stored with its responses).
+#[derive(Debug, Clone, Default)]
+pub struct ReasoningTracker {
+    inner: Arc<Mutex<Vec<String>>>,
+}
+
+impl ReasoningTracker {
+    /// Record the reasoning summary of an LLM call.
+    pub fn record(&self, summary: &str) {
+        self.inner.lock().unwrap().push(summary.to_string());
+    }
+
+    /// Take the summaries recorded since this was last called (e.g., to store them with a response).
+    pub fn take(&self) -> Vec<String> {
+        std::mem::take(&mut self.inner.lock().unwrap())
+    }
+}
+
+/// Trackers are equal when they are the same tracker (so that contexts can be compared).
+impl PartialEq for ReasoningTracker {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.inner, &other.inner)
+    }
+}
+
+impl Eq for ReasoningTracker {}
+
 /// Arguments for the direct / context update function tools.
 #[derive(Debug, Serialize, Deserialize)]
 pub struct ToolContextFunctionCallArgs {
     /// The message that represents what the bot "thinks about" the directive / context update.
     pub message: String,
+    /// How many days the context is remembered for (forever if omitted).
+    #[serde(default)]
+    pub expires_in_days: Option<u64>,
+}
+
+/// Arguments for the channel directive function tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolDirectiveFunctionCallArgs {
+    /// The message that represents what the bot "thinks about" the directive update.
+    pub message: String,
+    /// Whether replies to incidents should also be broadcast to the channel (unchanged if omitted).
+    #[serde(default)]
+    pub broadcast_incidents: Option<bool>,
+    /// The channels whose message history may also be searched (unchanged if omitted).
+    #[serde(default)]
+    pub linked_channels: Option<Vec<String>>,
+    /// How much the bot does in the channel (unchanged if omitted).
+    #[serde(default)]
+    pub mode: Option<ChannelMode>,
+}
+
+/// Arguments for the user context function tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolUserContextFunctionCallArgs {
+    /// The ID of the user that the context is about.
+    pub user_id: String,
+    /// The message that represents what the bot "thinks about" the user.
+    pub message: String,
+}
+
+/// Arguments for the forget context function tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolForgetFunctionCallArgs {
+    /// The IDs of the contexts to forget (empty to list the stored contexts).
+    #[serde(default)]
+    pub context_ids: Vec<String>,
+}
+
+/// Arguments for the revert channel directive function tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolRevertFunctionCallArgs {
+    /// The ID of the directive version to restore (omitted to list the directive history).
+    #[serde(default)]
+    pub version_id: Option<String>,
+}
+
+/// Arguments for the pin message function tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolPinFunctionCallArgs {
+    /// The timestamp of the message to pin.
+    pub ts: String,
+}
+
+/// Arguments for the fetch thread context function tool.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct ToolFetchThreadFunctionCallArgs {
+    /// The ID of the channel that the thread is in.
+    pub channel_id: String,
+    /// The timestamp of the thread's root message.
+    pub thread_ts: String,
+    /// How many of the thread's most recent messages to fetch.
+    #[serde(default)]
+    pub limit: Option<usize>,
 }
 
 /// Definition of a tool, as sent to the LLM.
@@ -132,6 +528,11 @@ pub struct WebSearchContext {
     pub channel_context: String,
     /// The context of the thread, which may include previous messages or relevant information.
     pub thread_context: String,
+    /// The values of the template variables in the search agent's directive.
+    pub variables: TemplateVariables,
+    /// Where the token usage of the search is added up.
+    #[serde(skip)]
+    pub usage: UsageTracker,
 }
 
 /// Helper struct to handle the context for the message search LLM.
@@ -150,6 +551,59 @@ pub struct MessageSearchContext {
     pub channel_context: String,
     /// The context of the thread, which may include previous messages or relevant information.
     pub thread_context: String,
+    /// The values of the template variables in the message search agent's directive.
+    pub variables: TemplateVariables,
+    /// Where the token usage of the search is added up.
+    #[serde(skip)]
+    pub usage: UsageTracker,
+}
+
+/// Helper struct to handle the context for the link summary LLM.
+///
+/// Contains the shared link, and the (already fetched) content of the page,
+/// so that the link summary agent can produce a short unfurl.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct LinkSummaryContext {
+    /// The URL of the shared link.
+    pub url: String,
+    /// The text content of the page behind the link.
+    pub content: String,
+}
+
+/// Helper struct to handle the context for the thread summary LLM.
+///
+/// Contains the (older) messages of a very long thread, so that the thread summary
+/// agent can condense them before they are handed to the assistant agent.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct SummaryContext {
+    /// The thread's messages to summarize (as JSON).
+    pub thread_context: String,
+    /// Where the token usage of the summary is added up.
+    #[serde(skip)]
+    pub usage: UsageTracker,
+}
+
+/// Helper struct to handle the context for the intent LLM.
+///
+/// Contains the user's message (and its thread), and the external tools that the assistant could call, so that the
+/// intent agent can label what the message asks for.
+#[derive(Debug, Serialize, PartialEq, Eq)]
+pub struct IntentContext {
+    /// The user's message to label.
+    pub user_message: String,
+    /// The bot's user ID, so that the intent agent knows when the bot is mentioned.
+    pub bot_user_id: String,
+    /// The channel ID where the message was sent.
+    pub channel_id: String,
+    /// The context of the thread, which may include previous messages or relevant information.
+    pub thread_context: String,
+    /// The names and descriptions of the external (MCP) tools, one per line.
+    pub tools: String,
+    /// The values of the template variables in the intent agent's directive.
+    pub variables: TemplateVariables,
+    /// Where the token usage of the intent agent is added up.
+    #[serde(skip)]
+    pub usage: UsageTracker,
 }
 
 /// Helper struct to handle the context for the assistant LLM.
@@ -165,18 +619,60 @@ pub struct AssistantContext {
     pub bot_user_id: String,
     /// The channel ID where the assistant is operating.
     pub channel_id: String,
-    /// The timestamp of the thread where the assistant is responding.
+    /// The timestamp of the thread root where the assistant is responding (for a top-level message, its own `ts`).
     pub thread_ts: String,
+    /// Whether the conversation is a (private) direct message with the bot.
+    pub is_direct_message: bool,
+    /// The channel's system addendum (set by an operator, and not editable by the channel's members), or empty if it has none.
+    pub system_addendum: String,
     /// The context of the channel, which may include settings or metadata relevant to the assistant's operation.
     pub channel_directive: String,
     /// The context of the thread, which may include previous messages or relevant information.
     pub channel_context: String,
+    /// What is known about the message's author, and the users it mentions (e.g., what they own).
+    pub user_context: String,
     /// The context of the thread, which may include previous messages or relevant information.
     pub thread_context: String,
+    /// The assistant's own most recent responses in the thread (e.g., replies, `NoAction`s, and tool calls), so that it does not re-answer a thread it already handled.
+    pub previous_responses: String,
+    /// The channel's most recent messages (outside of this thread, oldest first), so that the assistant knows what is going on in the channel (e.g., an ongoing incident).
+    pub recent_messages: String,
+    /// The images attached to the user's message, which are shown to the assistant if its model can see images.
+    pub images: Vec<AssistantImage>,
+    /// The language to reply in (e.g., `German`), as detected in the user's message, or set for the channel (empty if it is not known).
+    pub language: String,
+    /// How much the assistant does for the message (anything but `Full` only classifies it, with a reduced prompt).
+    pub mode: ChannelMode,
     /// The web search context, which may include search results or relevant information gathered from the web.
     pub web_search_context: String,
+    /// The web pages that the web search context cites, which are attached to the assistant's replies.
+    pub web_search_sources: Vec<Source>,
+    /// Whether the web was searched for the message (if not, the assistant is told not to offer web results).
+    pub web_search_enabled: bool,
     /// The message search context, which may include keywords or relevant information gathered from the channel history.
     pub message_search_context: String,
     /// A list of tools that the assistant can use to perform actions or gather information.
     pub tools: Vec<AssistantTool>,
+    /// What the message asks for (as labeled by the intent agent), which decides the tools that the assistant is offered.
+    pub intent: AssistantIntent,
+    /// The values of the template variables in the assistant's directives.
+    pub variables: TemplateVariables,
+    /// Where the token usage of the assistant's calls is added up.
+    #[serde(skip)]
+    pub usage: UsageTracker,
+    /// Where the reasoning summaries of the assistant's calls are collected.
+    #[serde(skip)]
+    pub reasoning: ReasoningTracker,
+}
+
+// Helpers.
+
+/// Deserialize a `null` as the type's default (e.g., an empty string), since the strict JSON schema makes the assistant
+/// write `null` for the fields that it leaves out.
+fn null_as_default<'de, D, T>(deserializer: D) -> Result<T, D::Error>
+where
+    D: serde::Deserializer<'de>,
+    T: Default + Deserialize<'de>,
+{
+    Ok(Option::<T>::deserialize(deserializer)?.unwrap_or_default())
 }
@@ -2,9 +2,12 @@
 //!
 //! This module provides the command-line interface for triage-bot with options
 //! for configuration file paths and logging verbosity. It initializes the
-//! necessary components and starts the service.
+//! necessary components and starts the service (or runs a maintenance command,
+//! such as exporting a channel).
 
-use clap::Parser;
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
 use opentelemetry::trace::TracerProvider;
 use opentelemetry_otlp::{Protocol, WithExportConfig};
 use tracing_subscriber::{fmt::format::FmtSpan, layer::SubscriberExt, util::SubscriberInitExt};
@@ -24,7 +27,7 @@ struct Args {
     /// By default, the bot will look for a config file at `.hidden/config.toml`
     /// in the current directory.
     #[arg(short, long)]
-    config: Option<std::path::PathBuf>,
+    config: Option<PathBuf>,
     /// Increase log verbosity (-v, -vv, etc.).
     ///
     /// Use multiple times to increase verbosity:
@@ -33,11 +36,58 @@ struct Args {
     /// - -vv or more: TRACE level
     #[arg(short, long, action = clap::ArgAction::Count)]
     verbose: u8,
+    /// A maintenance command to run instead of the bot (optional).
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+/// Maintenance commands, which run against the configured database instead of starting the bot.
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Export a channel's directive, contexts, and messages to a JSON file.
+    Export {
+        /// The ID of the channel to export (e.g., `C123`).
+        #[arg(long)]
+        channel: String,
+        /// The file to write the export to.
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Import a channel export (see `export`) into the database.
+    Import {
+        /// The ID of the channel to import into (defaults to the exported channel).
+        #[arg(long)]
+        channel: Option<String>,
+        /// The export file to read.
+        #[arg(long)]
+        file: PathBuf,
+    },
+    /// Print a channel's statistics (message, context, and response counts) as JSON.
+    Stats {
+        /// The ID of the channel to report on (e.g., `C123`).
+        #[arg(long)]
+        channel: String,
+        /// How many days of activity to count.
+        #[arg(long, default_value_t = 7)]
+        days: u64,
+    },
+    /// Set a channel's system addendum (operator instructions, e.g., tone or escalation policy, that channel members cannot change).
+    Addendum {
+        /// The ID of the channel to set the addendum of (e.g., `C123`).
+        #[arg(long)]
+        channel: String,
+        /// The file to read the addendum from.
+        #[arg(long, required_unless_present = "clear", conflicts_with = "clear")]
+        file: Option<PathBuf>,
+        /// Remove the channel's addendum instead.
+        #[arg(long)]
+        clear: bool,
+    },
 }
 
 /// Main entry point for the triage-bot binary.
 ///
-/// Sets up logging based on verbosity, loads configuration, and starts the bot.
+/// Sets up logging based on verbosity, loads configuration, and starts the bot (or runs the given command).
 #[tokio::main]
 async fn main() -> Void {
     let args = Args::parse();
@@ -74,5 +124,11 @@ async fn main() -> Void {
 
     let config = Config::load(args.config.as_deref())?;
 
-    triage_bot::start(config).await
+    match args.command {
+        Some(Command::Export { channel, out }) => triage_bot::export_channel(config, &channel, &out).await,
+        Some(Command::Import { channel, file }) => triage_bot::import_channel(config, channel.as_deref(), &file).await,
+        Some(Command::Stats { channel, days }) => triage_bot::channel_stats(config, &channel, days).await,
+        Some(Command::Addendum { channel, file, .. }) => triage_bot::set_channel_system_addendum(config, &channel, file.as_deref()).await,
+        None => triage_bot::start(config).await,
+    }
 }
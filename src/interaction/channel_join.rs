@@ -0,0 +1,83 @@
+//! This module handles the bot joining a channel.
+
+use serde_json::json;
+use tracing::{Instrument, Span, error, info, instrument};
+
+use crate::{
+    base::types::Void,
+    service::{
+        chat::ChatClient,
+        db::{Channel, DbClient, LlmContext, Message},
+    },
+};
+
+/// The onboarding message posted when the bot joins a channel.
+pub const ONBOARDING_MESSAGE: &str = "\
+:wave: Hi!  I'm *TriageBot*.  I'll quietly read this channel, and step in to triage requests (summary, classification, related threads, and a tag for the oncall).\n\n\
+To get started:\n\
+• *Set the channel directive* (including who is oncall): `@triage-bot please update the channel directive: @some-oncall is the oncall, and ...`\n\
+• *Teach me things* as you go: `@triage-bot please remember that FooService owns bar-api`\n\
+• *Check on me* with `/triage status`, or `/triage pause` if you need me to be quiet.";
+
+/// Handles the bot joining a channel.
+///
+/// This function is responsible for initializing the channel: creating its record, storing its topic and purpose as
+/// initial context, and posting an onboarding message.
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_bot_joined_channel<L, C, M>(channel_id: String, db: DbClient<L, C, M>, chat: ChatClient)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = handle_bot_joined_channel_internal(channel_id, &db, &chat).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Internal function to handle the bot joining a channel.
+#[instrument(skip_all)]
+async fn handle_bot_joined_channel_internal<L, C, M>(channel_id: String, db: &DbClient<L, C, M>, chat: &ChatClient) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    db.get_or_create_channel(&channel_id).await?;
+
+    // Store the topic and purpose, since they usually describe what the channel is for.
+    let channel_info = chat.get_channel_info(&channel_id).await?;
+
+    if !channel_info.name.is_empty() {
+        db.set_channel_name(&channel_id, &channel_info.name).await?;
+    }
+
+    if !channel_info.topic.is_empty() || !channel_info.purpose.is_empty() {
+        let notes = format!(
+            "This channel is `#{}`.  Its topic is: \"{}\".  Its purpose is: \"{}\".",
+            channel_info.name, channel_info.topic, channel_info.purpose
+        );
+        let context = L::new(
+            json!({ "type": "channel_info", "name": channel_info.name, "topic": channel_info.topic, "purpose": channel_info.purpose }),
+            notes,
+        );
+
+        db.add_channel_context(&channel_id, &context).await?;
+    }
+
+    chat.send_message(&channel_id, "", ONBOARDING_MESSAGE).await?;
+
+    info!("Initialized channel `{}`.", channel_id);
+
+    Ok(())
+}
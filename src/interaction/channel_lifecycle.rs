@@ -0,0 +1,90 @@
+//! This module handles channels being archived, unarchived, and renamed.
+
+use tracing::{Instrument, Span, error, info, instrument};
+
+use crate::{
+    base::types::Void,
+    service::db::{Channel, DbClient, LlmContext, Message},
+};
+
+/// Handles a channel being archived (or unarchived).
+///
+/// Archived channels are marked inactive, so that the bot (and any background work) skips them, and
+/// unarchived channels are reactivated.  It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_channel_archived<L, C, M>(channel_id: String, archived: bool, db: DbClient<L, C, M>)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = handle_channel_archived_internal(&channel_id, archived, &db).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Handles a channel being renamed.
+///
+/// The new name is stored on the channel record, so that it can be referred to by name.  It spawns a new
+/// task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_channel_renamed<L, C, M>(channel_id: String, name: String, db: DbClient<L, C, M>)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = handle_channel_renamed_internal(&channel_id, &name, &db).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Internal function to handle a channel being archived (or unarchived).
+#[instrument(skip_all)]
+async fn handle_channel_archived_internal<L, C, M>(channel_id: &str, archived: bool, db: &DbClient<L, C, M>) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    db.get_or_create_channel(channel_id).await?;
+    db.set_channel_active(channel_id, !archived).await?;
+
+    info!("Channel `{}` was {}.", channel_id, if archived { "archived" } else { "unarchived" });
+
+    Ok(())
+}
+
+/// Internal function to handle a channel being renamed.
+#[instrument(skip_all)]
+async fn handle_channel_renamed_internal<L, C, M>(channel_id: &str, name: &str, db: &DbClient<L, C, M>) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    db.get_or_create_channel(channel_id).await?;
+    db.set_channel_name(channel_id, name).await?;
+
+    info!("Channel `{}` was renamed to `#{}`.", channel_id, name);
+
+    Ok(())
+}
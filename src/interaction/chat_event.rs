@@ -1,21 +1,87 @@
 //! This module handles the storage of messages in the database.
 
-use std::pin::Pin;
+use std::{
+    collections::{HashMap, HashSet},
+    pin::Pin,
+    sync::{
+        Arc, Mutex, OnceLock,
+        atomic::{AtomicUsize, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
+use regex::Regex;
 use serde::Serialize;
 use serde_json::{Value, json};
 use tracing::{Instrument, Span, error, info, instrument, warn};
 
 use crate::{
-    base::types::{AssistantClassification, AssistantContext, AssistantResponse, MessageSearchContext, Res, Void, WebSearchContext},
+    base::{
+        config::Config,
+        injection::detect_injections,
+        language::detect_language,
+        template::TemplateVariables,
+        types::{
+            AssistantClassification, AssistantContext, AssistantImage, AssistantIntent, AssistantResponse, AssistantTool, ChannelMode, IntentContext, MessageSearchContext, ReasoningTracker, Res,
+            Source, SummaryContext, UsageTracker, Void, WebSearchContext, WebSearchResponse,
+        },
+    },
+    interaction::{file_attachment, moderation, outbox, reply_action},
     service::{
-        chat::ChatClient,
-        db::{Channel, DbClient, LlmContext, Message},
-        llm::LlmClient,
+        chat::{BODY_BLOCK_ID_PREFIX, ChatClient, MessageOptions},
+        db::{Channel, DbClient, DbUnavailable, LlmContext, Message, is_db_unavailable},
+        llm::{
+            BoxedProgressCallback, LlmClient, WEB_SEARCH_DISABLED, is_llm_unavailable,
+            tokens::{Truncation, truncate},
+        },
         mcp::McpClient,
     },
 };
 
+// Statics.
+
+/// Placeholder posted to the thread while the assistant is working on a response.
+pub const THINKING_PLACEHOLDER_TEXT: &str = "👀 Looking into this…";
+
+/// How often a streamed reply is edited into its placeholder (Slack rate limits message edits).
+const STREAMING_UPDATE_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The number of messages found by semantic search (on top of the keyword search results).
+const SEMANTIC_SEARCH_RESULTS: usize = 10;
+
+/// The number of directive versions listed when the assistant is asked to revert the channel directive.
+const DIRECTIVE_HISTORY_LIMIT: usize = 10;
+
+/// The number of the assistant's own previous responses in a thread that are given back to it.
+const PREVIOUS_RESPONSES_LIMIT: usize = 5;
+
+/// The number of web pages cited under a reply.
+const SOURCES_LIMIT: usize = 5;
+
+/// The number of threads that the assistant can fetch (with `fetch_thread_context`) per message.
+const THREAD_FETCH_LIMIT: usize = 2;
+
+/// The number of a fetched thread's most recent messages given to the assistant, by default (and at most).
+const FETCHED_THREAD_MESSAGES_DEFAULT: usize = 50;
+const FETCHED_THREAD_MESSAGES_MAX: usize = 200;
+
+/// The number of tokens of a fetched thread given to the assistant (its oldest messages are dropped beyond that).
+const FETCHED_THREAD_MAX_TOKENS: usize = 8_000;
+
+/// The links in a message, either Slack-formatted (e.g., `<https://example.com|text>`) or markdown (e.g.,
+/// `[text](https://example.com)`), since the chat client converts the reply's formatting when it is posted.
+static LINKS: OnceLock<Regex> = OnceLock::new();
+
+fn link_pattern() -> &'static Regex {
+    LINKS.get_or_init(|| Regex::new(r"<https?://[^>]+>|\[[^\]\n]+\]\(https?://[^)\s]+\)").expect("The regex is valid."))
+}
+
+/// The reaction added to a message that the model refused to respond to.
+const REFUSAL_EMOJI: &str = "neutral_face";
+
+/// The reaction added to a message that is not answered, since the LLM is unavailable (its circuit breaker is open).
+const LLM_UNAVAILABLE_EMOJI: &str = "hourglass_flowing_sand";
+
 /// Handles the chat event.
 ///
 /// This function is responsible for processing chat events and taking appropriate actions based on the responses from the LLM.
@@ -23,7 +89,8 @@ use crate::{
 /// It first retrieves the channel information and context from the database, then generates a response using the LLM,
 /// and finally takes action based on the response.
 #[instrument(skip_all)]
-pub fn handle_chat_event<E, L, C, M>(event: E, channel_id: String, thread_ts: String, db: DbClient<L, C, M>, llm: LlmClient, chat: ChatClient, mcp: McpClient)
+#[allow(clippy::too_many_arguments)]
+pub fn handle_chat_event<E, L, C, M>(event: E, channel_id: String, thread_ts: String, config: Config, db: DbClient<L, C, M>, llm: LlmClient, chat: ChatClient, mcp: McpClient)
 where
     E: Serialize + Clone + Send + Sync + 'static,
     L: LlmContext,
@@ -33,11 +100,14 @@ where
     tokio::spawn(
         async move {
             // Process the event.
-            let result = handle_chat_event_internal(event, channel_id, thread_ts, &db, &llm, &chat, &mcp).in_current_span().await;
+            let result = handle_chat_event_internal(event, channel_id, thread_ts, &config, &db, &llm, &chat, &mcp).in_current_span().await;
 
-            // Log any errors.
-            if let Err(err) = &result {
-                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            // Log any errors (an outage of the database is already reported by the database client).
+            match &result {
+                Err(err) if is_db_unavailable(err) => warn!("Dropped a chat event, since the database is unavailable."),
+                Err(err) if is_llm_unavailable(err) => warn!("Skipped the reply to a chat event, since the LLM is unavailable."),
+                Err(err) => error!("Error while handling: {}\n\n{}", err, err.backtrace()),
+                Ok(()) => {}
             }
         }
         .instrument(Span::current()),
@@ -46,38 +116,156 @@ where
 
 /// Internal function to handle the chat event.
 #[instrument(skip_all)]
-async fn handle_chat_event_internal<E, L, C, M>(event: E, channel_id: String, thread_ts: String, db: &DbClient<L, C, M>, llm: &LlmClient, chat: &ChatClient, mcp: &McpClient) -> Void
+#[allow(clippy::too_many_arguments)]
+async fn handle_chat_event_internal<E, L, C, M>(event: E, channel_id: String, thread_ts: String, config: &Config, db: &DbClient<L, C, M>, llm: &LlmClient, chat: &ChatClient, mcp: &McpClient) -> Void
 where
     E: Serialize + Clone + Send + Sync + 'static,
     L: LlmContext,
     C: Channel,
     M: Message,
 {
-    let user_message = serde_json::to_string(&event).unwrap();
+    let started_at = Instant::now();
+
+    // Without the database, there is no context to answer with (or anywhere to store the answer), so skip the LLM calls.
+    if !db.is_available() {
+        return Err(DbUnavailable.into());
+    }
+
+    let mut event_value = serde_json::to_value(&event)?;
+    file_attachment::append_file_contents(&mut event_value, chat).await;
+
+    let user_message = annotate_user_names(&event_value.to_string(), chat).await;
+    let user_id = event_value.get("user").and_then(Value::as_str).map(str::to_string);
+    let is_direct_message = event_value.get("channel_type").and_then(Value::as_str) == Some("im");
+
+    // The thread root should always be known (a top-level message is the root of its own thread), but fall back to the event's own `ts` if it is not.
+    let thread_ts = if thread_ts.is_empty() {
+        event_value.get("ts").and_then(Value::as_str).unwrap_or_default().to_string()
+    } else {
+        thread_ts
+    };
+    let message_ts = event_value.get("ts").and_then(Value::as_str).unwrap_or(&thread_ts).to_string();
 
     // First, get the channel info from the database.
 
     let channel = db.get_or_create_channel(&channel_id).await?;
-    let channel_directive = serde_json::to_string(&channel.channel_directive())?;
+
+    if channel.is_paused() {
+        info!("Skipping chat event because the bot is paused in channel `{}`.", channel_id);
+        return Ok(());
+    }
+
+    if !channel.is_active() {
+        info!("Skipping chat event because channel `{}` is archived.", channel_id);
+        return Ok(());
+    }
+
+    // While the LLM is unavailable (e.g., during an outage of the provider), the message is still stored (see
+    // `message_storage`), but it is only marked as not answered.
+    if !llm.is_available() {
+        warn!("Skipping chat event because the LLM is unavailable.");
+        react_llm_unavailable(&channel_id, &message_ts, chat).await;
+        return Ok(());
+    }
+
+    // The channel's mode decides how much is done with the message, but @-mentions (and DMs) are always answered in
+    // full, so that the bot can still be asked about (or to change) its mode.
+    let text = event_value.get("text").and_then(Value::as_str).unwrap_or_default();
+    let is_mention = mentioned_user_ids(text).iter().any(|id| id == chat.bot_user_id());
+    let mode = if is_direct_message || is_mention { ChannelMode::Full } else { channel.mode() };
+
+    let directive = db.get_channel_directive(&channel_id).await?;
+    let channel_directive = serde_json::to_string(&directive)?;
 
     // Next, get the other context from the database.
 
-    let channel_context = db.get_channel_context(&channel_id).await?;
+    let channel_context = db.get_channel_context_paged(&channel_id, config.channel_context_limit, config.channel_context_max_chars).await?;
+
+    // Get what is known about the author, and the users mentioned in the message.
+    let user_ids = user_id.iter().cloned().chain(mentioned_user_ids(text)).filter(|id| id != chat.bot_user_id());
+    let user_context = get_user_contexts(&channel_id, user_ids, db).await?;
+    let user_context = annotate_user_names(&user_context, chat).await;
 
-    // Get the thread context from the event.
-    // TODO: Now that we store the messages in the database, we can also get the thread context from the database (probably better).
-    let thread_context = chat.get_thread_context(&channel_id, &thread_ts).await?;
+    // Get the thread context (from the stored messages, when possible).
+    let thread_context = get_thread_context(&channel_id, &thread_ts, db, chat).await?;
+    let thread_context = annotate_user_names(&thread_context, chat).await;
 
-    // Compile all relevant context for the assistant agent.
+    // Get the assistant's own previous responses in the thread, so that it does not re-answer a thread it already handled.
+    let previous_responses = serde_json::to_string(&db.get_assistant_responses(&channel_id, &thread_ts, PREVIOUS_RESPONSES_LIMIT).await?)?;
+
+    // Get the channel's recent activity (if enabled), so that the assistant knows what is going on beyond this thread.
+    let recent_messages = if config.channel_recent_messages_limit > 0 {
+        let messages = db.get_recent_channel_messages(&channel_id, config.channel_recent_messages_limit).await?;
+        let raws = messages.iter().map(Message::raw).cloned().collect::<Vec<_>>();
+
+        annotate_user_names(&format_recent_messages(&raws, &thread_ts, config.channel_recent_message_max_chars), chat).await
+    } else {
+        "[]".to_string()
+    };
+
+    // Download any attached images (e.g., screenshots of error dialogs), so that they can be shown to the assistant.
+    let images = if mode == ChannelMode::Full {
+        file_attachment::download_images(&event_value, config.slack_image_max_count, config.slack_image_max_bytes, chat).await
+    } else {
+        Vec::new()
+    };
+
+    // Let the user know that we are on it (the placeholder is later edited into the answer, or deleted).
+    // This happens after reading the thread context, so that the placeholder is not part of it.  Channels that are only
+    // classified get no reply, so they get no placeholder either.
+
+    let placeholder_ts = if mode != ChannelMode::Full {
+        None
+    } else {
+        match chat.send_placeholder(&channel_id, &thread_ts, THINKING_PLACEHOLDER_TEXT).await {
+            Ok(ts) => ts,
+            Err(err) => {
+                warn!("Failed to post placeholder: {}", err);
+                None
+            }
+        }
+    };
+    let placeholder = Arc::new(Mutex::new(placeholder_ts.map(|ts| (thread_ts.clone(), ts))));
+
+    // Get the values of the template variables in the directives (e.g., `{date}`).
+
+    let variables = TemplateVariables {
+        channel_name: get_channel_name(&channel, &channel_id, db, chat).await,
+        date: chrono::Utc::now().format("%Y-%m-%d").to_string(),
+        bot_user_id: chat.bot_user_id().to_string(),
+        oncall: reply_action::directive_oncalls(directive.as_ref()).join(" "),
+        language: String::new(),
+    };
+
+    // Compile all relevant context for the assistant agent (adding up the token usage of every agent along the way).
+
+    let usage = UsageTracker::default();
+
+    // Very long threads are summarized, so that the agents are not given (and billed for) every message of them.
+    let thread_context = condense_thread_context(thread_context, config.thread_summary_threshold_chars, config.thread_summary_recent_messages, llm, &usage).await;
 
     let assistant_context = compile_contexts(
         user_message.clone(),
         chat.bot_user_id().to_string(),
         channel_id.clone(),
         thread_ts.clone(),
+        is_direct_message,
+        channel.system_addendum().unwrap_or_default().to_string(),
         channel_directive.clone(),
         channel_context.clone(),
+        user_context,
         thread_context.clone(),
+        previous_responses,
+        recent_messages,
+        images,
+        channel.linked_channels().to_vec(),
+        channel.force_language().map(str::to_string),
+        mode,
+        // A channel can only turn web search off (not on, when the deployment has it off).
+        config.web_search_enabled && channel.web_search_enabled().unwrap_or(true),
+        variables,
+        usage.clone(),
+        config,
         db,
         llm,
         chat,
@@ -85,32 +273,173 @@ where
     )
     .await?;
 
+    // Anything that looks like a prompt injection (e.g., in a stored channel context) is flagged, and the flags are stored
+    // with the responses, so that they can be reviewed.
+    let injection_flags = Arc::new(detect_injections(&[
+        ("user message", assistant_context.user_message.as_str()),
+        ("channel directive", assistant_context.channel_directive.as_str()),
+        ("channel context", assistant_context.channel_context.as_str()),
+        ("user context", assistant_context.user_context.as_str()),
+        ("thread context", assistant_context.thread_context.as_str()),
+        ("recent channel activity", assistant_context.recent_messages.as_str()),
+        ("web search results", assistant_context.web_search_context.as_str()),
+        ("message search results", assistant_context.message_search_context.as_str()),
+    ]));
+    if !injection_flags.is_empty() {
+        warn!("Possible prompt injection ({}).", injection_flags.join(", "));
+    }
+
+    // Replies are checked before they are posted, if the channel (or, by default, the config) asks for it.
+    let moderation_denylist = if channel.moderation_enabled().unwrap_or(config.moderation_enabled) {
+        Some(Arc::new(config.moderation_denylist_regexes()?))
+    } else {
+        None
+    };
+
+    // Answers below the channel's (or, by default, the configured) confidence threshold are withheld.
+    let confidence_threshold = channel.confidence_threshold().unwrap_or(config.reply_confidence_threshold);
+    let oncalls = reply_action::directive_oncalls(Some(channel.channel_directive()));
+
+    // What is posted when the model refuses to respond (the channel's, or, by default, the configured reply).
+    let refusal_reply = channel.refusal_reply().map(str::to_string).unwrap_or_else(|| config.refusal_reply.clone());
+
     // Define the callback function to handle the assistant's response.
 
-    let db = db.clone();
-    let chat = chat.clone();
-    let mcp = mcp.clone();
+    let callback_config = config.clone();
+    let callback_channel_id = channel_id.clone();
+    let callback_thread_ts = thread_ts.clone();
+    let callback_placeholder = placeholder.clone();
+    let callback_db = db.clone();
+    let callback_chat = chat.clone();
+    let callback_mcp = mcp.clone();
+    let callback_usage = usage.clone();
+    let callback_llm = llm.clone();
+    let callback_moderation_denylist = moderation_denylist.clone();
+    let callback_oncalls = oncalls.clone();
+    let callback_refusal_reply = refusal_reply.clone();
+    let callback_message_ts = message_ts.clone();
+    let callback_reasoning = assistant_context.reasoning.clone();
+    let callback_injection_flags = injection_flags.clone();
+    let thread_fetches = Arc::new(AtomicUsize::new(0));
     let response_callback = Box::new(move |responses: Vec<AssistantResponse>| {
         let event = event.clone();
-        let channel_id = channel_id.clone();
-        let db = db.clone();
-        let chat = chat.clone();
-        let mcp = mcp.clone();
+        let config = callback_config.clone();
+        let channel_id = callback_channel_id.clone();
+        let event_thread_ts = callback_thread_ts.clone();
+        let user_id = user_id.clone();
+        let placeholder = callback_placeholder.clone();
+        let db = callback_db.clone();
+        let chat = callback_chat.clone();
+        let mcp = callback_mcp.clone();
+        let usage = callback_usage.clone();
+        let llm = callback_llm.clone();
+        let moderation_denylist = callback_moderation_denylist.clone();
+        let oncalls = callback_oncalls.clone();
+        let refusal_reply = callback_refusal_reply.clone();
+        let message_ts = callback_message_ts.clone();
+        let reasoning = callback_reasoning.clone();
+        let injection_flags = callback_injection_flags.clone();
+        let thread_fetches = thread_fetches.clone();
 
         Box::pin(
             async move {
                 let mut messages = Vec::new();
 
                 for response in responses {
+                    // Keep the response (and, for tool calls, their output) for auditing.
+                    let mut record = json!({
+                        "response": &response,
+                        "model": config.openai_assistant_agent_model,
+                        "latency_ms": started_at.elapsed().as_millis() as u64,
+                        "created_at": chrono::Utc::now().timestamp(),
+                    });
+                    let output_count = messages.len();
+
+                    // The usage (of every call since the last stored response) is stored with the response, so that it adds up to the total.
+                    let response_usage = usage.take_unreported();
+                    if !response_usage.is_empty() {
+                        record["usage"] = serde_json::to_value(&response_usage)?;
+                    }
+
+                    // Likewise, the reasoning summaries (if they are stored at all) are stored with the response that they led to.
+                    let summaries = reasoning.take();
+                    if config.store_reasoning_summaries && !summaries.is_empty() {
+                        record["reasoning_summary"] = json!(summaries.join("\n\n"));
+                    }
+
+                    if !injection_flags.is_empty() {
+                        record["injection_flags"] = json!(injection_flags.as_slice());
+                    }
+
+                    // The withheld answer (and its confidence) is still stored, so that the threshold can be tuned.
+                    let response = match withhold_low_confidence(&response, confidence_threshold, &config.low_confidence_reply, &oncalls) {
+                        Some(replacement) => {
+                            info!("Withholding an answer below the confidence threshold ({}%).", confidence_threshold);
+                            record["withheld"] = json!(true);
+                            replacement
+                        }
+                        None => response,
+                    };
+
+                    // Channels that are only classified get the classification, but not the answer.
+                    let response = reduce_to_classification(response, mode, &oncalls);
+
                     match response {
-                        AssistantResponse::NoAction => warn!("No action taken."),
-                        AssistantResponse::UpdateChannelDirective { call_id, message } => {
+                        AssistantResponse::NoAction => {
+                            warn!("No action taken.");
+
+                            // Nothing to say, so the placeholder goes away.
+                            let placeholder_ts = placeholder.lock().unwrap().take();
+                            if let Some((_, ts)) = placeholder_ts {
+                                chat.delete_message(&channel_id, &ts).await?;
+                            }
+                        }
+                        AssistantResponse::Refused { reason } => {
+                            // The reason is stored with the response, for the operators.
+                            warn!("The model refused to respond: {}", reason);
+                            record["refused"] = json!(true);
+
+                            let placeholder_ts = placeholder.lock().unwrap().take();
+                            if let Some((_, ts)) = placeholder_ts {
+                                chat.delete_message(&channel_id, &ts).await?;
+                            }
+
+                            // The reaction goes on the message itself, and the reply (if any) in its thread.
+                            if let Err(err) = chat.react_to_message(&channel_id, &message_ts, REFUSAL_EMOJI).await {
+                                error!("Failed to react with `{}`: {}", REFUSAL_EMOJI, err);
+                            }
+
+                            if !refusal_reply.is_empty() {
+                                chat.send_message(&channel_id, &event_thread_ts, &refusal_reply).await?;
+                            }
+                        }
+                        AssistantResponse::UpdateChannelDirective {
+                            call_id,
+                            message,
+                            broadcast_incidents,
+                            linked_channels,
+                            mode,
+                        } => {
                             info!("Updating channel directive ...");
 
                             let directive = L::new(serde_json::to_value(&event)?, message);
 
                             db.update_channel_directive(&channel_id, &directive).await?;
 
+                            if let Some(broadcast_incidents) = broadcast_incidents {
+                                db.set_channel_broadcast_incidents(&channel_id, broadcast_incidents).await?;
+                            }
+
+                            if let Some(linked_channels) = linked_channels {
+                                db.set_channel_linked_channels(&channel_id, &parse_linked_channels(&channel_id, &linked_channels)).await?;
+                            }
+
+                            if let Some(mode) = mode {
+                                db.set_channel_mode(&channel_id, mode).await?;
+                            }
+
+                            publish_channel_directive(&channel_id, db, chat).await?;
+
                             // Send the result back to the LLM.
                             messages.push(json!({
                                 "type": "function_call_output",
@@ -118,18 +447,154 @@ where
                                 "output": "Channel directive updated successfully.",
                             }));
                         }
-                        AssistantResponse::UpdateContext { call_id, message } => {
+                        AssistantResponse::UpdateContext { call_id, message, expires_in_days } => {
                             info!("Updating context ...");
 
-                            let context = L::new(serde_json::to_value(&event)?, message);
+                            let expires_at = context_expires_at(expires_in_days, chrono::Utc::now().timestamp());
+                            let context = L::new(serde_json::to_value(&event)?, message).with_expires_at(expires_at);
 
                             db.add_channel_context(&channel_id, &context).await?;
 
+                            let output = match expires_in_days {
+                                Some(days) => format!("Context updated successfully (it expires in {days} days)."),
+                                None => "Context updated successfully.".to_string(),
+                            };
+
+                            // Send the result back to the LLM.
+                            messages.push(json!({
+                                "type": "function_call_output",
+                                "call_id": call_id,
+                                "output": output,
+                            }));
+                        }
+                        AssistantResponse::UpdateUserContext { call_id, user_id, message } => {
+                            // The assistant may pass a mention (e.g., `<@U123>`) rather than the bare ID.
+                            let user_id = mentioned_user_ids(&user_id).pop().unwrap_or(user_id);
+
+                            info!("Updating context about user `{}` ...", user_id);
+
+                            let context = L::new(serde_json::to_value(&event)?, message);
+
+                            db.add_user_context(&channel_id, &user_id, &context).await?;
+
+                            // Send the result back to the LLM.
+                            messages.push(json!({
+                                "type": "function_call_output",
+                                "call_id": call_id,
+                                "output": "User context updated successfully.",
+                            }));
+                        }
+                        AssistantResponse::ForgetContext { call_id, context_ids } => {
+                            let output = if context_ids.is_empty() {
+                                info!("Listing contexts to forget ...");
+
+                                let contexts = db.list_channel_contexts(&channel_id).await?;
+                                let contexts = contexts.into_iter().map(|(id, summary)| json!({ "id": id, "summary": summary })).collect::<Vec<_>>();
+
+                                serde_json::to_string(&contexts)?
+                            } else {
+                                info!("Forgetting {} contexts ...", context_ids.len());
+
+                                let mut forgotten = 0;
+                                for context_id in &context_ids {
+                                    if db.remove_channel_context(&channel_id, context_id).await? {
+                                        forgotten += 1;
+                                    } else {
+                                        warn!("Context `{}` was not found in channel `{}`.", context_id, channel_id);
+                                    }
+                                }
+
+                                format!("Forgot {forgotten} of {} contexts (the rest were not found).", context_ids.len())
+                            };
+
+                            // Send the result back to the LLM.
+                            messages.push(json!({
+                                "type": "function_call_output",
+                                "call_id": call_id,
+                                "output": output,
+                            }));
+                        }
+                        AssistantResponse::RevertChannelDirective { call_id, version_id } => {
+                            let output = match version_id {
+                                None => {
+                                    info!("Listing channel directive history ...");
+
+                                    let history = db.get_directive_history(&channel_id, DIRECTIVE_HISTORY_LIMIT).await?;
+
+                                    serde_json::to_string(&history)?
+                                }
+                                Some(version_id) => {
+                                    info!("Reverting channel directive to `{}` ...", version_id);
+
+                                    if db.restore_channel_directive(&channel_id, &version_id).await?.is_some() {
+                                        publish_channel_directive(&channel_id, db, chat).await?;
+
+                                        "Channel directive restored successfully.".to_string()
+                                    } else {
+                                        warn!("Directive version `{}` was not found in channel `{}`.", version_id, channel_id);
+
+                                        format!("Directive version `{version_id}` was not found in this channel.")
+                                    }
+                                }
+                            };
+
+                            // Send the result back to the LLM.
+                            messages.push(json!({
+                                "type": "function_call_output",
+                                "call_id": call_id,
+                                "output": output,
+                            }));
+                        }
+                        AssistantResponse::PinMessage { call_id, ts } => {
+                            info!("Pinning message ...");
+
+                            // A failed pin (e.g., a missing scope) is reported back to the LLM, rather than failing the reply.
+                            let output = match chat.pin_message(&channel_id, &ts).await {
+                                Ok(()) => "Message pinned successfully.".to_string(),
+                                Err(err) => {
+                                    warn!("Failed to pin message `{}`: {}", ts, err);
+                                    format!("Failed to pin the message: {err}")
+                                }
+                            };
+
+                            // Send the result back to the LLM.
+                            messages.push(json!({
+                                "type": "function_call_output",
+                                "call_id": call_id,
+                                "output": output,
+                            }));
+                        }
+                        AssistantResponse::FetchThreadContext {
+                            call_id,
+                            channel_id: requested_channel_id,
+                            thread_ts: requested_thread_ts,
+                            limit,
+                        } => {
+                            info!("Fetching thread context ...");
+
+                            // Only threads in this channel (so that no other channel leaks into the reply), and only a couple per message.
+                            let output = if !is_current_channel(&requested_channel_id, &channel_id) {
+                                warn!("Refused to fetch a thread in channel `{}` from channel `{}`.", requested_channel_id, channel_id);
+                                format!("Only threads in this channel (`{channel_id}`) can be fetched.")
+                            } else if thread_fetches.fetch_add(1, Ordering::SeqCst) >= THREAD_FETCH_LIMIT {
+                                warn!("Refused to fetch more than {} threads for one message.", THREAD_FETCH_LIMIT);
+                                format!("At most {THREAD_FETCH_LIMIT} threads can be fetched per message, so answer with what you have.")
+                            } else {
+                                // A failed fetch (e.g., a made up thread) is reported back to the LLM, rather than failing the reply.
+                                match get_thread_context(&channel_id, &parse_thread_ts(&requested_thread_ts), db, chat).await {
+                                    Ok(thread_context) => cap_fetched_thread_context(&annotate_user_names(&thread_context, chat).await, limit),
+                                    Err(err) => {
+                                        warn!("Failed to fetch thread `{}`: {}", requested_thread_ts, err);
+                                        format!("Failed to fetch the thread: {err}")
+                                    }
+                                }
+                            };
+
                             // Send the result back to the LLM.
                             messages.push(json!({
                                 "type": "function_call_output",
                                 "call_id": call_id,
-                                "output": "Context updated successfully.",
+                                "output": output,
                             }));
                         }
                         AssistantResponse::McpTool { call_id, name, arguments } => {
@@ -145,22 +610,111 @@ where
                                 "output": mcp_result,
                             }));
                         }
-                        AssistantResponse::ReplyToThread { thread_ts, classification, message } => {
+                        AssistantResponse::ReplyToThread {
+                            thread_ts: requested_thread_ts,
+                            classification,
+                            message,
+                            sources,
+                            ..
+                        } => {
                             info!("Replying to thread ...");
 
-                            // Set the emoji.
-                            let emoji = match classification {
-                                AssistantClassification::Question => "question",
-                                AssistantClassification::Feature => "bulb",
-                                AssistantClassification::Bug => "bug",
-                                AssistantClassification::Incident => "warning",
-                                AssistantClassification::Other => "grey_question",
+                            let thread_ts = validate_thread_ts(&requested_thread_ts, &event_thread_ts, &message_ts);
+
+                            let message = match &moderation_denylist {
+                                Some(denylist) if !message.is_empty() => moderation::moderate_reply(message, denylist, &llm).await,
+                                _ => message,
                             };
 
-                            let _ = chat.react_to_message(&channel_id, &thread_ts, emoji).await;
-                            chat.send_message(&channel_id, &thread_ts, &message).await?;
+                            // Make any user group handles (e.g., the oncall from the channel directive) live mentions (the chat client converts the formatting when it posts the reply).
+                            let message = chat.resolve_user_group_mentions(&message).await;
+
+                            // Set the emoji (the chat client tolerates duplicate reactions, so any error here is real, e.g., a missing
+                            // custom emoji or scope, but it should never cost the reply).
+                            let emoji = config.classification_emoji(&classification);
+
+                            if let Err(err) = chat.react_to_message(&channel_id, &thread_ts, &emoji).await {
+                                error!("Failed to react with `{}`: {}", emoji, err);
+                            }
+
+                            // Channels that are only classified (see `ChannelMode::Silent`) only get the reaction.
+                            if message.is_empty() {
+                                info!("Only reacting, since the channel is only classified.");
+                            } else {
+                                // Render the reply into blocks (the chat client falls back to plain text if needed).
+                                let mut blocks = render_reply_blocks(&classification, &emoji, &message);
+
+                                // Cite the web pages that the reply is based on (kept out of the links section, which lists the message's own links).
+                                // The sources get their own block, and stay out of the text (which the chat client splits into the body sections).
+                                if let Some(line) = render_sources_line(&sources, &message) {
+                                    blocks.push(json!({
+                                        "type": "context",
+                                        "elements": [{ "type": "mrkdwn", "text": line }],
+                                    }));
+                                }
+
+                                blocks.push(reply_action::render_reply_action_block(&thread_ts));
+
+                                // Incidents are also surfaced to the channel, if the channel allows it.
+                                let options = MessageOptions {
+                                    reply_broadcast: matches!(classification, AssistantClassification::Incident) && db.get_or_create_channel(&channel_id).await?.broadcasts_incidents(),
+                                };
+
+                                // Edit the placeholder into the answer, if one was posted to this thread.
+                                let placeholder_ts = placeholder.lock().unwrap().take_if(|(placeholder_thread_ts, _)| *placeholder_thread_ts == thread_ts).map(|(_, ts)| ts);
+                                // A reply that fails to send is queued in the outbox for a retry (and there is nothing to store yet).
+                                let ts = match placeholder_ts {
+                                    Some(ts) => {
+                                        chat.update_blocks(&channel_id, &ts, &message, &blocks, &options).await?;
+                                        Some(ts)
+                                    }
+                                    None => outbox::send_or_queue(&channel_id, &thread_ts, &message, &blocks, &options, &chat, &db).await?,
+                                };
+
+                                // Store the bot's own reply, so that it is part of the channel history.
+                                if let Some(ts) = ts {
+                                    let reply = json!({
+                                        "type": "message",
+                                        "user": chat.bot_user_id(),
+                                        "channel": channel_id,
+                                        "text": message,
+                                        "ts": ts,
+                                        "thread_ts": thread_ts,
+                                        "classification": classification,
+                                    });
+
+                                    db.add_channel_message(&channel_id, &reply).await?;
+                                }
+                            }
+                        }
+                        AssistantResponse::EphemeralReplyToThread { thread_ts: requested_thread_ts, message } => {
+                            info!("Replying ephemerally to thread ...");
+
+                            let thread_ts = validate_thread_ts(&requested_thread_ts, &event_thread_ts, &message_ts);
+
+                            let message = match &moderation_denylist {
+                                Some(denylist) if !message.is_empty() => moderation::moderate_reply(message, denylist, &llm).await,
+                                _ => message,
+                            };
+
+                            // Without a user to address, fall back to a normal threaded reply.
+                            match &user_id {
+                                Some(user_id) => chat.send_ephemeral_message(&channel_id, user_id, &thread_ts, &message).await?,
+                                None => {
+                                    chat.send_message(&channel_id, &thread_ts, &message).await?;
+                                }
+                            }
                         }
                     }
+
+                    if let Some(output) = messages.get(output_count).and_then(|m| m.get("output")) {
+                        record["output"] = output.clone();
+                    }
+
+                    // A response that cannot be stored should never cost the reply.
+                    if let Err(err) = db.add_assistant_response(&channel_id, &event_thread_ts, &record).await {
+                        warn!("Failed to store the assistant's response: {}", err);
+                    }
                 }
 
                 Ok(messages)
@@ -169,28 +723,190 @@ where
         ) as Pin<Box<dyn Future<Output = Res<Vec<Value>>> + Send>>
     });
 
-    // Call the assistant agent with all of the context.
-    llm.get_assistant_agent_response(assistant_context, response_callback).await?;
+    // Call the assistant agent with all of the context (streaming replies into the placeholder, if enabled).
+    // Moderated replies (and replies that may be withheld) are never streamed, since they would be shown before they are checked.
+    // Nor are the classifications of channels that are only classified, since they are never shown.
+    let result = if config.slack_streaming_enabled && moderation_denylist.is_none() && confidence_threshold == 0 && mode == ChannelMode::Full {
+        let progress_callback = streaming_progress_callback(channel_id.clone(), thread_ts.clone(), placeholder.clone(), config.slack_message_max_chars, chat.clone());
+
+        llm.get_assistant_agent_response_streaming(assistant_context, response_callback, progress_callback).await
+    } else {
+        llm.get_assistant_agent_response(assistant_context, response_callback).await
+    };
+
+    let total_usage = usage.total();
+    info!(
+        "Token usage: {} requests, {} input tokens, {} output tokens ({} reasoning), ~${:.4}.",
+        total_usage.requests, total_usage.input_tokens, total_usage.output_tokens, total_usage.reasoning_tokens, total_usage.cost_usd
+    );
+
+    // Clean up a placeholder that was never used (e.g., the assistant only replied ephemerally, or failed).
+    let placeholder_ts = placeholder.lock().unwrap().take();
+    if let Some((_, ts)) = placeholder_ts
+        && let Err(err) = chat.delete_message(&channel_id, &ts).await
+    {
+        warn!("Failed to delete placeholder: {}", err);
+    }
+
+    // Likewise, if the LLM became unavailable while the message was being handled.
+    if let Err(err) = &result
+        && is_llm_unavailable(err)
+    {
+        react_llm_unavailable(&channel_id, &message_ts, chat).await;
+    }
+
+    result?;
 
     Ok(())
 }
 
+/// Mark a message as not answered, since the LLM is unavailable.
+async fn react_llm_unavailable(channel_id: &str, message_ts: &str, chat: &ChatClient) {
+    if let Err(err) = chat.react_to_message(channel_id, message_ts, LLM_UNAVAILABLE_EMOJI).await {
+        error!("Failed to react with `{}`: {}", LLM_UNAVAILABLE_EMOJI, err);
+    }
+}
+
+/// The response that replaces an answer below the confidence threshold, or `None` if it is not withheld.
+///
+/// Withheld answers are replaced by a reply that only tags the oncall (keeping the classification), or, if the
+/// channel prefers silence, by no action.  Answers without a confidence are never withheld.
+fn withhold_low_confidence(response: &AssistantResponse, threshold: u8, low_confidence_reply: &str, oncalls: &[String]) -> Option<AssistantResponse> {
+    let AssistantResponse::ReplyToThread {
+        thread_ts,
+        classification,
+        confidence: Some(confidence),
+        ..
+    } = response
+    else {
+        return None;
+    };
+
+    if *confidence >= threshold {
+        return None;
+    }
+
+    if low_confidence_reply == "silent" {
+        return Some(AssistantResponse::NoAction);
+    }
+
+    let message = if oncalls.is_empty() {
+        "I'm not confident enough to answer this, so I'll leave it for a human.".to_string()
+    } else {
+        format!("{}: I'm not confident enough to answer this, could you take a look?", oncalls.join(" "))
+    };
+
+    Some(AssistantResponse::ReplyToThread {
+        thread_ts: thread_ts.clone(),
+        classification: *classification,
+        message,
+        confidence: Some(*confidence),
+        sources: Vec::new(),
+    })
+}
+
+/// The response of a channel that is only classified (see `ChannelMode`): a reply keeps its classification (for the
+/// reaction), but its message is replaced by a tag of the oncall (`ClassifyOnly`), or left out (`Silent`).
+fn reduce_to_classification(response: AssistantResponse, mode: ChannelMode, oncalls: &[String]) -> AssistantResponse {
+    let AssistantResponse::ReplyToThread {
+        thread_ts, classification, confidence, ..
+    } = response
+    else {
+        return response;
+    };
+
+    let message = match mode {
+        ChannelMode::Full => return response,
+        ChannelMode::ClassifyOnly if !oncalls.is_empty() => format!("{}: could you take a look?", oncalls.join(" ")),
+        ChannelMode::ClassifyOnly | ChannelMode::Silent => String::new(),
+    };
+
+    AssistantResponse::ReplyToThread {
+        thread_ts,
+        classification,
+        message,
+        confidence,
+        sources: Vec::new(),
+    }
+}
+
+/// Create the callback that shows a streamed reply, as it is generated, in the thread's placeholder.
+///
+/// The placeholder is edited at most once per `STREAMING_UPDATE_INTERVAL`, and, if there is none (e.g., placeholders
+/// are disabled), the reply's first progress posts one.  The complete reply later replaces it, as usual.
+fn streaming_progress_callback(channel_id: String, thread_ts: String, placeholder: Arc<Mutex<Option<(String, String)>>>, max_chars: usize, chat: ChatClient) -> BoxedProgressCallback {
+    let last_update = Arc::new(Mutex::new(None::<Instant>));
+
+    Box::new(move |message: String| {
+        let channel_id = channel_id.clone();
+        let thread_ts = thread_ts.clone();
+        let placeholder = placeholder.clone();
+        let last_update = last_update.clone();
+        let chat = chat.clone();
+
+        Box::pin(
+            async move {
+                {
+                    let mut last_update = last_update.lock().unwrap();
+                    if last_update.is_some_and(|at| at.elapsed() < STREAMING_UPDATE_INTERVAL) {
+                        return Ok(());
+                    }
+
+                    *last_update = Some(Instant::now());
+                }
+
+                let text = render_streaming_preview(&message, max_chars);
+                let placeholder_ts = placeholder
+                    .lock()
+                    .unwrap()
+                    .clone()
+                    .filter(|(placeholder_thread_ts, _)| *placeholder_thread_ts == thread_ts)
+                    .map(|(_, ts)| ts);
+
+                match placeholder_ts {
+                    Some(ts) => chat.update_message(&channel_id, &ts, &text).await?,
+                    None => {
+                        let ts = chat.send_message(&channel_id, &thread_ts, &text).await?;
+                        *placeholder.lock().unwrap() = Some((thread_ts, ts));
+                    }
+                }
+
+                Ok(())
+            }
+            .instrument(Span::current()),
+        ) as Pin<Box<dyn Future<Output = Void> + Send>>
+    })
+}
+
 /// Kick off all of the "helper agents" to do their thing in parallel.
 ///
 /// Builds a single context for the assistant agent to use.
-#[instrument(skip_all)]
+#[instrument(skip_all, fields(intent = tracing::field::Empty))]
 #[allow(clippy::too_many_arguments)]
 async fn compile_contexts<L, C, M>(
     user_message: String,
     bot_user_id: String,
     channel_id: String,
     thread_ts: String,
+    is_direct_message: bool,
+    system_addendum: String,
     channel_directive: String,
     channel_context: String,
+    user_context: String,
     thread_context: String,
+    previous_responses: String,
+    recent_messages: String,
+    images: Vec<AssistantImage>,
+    linked_channels: Vec<String>,
+    force_language: Option<String>,
+    mode: ChannelMode,
+    web_search_enabled: bool,
+    mut variables: TemplateVariables,
+    usage: UsageTracker,
+    config: &Config,
     db: &DbClient<L, C, M>,
     llm: &LlmClient,
-    _chat: &ChatClient,
+    chat: &ChatClient,
     mcp: &McpClient,
 ) -> Res<AssistantContext>
 where
@@ -198,41 +914,145 @@ where
     C: Channel,
     M: Message,
 {
-    // Execute the search agent to gather relevant information.
+    // Decide which language to reply in (the channel's, if it has one, or else the message's).
+
+    let language = reply_language(&user_message, force_language);
+    variables.language = language.clone();
+
+    // Channels that are only classified need none of the helper agents (or tools).
+
+    if mode != ChannelMode::Full {
+        return Ok(AssistantContext {
+            user_message,
+            bot_user_id,
+            web_search_context: String::new(),
+            web_search_sources: Vec::new(),
+            web_search_enabled: false,
+            message_search_context: String::new(),
+            channel_id,
+            thread_ts,
+            is_direct_message,
+            system_addendum,
+            channel_directive,
+            channel_context,
+            user_context,
+            thread_context,
+            previous_responses,
+            recent_messages,
+            images,
+            language,
+            mode,
+            tools: Vec::new(),
+            intent: AssistantIntent::Ordinary,
+            variables,
+            usage,
+            reasoning: ReasoningTracker::default(),
+        });
+    }
+
+    // Prepare the list of tools.
+
+    let tools = mcp.get_assistant_tools();
+
+    // Execute the intent agent to decide which tools the assistant is offered.
 
     let llm_clone = llm.clone();
-    let web_search_context = WebSearchContext {
+    let intent_context = IntentContext {
         user_message: user_message.clone(),
         bot_user_id: bot_user_id.clone(),
         channel_id: channel_id.clone(),
-        channel_context: channel_context.clone(),
         thread_context: thread_context.clone(),
+        tools: describe_tools(&tools),
+        variables: variables.clone(),
+        usage: usage.clone(),
     };
 
-    let web_search_task = tokio::spawn(async move { llm_clone.get_web_search_agent_response(web_search_context).await });
+    let intent_task = tokio::spawn(async move { get_intent(intent_context, &llm_clone).await });
+
+    // Execute the search agent to gather relevant information (unless web search is disabled, for the deployment or the
+    // channel, in which case nothing about the message is sent to it).
+
+    let web_search_task = web_search_enabled.then(|| {
+        let llm_clone = llm.clone();
+        let web_search_context = WebSearchContext {
+            user_message: user_message.clone(),
+            bot_user_id: bot_user_id.clone(),
+            channel_id: channel_id.clone(),
+            channel_context: channel_context.clone(),
+            thread_context: thread_context.clone(),
+            variables: variables.clone(),
+            usage: usage.clone(),
+        };
+
+        tokio::spawn(async move { llm_clone.get_web_search_agent_response(web_search_context).await })
+    });
+
+    let web_search_task = async move {
+        let Some(web_search_task) = web_search_task else {
+            return Ok(WebSearchResponse {
+                text: WEB_SEARCH_DISABLED.to_string(),
+                sources: Vec::new(),
+            });
+        };
+
+        Result::<_, anyhow::Error>::Ok(web_search_task.await??)
+    };
 
     // Execute the message search agent to identify relevant messages from the channel history.
 
     let llm_clone = llm.clone();
     let db_clone = db.clone();
     let channel_id_clone = channel_id.clone();
+    let user_message_clone = user_message.clone();
+    let semantic_search_enabled = config.semantic_search_enabled;
     let message_search_context = MessageSearchContext {
         user_message: user_message.clone(),
         bot_user_id: bot_user_id.clone(),
         channel_id: channel_id.clone(),
         channel_context: channel_context.clone(),
         thread_context: thread_context.clone(),
+        variables: variables.clone(),
+        usage: usage.clone(),
     };
 
     let message_search_task = tokio::spawn(async move {
-        // Get search terms from the message search agent
-        let search_terms = llm_clone.get_message_search_agent_response(message_search_context).await?;
+        let keyword_search = async {
+            // Get search terms (and an optional time hint) from the message search agent
+            let response = llm_clone.get_message_search_agent_response(message_search_context).await?;
+            let (search_terms, after_ts) = parse_search_time_hint(&response, chrono::Utc::now().timestamp());
 
-        // Search for relevant messages using the search terms
-        let messages = if !search_terms.is_empty() {
-            db_clone.search_channel_messages(&channel_id_clone, &search_terms).await?
-        } else {
-            "No relevant messages found.".to_string()
+            // Search for relevant messages using the search terms (in the linked channels too, if there are any).
+            if search_terms.is_empty() {
+                Ok("No relevant messages found.".to_string())
+            } else if linked_channels.is_empty() {
+                db_clone.search_channel_messages_filtered(&channel_id_clone, &search_terms, after_ts.as_deref(), None).await
+            } else {
+                let channel_ids = std::iter::once(channel_id_clone.clone()).chain(linked_channels).collect::<Vec<_>>();
+                db_clone.search_messages_across_channels(&channel_ids, &search_terms, after_ts.as_deref(), None).await
+            }
+        };
+
+        // Also search by meaning (if enabled), which finds paraphrases that share no keywords.
+        let semantic_search = async {
+            if !semantic_search_enabled {
+                return Ok(None);
+            }
+
+            let embedding = llm_clone.get_embedding(&user_message_clone).await?;
+            db_clone.search_channel_messages_semantic(&channel_id_clone, &embedding, SEMANTIC_SEARCH_RESULTS).await.map(Some)
+        };
+
+        let (keyword_results, semantic_results) = futures::future::join(keyword_search, semantic_search).await;
+        let keyword_results = keyword_results?;
+
+        // A failed semantic search only costs its own results.
+        let messages = match semantic_results {
+            Ok(Some(semantic_results)) => merge_message_search_results(&keyword_results, &semantic_results),
+            Ok(None) => keyword_results,
+            Err(err) => {
+                warn!("Failed to search messages semantically: {}", err);
+                keyword_results
+            }
         };
 
         Result::<_, anyhow::Error>::Ok(messages)
@@ -240,28 +1060,976 @@ where
 
     // Wait for all tasks to complete.
 
-    let (web_search_result, message_search_result) = futures::future::join(web_search_task, message_search_task).await;
-    let web_search_result = web_search_result??;
-    let message_search_result = message_search_result??;
-
-    // Prepare the list of tools.
+    let (intent, web_search_result, message_search_result) = futures::future::join3(intent_task, web_search_task, message_search_task).await;
+    let intent = intent?;
+    let web_search_result = web_search_result?;
+    let message_search_result = annotate_user_names(&message_search_result??, chat).await;
+    let message_search_result = label_message_search_results(&channel_id, &message_search_result, chat).await;
 
-    let tools = mcp.get_assistant_tools();
+    Span::current().record("intent", intent.label());
 
     // Prepare results.
 
     let agent_responses = AssistantContext {
         user_message,
         bot_user_id,
-        web_search_context: web_search_result,
+        web_search_context: web_search_result.text,
+        web_search_sources: web_search_result.sources,
+        web_search_enabled,
         message_search_context: message_search_result,
         channel_id,
         thread_ts,
+        is_direct_message,
+        system_addendum,
         channel_directive,
         channel_context,
+        user_context,
         thread_context,
+        previous_responses,
+        recent_messages,
+        images,
+        language,
+        mode,
         tools,
+        intent,
+        variables,
+        usage,
+        reasoning: ReasoningTracker::default(),
     };
 
     Ok(agent_responses)
 }
+
+/// Get what a message asks for, from the intent agent.
+///
+/// If the intent agent fails, the message is treated as ordinary, so that the assistant still answers it (but is offered
+/// no tools that change its directive or context, or call an MCP tool).
+pub async fn get_intent(context: IntentContext, llm: &LlmClient) -> AssistantIntent {
+    match llm.get_intent_agent_response(context).await {
+        Ok(intent) => intent,
+        Err(err) => {
+            warn!("Failed to get the message's intent, so it is treated as ordinary: {}", err);
+            AssistantIntent::Ordinary
+        }
+    }
+}
+
+/// The language to reply in: the channel's forced language, if it has one, or else the language detected in the
+/// message's text (empty if it is not clear).
+fn reply_language(user_message: &str, force_language: Option<String>) -> String {
+    force_language
+        .or_else(|| {
+            let event = serde_json::from_str::<Value>(user_message).ok()?;
+            let text = event.get("text").and_then(Value::as_str)?;
+
+            detect_language(text).map(str::to_string)
+        })
+        .unwrap_or_default()
+}
+
+/// Describe the MCP tools for the intent agent (a line with the name and description of each).
+fn describe_tools(tools: &[AssistantTool]) -> String {
+    tools
+        .iter()
+        .map(|tool| format!("- `{}`: {}", tool.name, tool.description.as_deref().unwrap_or_default()))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Get the thread context (the thread's messages, as JSON).
+///
+/// The stored messages are preferred, since they save a chat API call; the chat API is only used when none of the
+/// thread's messages are stored (e.g., right after a fresh install), or the database cannot be read.
+#[instrument(skip(db, chat))]
+pub async fn get_thread_context<L, C, M>(channel_id: &str, thread_ts: &str, db: &DbClient<L, C, M>, chat: &ChatClient) -> Res<String>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    match db.get_channel_thread_messages(channel_id, thread_ts).await {
+        Ok(messages) if !messages.is_empty() => {
+            let raws = messages.iter().map(Message::raw).collect::<Vec<_>>();
+
+            return Ok(serde_json::to_string(&raws)?);
+        }
+        Ok(_) => info!("No stored messages for thread `{}`, reading it from the chat API.", thread_ts),
+        Err(err) => warn!("Failed to read thread `{}` from the database, reading it from the chat API: {}", thread_ts, err),
+    }
+
+    chat.get_thread_context(channel_id, thread_ts).await
+}
+
+/// Get the channel's name (for the `{channel_name}` template variable).
+///
+/// The stored name is preferred; otherwise, it is read from the chat API (and stored).  If it cannot be read (e.g., for
+/// a direct message), the channel ID is used instead.
+async fn get_channel_name<L, C, M>(channel: &C, channel_id: &str, db: &DbClient<L, C, M>, chat: &ChatClient) -> String
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    if let Some(name) = channel.name().filter(|name| !name.is_empty()) {
+        return name.to_string();
+    }
+
+    match chat.get_channel_info(channel_id).await {
+        Ok(info) if !info.name.is_empty() => {
+            if let Err(err) = db.set_channel_name(channel_id, &info.name).await {
+                warn!("Failed to store the name of channel `{}`: {}", channel_id, err);
+            }
+
+            info.name
+        }
+        Ok(_) => channel_id.to_string(),
+        Err(err) => {
+            warn!("Failed to get the name of channel `{}`: {}", channel_id, err);
+            channel_id.to_string()
+        }
+    }
+}
+
+/// Condense a very long thread context (the thread's messages, as JSON) for the assistant.
+///
+/// Threads of more than `threshold_chars` (zero disables summaries) have their older messages replaced by a summary from
+/// the thread summary agent, followed by their `recent_messages` most recent messages, so that the result is still a
+/// JSON array of messages.  If the summary fails, the thread context is left as it is (it is truncated later, if need be).
+#[instrument(skip_all)]
+pub async fn condense_thread_context(thread_context: String, threshold_chars: usize, recent_messages: usize, llm: &LlmClient, usage: &UsageTracker) -> String {
+    if threshold_chars == 0 || thread_context.len() <= threshold_chars {
+        return thread_context;
+    }
+
+    let Ok(mut messages) = serde_json::from_str::<Vec<Value>>(&thread_context) else {
+        return thread_context;
+    };
+
+    if messages.len() <= recent_messages {
+        return thread_context;
+    }
+
+    let recent = messages.split_off(messages.len() - recent_messages);
+    let older = messages;
+
+    let summary_context = SummaryContext {
+        thread_context: Value::from(older.clone()).to_string(),
+        usage: usage.clone(),
+    };
+
+    match llm.get_summary_agent_response(summary_context).await {
+        Ok(summary) => {
+            info!("Summarized {} older messages of a {} character thread.", older.len(), thread_context.len());
+
+            let summary = json!({ "summary": summary, "summarized_messages": older.len() });
+            Value::from(std::iter::once(summary).chain(recent).collect::<Vec<_>>()).to_string()
+        }
+        Err(err) => {
+            warn!("Failed to summarize the thread: {}", err);
+            thread_context
+        }
+    }
+}
+
+/// Publish the channel's current directive where the channel can see it.
+///
+/// Publishing is best-effort (e.g., canvases may be disabled), so only reading the channel can fail.
+async fn publish_channel_directive<L, C, M>(channel_id: &str, db: &DbClient<L, C, M>, chat: &ChatClient) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let channel = db.get_or_create_channel(channel_id).await?;
+    let markdown = render_directive_markdown(channel.channel_directive().your_notes(), channel.broadcasts_incidents());
+
+    if let Err(err) = chat.publish_channel_directive(channel_id, &markdown).await {
+        warn!("Failed to publish the channel directive: {}", err);
+    }
+
+    Ok(())
+}
+
+/// Get what is known about some users in the channel, as JSON (a list of `{ user, contexts }`, skipping users without any).
+#[instrument(skip(user_ids, db))]
+async fn get_user_contexts<L, C, M>(channel_id: &str, user_ids: impl IntoIterator<Item = String>, db: &DbClient<L, C, M>) -> Res<String>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let mut seen = HashSet::new();
+    let mut users = Vec::new();
+
+    for user_id in user_ids {
+        if !seen.insert(user_id.clone()) {
+            continue;
+        }
+
+        let contexts = db.get_user_context(channel_id, &user_id).await?;
+        if !contexts.is_empty() {
+            users.push(json!({ "user": user_id, "contexts": contexts }));
+        }
+    }
+
+    Ok(serde_json::to_string(&users)?)
+}
+
+/// Annotate every message (any JSON object with a `user` ID) in some JSON context with the author's `user_name`.
+///
+/// Failed lookups are skipped, and context that is not JSON is returned unchanged.
+pub async fn annotate_user_names(context: &str, chat: &ChatClient) -> String {
+    let Ok(mut value) = serde_json::from_str::<Value>(context) else {
+        return context.to_string();
+    };
+
+    let mut user_ids = Vec::new();
+    collect_user_ids(&value, &mut user_ids);
+
+    if user_ids.is_empty() {
+        return context.to_string();
+    }
+
+    let mut names = HashMap::new();
+    for user_id in user_ids {
+        match chat.get_user_info(&user_id).await {
+            Ok(user_info) => {
+                names.insert(user_id, user_info.display_name);
+            }
+            Err(err) => warn!("Failed to resolve user `{}`: {}", user_id, err),
+        }
+    }
+
+    insert_user_names(&mut value, &names);
+
+    value.to_string()
+}
+
+/// The IDs of the users mentioned in a message (e.g., `<@U12345>`, or `<@U12345|alice>`), in order of appearance.
+fn mentioned_user_ids(text: &str) -> Vec<String> {
+    text.split("<@")
+        .skip(1)
+        .filter_map(|rest| rest.split_once('>'))
+        .map(|(mention, _)| mention.split('|').next().unwrap_or_default().to_string())
+        .filter(|id| !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric()))
+        .collect()
+}
+
+/// Validate the thread that the assistant asked to reply to against the event.
+///
+/// The assistant can only reply to the message it was asked about, by its thread (`event_thread_ts`) or by the message
+/// itself (`event_ts`), so anything else (e.g., an empty timestamp, or one copied from another message) falls back to
+/// the known-good thread root.
+fn validate_thread_ts(requested_thread_ts: &str, event_thread_ts: &str, event_ts: &str) -> String {
+    let requested_thread_ts = requested_thread_ts.trim();
+
+    if requested_thread_ts == event_thread_ts || requested_thread_ts == event_ts {
+        return requested_thread_ts.to_string();
+    }
+
+    warn!("Assistant replied to thread `{}` instead of `{}`; using the event's thread.", requested_thread_ts, event_thread_ts);
+
+    event_thread_ts.to_string()
+}
+
+/// Check whether a channel that the assistant asks for (an ID, or a mention, e.g., `<#C0123|support>`) is the current
+/// channel (whose ID may be qualified with its workspace namespace, e.g., `T0123:C0456`).
+fn is_current_channel(requested_channel_id: &str, channel_id: &str) -> bool {
+    let requested = requested_channel_id.trim().trim_start_matches("<#").trim_start_matches('#');
+    let requested = requested.split(['|', '>']).next().unwrap_or_default();
+    let bare_channel_id = channel_id.rsplit_once(':').map_or(channel_id, |(_, id)| id);
+
+    !requested.is_empty() && (requested == channel_id || requested == bare_channel_id)
+}
+
+/// Parse the timestamp of a thread that the assistant asks for, which may be in a permalink's form (e.g., `p1700000000000100`).
+fn parse_thread_ts(requested_thread_ts: &str) -> String {
+    let ts = requested_thread_ts.trim();
+
+    match ts.strip_prefix('p') {
+        Some(digits) if digits.len() > 6 && digits.chars().all(|c| c.is_ascii_digit()) => format!("{}.{}", &digits[..digits.len() - 6], &digits[digits.len() - 6..]),
+        _ => ts.to_string(),
+    }
+}
+
+/// Cap a fetched thread context (the thread's messages, as JSON) to its `limit` most recent messages, and to
+/// `FETCHED_THREAD_MAX_TOKENS` (dropping its oldest messages).
+fn cap_fetched_thread_context(thread_context: &str, limit: Option<usize>) -> String {
+    let limit = limit.unwrap_or(FETCHED_THREAD_MESSAGES_DEFAULT).clamp(1, FETCHED_THREAD_MESSAGES_MAX);
+
+    let thread_context = match serde_json::from_str::<Vec<Value>>(thread_context) {
+        Ok(messages) if messages.len() > limit => Value::from(messages[messages.len() - limit..].to_vec()).to_string(),
+        _ => thread_context.to_string(),
+    };
+
+    truncate(&thread_context, FETCHED_THREAD_MAX_TOKENS, Truncation::DropOldest)
+}
+
+/// Collect the (deduplicated) `user` IDs of all objects in a JSON value.
+fn collect_user_ids(value: &Value, user_ids: &mut Vec<String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(user_id) = map.get("user").and_then(Value::as_str)
+                && !user_ids.iter().any(|id| id == user_id)
+            {
+                user_ids.push(user_id.to_string());
+            }
+
+            map.values().for_each(|v| collect_user_ids(v, user_ids));
+        }
+        Value::Array(items) => items.iter().for_each(|v| collect_user_ids(v, user_ids)),
+        _ => {}
+    }
+}
+
+/// Insert a `user_name` next to every resolved `user` ID in a JSON value.
+fn insert_user_names(value: &mut Value, names: &HashMap<String, String>) {
+    match value {
+        Value::Object(map) => {
+            if let Some(name) = map.get("user").and_then(Value::as_str).and_then(|id| names.get(id)) {
+                map.insert("user_name".to_string(), Value::String(name.clone()));
+            }
+
+            map.values_mut().for_each(|v| insert_user_names(v, names));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| insert_user_names(v, names)),
+        _ => {}
+    }
+}
+
+/// Label each message search hit with its timestamp and permalink (and, for hits from linked channels, its channel), so that the assistant can cite it.
+///
+/// Permalinks are looked up concurrently, and failed lookups are tolerated (the hit is simply not linked).
+/// Results that are not a JSON array of hits are returned unchanged.
+pub async fn label_message_search_results(channel_id: &str, results: &str, chat: &ChatClient) -> String {
+    let Ok(Value::Array(hits)) = serde_json::from_str::<Value>(results) else {
+        return results.to_string();
+    };
+
+    if hits.is_empty() {
+        return results.to_string();
+    }
+
+    let permalinks = futures::future::join_all(hits.iter().map(|hit| async move {
+        let ts = hit.pointer("/raw/ts").and_then(Value::as_str)?;
+        let channel_id = hit.get("channel_id").and_then(Value::as_str).unwrap_or(channel_id);
+
+        match chat.get_permalink(channel_id, ts).await {
+            Ok(permalink) => Some(permalink),
+            Err(err) => {
+                warn!("Failed to get permalink for message `{}`: {}", ts, err);
+                None
+            }
+        }
+    }))
+    .await;
+
+    format_message_search_results(&hits, &permalinks)
+}
+
+/// Normalize the channels that the assistant linked to a channel into channel IDs.
+///
+/// Channel mentions (e.g., `<#C0123|support>`) are reduced to their IDs, IDs are qualified with the channel's
+/// workspace namespace (if it has one, e.g., `T0123:C0456`), and duplicates (and the channel itself) are dropped.
+fn parse_linked_channels(channel_id: &str, linked_channels: &[String]) -> Vec<String> {
+    let namespace = channel_id.rsplit_once(':').map(|(namespace, _)| namespace);
+
+    let mut result: Vec<String> = Vec::new();
+    for linked in linked_channels {
+        let id = linked.trim().trim_start_matches("<#").trim_start_matches('#').trim_end_matches('>');
+        let id = id.split('|').next().unwrap_or_default().trim();
+
+        if id.is_empty() {
+            continue;
+        }
+
+        let id = match namespace {
+            Some(namespace) if !id.contains(':') => format!("{namespace}:{id}"),
+            _ => id.to_string(),
+        };
+
+        if id != channel_id && !result.contains(&id) {
+            result.push(id);
+        }
+    }
+
+    result
+}
+
+/// When a context that is remembered for `expires_in_days` from `now` (unix seconds) expires, or `None` if it never does.
+fn context_expires_at(expires_in_days: Option<u64>, now: i64) -> Option<i64> {
+    let days = i64::try_from(expires_in_days?).unwrap_or(i64::MAX);
+
+    Some(now.saturating_add(days.saturating_mul(24 * 60 * 60)))
+}
+
+/// Split the message search agent's response into its search terms, and the start of its time hint (if any).
+///
+/// The time hint (e.g., `since: 14 days`) becomes the Slack timestamp that many days before `now` (unix seconds), and
+/// anything that does not parse as a hint is kept as a search term.
+fn parse_search_time_hint(response: &str, now: i64) -> (String, Option<String>) {
+    let mut terms = Vec::new();
+    let mut after_ts = None;
+
+    for segment in response.split([',', '\n']).map(str::trim).filter(|s| !s.is_empty()) {
+        let days = segment
+            .to_lowercase()
+            .strip_prefix("since:")
+            .and_then(|hint| hint.split_whitespace().next().and_then(|days| days.parse::<u32>().ok()));
+
+        match days {
+            Some(days) => after_ts = Some(format!("{}.000000", now - i64::from(days) * 86_400)),
+            None => terms.push(segment),
+        }
+    }
+
+    (terms.join(", "), after_ts)
+}
+
+/// Merge keyword and semantic message search results (both JSON arrays of messages), dropping duplicates.
+///
+/// Keyword results come first, and a message found by both searches is kept where the keyword search ranked it.
+/// If neither search found anything, the keyword results are returned as they are (e.g., "No relevant messages found.").
+pub fn merge_message_search_results(keyword_results: &str, semantic_results: &str) -> String {
+    let hits = |results: &str| match serde_json::from_str::<Value>(results) {
+        Ok(Value::Array(hits)) => hits,
+        _ => Vec::new(),
+    };
+
+    let mut seen = HashSet::new();
+    let merged = hits(keyword_results)
+        .into_iter()
+        .chain(hits(semantic_results))
+        .filter(|hit| {
+            // Messages are identified by their `ts` (or, failing that, their record ID).
+            let key = hit.pointer("/raw/ts").or_else(|| hit.get("id")).map(Value::to_string).unwrap_or_default();
+            key.is_empty() || seen.insert(key)
+        })
+        .collect::<Vec<_>>();
+
+    if merged.is_empty() {
+        return keyword_results.to_string();
+    }
+
+    Value::Array(merged).to_string()
+}
+
+/// Format message search hits (and their permalinks) as labeled sections.
+fn format_message_search_results(hits: &[Value], permalinks: &[Option<String>]) -> String {
+    hits.iter()
+        .zip(permalinks)
+        .enumerate()
+        .map(|(k, (hit, permalink))| {
+            let raw = hit.get("raw").unwrap_or(hit);
+            let posted = raw.get("ts").and_then(Value::as_str).and_then(format_slack_ts).unwrap_or_else(|| "unknown".to_string());
+            let link = permalink.as_deref().unwrap_or("unavailable");
+
+            // Hits from a search across channels carry their channel (which may be namespaced by workspace).
+            let channel = match hit.get("channel_id").and_then(Value::as_str) {
+                Some(channel_id) => format!("channel: <#{}>, ", channel_id.rsplit(':').next().unwrap_or(channel_id)),
+                None => String::new(),
+            };
+
+            format!("### Message {} ({}posted: {}, permalink: {})\n\n{}", k + 1, channel, posted, link, raw)
+        })
+        .collect::<Vec<_>>()
+        .join("\n\n")
+}
+
+/// Format a channel's recent messages (newest first, as stored) as JSON, oldest first.
+///
+/// Messages in the current thread are skipped (they are already in the thread context), only the fields that the
+/// assistant needs are kept, and each message's text is truncated to `max_chars` characters, to keep the context small.
+fn format_recent_messages(messages: &[Value], thread_ts: &str, max_chars: usize) -> String {
+    let messages = messages
+        .iter()
+        .rev()
+        .filter(|m| m.get("ts").and_then(Value::as_str) != Some(thread_ts) && m.get("thread_ts").and_then(Value::as_str) != Some(thread_ts))
+        .map(|m| {
+            let text = m.get("text").and_then(Value::as_str).unwrap_or_default();
+            let text = if text.chars().count() > max_chars {
+                format!("{}…", text.chars().take(max_chars).collect::<String>())
+            } else {
+                text.to_string()
+            };
+
+            json!({
+                "user": m.get("user"),
+                "ts": m.get("ts"),
+                "thread_ts": m.get("thread_ts"),
+                "text": text,
+            })
+        })
+        .collect::<Vec<_>>();
+
+    Value::Array(messages).to_string()
+}
+
+/// Format a Slack timestamp (e.g., `1700000000.000100`) as a human-readable UTC time.
+fn format_slack_ts(ts: &str) -> Option<String> {
+    let seconds = ts.split('.').next()?.parse::<i64>().ok()?;
+    let time = chrono::DateTime::from_timestamp(seconds, 0)?;
+
+    Some(time.format("%Y-%m-%d %H:%M UTC").to_string())
+}
+
+/// Maximum length of the text in a single section block.
+const SECTION_TEXT_LIMIT: usize = 3000;
+
+/// Render a reply that is still being generated, marked as unfinished, and cut to fit a single message.
+fn render_streaming_preview(message: &str, max_chars: usize) -> String {
+    let preview = message.chars().take(max_chars.saturating_sub(1)).collect::<String>();
+
+    format!("{}…", preview.trim_end())
+}
+
+/// Render a `ReplyToThread` response into Block Kit sections.
+///
+/// The classification badge goes into a context block, the message body into section blocks
+/// (so that any oncall mention still notifies), and the links from the message into a separate section.
+pub fn render_reply_blocks(classification: &AssistantClassification, emoji: &str, message: &str) -> Vec<Value> {
+    let mut blocks = Vec::new();
+
+    // Classification badge.
+
+    blocks.push(json!({
+        "type": "context",
+        "elements": [{ "type": "mrkdwn", "text": format!(":{emoji}: *{classification:?}*") }],
+    }));
+
+    // Message body, split to respect the section text limit, and tagged so that the chat client can find it.
+
+    let chars = message.chars().collect::<Vec<_>>();
+    for (k, chunk) in chars.chunks(SECTION_TEXT_LIMIT).enumerate() {
+        blocks.push(json!({
+            "type": "section",
+            "block_id": format!("{BODY_BLOCK_ID_PREFIX}{k}"),
+            "text": { "type": "mrkdwn", "text": chunk.iter().collect::<String>() },
+        }));
+    }
+
+    // Links.
+
+    let links = extract_links(message);
+    if !links.is_empty() {
+        let list = links.iter().map(|link| format!("• {link}")).collect::<Vec<_>>().join("\n");
+
+        blocks.push(json!({
+            "type": "section",
+            "text": { "type": "mrkdwn", "text": format!("*Links*\n{list}") },
+        }));
+    }
+
+    blocks
+}
+
+/// Render the web pages that a reply is based on as a compact line of Slack-formatted links.
+///
+/// Pages are deduplicated (by URL), pages that the message already links to are left out, and at most
+/// `SOURCES_LIMIT` are shown.  Returns `None` if there is nothing to show.
+fn render_sources_line(sources: &[Source], message: &str) -> Option<String> {
+    let mut urls: Vec<&str> = Vec::new();
+    let mut links = Vec::new();
+
+    for source in sources {
+        let url = source.url.trim();
+        let is_linked = message.contains(&format!("<{url}|")) || message.contains(&format!("<{url}>")) || message.contains(&format!("]({url})"));
+
+        if url.is_empty() || is_linked || urls.contains(&url) {
+            continue;
+        }
+        urls.push(url);
+
+        // Link text cannot contain the link delimiters, so they are escaped (falling back to the URL's host).
+        let title = source.title.trim();
+        let title = if title.is_empty() {
+            url.split("://").nth(1).unwrap_or(url).split('/').next().unwrap_or(url)
+        } else {
+            title
+        };
+        let title = title.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;").replace('|', "-");
+
+        links.push(format!("<{url}|{title}>"));
+    }
+
+    if links.is_empty() {
+        return None;
+    }
+
+    links.truncate(SOURCES_LIMIT);
+
+    Some(format!("*Sources:* {}", links.join(" · ")))
+}
+
+/// Render the channel directive (and how the bot replies to incidents) as markdown, for publishing in the channel.
+fn render_directive_markdown(notes: &str, broadcasts_incidents: bool) -> String {
+    let incidents = if broadcasts_incidents {
+        "Replies to incidents are also broadcast to the channel."
+    } else {
+        "Replies to incidents stay in their threads."
+    };
+
+    format!("# Triage Bot Directive\n\n{notes}\n\n## Incidents\n\n{incidents}\n\n_Ask me to update the channel directive to change this._")
+}
+
+/// Extract the (deduplicated) links (e.g., `<https://example.com|text>`, or `[text](https://example.com)`) from a message, as written.
+fn extract_links(message: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+
+    for link in link_pattern().find_iter(message).map(|m| m.as_str()) {
+        if !links.iter().any(|l| l == link) {
+            links.push(link.to_string());
+        }
+    }
+
+    links
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_directive_markdown() {
+        let markdown = render_directive_markdown("@payments-oncall is the oncall.", true);
+
+        assert!(markdown.starts_with("# Triage Bot Directive\n\n@payments-oncall is the oncall.\n\n"));
+        assert!(markdown.contains("also broadcast to the channel"));
+        assert!(render_directive_markdown("Be nice.", false).contains("stay in their threads"));
+    }
+
+    #[test]
+    fn test_reply_language() {
+        let event = |text: &str| json!({ "type": "message", "user": "U1", "text": text }).to_string();
+        let german = event("<@U12345> Seit dem letzten Deployment startet der Dienst nicht mehr, kann sich das bitte jemand ansehen?");
+
+        assert_eq!(reply_language(&german, None), "German");
+        assert_eq!(reply_language(&event("デプロイ後にサービスが起動しなくなりました。誰か確認していただけますか？"), None), "Japanese");
+
+        // The channel's language wins over the detected one.
+        assert_eq!(reply_language(&german, Some("English".to_string())), "English");
+
+        // Unclear (or unparseable) messages have no language.
+        assert_eq!(reply_language(&event("<@U12345> ok"), None), "");
+        assert_eq!(reply_language("not an event", None), "");
+    }
+
+    #[test]
+    fn test_merge_message_search_results() {
+        let keyword = json!([{ "raw": { "ts": "1.0", "text": "Deploys are stuck." } }, { "raw": { "ts": "2.0", "text": "Deploy failed." } }]).to_string();
+        let semantic = json!([{ "raw": { "ts": "3.0", "text": "The pipeline hangs." } }, { "raw": { "ts": "1.0", "text": "Deploys are stuck." } }]).to_string();
+
+        let merged = serde_json::from_str::<Vec<Value>>(&merge_message_search_results(&keyword, &semantic)).unwrap();
+        let ts = merged.iter().map(|m| m["raw"]["ts"].as_str().unwrap()).collect::<Vec<_>>();
+
+        assert_eq!(ts, vec!["1.0", "2.0", "3.0"]);
+
+        // Either search may come up empty (or not have run at all).
+        assert_eq!(merge_message_search_results("No relevant messages found.", &semantic), semantic);
+        assert_eq!(merge_message_search_results("No relevant messages found.", "[]"), "No relevant messages found.");
+    }
+
+    #[test]
+    fn test_validate_thread_ts() {
+        // The event's thread, and the event's message (e.g., a reply in the thread), are accepted.
+        assert_eq!(validate_thread_ts("1700000000.000001", "1700000000.000001", "1700000000.000003"), "1700000000.000001");
+        assert_eq!(validate_thread_ts(" 1700000000.000003", "1700000000.000001", "1700000000.000003"), "1700000000.000003");
+
+        // Anything else falls back to the event's thread.
+        assert_eq!(validate_thread_ts("", "1700000000.000001", "1700000000.000003"), "1700000000.000001");
+        assert_eq!(validate_thread_ts("1700000000.000002", "1700000000.000001", "1700000000.000003"), "1700000000.000001");
+    }
+
+    #[test]
+    fn test_thread_fetch_guardrails() {
+        // Only the current channel (by its ID, bare ID, or mention).
+        assert!(is_current_channel("C1", "C1"));
+        assert!(is_current_channel(" <#C1|support> ", "C1"));
+        assert!(is_current_channel("C1", "T1:C1"));
+        assert!(is_current_channel("T1:C1", "T1:C1"));
+        assert!(!is_current_channel("C2", "C1"));
+        assert!(!is_current_channel("T2:C1", "T1:C1"));
+        assert!(!is_current_channel("", "C1"));
+
+        // Permalink timestamps are read as message timestamps.
+        assert_eq!(parse_thread_ts("p1700000000000100"), "1700000000.000100");
+        assert_eq!(parse_thread_ts(" 1700000000.000100 "), "1700000000.000100");
+        assert_eq!(parse_thread_ts("pending"), "pending");
+
+        // Only the most recent messages are kept (and only as many tokens as the cap allows).
+        let messages = (0..300)
+            .map(|i| json!({ "user": "U1", "ts": format!("1700000000.{i:06}"), "text": format!("message {i}") }))
+            .collect::<Vec<_>>();
+        let thread_context = Value::from(messages.clone()).to_string();
+
+        let capped = serde_json::from_str::<Vec<Value>>(&cap_fetched_thread_context(&thread_context, Some(3))).unwrap();
+        assert_eq!(capped, messages[297..]);
+
+        let capped = serde_json::from_str::<Vec<Value>>(&cap_fetched_thread_context(&thread_context, None)).unwrap();
+        assert_eq!(capped, messages[300 - FETCHED_THREAD_MESSAGES_DEFAULT..]);
+
+        let long_messages = (0..FETCHED_THREAD_MESSAGES_DEFAULT)
+            .map(|i| json!({ "ts": format!("1700000000.{i:06}"), "text": "word ".repeat(500) }))
+            .collect::<Vec<_>>();
+        let capped = cap_fetched_thread_context(&Value::from(long_messages.clone()).to_string(), None);
+        let capped = serde_json::from_str::<Vec<Value>>(&capped).unwrap();
+        assert!(capped.len() < long_messages.len());
+        assert_eq!(capped.last(), long_messages.last());
+    }
+
+    #[test]
+    fn test_parse_search_time_hint() {
+        let now = 1_700_000_000;
+
+        assert_eq!(parse_search_time_hint("checkout errors, payment timeout", now), ("checkout errors, payment timeout".to_string(), None));
+        assert_eq!(
+            parse_search_time_hint("checkout errors, payment timeout\nsince: 14 days", now),
+            ("checkout errors, payment timeout".to_string(), Some("1698790400.000000".to_string()))
+        );
+        assert_eq!(parse_search_time_hint("502, Since: 1 day", now), ("502".to_string(), Some("1699913600.000000".to_string())));
+
+        // A hint that does not parse is just another search term.
+        assert_eq!(parse_search_time_hint("502, since: last week", now), ("502, since: last week".to_string(), None));
+        assert_eq!(parse_search_time_hint("", now), (String::new(), None));
+    }
+
+    #[test]
+    fn test_parse_linked_channels() {
+        let linked = ["<#C2|payments>", "#C3", " C2 ", "C1", ""].map(str::to_string);
+
+        assert_eq!(parse_linked_channels("C1", &linked), vec!["C2", "C3"]);
+        assert!(parse_linked_channels("C1", &[]).is_empty());
+
+        // With multiple workspaces, linked channels are in the channel's workspace.
+        assert_eq!(parse_linked_channels("T1:C1", &linked), vec!["T1:C2", "T1:C3"]);
+        assert_eq!(parse_linked_channels("T1:C1", &["T2:C9".to_string()]), vec!["T2:C9"]);
+    }
+
+    #[test]
+    fn test_context_expires_at() {
+        assert_eq!(context_expires_at(None, 1_700_000_000), None);
+        assert_eq!(context_expires_at(Some(14), 1_700_000_000), Some(1_700_000_000 + 14 * 24 * 60 * 60));
+        assert_eq!(context_expires_at(Some(u64::MAX), 1_700_000_000), Some(i64::MAX));
+    }
+
+    #[test]
+    fn test_mentioned_user_ids() {
+        assert_eq!(mentioned_user_ids("<@U12345> please remember that <@U999|bob> owns billing"), vec!["U12345", "U999"]);
+        assert_eq!(mentioned_user_ids("<@U12345>"), vec!["U12345"]);
+
+        // Other tokens (e.g., user groups and links), and broken mentions, are not users.
+        assert!(mentioned_user_ids("<!subteam^S999|@horses> see <https://example.com> <@U1").is_empty());
+        assert!(mentioned_user_ids("U12345").is_empty());
+    }
+
+    #[test]
+    fn test_render_reply_blocks() {
+        let message = "*Summary*: The build is failing.\n\n<@U12345> can you take a look?  See <https://example.com/docs|the docs> and <https://example.com/docs|the docs>.";
+
+        let blocks = render_reply_blocks(&AssistantClassification::Bug, "bug", message);
+
+        assert_eq!(
+            blocks,
+            vec![
+                json!({ "type": "context", "elements": [{ "type": "mrkdwn", "text": ":bug: *Bug*" }] }),
+                json!({ "type": "section", "block_id": "body_0", "text": { "type": "mrkdwn", "text": message } }),
+                json!({ "type": "section", "text": { "type": "mrkdwn", "text": "*Links*\n• <https://example.com/docs|the docs>" } }),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_render_sources_line() {
+        let source = |title: &str, url: &str| Source {
+            title: title.to_string(),
+            url: url.to_string(),
+        };
+        let sources = vec![
+            source("Rust 1.80 <release> notes", "https://blog.rust-lang.org/1.80"),
+            source("Duplicate", "https://blog.rust-lang.org/1.80"),
+            source("Already linked", "https://example.com/docs"),
+            source("", "https://docs.rs/tokio/latest"),
+        ];
+
+        assert_eq!(
+            render_sources_line(&sources, "See <https://example.com/docs|the docs>."),
+            Some("*Sources:* <https://blog.rust-lang.org/1.80|Rust 1.80 &lt;release&gt; notes> · <https://docs.rs/tokio/latest|docs.rs>".to_string())
+        );
+
+        // The number of sources is capped, and nothing is shown without any.
+        let many = (0..10).map(|i| source(&format!("Page {i}"), &format!("https://example.com/{i}"))).collect::<Vec<_>>();
+        assert_eq!(render_sources_line(&many, "").unwrap().matches("<https://").count(), SOURCES_LIMIT);
+        assert_eq!(render_sources_line(&[], "Hello."), None);
+    }
+
+    #[test]
+    fn test_render_reply_blocks_markdown_links() {
+        let message = "See [the docs](https://example.com/docs), <https://example.com/status>, and [the docs](https://example.com/docs) again.";
+
+        let blocks = render_reply_blocks(&AssistantClassification::Question, "question", message);
+
+        assert_eq!(blocks[2]["text"]["text"], "*Links*\n• [the docs](https://example.com/docs)\n• <https://example.com/status>");
+    }
+
+    #[test]
+    fn test_render_reply_blocks_without_links() {
+        let blocks = render_reply_blocks(&AssistantClassification::Question, "question", "<@U12345> please take a look.");
+
+        assert_eq!(blocks.len(), 2);
+        assert_eq!(blocks[1]["text"]["text"], "<@U12345> please take a look.");
+    }
+
+    #[test]
+    fn test_insert_user_names() {
+        let mut value = json!([
+            { "user": "U1", "text": "Hello" },
+            { "id": "message:1", "raw": { "user": "U2", "text": "World" } },
+            { "user": "U1", "text": "Again" },
+        ]);
+
+        let mut user_ids = Vec::new();
+        collect_user_ids(&value, &mut user_ids);
+        assert_eq!(user_ids, vec!["U1", "U2"]);
+
+        let names = HashMap::from([("U1".to_string(), "Alice".to_string())]);
+        insert_user_names(&mut value, &names);
+
+        assert_eq!(value[0]["user_name"], "Alice");
+        assert_eq!(value[2]["user_name"], "Alice");
+        assert!(value[1]["raw"].get("user_name").is_none());
+    }
+
+    #[test]
+    fn test_format_message_search_results() {
+        let hits = vec![
+            json!({ "id": "message:1", "score": 2.0, "raw": { "user": "U1", "text": "The horse API is down.", "ts": "1700000000.000100" } }),
+            json!({ "id": "message:2", "score": 1.0, "raw": { "user": "U2", "text": "Still down?" } }),
+        ];
+        let permalinks = vec![Some("https://example.slack.com/archives/C1/p1700000000000100".to_string()), None];
+
+        let formatted = format_message_search_results(&hits, &permalinks);
+
+        assert!(formatted.starts_with("### Message 1 (posted: 2023-11-14 22:13 UTC, permalink: https://example.slack.com/archives/C1/p1700000000000100)"));
+        assert!(formatted.contains("The horse API is down."));
+        assert!(formatted.contains("### Message 2 (posted: unknown, permalink: unavailable)"));
+        assert!(!formatted.contains("\"score\""));
+
+        // Hits from a search across channels are labelled with their channel.
+        let hits = vec![json!({ "channel_id": "T1:C2", "raw": { "user": "U1", "text": "Try restarting the consumer.", "ts": "1700000000.000100" } })];
+
+        let formatted = format_message_search_results(&hits, &[None]);
+
+        assert!(formatted.starts_with("### Message 1 (channel: <#C2>, posted: 2023-11-14 22:13 UTC, permalink: unavailable)"));
+    }
+
+    #[test]
+    fn test_format_recent_messages() {
+        let messages = vec![
+            json!({ "user": "U2", "ts": "3.0", "text": "a".repeat(20), "blocks": [] }),
+            json!({ "user": "U1", "ts": "2.5", "thread_ts": "2.0", "text": "In this thread" }),
+            json!({ "user": "U1", "ts": "2.0", "text": "This thread" }),
+            json!({ "user": "U1", "ts": "1.0", "text": "Deploys are failing" }),
+        ];
+
+        let formatted: Vec<Value> = serde_json::from_str(&format_recent_messages(&messages, "2.0", 10)).unwrap();
+
+        // Oldest first, without the current thread, and with long texts truncated.
+        assert_eq!(formatted.len(), 2);
+        assert_eq!(formatted[0]["text"], "Deploys ar…");
+        assert_eq!(formatted[1]["text"], format!("{}…", "a".repeat(10)));
+        assert_eq!(formatted[1]["user"], "U2");
+        assert!(formatted[1].get("blocks").is_none());
+    }
+
+    #[test]
+    fn test_withhold_low_confidence() {
+        let reply = |confidence| AssistantResponse::ReplyToThread {
+            thread_ts: "1.0".to_string(),
+            classification: AssistantClassification::Bug,
+            message: "It is probably DNS.".to_string(),
+            confidence,
+            sources: Vec::new(),
+        };
+        let oncalls = vec!["@horse-oncall".to_string()];
+
+        // Confident answers (and answers without a confidence) are posted.
+        assert!(withhold_low_confidence(&reply(Some(80)), 70, "tag", &oncalls).is_none());
+        assert!(withhold_low_confidence(&reply(Some(70)), 70, "tag", &oncalls).is_none());
+        assert!(withhold_low_confidence(&reply(None), 70, "tag", &oncalls).is_none());
+        assert!(withhold_low_confidence(&reply(Some(10)), 0, "tag", &oncalls).is_none());
+        assert!(withhold_low_confidence(&AssistantResponse::NoAction, 70, "tag", &oncalls).is_none());
+
+        // Others are replaced by a tag of the oncall (keeping the classification), or nothing.
+        let Some(AssistantResponse::ReplyToThread {
+            message,
+            classification: AssistantClassification::Bug,
+            confidence: Some(40),
+            ..
+        }) = withhold_low_confidence(&reply(Some(40)), 70, "tag", &oncalls)
+        else {
+            panic!("Expected a tag of the oncall");
+        };
+        assert!(message.starts_with("@horse-oncall: ") && !message.contains("DNS"));
+        assert!(matches!(withhold_low_confidence(&reply(Some(40)), 70, "silent", &oncalls), Some(AssistantResponse::NoAction)));
+    }
+
+    #[test]
+    fn test_reduce_to_classification() {
+        let reply = || AssistantResponse::ReplyToThread {
+            thread_ts: "1.0".to_string(),
+            classification: AssistantClassification::Incident,
+            message: "It is probably DNS.".to_string(),
+            confidence: Some(90),
+            sources: vec![Source {
+                title: "DNS".to_string(),
+                url: "https://example.com/dns".to_string(),
+            }],
+        };
+        let message = |response| match response {
+            AssistantResponse::ReplyToThread {
+                classification: AssistantClassification::Incident,
+                message,
+                sources,
+                ..
+            } => {
+                assert!(sources.is_empty() || message == "It is probably DNS.");
+                message
+            }
+            _ => panic!("Expected a reply with the classification"),
+        };
+        let oncalls = vec!["@horse-oncall".to_string()];
+
+        // Full channels get the answer.
+        assert_eq!(message(reduce_to_classification(reply(), ChannelMode::Full, &oncalls)), "It is probably DNS.");
+
+        // Others get a tag of the oncall (if there is one), or nothing but the reaction.
+        assert_eq!(message(reduce_to_classification(reply(), ChannelMode::ClassifyOnly, &oncalls)), "@horse-oncall: could you take a look?");
+        assert_eq!(message(reduce_to_classification(reply(), ChannelMode::ClassifyOnly, &[])), "");
+        assert_eq!(message(reduce_to_classification(reply(), ChannelMode::Silent, &oncalls)), "");
+
+        // Other responses are unchanged.
+        assert!(matches!(
+            reduce_to_classification(AssistantResponse::NoAction, ChannelMode::Silent, &oncalls),
+            AssistantResponse::NoAction
+        ));
+    }
+
+    #[test]
+    fn test_render_streaming_preview() {
+        assert_eq!(render_streaming_preview("Try restarting ", 3000), "Try restarting…");
+        assert_eq!(render_streaming_preview(&"é".repeat(20), 10), format!("{}…", "é".repeat(9)));
+    }
+
+    #[test]
+    fn test_render_reply_blocks_long_message() {
+        let message = "a".repeat(SECTION_TEXT_LIMIT + 10);
+
+        let blocks = render_reply_blocks(&AssistantClassification::Other, "grey_question", &message);
+
+        assert_eq!(blocks.len(), 3);
+        assert_eq!(blocks[1]["text"]["text"].as_str().unwrap().len(), SECTION_TEXT_LIMIT);
+        assert_eq!(blocks[2]["text"]["text"].as_str().unwrap().len(), 10);
+    }
+}
@@ -0,0 +1,397 @@
+//! This module handles the `/triage` slash command.
+
+use std::collections::BTreeMap;
+
+use tracing::{info, instrument};
+
+use crate::{
+    base::{
+        config::Config,
+        types::{ChannelMode, Res, TokenUsage},
+    },
+    service::db::{Channel, DbClient, LlmContext, Message},
+};
+
+/// Usage help for the `/triage` command.
+pub const TRIAGE_COMMAND_USAGE: &str = "*Usage:* `/triage <subcommand>`\n\
+    • `directive`: show the current channel directive\n\
+    • `context`: list the stored channel contexts\n\
+    • `pause`: stop the bot from responding in this channel\n\
+    • `resume`: let the bot respond in this channel again\n\
+    • `edits <on|off|minutes>`: re-evaluate messages that are edited after the bot replied (`on` uses the default window)\n\
+    • `moderation <on|off|default>`: check my replies before they are posted\n\
+    • `confidence <0-100|default>`: withhold my answers when I am less confident than this\n\
+    • `language <language|auto>`: always reply in this language (`auto` replies in each message's language)\n\
+    • `refusal <reply|off|default>`: what I post when I can't help with a message (`off` only reacts)\n\
+    • `mode <full|classify_only|silent>`: how much I do with messages (`classify_only` only classifies them, and tags the oncall)\n\
+    • `websearch <on|off|default>`: search the web for what messages ask about\n\
+    • `status`: report the bot's status in this channel";
+
+/// How many days of activity `/triage status` reports on.
+const STATUS_WINDOW_DAYS: i64 = 7;
+
+/// The subcommands of the `/triage` command.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TriageCommand {
+    /// Show the current channel directive.
+    Directive,
+    /// List the stored channel contexts.
+    Context,
+    /// Pause the bot in the channel.
+    Pause,
+    /// Resume the bot in the channel.
+    Resume,
+    /// Set the channel's edit re-evaluation window, in seconds (`None` uses the configured default, and zero disables it).
+    Edits(Option<u64>),
+    /// Set whether the channel's replies are moderated (`None` uses the configured default).
+    Moderation(Option<bool>),
+    /// Set the channel's reply confidence threshold (`None` uses the configured default, and zero posts every answer).
+    Confidence(Option<u8>),
+    /// Set the language that the channel's replies are always in (`None` replies in each message's detected language).
+    Language(Option<String>),
+    /// Set what is posted in the channel when the model refuses to respond (`None` uses the configured default, and an
+    /// empty reply only reacts).
+    Refusal(Option<String>),
+    /// Set how much the bot does with the channel's messages.
+    Mode(ChannelMode),
+    /// Set whether the channel's messages are searched for on the web (`None` uses the configured default).  This can
+    /// only turn web search off: when it is turned off for the deployment, it stays off in every channel.
+    WebSearch(Option<bool>),
+    /// Report the bot's status in the channel.
+    Status,
+    /// Show usage help (for `help`, no subcommand, or an unknown subcommand).
+    Help,
+}
+
+impl TriageCommand {
+    /// Parse the subcommand from the text of the command (e.g., `pause`).
+    pub fn parse(text: &str) -> Self {
+        let mut words = text.split_whitespace().map(str::to_lowercase);
+
+        match words.next().unwrap_or_default().as_str() {
+            "directive" => Self::Directive,
+            "context" => Self::Context,
+            "pause" => Self::Pause,
+            "resume" => Self::Resume,
+            "status" => Self::Status,
+            "edits" => match words.next().as_deref() {
+                Some("on") => Self::Edits(None),
+                Some("off") => Self::Edits(Some(0)),
+                Some(minutes) => minutes.parse::<u64>().map(|m| Self::Edits(Some(m * 60))).unwrap_or(Self::Help),
+                None => Self::Help,
+            },
+            "moderation" => match words.next().as_deref() {
+                Some("on") => Self::Moderation(Some(true)),
+                Some("off") => Self::Moderation(Some(false)),
+                Some("default") => Self::Moderation(None),
+                _ => Self::Help,
+            },
+            "confidence" => match words.next().as_deref() {
+                Some("default") => Self::Confidence(None),
+                Some(threshold) => threshold.parse::<u8>().ok().filter(|t| *t <= 100).map(|t| Self::Confidence(Some(t))).unwrap_or(Self::Help),
+                None => Self::Help,
+            },
+            // Language names keep their case (e.g., `Brazilian Portuguese`), since they are given to the assistant.
+            "language" => match text.split_whitespace().skip(1).collect::<Vec<_>>().join(" ") {
+                language if language.is_empty() => Self::Help,
+                language if language.eq_ignore_ascii_case("auto") => Self::Language(None),
+                language => Self::Language(Some(language)),
+            },
+            // Likewise, the reply is posted as it is written.
+            "refusal" => match text.split_whitespace().skip(1).collect::<Vec<_>>().join(" ") {
+                reply if reply.is_empty() => Self::Help,
+                reply if reply.eq_ignore_ascii_case("default") => Self::Refusal(None),
+                reply if reply.eq_ignore_ascii_case("off") => Self::Refusal(Some(String::new())),
+                reply => Self::Refusal(Some(reply)),
+            },
+            "mode" => words.next().as_deref().and_then(ChannelMode::parse).map(Self::Mode).unwrap_or(Self::Help),
+            "websearch" => match words.next().as_deref() {
+                Some("on") => Self::WebSearch(Some(true)),
+                Some("off") => Self::WebSearch(Some(false)),
+                Some("default") => Self::WebSearch(None),
+                _ => Self::Help,
+            },
+            _ => Self::Help,
+        }
+    }
+}
+
+/// Handles the `/triage` command.
+///
+/// Returns the text of the (ephemeral) response to the user.  Unlike the event handlers, this runs inline,
+/// since the response is part of the command's acknowledgement.
+#[instrument(skip(config, db))]
+pub async fn handle_triage_command<L, C, M>(text: &str, channel_id: &str, config: &Config, db: &DbClient<L, C, M>) -> Res<String>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let command = TriageCommand::parse(text);
+
+    info!("Handling `/triage` command: {:?} ...", command);
+
+    let response = match command {
+        TriageCommand::Directive => match db.get_channel_directive(channel_id).await?.filter(|directive| !directive.your_notes().is_empty()) {
+            None => "No channel directive has been set.  @-mention me, and ask me to update the channel directive.".to_string(),
+            Some(directive) => {
+                let requested = directive.user_message().get("text").and_then(|t| t.as_str()).unwrap_or_default();
+
+                format!("*Channel directive*\n\n{}\n\n*Requested as*\n\n> {}", directive.your_notes(), requested)
+            }
+        },
+        TriageCommand::Context => {
+            let contexts: Vec<L> = serde_json::from_str(&db.get_channel_context(channel_id).await?)?;
+
+            if contexts.is_empty() {
+                "No channel context has been stored.".to_string()
+            } else {
+                let items = contexts.iter().map(|c| format!("• {}", c.your_notes())).collect::<Vec<_>>().join("\n");
+
+                format!("*Channel context* ({} stored)\n\n{}", contexts.len(), items)
+            }
+        }
+        TriageCommand::Pause => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_paused(channel_id, true).await?;
+
+            "Paused: I will keep reading this channel, but I won't respond until `/triage resume`.".to_string()
+        }
+        TriageCommand::Resume => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_paused(channel_id, false).await?;
+
+            "Resumed: I will respond in this channel again.".to_string()
+        }
+        TriageCommand::Edits(window_seconds) => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_edit_window(channel_id, window_seconds).await?;
+
+            match window_seconds {
+                None => "Edits: I will re-evaluate messages that are edited shortly after I reply (using the default window).".to_string(),
+                Some(0) => "Edits: I will no longer re-evaluate edited messages in this channel.".to_string(),
+                Some(seconds) => format!("Edits: I will re-evaluate messages that are edited within {} minutes of posting.", seconds / 60),
+            }
+        }
+        TriageCommand::Moderation(moderation_enabled) => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_moderation(channel_id, moderation_enabled).await?;
+
+            match moderation_enabled {
+                None => "Moderation: I will check my replies in this channel if moderation is enabled by default.".to_string(),
+                Some(true) => "Moderation: I will check my replies before posting them, and flag any that fail for a human.".to_string(),
+                Some(false) => "Moderation: I will no longer check my replies in this channel.".to_string(),
+            }
+        }
+        TriageCommand::Confidence(confidence_threshold) => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_confidence_threshold(channel_id, confidence_threshold).await?;
+
+            match confidence_threshold {
+                None => "Confidence: I will use the default confidence threshold in this channel.".to_string(),
+                Some(0) => "Confidence: I will post every answer in this channel.".to_string(),
+                Some(threshold) => format!("Confidence: I will withhold answers that I am less than {threshold}% confident in."),
+            }
+        }
+        TriageCommand::Language(force_language) => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_force_language(channel_id, force_language.as_deref()).await?;
+
+            match force_language {
+                None => "Language: I will reply in the language of each message in this channel.".to_string(),
+                Some(language) => format!("Language: I will always reply in {language} in this channel."),
+            }
+        }
+        TriageCommand::Refusal(refusal_reply) => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_refusal_reply(channel_id, refusal_reply.as_deref()).await?;
+
+            match refusal_reply.as_deref() {
+                None => "Refusals: I will post the default reply when I can't help with a message in this channel.".to_string(),
+                Some("") => "Refusals: I will only react when I can't help with a message in this channel.".to_string(),
+                Some(reply) => format!("Refusals: I will reply \"{reply}\" when I can't help with a message in this channel."),
+            }
+        }
+        TriageCommand::Mode(mode) => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_mode(channel_id, mode).await?;
+
+            match mode {
+                ChannelMode::Full => "Mode: I will triage and answer messages in this channel.".to_string(),
+                ChannelMode::ClassifyOnly => "Mode: I will only classify messages in this channel, and tag the oncall (@-mention me for more).".to_string(),
+                ChannelMode::Silent => "Mode: I will only classify messages in this channel, with a reaction (@-mention me for more).".to_string(),
+            }
+        }
+        TriageCommand::WebSearch(web_search_enabled) => {
+            db.get_or_create_channel(channel_id).await?;
+            db.set_channel_web_search(channel_id, web_search_enabled).await?;
+
+            match web_search_enabled {
+                Some(false) => "Web search: I will no longer send this channel's messages to web search.".to_string(),
+                _ if !config.web_search_enabled => "Web search: web search is turned off for the whole deployment, so I will still not search the web for this channel's messages.".to_string(),
+                None => "Web search: I will search the web for this channel's messages, as by default.".to_string(),
+                Some(true) => "Web search: I will search the web for what this channel's messages ask about.".to_string(),
+            }
+        }
+        TriageCommand::Status => {
+            let channel = db.get_or_create_channel(channel_id).await?;
+            let since = chrono::Utc::now().timestamp() - STATUS_WINDOW_DAYS * 24 * 60 * 60;
+            let stats = db.get_channel_stats(channel_id, since).await?;
+            let feedback_count = db.get_channel_feedback(channel_id).await?.len();
+
+            format!(
+                "*Status:* {}\n• Stored messages: {}\n• Stored contexts: {}\n• Messages in the last {} days: {}\n• Responses in the last {} days: {}{}\n• Tokens in the last {} days: {}\n• Feedback received: {}\n• Directive set: {}\n• Incidents broadcast: {}\n• Edits re-evaluated: {}\n• Replies moderated: {}\n• Confidence threshold: {}\n• Language: {}\n• Refusal reply: {}\n• Mode: {}\n• Web search: {}",
+                if !channel.is_active() {
+                    "archived"
+                } else if channel.is_paused() {
+                    "paused"
+                } else {
+                    "active"
+                },
+                stats.messages,
+                stats.contexts,
+                STATUS_WINDOW_DAYS,
+                stats.messages_since,
+                STATUS_WINDOW_DAYS,
+                stats.responses_since,
+                format_classifications(&stats.classifications),
+                STATUS_WINDOW_DAYS,
+                format_usage(&stats.usage),
+                feedback_count,
+                if channel.channel_directive().your_notes().is_empty() { "no" } else { "yes" },
+                if channel.broadcasts_incidents() { "yes" } else { "no" },
+                match channel.edit_window_seconds() {
+                    None => "default".to_string(),
+                    Some(0) => "no".to_string(),
+                    Some(seconds) => format!("within {} minutes", seconds / 60),
+                },
+                match channel.moderation_enabled() {
+                    None => "default",
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                },
+                match channel.confidence_threshold() {
+                    None => "default".to_string(),
+                    Some(threshold) => format!("{threshold}%"),
+                },
+                channel.force_language().unwrap_or("auto"),
+                match channel.refusal_reply() {
+                    None => "default",
+                    Some("") => "off",
+                    Some(_) => "custom",
+                },
+                channel.mode().label(),
+                match channel.web_search_enabled() {
+                    _ if !config.web_search_enabled => "off for the deployment",
+                    None => "default",
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                },
+            )
+        }
+        TriageCommand::Help => TRIAGE_COMMAND_USAGE.to_string(),
+    };
+
+    Ok(response)
+}
+
+// Helpers.
+
+/// Format a breakdown of replies by classification (e.g., ` (Bug: 2, Question: 1)`), or nothing if there is none.
+fn format_classifications(classifications: &BTreeMap<String, u64>) -> String {
+    if classifications.is_empty() {
+        return String::new();
+    }
+
+    let breakdown = classifications
+        .iter()
+        .map(|(classification, count)| format!("{classification}: {count}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+
+    format!(" ({breakdown})")
+}
+
+/// Format token usage (e.g., `12.3k in, 1.2k out (~$0.05)`), leaving out the cost if no model has a price.
+fn format_usage(usage: &TokenUsage) -> String {
+    let tokens = |count: u64| if count >= 1000 { format!("{:.1}k", count as f64 / 1000.0) } else { count.to_string() };
+    let cost = if usage.cost_usd > 0.0 { format!(" (~${:.2})", usage.cost_usd) } else { String::new() };
+
+    format!("{} in, {} out{}", tokens(usage.input_tokens), tokens(usage.output_tokens), cost)
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_triage_command() {
+        assert_eq!(TriageCommand::parse("directive"), TriageCommand::Directive);
+        assert_eq!(TriageCommand::parse("  Context "), TriageCommand::Context);
+        assert_eq!(TriageCommand::parse("pause now"), TriageCommand::Pause);
+        assert_eq!(TriageCommand::parse("resume"), TriageCommand::Resume);
+        assert_eq!(TriageCommand::parse("status"), TriageCommand::Status);
+        assert_eq!(TriageCommand::parse("edits on"), TriageCommand::Edits(None));
+        assert_eq!(TriageCommand::parse("Edits OFF"), TriageCommand::Edits(Some(0)));
+        assert_eq!(TriageCommand::parse("edits 10"), TriageCommand::Edits(Some(600)));
+        assert_eq!(TriageCommand::parse("edits"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("edits soon"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("moderation on"), TriageCommand::Moderation(Some(true)));
+        assert_eq!(TriageCommand::parse("Moderation Off"), TriageCommand::Moderation(Some(false)));
+        assert_eq!(TriageCommand::parse("moderation default"), TriageCommand::Moderation(None));
+        assert_eq!(TriageCommand::parse("moderation"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("confidence 60"), TriageCommand::Confidence(Some(60)));
+        assert_eq!(TriageCommand::parse("confidence default"), TriageCommand::Confidence(None));
+        assert_eq!(TriageCommand::parse("confidence 101"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("confidence high"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("language German"), TriageCommand::Language(Some("German".to_string())));
+        assert_eq!(
+            TriageCommand::parse("Language  Brazilian Portuguese "),
+            TriageCommand::Language(Some("Brazilian Portuguese".to_string()))
+        );
+        assert_eq!(TriageCommand::parse("language AUTO"), TriageCommand::Language(None));
+        assert_eq!(TriageCommand::parse("language"), TriageCommand::Help);
+        assert_eq!(
+            TriageCommand::parse("refusal Please ask in #security instead."),
+            TriageCommand::Refusal(Some("Please ask in #security instead.".to_string()))
+        );
+        assert_eq!(TriageCommand::parse("refusal OFF"), TriageCommand::Refusal(Some(String::new())));
+        assert_eq!(TriageCommand::parse("refusal default"), TriageCommand::Refusal(None));
+        assert_eq!(TriageCommand::parse("refusal"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("mode classify_only"), TriageCommand::Mode(ChannelMode::ClassifyOnly));
+        assert_eq!(TriageCommand::parse("Mode Silent"), TriageCommand::Mode(ChannelMode::Silent));
+        assert_eq!(TriageCommand::parse("mode full"), TriageCommand::Mode(ChannelMode::Full));
+        assert_eq!(TriageCommand::parse("mode loud"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("mode"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("websearch off"), TriageCommand::WebSearch(Some(false)));
+        assert_eq!(TriageCommand::parse("WebSearch On"), TriageCommand::WebSearch(Some(true)));
+        assert_eq!(TriageCommand::parse("websearch default"), TriageCommand::WebSearch(None));
+        assert_eq!(TriageCommand::parse("websearch"), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse(""), TriageCommand::Help);
+        assert_eq!(TriageCommand::parse("frobnicate"), TriageCommand::Help);
+    }
+
+    #[test]
+    fn test_format_classifications() {
+        let classifications = BTreeMap::from([("Question".to_string(), 1), ("Bug".to_string(), 2)]);
+
+        assert_eq!(format_classifications(&classifications), " (Bug: 2, Question: 1)");
+        assert_eq!(format_classifications(&BTreeMap::new()), "");
+    }
+
+    #[test]
+    fn test_format_usage() {
+        let usage = TokenUsage {
+            requests: 3,
+            input_tokens: 12_345,
+            output_tokens: 678,
+            reasoning_tokens: 0,
+            cost_usd: 0.0321,
+        };
+
+        assert_eq!(format_usage(&usage), "12.3k in, 678 out (~$0.03)");
+        assert_eq!(format_usage(&TokenUsage::default()), "0 in, 0 out");
+    }
+}
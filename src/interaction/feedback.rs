@@ -0,0 +1,95 @@
+//! This module handles user feedback on the bot's replies.
+
+use tracing::{Instrument, Span, error, info, instrument, warn};
+
+use crate::{
+    base::types::Void,
+    service::db::{Channel, DbClient, Feedback, LlmContext, Message},
+};
+
+/// Reactions that are treated as feedback on the bot's replies.
+pub const FEEDBACK_REACTIONS: &[&str] = &["+1", "thumbsup", "-1", "thumbsdown"];
+
+/// Check whether a reaction counts as feedback (ignoring skin tone modifiers, e.g., `+1::skin-tone-2`).
+pub fn is_feedback_reaction(emoji: &str) -> bool {
+    let base = emoji.split("::").next().unwrap_or_default();
+
+    FEEDBACK_REACTIONS.contains(&base)
+}
+
+/// Handles the feedback event.
+///
+/// This function is responsible for storing a reaction on one of the bot's replies as feedback.
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_feedback<L, C, M>(channel_id: String, message_ts: String, user_id: String, emoji: String, db: DbClient<L, C, M>)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = record_feedback(channel_id, message_ts, user_id, emoji, &db).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Records feedback on one of the bot's replies.
+///
+/// Shared by reactions and the interactive buttons on the bot's replies.
+#[instrument(skip_all)]
+pub async fn record_feedback<L, C, M>(channel_id: String, message_ts: String, user_id: String, emoji: String, db: &DbClient<L, C, M>) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    // Look up the bot's reply, so that the feedback can be tied to its thread and classification.
+    let Some(message) = db.get_channel_message(&channel_id, &message_ts).await? else {
+        warn!("Skipping feedback because the reacted message `{}` is not stored.", message_ts);
+        return Ok(());
+    };
+
+    let raw = message.raw();
+    let thread_ts = raw.get("thread_ts").and_then(|v| v.as_str()).unwrap_or(&message_ts).to_string();
+    let classification = raw.get("classification").and_then(|v| v.as_str()).map(str::to_string);
+
+    let feedback = Feedback {
+        thread_ts,
+        message_ts,
+        user_id,
+        emoji,
+        classification,
+    };
+
+    db.add_feedback(&channel_id, &feedback).await?;
+
+    info!("Stored `{}` feedback from `{}`.", feedback.emoji, feedback.user_id);
+
+    Ok(())
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_is_feedback_reaction() {
+        assert!(is_feedback_reaction("+1"));
+        assert!(is_feedback_reaction("-1"));
+        assert!(is_feedback_reaction("thumbsup"));
+        assert!(is_feedback_reaction("+1::skin-tone-3"));
+        assert!(!is_feedback_reaction("eyes"));
+        assert!(!is_feedback_reaction("+100"));
+    }
+}
@@ -0,0 +1,134 @@
+//! This module extracts the text from files (and snippets) attached to messages.
+
+use serde_json::Value;
+use tracing::{instrument, warn};
+
+use crate::{
+    base::types::AssistantImage,
+    service::chat::{ChatClient, is_text_mime_type},
+};
+
+/// Append the text of any attached files to the message's `text`, so that both the assistant and the full-text index see it.
+///
+/// Text files (and snippets) are downloaded through the chat client (which truncates them), and images are noted by name.
+/// Other files, and failed downloads, are skipped.  Returns whether the message was changed.
+#[instrument(skip_all)]
+pub async fn append_file_contents(message: &mut Value, chat: &ChatClient) -> bool {
+    let Some(files) = message.get("files").and_then(Value::as_array) else {
+        return false;
+    };
+
+    let mut sections = Vec::new();
+
+    for file in files {
+        let name = file.get("name").or_else(|| file.get("title")).and_then(Value::as_str).unwrap_or("unnamed");
+
+        match file_kind(file) {
+            FileKind::Image => sections.push(format!("[User attached an image named `{name}`.]")),
+            FileKind::Text => {
+                let Some(url) = file.get("url_private_download").or_else(|| file.get("url_private")).and_then(Value::as_str) else {
+                    continue;
+                };
+
+                match chat.get_file_text(url).await {
+                    Ok(content) => sections.push(format!("[User attached a file named `{name}`:]\n```\n{content}\n```")),
+                    Err(err) => warn!("Failed to download attached file `{}`: {}", name, err),
+                }
+            }
+            FileKind::Other => {}
+        }
+    }
+
+    if sections.is_empty() {
+        return false;
+    }
+
+    let text = message.get("text").and_then(Value::as_str).unwrap_or_default();
+    let text = std::iter::once(text.to_string()).filter(|t| !t.is_empty()).chain(sections).collect::<Vec<_>>().join("\n\n");
+
+    message["text"] = Value::String(text);
+
+    true
+}
+
+/// Download the images attached to a message (e.g., screenshots), so that they can be shown to a model that can see them.
+///
+/// At most `max_count` images are downloaded, and larger images than `max_bytes` are skipped (as are failed downloads).
+/// A `max_count` of `0` disables downloading images.
+#[instrument(skip_all)]
+pub async fn download_images(message: &Value, max_count: usize, max_bytes: usize, chat: &ChatClient) -> Vec<AssistantImage> {
+    let Some(files) = message.get("files").and_then(Value::as_array) else {
+        return Vec::new();
+    };
+
+    let mut images = Vec::new();
+
+    for file in files.iter().filter(|file| file_kind(file) == FileKind::Image) {
+        if images.len() >= max_count {
+            break;
+        }
+
+        let name = file.get("name").or_else(|| file.get("title")).and_then(Value::as_str).unwrap_or("unnamed");
+
+        if file.get("size").and_then(Value::as_u64).is_some_and(|size| size > max_bytes as u64) {
+            warn!("Skipped attached image `{}`, since it is larger than {} bytes.", name, max_bytes);
+            continue;
+        }
+
+        let Some(url) = file.get("url_private_download").or_else(|| file.get("url_private")).and_then(Value::as_str) else {
+            continue;
+        };
+
+        match chat.get_file_bytes(url, max_bytes).await {
+            Ok(data) => images.push(AssistantImage {
+                name: name.to_string(),
+                mime_type: file.get("mimetype").and_then(Value::as_str).unwrap_or_default().to_string(),
+                data,
+            }),
+            Err(err) => warn!("Failed to download attached image `{}`: {}", name, err),
+        }
+    }
+
+    images
+}
+
+// Helpers.
+
+/// The kinds of attached files that are handled differently.
+#[derive(Debug, PartialEq, Eq)]
+enum FileKind {
+    Text,
+    Image,
+    Other,
+}
+
+/// Classify an attached file by its MIME type.
+fn file_kind(file: &Value) -> FileKind {
+    let mimetype = file.get("mimetype").and_then(Value::as_str).unwrap_or_default();
+
+    if is_text_mime_type(mimetype) {
+        FileKind::Text
+    } else if mimetype.starts_with("image/") {
+        FileKind::Image
+    } else {
+        FileKind::Other
+    }
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_file_kind() {
+        assert_eq!(file_kind(&json!({ "mimetype": "text/plain" })), FileKind::Text);
+        assert_eq!(file_kind(&json!({ "mimetype": "application/json" })), FileKind::Text);
+        assert_eq!(file_kind(&json!({ "mimetype": "image/png" })), FileKind::Image);
+        assert_eq!(file_kind(&json!({ "mimetype": "application/pdf" })), FileKind::Other);
+        assert_eq!(file_kind(&json!({})), FileKind::Other);
+    }
+}
@@ -0,0 +1,100 @@
+//! This module handles backfilling the message history of a channel.
+
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde_json::Value;
+use tracing::{Instrument, Span, error, info, instrument};
+
+use crate::{
+    base::types::Res,
+    interaction::file_attachment,
+    service::{
+        chat::ChatClient,
+        db::{Channel, DbClient, LlmContext, Message},
+    },
+};
+
+/// How often (in prepared messages) to report backfill progress.
+const PROGRESS_INTERVAL: usize = 100;
+
+/// Handles the history backfill for a channel (e.g., when the bot is invited to an established channel).
+///
+/// This function is responsible for storing the last `days` of channel history, so that message search is useful right away.
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_history_backfill<L, C, M>(channel_id: String, days: u32, db: DbClient<L, C, M>, chat: ChatClient)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    if days == 0 {
+        return;
+    }
+
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = backfill_channel_history(&channel_id, days, &db, &chat).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Backfill the last `days` of channel history into the database.
+///
+/// Messages that are already stored (matched by `ts`), or that have no text, are skipped, so this is safe to re-run.
+/// Returns the number of messages stored.
+#[instrument(skip(db, chat))]
+pub async fn backfill_channel_history<L, C, M>(channel_id: &str, days: u32, db: &DbClient<L, C, M>, chat: &ChatClient) -> Res<usize>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_secs();
+    let oldest_ts = format!("{}.000000", now.saturating_sub(u64::from(days) * 24 * 60 * 60));
+
+    info!("Backfilling the last {} days of history for channel `{}` ...", days, channel_id);
+
+    db.get_or_create_channel(channel_id).await?;
+
+    let messages = chat.get_channel_history(channel_id, &oldest_ts).await?;
+    let total = messages.len();
+
+    let mut batch = Vec::new();
+    for mut message in messages {
+        let Some(ts) = message.get("ts").and_then(Value::as_str).map(str::to_string) else {
+            continue;
+        };
+
+        if message.get("text").and_then(Value::as_str).is_none() || db.get_channel_message(channel_id, &ts).await?.is_some() {
+            continue;
+        }
+
+        // History messages don't carry their channel, so add it for consistency with live messages.
+        if let Some(map) = message.as_object_mut() {
+            map.entry("channel").or_insert_with(|| Value::String(channel_id.to_string()));
+        }
+
+        file_attachment::append_file_contents(&mut message, chat).await;
+
+        batch.push(message);
+
+        if batch.len() % PROGRESS_INTERVAL == 0 {
+            info!("Prepared {} messages for channel `{}` so far ...", batch.len(), channel_id);
+        }
+    }
+
+    // The messages are stored in bulk, rather than one transaction per message.
+    let stored = db.add_channel_messages(channel_id, &batch).await? as usize;
+
+    info!("Backfilled {} of {} messages for channel `{}`.", stored, total, channel_id);
+
+    Ok(stored)
+}
@@ -0,0 +1,138 @@
+//! This module handles unfurling links that are shared in a channel.
+
+use std::time::Duration;
+
+use tracing::{Instrument, Span, error, info, instrument, warn};
+
+use crate::{
+    base::types::{LinkSummaryContext, Res, Void},
+    service::{chat::ChatClient, llm::LlmClient},
+};
+
+// Statics.
+
+/// Maximum number of characters of page content to send to the link summary agent.
+const MAX_PAGE_CONTENT_CHARS: usize = 20_000;
+
+/// Timeout for fetching the page behind a link.
+const FETCH_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Handles the link shared event.
+///
+/// This function is responsible for summarizing each (already allowlisted) link, and attaching the summary as an unfurl.
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_link_shared(channel_id: String, message_ts: String, urls: Vec<String>, llm: LlmClient, chat: ChatClient) {
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = handle_link_shared_internal(channel_id, message_ts, urls, &llm, &chat).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Internal function to handle the link shared event.
+#[instrument(skip_all)]
+async fn handle_link_shared_internal(channel_id: String, message_ts: String, urls: Vec<String>, llm: &LlmClient, chat: &ChatClient) -> Void {
+    for url in urls {
+        // Unfurl failures are never fatal: the message is simply left as-is.
+        if let Err(err) = unfurl_link(&channel_id, &message_ts, &url, llm, chat).await {
+            warn!("Failed to unfurl `{}`: {}", url, err);
+        }
+    }
+
+    Ok(())
+}
+
+/// Fetch, summarize, and unfurl a single link.
+#[instrument(skip(llm, chat))]
+async fn unfurl_link(channel_id: &str, message_ts: &str, url: &str, llm: &LlmClient, chat: &ChatClient) -> Void {
+    let content = fetch_page_content(url).await?;
+
+    if content.is_empty() {
+        info!("Skipping unfurl because the page has no readable content.");
+        return Ok(());
+    }
+
+    let context = LinkSummaryContext { url: url.to_string(), content };
+    let summary = llm.get_link_summary_agent_response(context).await?;
+
+    if summary.trim().is_empty() {
+        info!("Skipping unfurl because the summary is empty.");
+        return Ok(());
+    }
+
+    chat.unfurl_link(channel_id, message_ts, url, summary.trim()).await?;
+
+    Ok(())
+}
+
+/// Fetch the page behind a link, and reduce it to (truncated) plain text.
+#[instrument]
+async fn fetch_page_content(url: &str) -> Res<String> {
+    let client = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build()?;
+    let response = client.get(url).send().await?.error_for_status()?;
+    let body = response.text().await?;
+
+    Ok(html_to_text(&body).chars().take(MAX_PAGE_CONTENT_CHARS).collect())
+}
+
+// Helpers.
+
+/// Strip the markup from an HTML document, keeping the visible text (collapsed whitespace).
+///
+/// This is intentionally crude: the result only needs to be good enough for the summary agent.
+pub fn html_to_text(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut rest = html;
+
+    while let Some(start) = rest.find('<') {
+        text.push_str(&rest[..start]);
+        text.push(' ');
+
+        let tag = &rest[start..];
+        let lower = tag.get(..8).unwrap_or(tag).to_ascii_lowercase();
+
+        // Skip the contents of script and style elements entirely.
+        let close = if lower.starts_with("<script") {
+            "</script>"
+        } else if lower.starts_with("<style") {
+            "</style>"
+        } else {
+            ">"
+        };
+
+        match tag.to_ascii_lowercase().find(close) {
+            Some(end) => rest = &tag[end + close.len()..],
+            None => {
+                rest = "";
+                break;
+            }
+        }
+    }
+    text.push_str(rest);
+
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_html_to_text() {
+        let html = "<html><head><style>body { color: red; }</style><script>alert('hi');</script></head><body><h1>Title</h1>\n<p>Some   <b>bold</b> text.</p></body></html>";
+
+        assert_eq!(html_to_text(html), "Title Some bold text.");
+        assert_eq!(html_to_text("plain text"), "plain text");
+        assert_eq!(html_to_text("unterminated <tag"), "unterminated");
+    }
+}
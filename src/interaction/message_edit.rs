@@ -0,0 +1,150 @@
+//! This module re-evaluates messages that are edited shortly after the bot replied to them.
+//!
+//! Users often post a short message (e.g., "help"), and then edit it to add the actual error.  Since the bot
+//! already replied to the first version, the edit is re-run through the chat event handler as a revision.
+
+use serde_json::Value;
+use tracing::{Instrument, Span, error, info, instrument};
+
+use crate::{
+    base::{config::Config, types::Void},
+    interaction::chat_event,
+    service::{
+        chat::ChatClient,
+        db::{Channel, DbClient, LlmContext, Message},
+        llm::LlmClient,
+        mcp::McpClient,
+    },
+};
+
+// Statics.
+
+/// The note appended to an edited message, so that the assistant knows that it is a revision.
+const REVISION_NOTE: &str = "[The user edited this message after you replied to it.  If the edit changes your answer, reply with an updated answer; otherwise, take no action.]";
+
+/// Handles a message being edited (e.g., a Slack `message_changed` event).
+///
+/// `message` is the edited message (with its `ts`, `user`, and `text`), and it is re-evaluated only if it was
+/// edited within the channel's window, and the bot already replied in its thread.  Edits to thread replies are
+/// not re-evaluated, since only top-level messages are their own thread roots.
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_message_edit<L, C, M>(message: Value, channel_id: String, config: Config, db: DbClient<L, C, M>, llm: LlmClient, chat: ChatClient, mcp: McpClient)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = handle_message_edit_internal(message, channel_id, config, db, llm, chat, mcp).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Internal function to handle a message being edited.
+#[instrument(skip_all)]
+async fn handle_message_edit_internal<L, C, M>(mut message: Value, channel_id: String, config: Config, db: DbClient<L, C, M>, llm: LlmClient, chat: ChatClient, mcp: McpClient) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let Some(ts) = message.get("ts").and_then(Value::as_str).map(str::to_string) else {
+        return Ok(());
+    };
+
+    // Guard against loops: the bot's own edits (e.g., a placeholder becoming the answer) are never re-evaluated.
+    let is_own_message = message.get("user").and_then(Value::as_str) == Some(chat.bot_user_id()) || message.get("bot_id").is_some();
+    if is_own_message {
+        return Ok(());
+    }
+
+    let channel = db.get_or_create_channel(&channel_id).await?;
+    let window_seconds = channel.edit_window_seconds().unwrap_or(config.slack_edit_reevaluation_window_seconds);
+
+    if !is_within_edit_window(&ts, chrono::Utc::now().timestamp(), window_seconds) {
+        return Ok(());
+    }
+
+    let thread_context = chat_event::get_thread_context(&channel_id, &ts, &db, &chat).await?;
+    if !bot_replied_in_thread(&thread_context, &ts, chat.bot_user_id()) {
+        return Ok(());
+    }
+
+    info!("Re-evaluating edited message `{}` in channel `{}` ...", ts, channel_id);
+
+    // Let the assistant know that this is a revision (it may post an updated answer, or take no action).
+    let text = message.get("text").and_then(Value::as_str).unwrap_or_default();
+    message["text"] = Value::String(format!("{text}\n\n{REVISION_NOTE}"));
+    message["channel"] = Value::String(channel_id.clone());
+
+    chat_event::handle_chat_event(message, channel_id, ts, config, db, llm, chat, mcp);
+
+    Ok(())
+}
+
+// Helpers.
+
+/// Whether a message (by its `ts`) is still within the edit window at `now` (unix seconds).
+///
+/// A zero window disables re-evaluation.
+fn is_within_edit_window(ts: &str, now: i64, window_seconds: u64) -> bool {
+    let Some(posted_at) = ts.split('.').next().and_then(|seconds| seconds.parse::<i64>().ok()) else {
+        return false;
+    };
+
+    window_seconds > 0 && now - posted_at <= window_seconds as i64
+}
+
+/// Whether the bot replied in the thread rooted at `ts` (given the thread's messages as JSON).
+fn bot_replied_in_thread(thread_context: &str, ts: &str, bot_user_id: &str) -> bool {
+    let Ok(messages) = serde_json::from_str::<Vec<Value>>(thread_context) else {
+        return false;
+    };
+
+    messages
+        .iter()
+        .any(|m| m.get("user").and_then(Value::as_str) == Some(bot_user_id) && m.get("ts").and_then(Value::as_str) != Some(ts))
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_is_within_edit_window() {
+        assert!(is_within_edit_window("1700000000.000100", 1700000000, 300));
+        assert!(is_within_edit_window("1700000000.000100", 1700000300, 300));
+        assert!(!is_within_edit_window("1700000000.000100", 1700000301, 300));
+
+        // A zero window disables re-evaluation, and bad timestamps are never within the window.
+        assert!(!is_within_edit_window("1700000000.000100", 1700000000, 0));
+        assert!(!is_within_edit_window("not-a-ts", 1700000000, 300));
+    }
+
+    #[test]
+    fn test_bot_replied_in_thread() {
+        let thread = json!([
+            { "user": "U1", "ts": "1.0", "text": "help" },
+            { "user": "U12345", "ts": "2.0", "text": "What is the error?" },
+        ])
+        .to_string();
+        let unanswered = json!([{ "user": "U1", "ts": "1.0", "text": "help" }]).to_string();
+
+        assert!(bot_replied_in_thread(&thread, "1.0", "U12345"));
+        assert!(!bot_replied_in_thread(&unanswered, "1.0", "U12345"));
+        assert!(!bot_replied_in_thread("", "1.0", "U12345"));
+    }
+}
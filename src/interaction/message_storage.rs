@@ -1,19 +1,27 @@
 //! This module handles the storage of messages in the database.
 
 use serde::Serialize;
-use tracing::{Instrument, Span, error, instrument};
+use serde_json::Value;
+use tracing::{Instrument, Span, error, instrument, warn};
 
 use crate::{
-    base::types::Void,
-    service::db::{Channel, DbClient, LlmContext, Message},
+    base::{config::Config, types::Void},
+    interaction::file_attachment,
+    service::{
+        chat::ChatClient,
+        db::{Channel, DbClient, LlmContext, Message},
+        llm::LlmClient,
+    },
 };
 
 /// Handles the message storage event.
 ///
 /// This function is responsible for processing message storage events and storing them in the database.
+/// The text of any attached files is appended to the stored message, so that it is searchable, and the message is
+/// embedded for semantic search (if enabled).
 /// It spawns a new task to handle the event asynchronously.
 #[instrument(skip_all)]
-pub fn handle_message_storage<E, L, C, M>(event: E, channel_id: String, db: DbClient<L, C, M>)
+pub fn handle_message_storage<E, L, C, M>(event: E, channel_id: String, config: Config, db: DbClient<L, C, M>, llm: LlmClient, chat: ChatClient)
 where
     E: Serialize + Send + 'static,
     L: LlmContext,
@@ -23,7 +31,7 @@ where
     tokio::spawn(
         async move {
             // Process the event.
-            let result = handle_message_storage_internal(event, channel_id, &db).in_current_span().await;
+            let result = handle_message_storage_internal(event, channel_id, &config, &db, &llm, &chat).in_current_span().await;
 
             // Log any errors.
             if let Err(err) = &result {
@@ -36,17 +44,127 @@ where
 
 /// Internal function to handle the message storage event.
 #[instrument(skip_all)]
-async fn handle_message_storage_internal<E, L, C, M>(event: E, channel_id: String, db: &DbClient<L, C, M>) -> Void
+async fn handle_message_storage_internal<E, L, C, M>(event: E, channel_id: String, config: &Config, db: &DbClient<L, C, M>, llm: &LlmClient, chat: &ChatClient) -> Void
 where
     E: Serialize,
     L: LlmContext,
     C: Channel,
     M: Message,
 {
-    let message = serde_json::to_value(&event).unwrap();
+    let mut message = serde_json::to_value(&event).unwrap();
+    file_attachment::append_file_contents(&mut message, chat).await;
+
     let _ = db.get_or_create_channel(&channel_id).await?;
 
-    db.add_channel_message(&channel_id, &message).await?;
+    // Slack retries slow deliveries, so the message may already be stored (and embedded).
+    if !db.add_channel_message(&channel_id, &message).await? {
+        return Ok(());
+    }
+
+    // A message that fails to embed is still stored (and found by keyword search).
+    if config.semantic_search_enabled
+        && let Err(err) = embed_message(&channel_id, &message, db, llm).await
+    {
+        warn!("Failed to embed message in channel `{}`: {}", channel_id, err);
+    }
+
+    Ok(())
+}
+
+/// Embeds a stored message (matched by channel and `ts`), so that it can be found by semantic search.
+///
+/// Messages without any text (e.g., bare file shares) are not embedded.
+pub async fn embed_message<L, C, M>(channel_id: &str, message: &Value, db: &DbClient<L, C, M>, llm: &LlmClient) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let ts = message.get("ts").and_then(Value::as_str);
+    let text = message.get("text").and_then(Value::as_str).filter(|text| !text.trim().is_empty());
+
+    let (Some(ts), Some(text)) = (ts, text) else {
+        return Ok(());
+    };
+
+    let embedding = llm.get_embedding(text).await?;
+    db.set_channel_message_embedding(channel_id, ts, &embedding).await?;
+
+    Ok(())
+}
+
+/// Handles the message update event (e.g., a Slack `message_changed` event).
+///
+/// This function updates the stored message matched by channel and `ts`, so that edits don't create duplicates.
+/// As with a new message, the text of any attached files is appended, and the message is re-embedded (if enabled).
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_message_update<E, L, C, M>(event: E, channel_id: String, ts: String, config: Config, db: DbClient<L, C, M>, llm: LlmClient, chat: ChatClient)
+where
+    E: Serialize + Send + 'static,
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = update_message(event, &channel_id, &ts, &config, &db, &llm, &chat).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Updates a stored message (matched by channel and `ts`) with its edited version, and re-embeds it (if enabled).
+#[instrument(skip_all)]
+pub async fn update_message<E, L, C, M>(event: E, channel_id: &str, ts: &str, config: &Config, db: &DbClient<L, C, M>, llm: &LlmClient, chat: &ChatClient) -> Void
+where
+    E: Serialize,
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let mut message = serde_json::to_value(&event).unwrap();
+    file_attachment::append_file_contents(&mut message, chat).await;
+
+    db.update_channel_message(channel_id, ts, &message).await?;
+
+    // A message that fails to embed keeps its old embedding (and is still found by keyword search).
+    if config.semantic_search_enabled
+        && let Err(err) = embed_message(channel_id, &message, db, llm).await
+    {
+        warn!("Failed to embed message in channel `{}`: {}", channel_id, err);
+    }
 
     Ok(())
 }
+
+/// Handles the message deletion event (e.g., a Slack `message_deleted` event).
+///
+/// This function removes the stored message matched by channel and `ts`, so that it no longer surfaces in search.
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_message_deletion<L, C, M>(channel_id: String, ts: String, db: DbClient<L, C, M>)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            // Process the event.
+            let result = db.delete_channel_message(&channel_id, &ts).in_current_span().await;
+
+            // Log any errors.
+            if let Err(err) = &result {
+                error!("Error while handling: {}\n\n{}", err, err.backtrace());
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
@@ -2,8 +2,32 @@
 //!
 //! This module provides functionality for handling chat and message events:
 //! - Processing incoming messages and @-mentions
+//! - Initializing channels (and backfilling their history) when the bot joins them
+//! - Tracking channels that are archived, unarchived, or renamed
+//! - Handling the `/triage` slash command
 //! - Managing message storage and retrieval
+//! - Re-evaluating messages that are edited shortly after the bot replied
+//! - Extracting the text from attached files and snippets
+//! - Recording user feedback on the bot's replies (reactions and buttons)
+//! - Unfurling shared links with short summaries
+//! - Checking the bot's replies (moderation, and a denylist) before they are posted
+//! - Queueing (and retrying) replies that failed to send
+//! - Pruning stored messages that are older than the retention policy (and long-expired contexts)
+//! - Scheduling (and cancelling) reminders in threads
 //! - Coordinating responses between services (LLM, database, chat)
 
+pub mod channel_join;
+pub mod channel_lifecycle;
 pub mod chat_event;
+pub mod command;
+pub mod feedback;
+pub mod file_attachment;
+pub mod history_backfill;
+pub mod link_unfurl;
+pub mod message_edit;
 pub mod message_storage;
+pub mod moderation;
+pub mod outbox;
+pub mod reminder;
+pub mod reply_action;
+pub mod retention;
@@ -0,0 +1,162 @@
+//! This module queues outbound messages that failed to send, and retries them in the background.
+
+use std::time::Duration;
+
+use serde_json::Value;
+use tracing::{info, instrument, warn};
+
+use crate::{
+    base::types::{Res, Void},
+    service::{
+        chat::{ChatClient, MessageOptions},
+        db::{Channel, DbClient, LlmContext, Message, PendingMessage},
+    },
+};
+
+// Statics.
+
+/// How often the outbox is checked for messages that are due for a retry.
+const OUTBOX_POLL_INTERVAL: Duration = Duration::from_secs(15);
+
+/// The delay before the first retry (doubled for each failed attempt).
+const OUTBOX_BASE_BACKOFF_SECONDS: i64 = 30;
+
+/// The longest delay between retries.
+const OUTBOX_MAX_BACKOFF_SECONDS: i64 = 15 * 60;
+
+/// Send a threaded message, or queue it in the outbox if the send fails.
+///
+/// Returns the timestamp of the posted message, or `None` if it was queued instead.
+#[instrument(skip_all)]
+pub async fn send_or_queue<L, C, M>(channel_id: &str, thread_ts: &str, text: &str, blocks: &[Value], options: &MessageOptions, chat: &ChatClient, db: &DbClient<L, C, M>) -> Res<Option<String>>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let err = match chat.send_blocks(channel_id, thread_ts, text, blocks, options).await {
+        Ok(ts) => return Ok(Some(ts)),
+        Err(err) => err,
+    };
+
+    warn!("Failed to send message to channel `{}`, queueing it for a retry: {}", channel_id, err);
+
+    let now = chrono::Utc::now().timestamp();
+    let message = PendingMessage {
+        id: None,
+        bot_user_id: chat.bot_user_id().to_string(),
+        channel_id: channel_id.to_string(),
+        thread_ts: thread_ts.to_string(),
+        text: text.to_string(),
+        blocks: blocks.to_vec(),
+        reply_broadcast: options.reply_broadcast,
+        created_at: now,
+        attempts: 1,
+        next_attempt_at: now + backoff_seconds(1),
+    };
+
+    db.add_pending_message(&message).await?;
+
+    Ok(None)
+}
+
+/// Run the outbox worker forever, retrying due messages every poll interval.
+#[instrument(skip_all)]
+pub async fn run_outbox_worker<L, C, M>(db: DbClient<L, C, M>, chats: Vec<ChatClient>, max_age: Duration) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let mut interval = tokio::time::interval(OUTBOX_POLL_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // A database hiccup should not stop the worker; the messages are retried on the next tick.
+        if let Err(err) = drain_outbox(&db, &chats, max_age, chrono::Utc::now().timestamp()).await {
+            warn!("Failed to drain the outbox: {}", err);
+        }
+    }
+}
+
+/// Retry every outbox message that is due at `now` (unix seconds).
+///
+/// Sent messages are deleted, messages older than `max_age` (or whose workspace is gone) are dropped,
+/// and failed messages are backed off exponentially.  Returns the number of messages that were sent.
+#[instrument(skip_all)]
+pub async fn drain_outbox<L, C, M>(db: &DbClient<L, C, M>, chats: &[ChatClient], max_age: Duration, now: i64) -> Res<usize>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let mut sent = 0;
+
+    for message in db.get_due_pending_messages(now).await? {
+        let Some(id) = message.id.clone() else {
+            continue;
+        };
+
+        if now - message.created_at > max_age.as_secs() as i64 {
+            warn!("Dropping message to channel `{}` after {} failed attempts.", message.channel_id, message.attempts);
+            db.delete_pending_message(&id).await?;
+            continue;
+        }
+
+        let Some(chat) = chats.iter().find(|chat| chat.bot_user_id() == message.bot_user_id) else {
+            warn!("Dropping message to channel `{}` because bot user `{}` is not connected.", message.channel_id, message.bot_user_id);
+            db.delete_pending_message(&id).await?;
+            continue;
+        };
+
+        let options = MessageOptions { reply_broadcast: message.reply_broadcast };
+
+        match chat.send_blocks(&message.channel_id, &message.thread_ts, &message.text, &message.blocks, &options).await {
+            Ok(_) => {
+                info!("Sent queued message to channel `{}` after {} failed attempts.", message.channel_id, message.attempts);
+                db.delete_pending_message(&id).await?;
+                sent += 1;
+            }
+            Err(err) => {
+                warn!("Retry of queued message to channel `{}` failed: {}", message.channel_id, err);
+
+                let attempts = message.attempts + 1;
+                let retry = PendingMessage {
+                    attempts,
+                    next_attempt_at: now + backoff_seconds(attempts),
+                    ..message
+                };
+
+                db.update_pending_message(&retry).await?;
+            }
+        }
+    }
+
+    Ok(sent)
+}
+
+// Helpers.
+
+/// The delay before the next retry, after `attempts` failed attempts.
+fn backoff_seconds(attempts: u32) -> i64 {
+    let exponent = attempts.saturating_sub(1).min(16);
+
+    (OUTBOX_BASE_BACKOFF_SECONDS << exponent).min(OUTBOX_MAX_BACKOFF_SECONDS)
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff_seconds() {
+        assert_eq!(backoff_seconds(1), 30);
+        assert_eq!(backoff_seconds(2), 60);
+        assert_eq!(backoff_seconds(3), 120);
+        assert_eq!(backoff_seconds(10), OUTBOX_MAX_BACKOFF_SECONDS);
+        assert_eq!(backoff_seconds(u32::MAX), OUTBOX_MAX_BACKOFF_SECONDS);
+    }
+}
@@ -0,0 +1,96 @@
+//! This module schedules messages (e.g., follow-ups and SLA nudges) to be posted to threads later.
+
+use tracing::{info, instrument, warn};
+
+use crate::{
+    base::types::{Res, Void},
+    service::{
+        chat::ChatClient,
+        db::{Channel, DbClient, LlmContext, Message, PendingMessage, ScheduledMessage},
+    },
+};
+
+/// Schedule a message to be posted to a thread at `post_at` (unix seconds).
+///
+/// The chat platform schedules the message natively when it can.  Otherwise, the message is queued in the outbox
+/// (due at `post_at`), and the outbox worker's timer posts it.  Either way, it is recorded by thread, so that it can be cancelled.
+#[instrument(skip(text, chat, db))]
+pub async fn schedule_reminder<L, C, M>(channel_id: &str, thread_ts: &str, text: &str, post_at: i64, chat: &ChatClient, db: &DbClient<L, C, M>) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let (scheduled_message_id, native) = match chat.schedule_message(channel_id, thread_ts, text, post_at).await? {
+        Some(scheduled_message_id) => (scheduled_message_id, true),
+        None => {
+            let message = PendingMessage {
+                id: None,
+                bot_user_id: chat.bot_user_id().to_string(),
+                channel_id: channel_id.to_string(),
+                thread_ts: thread_ts.to_string(),
+                text: text.to_string(),
+                blocks: Vec::new(),
+                reply_broadcast: false,
+                created_at: post_at,
+                attempts: 0,
+                next_attempt_at: post_at,
+            };
+
+            (db.add_pending_message(&message).await?, false)
+        }
+    };
+
+    let scheduled = ScheduledMessage {
+        id: None,
+        channel_id: channel_id.to_string(),
+        thread_ts: thread_ts.to_string(),
+        scheduled_message_id,
+        native,
+        post_at,
+    };
+
+    db.add_scheduled_message(&scheduled).await?;
+
+    info!("Scheduled a reminder in thread `{}` (native: {}).", thread_ts, native);
+
+    Ok(())
+}
+
+/// Cancel all of the reminders that have not been posted yet in a thread (e.g., once it is resolved).
+///
+/// Returns the number of reminders that were cancelled.
+#[instrument(skip(chat, db))]
+pub async fn cancel_thread_reminders<L, C, M>(channel_id: &str, thread_ts: &str, chat: &ChatClient, db: &DbClient<L, C, M>) -> Res<usize>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let now = chrono::Utc::now().timestamp();
+    let mut cancelled = 0;
+
+    for scheduled in db.get_thread_scheduled_messages(channel_id, thread_ts).await? {
+        // Reminders that were already posted are just forgotten.
+        if scheduled.post_at > now {
+            if scheduled.native {
+                // The platform may have already posted (or dropped) it, which is fine.
+                if let Err(err) = chat.delete_scheduled_message(channel_id, &scheduled.scheduled_message_id).await {
+                    warn!("Failed to cancel scheduled message `{}`: {}", scheduled.scheduled_message_id, err);
+                }
+            } else {
+                db.delete_pending_message(&scheduled.scheduled_message_id).await?;
+            }
+
+            cancelled += 1;
+        }
+
+        if let Some(id) = &scheduled.id {
+            db.delete_scheduled_message(id).await?;
+        }
+    }
+
+    info!("Cancelled {} reminders in thread `{}`.", cancelled, thread_ts);
+
+    Ok(cancelled)
+}
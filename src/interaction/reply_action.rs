@@ -0,0 +1,298 @@
+//! This module handles the interactive buttons on the bot's replies.
+
+use serde_json::{Value, json};
+use tracing::{Instrument, Span, error, info, instrument, warn};
+
+use crate::{
+    base::types::Void,
+    interaction::feedback::record_feedback,
+    service::{
+        chat::ChatClient,
+        db::{Channel, DbClient, LlmContext, Message},
+    },
+};
+
+// Statics.
+
+/// Action ID of the "Helpful" button.
+pub const HELPFUL_ACTION_ID: &str = "triage_helpful";
+
+/// Action ID of the "Not helpful" button.
+pub const NOT_HELPFUL_ACTION_ID: &str = "triage_not_helpful";
+
+/// Action ID of the "Escalate to oncall" button.
+pub const ESCALATE_ACTION_ID: &str = "triage_escalate";
+
+// Types.
+
+/// The kind of button that was clicked on one of the bot's replies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplyActionKind {
+    /// The reply was helpful.
+    Helpful,
+    /// The reply was not helpful.
+    NotHelpful,
+    /// The user wants a human to take over.
+    Escalate,
+}
+
+impl ReplyActionKind {
+    /// Get the kind of button from its action ID.
+    pub fn from_action_id(action_id: &str) -> Option<Self> {
+        match action_id {
+            HELPFUL_ACTION_ID => Some(Self::Helpful),
+            NOT_HELPFUL_ACTION_ID => Some(Self::NotHelpful),
+            ESCALATE_ACTION_ID => Some(Self::Escalate),
+            _ => None,
+        }
+    }
+
+    /// Get the reaction that the button is recorded as, so that buttons and reactions aggregate together.
+    pub fn feedback_emoji(&self) -> &'static str {
+        match self {
+            Self::Helpful => "+1",
+            Self::NotHelpful => "-1",
+            Self::Escalate => "rotating_light",
+        }
+    }
+}
+
+/// A button click on one of the bot's replies.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReplyAction {
+    /// The kind of button that was clicked.
+    pub kind: ReplyActionKind,
+    /// The channel of the bot's reply.
+    pub channel_id: String,
+    /// The timestamp of the bot's reply.
+    pub message_ts: String,
+    /// The thread that the bot's reply belongs to.
+    pub thread_ts: String,
+    /// The user who clicked the button.
+    pub user_id: String,
+}
+
+/// Render the action block (the buttons) attached to the bot's replies.
+///
+/// The thread is carried in the button value, so that it is available when the button is clicked.
+pub fn render_reply_action_block(thread_ts: &str) -> Value {
+    json!({
+        "type": "actions",
+        "block_id": "triage_reply_actions",
+        "elements": [
+            { "type": "button", "action_id": HELPFUL_ACTION_ID, "text": { "type": "plain_text", "text": "Helpful", "emoji": true }, "value": thread_ts },
+            { "type": "button", "action_id": NOT_HELPFUL_ACTION_ID, "text": { "type": "plain_text", "text": "Not helpful", "emoji": true }, "value": thread_ts },
+            { "type": "button", "action_id": ESCALATE_ACTION_ID, "text": { "type": "plain_text", "text": "Escalate to oncall", "emoji": true }, "style": "danger", "value": thread_ts },
+        ],
+    })
+}
+
+/// Parse the button clicks from a `block_actions` interaction payload.
+///
+/// Actions that are not one of the reply buttons (or that lack a channel, message, or user) are ignored.
+pub fn parse_reply_actions(payload: &Value) -> Vec<ReplyAction> {
+    if payload.get("type").and_then(Value::as_str) != Some("block_actions") {
+        return Vec::new();
+    }
+
+    let str_at = |pointers: &[&str]| pointers.iter().find_map(|p| payload.pointer(p).and_then(Value::as_str)).map(str::to_string);
+
+    let (Some(user_id), Some(channel_id), Some(message_ts)) = (
+        str_at(&["/user/id"]),
+        str_at(&["/container/channel_id", "/channel/id"]),
+        str_at(&["/container/message_ts", "/message/ts"]),
+    ) else {
+        return Vec::new();
+    };
+
+    let fallback_thread_ts = str_at(&["/message/thread_ts"]).unwrap_or_else(|| message_ts.clone());
+
+    payload
+        .get("actions")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter_map(|action| {
+            let kind = ReplyActionKind::from_action_id(action.get("action_id")?.as_str()?)?;
+            let thread_ts = action
+                .get("value")
+                .and_then(Value::as_str)
+                .filter(|v| !v.is_empty())
+                .map(str::to_string)
+                .unwrap_or_else(|| fallback_thread_ts.clone());
+
+            Some(ReplyAction {
+                kind,
+                channel_id: channel_id.clone(),
+                message_ts: message_ts.clone(),
+                thread_ts,
+                user_id: user_id.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Handles an interaction payload.
+///
+/// This function is responsible for recording button clicks on the bot's replies as feedback, and escalating to the oncall.
+/// It spawns a new task to handle the event asynchronously.
+#[instrument(skip_all)]
+pub fn handle_reply_actions<L, C, M>(payload: Value, db: DbClient<L, C, M>, chat: ChatClient)
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    tokio::spawn(
+        async move {
+            for action in parse_reply_actions(&payload) {
+                // Process the event.
+                let result = handle_reply_action_internal(action, &db, &chat).in_current_span().await;
+
+                // Log any errors.
+                if let Err(err) = &result {
+                    error!("Error while handling: {}\n\n{}", err, err.backtrace());
+                }
+            }
+        }
+        .instrument(Span::current()),
+    );
+}
+
+/// Internal function to handle a single button click.
+#[instrument(skip(db, chat))]
+async fn handle_reply_action_internal<L, C, M>(action: ReplyAction, db: &DbClient<L, C, M>, chat: &ChatClient) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    record_feedback(
+        action.channel_id.clone(),
+        action.message_ts.clone(),
+        action.user_id.clone(),
+        action.kind.feedback_emoji().to_string(),
+        db,
+    )
+    .await?;
+
+    if action.kind != ReplyActionKind::Escalate {
+        return Ok(());
+    }
+
+    info!("Escalating thread to the oncall ...");
+
+    // The oncall is whoever is mentioned in the channel directive.
+    let oncalls = directive_oncalls(db.get_channel_directive(&action.channel_id).await?.as_ref());
+
+    if oncalls.is_empty() {
+        warn!("Cannot escalate because the channel directive does not mention an oncall.");
+
+        chat.send_ephemeral_message(
+            &action.channel_id,
+            &action.user_id,
+            &action.thread_ts,
+            "I couldn't find an oncall in this channel's directive, so I couldn't escalate.  Try `/triage directive` to see who is listed.",
+        )
+        .await?;
+
+        return Ok(());
+    }
+
+    let message = format!("{}: <@{}> has escalated this thread, and is asking for a human to take a look.", oncalls.join(" "), action.user_id);
+    chat.send_message(&action.channel_id, &action.thread_ts, &message).await?;
+
+    Ok(())
+}
+
+// Helpers.
+
+/// The oncalls of a channel: whoever is mentioned in its directive (if it has one).
+pub fn directive_oncalls<L: LlmContext>(directive: Option<&L>) -> Vec<String> {
+    let Some(directive) = directive else {
+        return Vec::new();
+    };
+
+    let directive_text = format!("{} {}", directive.your_notes(), directive.user_message().get("text").and_then(Value::as_str).unwrap_or_default());
+
+    extract_mentions(&directive_text)
+}
+
+/// Extract the (deduplicated) user and group mentions from some text.
+///
+/// Both Slack-formatted mentions (e.g., `<@U12345>` and `<!subteam^S12345>`) and plain handles (e.g., `@horse-oncall`) are supported.
+pub fn extract_mentions(text: &str) -> Vec<String> {
+    let mut mentions: Vec<String> = Vec::new();
+
+    for word in text.split_whitespace() {
+        let mention = if let Some(start) = word.find("<@").or_else(|| word.find("<!subteam^")) {
+            word[start..].find('>').map(|end| &word[start..start + end + 1])
+        } else if word.starts_with('@') {
+            Some(word.trim_end_matches(|c: char| !c.is_alphanumeric() && c != '-' && c != '_'))
+        } else {
+            None
+        };
+
+        if let Some(mention) = mention
+            && mention.len() > 1
+            && !mentions.iter().any(|m| m == mention)
+        {
+            mentions.push(mention.to_string());
+        }
+    }
+
+    mentions
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_reply_actions() {
+        let payload = json!({
+            "type": "block_actions",
+            "user": { "id": "U54321" },
+            "container": { "type": "message", "message_ts": "1234567890.999999", "channel_id": "C12345" },
+            "message": { "ts": "1234567890.999999", "thread_ts": "1234567890.123456" },
+            "actions": [
+                { "type": "button", "action_id": ESCALATE_ACTION_ID, "value": "1234567890.123456" },
+                { "type": "button", "action_id": "something_else", "value": "x" },
+            ],
+        });
+
+        assert_eq!(
+            parse_reply_actions(&payload),
+            vec![ReplyAction {
+                kind: ReplyActionKind::Escalate,
+                channel_id: "C12345".into(),
+                message_ts: "1234567890.999999".into(),
+                thread_ts: "1234567890.123456".into(),
+                user_id: "U54321".into(),
+            }]
+        );
+
+        assert!(parse_reply_actions(&json!({ "type": "view_submission" })).is_empty());
+    }
+
+    #[test]
+    fn test_render_reply_action_block() {
+        let block = render_reply_action_block("1234567890.123456");
+        let elements = block["elements"].as_array().unwrap();
+
+        assert_eq!(block["type"], "actions");
+        assert_eq!(elements.len(), 3);
+        assert!(elements.iter().all(|e| e["value"] == "1234567890.123456"));
+        assert!(elements.iter().all(|e| ReplyActionKind::from_action_id(e["action_id"].as_str().unwrap()).is_some()));
+    }
+
+    #[test]
+    fn test_extract_mentions() {
+        let text = "Tag <@U12345> for bugs, and @horse-oncall (or <!subteam^S999|@horses>) for horses.  Again: <@U12345>.";
+
+        assert_eq!(extract_mentions(text), vec!["<@U12345>", "@horse-oncall", "<!subteam^S999|@horses>"]);
+        assert!(extract_mentions("nobody here").is_empty());
+    }
+}
@@ -0,0 +1,176 @@
+//! This module prunes stored messages that are older than the retention policy (and long-expired contexts), in the background.
+
+use std::time::Duration;
+
+use tracing::{info, instrument, warn};
+
+use crate::{
+    base::types::{Res, Void},
+    service::db::{Channel, DbClient, LlmContext, Message},
+};
+
+// Statics.
+
+/// How often old messages are pruned.
+const RETENTION_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// The number of seconds in a day.
+const SECONDS_PER_DAY: i64 = 24 * 60 * 60;
+
+/// How long expired contexts are kept (e.g., so that a mistaken expiry can still be recovered) before they are pruned.
+const EXPIRED_CONTEXT_GRACE_DAYS: u64 = 30;
+
+/// Run the retention worker forever, pruning old messages (and long-expired contexts) every interval.
+///
+/// `default_retention_days` applies to channels that do not override it, and zero keeps messages forever.
+#[instrument(skip_all)]
+pub async fn run_retention_worker<L, C, M>(db: DbClient<L, C, M>, default_retention_days: u64) -> Void
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let mut interval = tokio::time::interval(RETENTION_INTERVAL);
+
+    loop {
+        interval.tick().await;
+
+        // A database hiccup should not stop the worker; the messages are pruned on the next tick.
+        match prune_channels(&db, default_retention_days, chrono::Utc::now().timestamp()).await {
+            Ok(0) => {}
+            Ok(pruned) => info!("Pruned {} old messages.", pruned),
+            Err(err) => warn!("Failed to prune old messages: {}", err),
+        }
+
+        match prune_expired_contexts(&db, chrono::Utc::now().timestamp()).await {
+            Ok(0) => {}
+            Ok(pruned) => info!("Pruned {} expired contexts.", pruned),
+            Err(err) => warn!("Failed to prune expired contexts: {}", err),
+        }
+    }
+}
+
+/// Prune the messages of every channel that are older than its retention at `now` (unix seconds).
+///
+/// Returns the number of messages that were pruned.
+#[instrument(skip_all)]
+pub async fn prune_channels<L, C, M>(db: &DbClient<L, C, M>, default_retention_days: u64, now: i64) -> Res<u64>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let mut pruned = 0;
+
+    for channel_id in db.list_channel_ids().await? {
+        let channel = db.get_or_create_channel(&channel_id).await?;
+        let retention_days = channel.retention_days().unwrap_or(default_retention_days);
+
+        let Some(cutoff) = retention_cutoff(retention_days, now) else {
+            continue;
+        };
+
+        let removed = db.prune_old_messages(&channel_id, cutoff).await?;
+        if removed > 0 {
+            info!("Pruned {} messages older than {} days from channel `{}`.", removed, retention_days, channel_id);
+        }
+
+        pruned += removed;
+    }
+
+    Ok(pruned)
+}
+
+/// Prune the contexts of every channel that expired more than `EXPIRED_CONTEXT_GRACE_DAYS` before `now` (unix seconds).
+///
+/// Expired contexts are already left out of the assistant's context, so this only cleans them up.  Returns the
+/// number of contexts that were pruned.
+#[instrument(skip_all)]
+pub async fn prune_expired_contexts<L, C, M>(db: &DbClient<L, C, M>, now: i64) -> Res<u64>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    let Some(cutoff) = retention_cutoff(EXPIRED_CONTEXT_GRACE_DAYS, now) else {
+        return Ok(0);
+    };
+
+    let mut pruned = 0;
+
+    for channel_id in db.list_channel_ids().await? {
+        pruned += db.prune_expired_contexts(&channel_id, cutoff).await?;
+    }
+
+    Ok(pruned)
+}
+
+// Helpers.
+
+/// The cutoff (unix seconds) for a retention of `retention_days` at `now`, or `None` if messages are kept forever.
+fn retention_cutoff(retention_days: u64, now: i64) -> Option<i64> {
+    let days = i64::try_from(retention_days).ok().filter(|days| *days > 0)?;
+
+    Some(now.saturating_sub(days.saturating_mul(SECONDS_PER_DAY)))
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+    use crate::service::db::surreal::SurrealLlmContext;
+
+    #[test]
+    fn test_retention_cutoff() {
+        assert_eq!(retention_cutoff(0, 1_000_000), None);
+        assert_eq!(retention_cutoff(1, 1_000_000), Some(1_000_000 - SECONDS_PER_DAY));
+        assert_eq!(retention_cutoff(30, 1_700_000_000), Some(1_700_000_000 - 30 * SECONDS_PER_DAY));
+        assert_eq!(retention_cutoff(u64::MAX, 1_000_000), None);
+    }
+
+    #[tokio::test]
+    async fn test_prune_channels() {
+        let db = DbClient::sqlite(":memory:").await.unwrap();
+        let now = 2_000_000_000;
+        let old_ts = format!("{}.000100", now - 10 * SECONDS_PER_DAY);
+
+        // One channel keeps messages forever (the default), and the other keeps a week of messages.
+        for channel_id in ["C1", "C2"] {
+            db.get_or_create_channel(channel_id).await.unwrap();
+            db.add_channel_message(channel_id, &json!({ "text": "Old", "ts": old_ts })).await.unwrap();
+            db.add_channel_message(channel_id, &json!({ "text": "New", "ts": format!("{now}.000100") })).await.unwrap();
+        }
+        db.set_channel_retention("C2", Some(7)).await.unwrap();
+
+        assert_eq!(prune_channels(&db, 0, now).await.unwrap(), 1);
+        assert_eq!(db.get_channel_message_count("C1").await.unwrap(), 2);
+        assert_eq!(db.get_channel_message_count("C2").await.unwrap(), 1);
+
+        // A default retention applies to the channels without an override.
+        assert_eq!(prune_channels(&db, 30, now).await.unwrap(), 0);
+        assert_eq!(prune_channels(&db, 5, now).await.unwrap(), 1);
+        assert_eq!(db.get_channel_message_count("C1").await.unwrap(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_prune_expired_contexts() {
+        let db = DbClient::sqlite(":memory:").await.unwrap();
+        let now = 2_000_000_000;
+
+        db.get_or_create_channel("C1").await.unwrap();
+        for (notes, expires_at) in [("Forever", None), ("Recently expired", Some(now - SECONDS_PER_DAY)), ("Long expired", Some(now - 60 * SECONDS_PER_DAY))] {
+            let context = SurrealLlmContext::new(json!({ "text": notes }), notes.to_string()).with_expires_at(expires_at);
+            db.add_channel_context("C1", &context).await.unwrap();
+        }
+
+        // Only the contexts that expired before the grace period are pruned.
+        assert_eq!(prune_expired_contexts(&db, now).await.unwrap(), 1);
+
+        let contexts = db.list_channel_contexts("C1").await.unwrap();
+        let notes = contexts.iter().map(|(_, summary)| summary.as_str()).collect::<Vec<_>>();
+        assert!(notes.contains(&"Forever") && notes.contains(&"Recently expired") && !notes.contains(&"Long expired"));
+    }
+}
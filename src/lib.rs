@@ -16,8 +16,11 @@ pub mod interaction;
 pub mod runtime;
 pub mod service;
 
+use std::path::Path;
+
 use base::{config::Config, types::Void};
 use rustls::crypto;
+use service::db::{ChannelExport, DbClient};
 use tracing::info;
 
 /// Public async entry for the binary crate.
@@ -25,6 +28,7 @@ use tracing::info;
 /// Sets up necessary services and starts the triage-bot runtime:
 /// - Initializes the crypto provider
 /// - Creates the runtime context with database, LLM, and chat clients
+/// - Checks that each of the services is healthy
 /// - Starts the main event loop for processing messages
 pub async fn start(config: Config) -> Void {
     info!("Starting triage-bot ...");
@@ -35,8 +39,72 @@ pub async fn start(config: Config) -> Void {
     // Initialize the runtime.
     let runtime = runtime::Runtime::new(config).await?;
 
+    // Check that the services are reachable (and the credentials valid) before listening for events.
+    runtime.ensure_healthy().await?;
+
     // Start the runtime.
     runtime.start().await?;
 
     Ok(())
 }
+
+/// Export a channel's data (its directive, contexts, and messages) from the configured database to a JSON file.
+pub async fn export_channel(config: Config, channel_id: &str, out: &Path) -> Void {
+    crypto::ring::default_provider().install_default().unwrap();
+
+    let db = DbClient::new(&config).await?;
+    let export = db.export_channel(channel_id).await?;
+
+    std::fs::write(out, serde_json::to_string_pretty(&export)?)?;
+
+    info!("Exported channel `{}` to `{}`.", channel_id, out.display());
+
+    Ok(())
+}
+
+/// Import a channel export (see `export_channel`) from a JSON file into a channel of the configured database.
+///
+/// The data is imported into the exported channel, unless another `channel_id` is given.
+pub async fn import_channel(config: Config, channel_id: Option<&str>, file: &Path) -> Void {
+    crypto::ring::default_provider().install_default().unwrap();
+
+    let export: ChannelExport = serde_json::from_str(&std::fs::read_to_string(file)?)?;
+    let channel_id = channel_id.unwrap_or(&export.channel_id);
+
+    let db = DbClient::new(&config).await?;
+    let added = db.import_channel(channel_id, &export).await?;
+
+    info!("Imported `{}` into channel `{}` ({} new messages).", file.display(), channel_id, added);
+
+    Ok(())
+}
+
+/// Set a channel's system addendum (see `GenericDbClient::set_channel_system_addendum`) from a file, or remove it if no
+/// file is given.
+pub async fn set_channel_system_addendum(config: Config, channel_id: &str, file: Option<&Path>) -> Void {
+    crypto::ring::default_provider().install_default().unwrap();
+
+    let system_addendum = file.map(std::fs::read_to_string).transpose()?;
+
+    let db = DbClient::new(&config).await?;
+    db.get_or_create_channel(channel_id).await?;
+    db.set_channel_system_addendum(channel_id, system_addendum.as_deref()).await?;
+
+    info!("{} the system addendum of channel `{}`.", if system_addendum.is_some() { "Set" } else { "Removed" }, channel_id);
+
+    Ok(())
+}
+
+/// Print a channel's statistics (see `GenericDbClient::get_channel_stats`) over the last `days` days as JSON.
+pub async fn channel_stats(config: Config, channel_id: &str, days: u64) -> Void {
+    crypto::ring::default_provider().install_default().unwrap();
+
+    let since = chrono::Utc::now().timestamp() - days as i64 * 24 * 60 * 60;
+
+    let db = DbClient::new(&config).await?;
+    let stats = db.get_channel_stats(channel_id, since).await?;
+
+    println!("{}", serde_json::to_string_pretty(&stats)?);
+
+    Ok(())
+}
@@ -1,12 +1,18 @@
 //! Runtime services and shared state for the triage-bot.
 
+use std::time::Duration;
+
+use serde::Serialize;
 use tracing::instrument;
 
-use crate::service::db::DbClient;
-use crate::{base::config::Config, service::mcp::McpClient};
 use crate::{
-    base::types::{Res, Void},
-    service::{chat::ChatClient, llm::LlmClient},
+    base::{
+        config::Config,
+        redact,
+        types::{Res, Void},
+    },
+    interaction::{outbox, retention},
+    service::{chat::ChatClient, db::DbClient, llm::LlmClient, mcp::McpClient},
 };
 
 /// Runtime service context that can be shared across the application.
@@ -22,8 +28,8 @@ pub struct Runtime {
     pub db: DbClient,
     /// The LLM client instance.
     pub llm: LlmClient,
-    /// The slack client instance.
-    pub chat: ChatClient,
+    /// The slack client instances (one per workspace).
+    pub chats: Vec<ChatClient>,
     /// The MCP client instance.
     pub mcp: McpClient,
 }
@@ -33,21 +39,105 @@ impl Runtime {
     #[instrument(name = "Runtime::new", skip_all)]
     pub async fn new(config: Config) -> Res<Self> {
         // Initialize the database.
-        let db = DbClient::surreal(&config).await?;
+        let db = DbClient::new(&config).await?;
 
         // Initialize the LLM client.
         let llm = LlmClient::openai(&config);
 
         // Initialize the MCP client.
-        let mcp = McpClient::new(&config.mcp_config_path).await?;
+        let mcp = McpClient::new(&config.mcp_config_path, redact::payload_redactor(&config)).await?;
 
-        // Initialize the slack client
-        let chat = ChatClient::slack(&config, db.clone(), llm.clone(), mcp.clone()).await?;
+        // Initialize the slack clients (one per workspace, sharing the other services).
+        let workspaces = config.workspaces();
+        let chats = futures::future::try_join_all(workspaces.iter().map(|workspace| ChatClient::slack(&config, workspace, db.clone(), llm.clone(), mcp.clone()))).await?;
 
-        Ok(Self { config, db, llm, chat, mcp })
+        Ok(Self { config, db, llm, chats, mcp })
     }
 
+    /// Start all of the chat listeners (and the outbox and retention workers) concurrently.
+    ///
+    /// If any listener fails, the others are shut down with it.
     pub async fn start(&self) -> Void {
-        self.chat.start().await
+        let listeners = futures::future::try_join_all(self.chats.iter().map(|chat| chat.start()));
+        let outbox_worker = outbox::run_outbox_worker(self.db.clone(), self.chats.clone(), Duration::from_secs(self.config.slack_outbox_max_age_seconds));
+        let retention_worker = retention::run_retention_worker(self.db.clone(), self.config.message_retention_days);
+
+        futures::future::try_join3(listeners, outbox_worker, retention_worker).await?;
+
+        Ok(())
+    }
+
+    /// Check the health of each of the services (the database, the LLM, and every chat client) concurrently.
+    #[instrument(name = "Runtime::health", skip_all)]
+    pub async fn health(&self) -> RuntimeHealth {
+        let chats = self
+            .chats
+            .iter()
+            .map(|chat| async move { ServiceHealth::new(format!("chat:{}", chat.bot_user_id()), chat.healthy().await) });
+        let (db, llm, chats) = futures::future::join3(self.db.healthy(), self.llm.healthy(), futures::future::join_all(chats)).await;
+
+        let mut services = vec![ServiceHealth::new("db".to_string(), db), ServiceHealth::new("llm".to_string(), llm)];
+        services.extend(chats);
+
+        RuntimeHealth { services }
+    }
+
+    /// Check that every service is healthy (see `health`), as a readiness check before the chat listeners start.
+    ///
+    /// Fails with the services that are not healthy (and why).
+    pub async fn ensure_healthy(&self) -> Void {
+        let health = self.health().await;
+
+        if !health.is_healthy() {
+            let failures = health
+                .services
+                .iter()
+                .filter_map(|service| service.error.as_ref().map(|error| format!("{}: {}", service.service, error)))
+                .collect::<Vec<_>>()
+                .join("; ");
+
+            return Err(anyhow::anyhow!("Failed the health check ({failures})."));
+        }
+
+        Ok(())
+    }
+}
+
+/// The health of the runtime's services (see `Runtime::health`).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RuntimeHealth {
+    /// The health of each service.
+    pub services: Vec<ServiceHealth>,
+}
+
+impl RuntimeHealth {
+    /// Whether every service is healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.services.iter().all(ServiceHealth::is_healthy)
+    }
+}
+
+/// The health of one of the runtime's services.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ServiceHealth {
+    /// The service (`db`, `llm`, or `chat:` and the bot's user ID in the workspace).
+    pub service: String,
+    /// Why the service's health check failed (if it did).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+impl ServiceHealth {
+    /// The health of a service, from the result of its health check.
+    fn new(service: String, result: Void) -> Self {
+        Self {
+            service,
+            error: result.err().map(|err| err.to_string()),
+        }
+    }
+
+    /// Whether the service is healthy.
+    pub fn is_healthy(&self) -> bool {
+        self.error.is_none()
     }
 }
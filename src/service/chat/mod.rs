@@ -1,11 +1,24 @@
+pub mod mrkdwn;
 pub mod slack;
+pub mod split;
 
 use std::{ops::Deref, sync::Arc};
 
 use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
 
 use crate::base::types::{Res, Void};
 
+// Statics.
+
+/// Non-`text/*` MIME types that are still readable as text.
+const TEXT_APPLICATION_MIME_TYPES: [&str; 5] = ["application/json", "application/xml", "application/x-yaml", "application/javascript", "application/x-sh"];
+
+/// The `block_id` prefix of the section blocks that hold the body of a message (e.g., `body_0`, `body_1`), which
+/// backends that split long messages replace with the first chunk.
+pub const BODY_BLOCK_ID_PREFIX: &str = "body_";
+
 // Traits.
 
 /// Generic "chat" trait that clients must implement.
@@ -21,24 +34,198 @@ pub trait GenericChatClient: Send + Sync + 'static {
     /// which is used to detect when the bot is mentioned.
     fn bot_user_id(&self) -> &str;
 
+    /// Get the time (unix seconds) of the last event received from the chat platform, if any.
+    ///
+    /// Used to report connection staleness.  Backends that do not track this keep the default (`None`).
+    fn last_event_at(&self) -> Option<i64> {
+        None
+    }
+
+    /// Convert the assistant's (markdown) text into the platform's formatting.
+    ///
+    /// Implementations apply this to the text they post.  Backends that render markdown as is keep the
+    /// default (no conversion), and others can supply their own converter.
+    fn convert_markdown(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    /// Check that the chat platform is reachable, and that the bot's credentials are valid.
+    ///
+    /// Used for health and readiness checks.  Backends that cannot check this keep the default (always healthy).
+    async fn healthy(&self) -> Void {
+        Ok(())
+    }
+
     /// Start the chat client listener.
     ///
     /// This sets up event listeners for the chat platform and begins processing
     /// incoming messages and events.
     async fn start(&self) -> Void;
 
-    /// Send a message to a channel thread.
+    /// Send a message to a channel thread (or, with an empty `thread_ts`, to the channel itself).
     ///
     /// Used to post responses in threads, allowing the bot to reply to user
-    /// messages in a structured way.
-    async fn send_message(&self, channel_id: &str, thread_ts: &str, text: &str) -> Void;
+    /// messages in a structured way.  Returns the timestamp (platform message ID)
+    /// of the posted message, so that it can be referenced or edited later.
+    async fn send_message(&self, channel_id: &str, thread_ts: &str, text: &str) -> Res<String>;
+
+    /// Update a previously posted message.
+    ///
+    /// Replaces the text of the message identified by `ts` (as returned from `send_message`).
+    async fn update_message(&self, channel_id: &str, ts: &str, text: &str) -> Void;
+
+    /// Update a previously posted message with structured (block-based) content.
+    ///
+    /// Backends without block support fall back to updating the plain `text`, and may ignore the `options`.
+    async fn update_blocks(&self, channel_id: &str, ts: &str, text: &str, blocks: &[Value], options: &MessageOptions) -> Void {
+        let _ = (blocks, options);
+        self.update_message(channel_id, ts, text).await
+    }
+
+    /// Delete a previously posted message.
+    async fn delete_message(&self, channel_id: &str, ts: &str) -> Void;
+
+    /// Post a short placeholder (e.g., "looking into this…") to a channel thread while a response is prepared.
+    ///
+    /// Returns the timestamp of the placeholder, so that it can be edited into the final answer (or deleted),
+    /// or `None` if placeholders are disabled.
+    async fn send_placeholder(&self, channel_id: &str, thread_ts: &str, text: &str) -> Res<Option<String>> {
+        let _ = (channel_id, thread_ts, text);
+
+        Ok(None)
+    }
+
+    /// Send a structured (block-based) message to a channel thread.
+    ///
+    /// The `blocks` are Block Kit JSON objects, and `text` is the plain-text version used for
+    /// notifications.  Backends without block support fall back to posting `text`, and may ignore the `options`.
+    async fn send_blocks(&self, channel_id: &str, thread_ts: &str, text: &str, blocks: &[Value], options: &MessageOptions) -> Res<String> {
+        let _ = (blocks, options);
+        self.send_message(channel_id, thread_ts, text).await
+    }
+
+    /// Send an ephemeral message to a channel thread, visible only to `user_id`.
+    ///
+    /// Used for confirmations that are only relevant to the requester.  Backends that do
+    /// not support ephemeral messages fall back to a normal threaded reply.
+    async fn send_ephemeral_message(&self, channel_id: &str, user_id: &str, thread_ts: &str, text: &str) -> Void {
+        let _ = user_id;
+        self.send_message(channel_id, thread_ts, text).await?;
+
+        Ok(())
+    }
+
+    /// Attach an unfurl (a short summary) to a link in a posted message.
+    ///
+    /// Backends without link unfurling leave the message untouched.
+    async fn unfurl_link(&self, channel_id: &str, ts: &str, url: &str, text: &str) -> Void {
+        let _ = (channel_id, ts, url, text);
+
+        Ok(())
+    }
 
     /// React to a message with an emoji.
     ///
     /// Adds an emoji reaction to a message, which can be used to indicate
-    /// the type of issue or state of a request.
+    /// the type of issue or state of a request.  Reacting with an emoji that the bot
+    /// has already reacted with is not an error.
     async fn react_to_message(&self, channel_id: &str, thread_ts: &str, emoji: &str) -> Void;
 
+    /// Schedule a threaded message to be posted later (at `post_at`, in unix seconds).
+    ///
+    /// Returns the platform ID of the scheduled message, so that it can be cancelled.  Backends
+    /// without native scheduling return `None` (the default), and callers fall back to the outbox.
+    async fn schedule_message(&self, channel_id: &str, thread_ts: &str, text: &str, post_at: i64) -> Res<Option<String>> {
+        let _ = (channel_id, thread_ts, text, post_at);
+        Ok(None)
+    }
+
+    /// List the natively scheduled messages that have not been posted yet in a channel.
+    async fn list_scheduled_messages(&self, channel_id: &str) -> Res<Vec<ScheduledMessageInfo>> {
+        let _ = channel_id;
+        Ok(Vec::new())
+    }
+
+    /// Cancel a natively scheduled message.
+    async fn delete_scheduled_message(&self, channel_id: &str, scheduled_message_id: &str) -> Void {
+        Err(anyhow::anyhow!("Scheduled messages are not supported (`{}` in `{}`).", scheduled_message_id, channel_id))
+    }
+
+    /// Pin a message to the channel.
+    ///
+    /// Used to keep important messages (e.g., a thread's resolution) easy to find.
+    async fn pin_message(&self, channel_id: &str, ts: &str) -> Void;
+
+    /// Remove the bot's emoji reaction from a message.
+    ///
+    /// Used when a message's classification changes (e.g., after an edit).  Removing a
+    /// reaction that is not there is not an error.
+    async fn remove_reaction(&self, channel_id: &str, ts: &str, emoji: &str) -> Void;
+
+    /// Publish the channel directive somewhere visible in the channel (e.g., the channel's canvas).
+    ///
+    /// This is best-effort: backends (or workspaces) without a place to publish it keep the default (no-op).
+    async fn publish_channel_directive(&self, channel_id: &str, markdown: &str) -> Void {
+        let _ = (channel_id, markdown);
+        Ok(())
+    }
+
+    /// Join a (public) channel.
+    ///
+    /// Implementations join lazily when a call fails because the bot is not in the channel, and this
+    /// can also be used to pre-join channels (e.g., the ones that are configured).  Joining a channel
+    /// that the bot is already in is not an error.
+    async fn join_channel(&self, channel_id: &str) -> Void;
+
+    /// Get information about a user (e.g., their display name).
+    ///
+    /// Used to annotate raw user IDs in the context handed to the LLM, so that it
+    /// can refer to (and tag) the right people.  Implementations should cache results.
+    async fn get_user_info(&self, user_id: &str) -> Res<UserInfo>;
+
+    /// Get the user groups (e.g., `@payments-oncall`) of the workspace.
+    ///
+    /// Implementations should cache results.  Backends without user groups return none.
+    async fn get_user_groups(&self) -> Res<Vec<UserGroup>> {
+        Ok(Vec::new())
+    }
+
+    /// Rewrite plain user group handles (e.g., `@payments-oncall`) in outgoing text into live mentions.
+    ///
+    /// Text inside code is never modified.  Backends without user groups return the text as is.
+    async fn resolve_user_group_mentions(&self, text: &str) -> String {
+        text.to_string()
+    }
+
+    /// Get a permalink (a URL that opens the message) for a message.
+    ///
+    /// Used to let the assistant cite relevant past messages.  Implementations should cache results.
+    async fn get_permalink(&self, channel_id: &str, ts: &str) -> Res<String>;
+
+    /// Get information about a channel (e.g., its topic and purpose).
+    async fn get_channel_info(&self, channel_id: &str) -> Res<ChannelInfo>;
+
+    /// Get the channel history (top-level messages) since `oldest_ts`, oldest pages first.
+    ///
+    /// Each message is returned as its raw JSON, like the messages that are stored from live events.
+    async fn get_channel_history(&self, channel_id: &str, oldest_ts: &str) -> Res<Vec<Value>>;
+
+    /// Download a text file (e.g., a snippet) attached to a message.
+    ///
+    /// Implementations should truncate the content to their configured limit.  Backends
+    /// without file downloads return an error, and the file is skipped.
+    async fn get_file_text(&self, url: &str) -> Res<String> {
+        Err(anyhow::anyhow!("File downloads are not supported (`{}`).", url))
+    }
+
+    /// Download an image attached to a message.
+    ///
+    /// Images larger than `max_bytes` fail (rather than being truncated, since part of an image is no use).  Backends
+    /// without file downloads return an error, and the image is skipped.
+    async fn get_file_bytes(&self, url: &str, _max_bytes: usize) -> Res<Vec<u8>> {
+        Err(anyhow::anyhow!("File downloads are not supported (`{}`).", url))
+    }
+
     /// Get the entirety of the thread context.
     ///
     /// Retrieves all messages in a thread, which provides context for
@@ -46,6 +233,61 @@ pub trait GenericChatClient: Send + Sync + 'static {
     async fn get_thread_context(&self, channel_id: &str, thread_ts: &str) -> Res<String>;
 }
 
+// Data types.
+
+/// Optional behavior when posting (or updating) a message.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct MessageOptions {
+    /// Whether a threaded reply is also surfaced to the channel (e.g., Slack's `reply_broadcast`).
+    pub reply_broadcast: bool,
+}
+
+/// Information about a chat user.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserInfo {
+    /// The platform user ID.
+    pub id: String,
+    /// The name to show for the user (display name, falling back to real name, then handle).
+    pub display_name: String,
+    /// Whether the user is a bot.
+    pub is_bot: bool,
+}
+
+/// Information about a chat user group.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserGroup {
+    /// The platform user group ID.
+    pub id: String,
+    /// The handle that the group is mentioned by (without the `@`).
+    pub handle: String,
+    /// The name of the group.
+    pub name: String,
+}
+
+/// Information about a natively scheduled chat message.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ScheduledMessageInfo {
+    /// The platform ID of the scheduled message.
+    pub id: String,
+    /// The channel the message is posted to.
+    pub channel_id: String,
+    /// When the message is posted (unix seconds).
+    pub post_at: i64,
+}
+
+/// Information about a chat channel.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ChannelInfo {
+    /// The platform channel ID.
+    pub id: String,
+    /// The channel name.
+    pub name: String,
+    /// The channel topic (empty if unset).
+    pub topic: String,
+    /// The channel purpose (empty if unset).
+    pub purpose: String,
+}
+
 // Structs.
 
 /// Slack client for the application.
@@ -70,3 +312,12 @@ impl ChatClient {
         Self { inner }
     }
 }
+
+// Functions.
+
+/// Whether a MIME type (or a `Content-Type`, with its parameters) is readable as text.
+pub fn is_text_mime_type(mime_type: &str) -> bool {
+    let mime_type = mime_type.split(';').next().unwrap_or_default().trim().to_ascii_lowercase();
+
+    mime_type.starts_with("text/") || TEXT_APPLICATION_MIME_TYPES.contains(&mime_type.as_str())
+}
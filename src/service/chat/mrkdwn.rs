@@ -0,0 +1,305 @@
+//! Conversion of (GitHub-flavored) markdown into Slack's `mrkdwn`.
+//!
+//! The assistant is asked for Slack markdown, but it frequently slips into regular markdown
+//! (e.g., `**bold**`, `[text](url)`, and `# headers`), which Slack renders literally.
+
+/// Convert markdown into Slack `mrkdwn`.
+///
+/// - `**bold**` becomes `*bold*` (and `***both***` becomes `*_both_*`), and `~~strike~~` becomes `~strike~`.
+/// - Links (and images) become `<url|text>`.
+/// - Headers become bold lines.
+/// - Unordered list items become `•` bullets, ordered items use `1.` (rather than `1)`), and nesting is normalized to four spaces per level.
+/// - Code (fenced and inline) is left untouched, except for the language of a fence, which Slack does not support.
+///
+/// Text that is already `mrkdwn` (e.g., `*bold*`, `<@U12345>`, and `<url|text>`) is left as is, so converting twice is harmless.
+pub fn markdown_to_mrkdwn(text: &str) -> String {
+    let mut lines = Vec::new();
+    let mut in_fence = false;
+    let mut nesting = ListNesting::default();
+
+    for line in text.split('\n') {
+        let trimmed = line.trim_start();
+
+        if let Some(rest) = trimmed.strip_prefix("```") {
+            let indent = &line[..line.len() - trimmed.len()];
+            let rest = rest.trim_end();
+
+            if !in_fence && rest.ends_with("```") {
+                // A fence that also closes on the same line (e.g., ```code```) does not change the state.
+                lines.push(line.to_string());
+            } else if !in_fence && !rest.is_empty() && !rest.contains(char::is_whitespace) {
+                // Slack shows the language of an opening fence as code, so drop it.
+                lines.push(format!("{indent}```"));
+                in_fence = true;
+            } else {
+                lines.push(line.to_string());
+                in_fence = !in_fence;
+            }
+
+            continue;
+        }
+
+        if in_fence {
+            lines.push(line.to_string());
+        } else {
+            lines.push(convert_line(line, &mut nesting));
+        }
+    }
+
+    lines.join("\n")
+}
+
+// Types.
+
+/// The enclosing list items of a line (by their indentation in the input, and their level), which decide the level of
+/// nested list items.
+///
+/// Levels follow from how items are nested, rather than from how far they are indented, so that any indentation (e.g.,
+/// two spaces, three, or a tab) is normalized, and normalized lists (four spaces per level) are left as is.
+#[derive(Debug, Default)]
+struct ListNesting {
+    items: Vec<(usize, usize)>,
+}
+
+impl ListNesting {
+    /// Get the level of a list item with some indentation (and keep it, as the enclosing item of deeper ones).
+    fn level(&mut self, indent: &str) -> usize {
+        let width = indent.chars().map(|c| if c == '\t' { 4 } else { 1 }).sum::<usize>();
+
+        while self.items.last().is_some_and(|&(item_width, _)| item_width >= width) {
+            self.items.pop();
+        }
+
+        // An indented item without an enclosing one gets a level for every (started) four spaces.
+        let level = match self.items.last() {
+            Some(&(_, parent_level)) => parent_level + 1,
+            None => width.div_ceil(4),
+        };
+        self.items.push((width, level));
+
+        level
+    }
+
+    /// End the list (e.g., at an unindented line that is not a list item).
+    fn reset(&mut self) {
+        self.items.clear();
+    }
+}
+
+// Helpers.
+
+/// Convert a single line (outside of any fenced code block).
+fn convert_line(line: &str, nesting: &mut ListNesting) -> String {
+    let trimmed = line.trim_start();
+    let indent = &line[..line.len() - trimmed.len()];
+
+    // Unordered list items become bullets.
+    if let Some(content) = ["- ", "* ", "+ "].iter().find_map(|marker| trimmed.strip_prefix(marker)) {
+        return format!("{}• {}", "    ".repeat(nesting.level(indent)), convert_inline(content.trim_start()));
+    }
+
+    // Ordered list items are normalized to `1.`.
+    if let Some((number, content)) = ordered_item(trimmed) {
+        return format!("{}{}. {}", "    ".repeat(nesting.level(indent)), number, convert_inline(content));
+    }
+
+    if indent.is_empty() && !trimmed.is_empty() {
+        nesting.reset();
+    }
+
+    // Headers become bold lines (any bold inside the header is redundant).
+    if let Some(content) = header_content(trimmed) {
+        return format!("*{}*", convert_inline(&content.replace("**", "")));
+    }
+
+    format!("{}{}", indent, convert_inline(trimmed))
+}
+
+/// Get the content of a header line (e.g., `## Summary`), if it is one.
+fn header_content(line: &str) -> Option<&str> {
+    let level = line.chars().take_while(|c| *c == '#').count();
+    if level == 0 || level > 6 {
+        return None;
+    }
+
+    let content = line[level..].strip_prefix(' ')?;
+
+    Some(content.trim().trim_end_matches('#').trim_end())
+}
+
+/// Get the number and content of an ordered list item (e.g., `2. Restart` or `2) Restart`), if it is one.
+fn ordered_item(line: &str) -> Option<(&str, &str)> {
+    let digits = line.chars().take_while(char::is_ascii_digit).count();
+    if digits == 0 || digits > 9 {
+        return None;
+    }
+
+    let rest = line[digits..].strip_prefix('.').or_else(|| line[digits..].strip_prefix(')'))?;
+    let content = rest.strip_prefix(' ')?;
+
+    Some((&line[..digits], content.trim_start()))
+}
+
+/// Convert the inline formatting of some text, leaving inline code untouched.
+fn convert_inline(text: &str) -> String {
+    // Odd segments between the backticks are code.
+    text.split('`')
+        .enumerate()
+        .map(|(i, segment)| if i % 2 == 1 { segment.to_string() } else { convert_emphasis(&convert_links(segment)) })
+        .collect::<Vec<_>>()
+        .join("`")
+}
+
+/// Convert markdown links (and images) into Slack links.
+fn convert_links(text: &str) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('[') {
+        let (before, after) = rest.split_at(start);
+
+        match parse_link(after) {
+            Some((label, url, consumed)) => {
+                // The `!` of an image is dropped, and the image is linked instead.
+                result.push_str(before.strip_suffix('!').unwrap_or(before));
+                result.push_str(&format!("<{}|{}>", url, label.replace("**", "").replace('|', "/")));
+                rest = &after[consumed..];
+            }
+            None => {
+                result.push_str(before);
+                result.push('[');
+                rest = &after[1..];
+            }
+        }
+    }
+
+    result.push_str(rest);
+
+    result
+}
+
+/// Parse a markdown link (e.g., `[text](url "title")`) at the start of some text.
+///
+/// Returns the label, the URL, and the length of the link.
+fn parse_link(text: &str) -> Option<(&str, &str, usize)> {
+    let close = text.find(']')?;
+    let label = &text[1..close];
+
+    let target_start = close + 2;
+    if label.is_empty() || !text[close + 1..].starts_with('(') {
+        return None;
+    }
+
+    let target_len = text[target_start..].find(')')?;
+    let url = text[target_start..target_start + target_len].split_whitespace().next()?;
+
+    Some((label, url, target_start + target_len + 1))
+}
+
+/// Convert bold (and bold italic) and strikethrough markers.
+fn convert_emphasis(text: &str) -> String {
+    // Bold italic markers alternate between opening and closing.
+    let mut result = String::with_capacity(text.len());
+    for (i, segment) in text.split("***").enumerate() {
+        if i > 0 {
+            result.push_str(if i % 2 == 1 { "*_" } else { "_*" });
+        }
+
+        result.push_str(segment);
+    }
+
+    result.replace("**", "*").replace("~~", "~")
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bold_and_strikethrough() {
+        assert_eq!(markdown_to_mrkdwn("This is **important**."), "This is *important*.");
+        assert_eq!(markdown_to_mrkdwn("**One** and **two**"), "*One* and *two*");
+        assert_eq!(markdown_to_mrkdwn("***Very*** important"), "*_Very_* important");
+        assert_eq!(markdown_to_mrkdwn("~~old~~ new"), "~old~ new");
+    }
+
+    #[test]
+    fn test_links() {
+        assert_eq!(markdown_to_mrkdwn("See [the docs](https://example.com/docs)."), "See <https://example.com/docs|the docs>.");
+        assert_eq!(markdown_to_mrkdwn("[a](https://a.com) and [b](https://b.com \"B\")"), "<https://a.com|a> and <https://b.com|b>");
+        assert_eq!(markdown_to_mrkdwn("![diagram](https://example.com/d.png)"), "<https://example.com/d.png|diagram>");
+
+        // Brackets that are not links are left alone.
+        assert_eq!(markdown_to_mrkdwn("[WIP] and [ ] todo"), "[WIP] and [ ] todo");
+        assert_eq!(markdown_to_mrkdwn("[a] then [b](https://b.com)"), "[a] then <https://b.com|b>");
+    }
+
+    #[test]
+    fn test_nested_formatting() {
+        assert_eq!(markdown_to_mrkdwn("**See [the docs](https://example.com)**"), "*See <https://example.com|the docs>*");
+        assert_eq!(markdown_to_mrkdwn("[**bold label**](https://example.com)"), "<https://example.com|bold label>");
+        assert_eq!(markdown_to_mrkdwn("- **Step**: run `make **all**`"), "• *Step*: run `make **all**`");
+    }
+
+    #[test]
+    fn test_headers() {
+        assert_eq!(markdown_to_mrkdwn("# Summary"), "*Summary*");
+        assert_eq!(markdown_to_mrkdwn("### **Next** steps ###"), "*Next steps*");
+        assert_eq!(markdown_to_mrkdwn("#hashtag"), "#hashtag");
+        assert_eq!(markdown_to_mrkdwn("####### Too deep"), "####### Too deep");
+    }
+
+    #[test]
+    fn test_lists() {
+        assert_eq!(markdown_to_mrkdwn("- one\n* two\n+ three"), "• one\n• two\n• three");
+        assert_eq!(markdown_to_mrkdwn("1. one\n2) two"), "1. one\n2. two");
+        assert_eq!(markdown_to_mrkdwn("- one\n  - nested\n    - deeper"), "• one\n    • nested\n        • deeper");
+        assert_eq!(markdown_to_mrkdwn("\t1. tabbed"), "    1. tabbed");
+        assert_eq!(markdown_to_mrkdwn("1. one\n   1. nested\n2. two"), "1. one\n    1. nested\n2. two");
+        assert_eq!(markdown_to_mrkdwn("- one\n\t- tabbed\n- two\n  - nested"), "• one\n    • tabbed\n• two\n    • nested");
+
+        // Emphasis at the start of a line is not a list.
+        assert_eq!(markdown_to_mrkdwn("*Summary*: broken"), "*Summary*: broken");
+        assert_eq!(markdown_to_mrkdwn("2024 was a year"), "2024 was a year");
+    }
+
+    #[test]
+    fn test_lists_convert_twice() {
+        let texts = [
+            "- one\n  - nested\n    - deeper\n  - back",
+            "1. one\n   1. nested\n      1. deeper\n2. two",
+            "1. one\n    - four\n        - eight\n\nDone.\n  - alone",
+            "\t1. tabbed\n\t\t1. twice",
+        ];
+
+        for text in texts {
+            let once = markdown_to_mrkdwn(text);
+            assert_eq!(markdown_to_mrkdwn(&once), once, "{text:?}");
+        }
+    }
+
+    #[test]
+    fn test_code_blocks() {
+        let text = "Try this:\n```rust\nlet x = **y**; // [not](a link)\n# not a header\n```\n**Done**";
+        assert_eq!(markdown_to_mrkdwn(text), "Try this:\n```\nlet x = **y**; // [not](a link)\n# not a header\n```\n*Done*");
+
+        // An unterminated fence protects the rest of the message.
+        assert_eq!(markdown_to_mrkdwn("```\n**raw**"), "```\n**raw**");
+
+        // A single-line fence does not swallow the rest of the message.
+        assert_eq!(markdown_to_mrkdwn("```**raw**```\n**bold**"), "```**raw**```\n*bold*");
+
+        // Inline code is untouched.
+        assert_eq!(markdown_to_mrkdwn("Run `rm -rf **/target` and **wait**"), "Run `rm -rf **/target` and *wait*");
+    }
+
+    #[test]
+    fn test_mrkdwn_is_untouched() {
+        let text = "*Summary*: <@U12345> please see <https://example.com|the docs>.\n\n• _one_\n• ~two~\n> quoted";
+
+        assert_eq!(markdown_to_mrkdwn(text), text);
+        assert_eq!(markdown_to_mrkdwn(&markdown_to_mrkdwn("# **Hi** [x](https://x.com)")), "*Hi <https://x.com|x>*");
+    }
+}
@@ -10,32 +10,70 @@
 
 use crate::{
     base::{
-        config::Config,
+        cache::TtlCache,
+        config::{Config, SlackWorkspaceConfig},
         types::{Res, Void},
     },
     interaction,
     service::{db::DbClient, llm::LlmClient, mcp::McpClient},
 };
 use async_trait::async_trait;
+use axum::{
+    Extension,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
 use hyper_rustls::HttpsConnector;
 use hyper_util::client::legacy::connect::HttpConnector;
-use slack_morphism::{errors::SlackClientError, prelude::*};
-use tracing::{info, instrument, warn};
+use serde_json::Value;
+use slack_morphism::{
+    errors::{SlackClientApiError, SlackClientError},
+    prelude::*,
+};
+use tracing::{error, info, instrument, warn};
 
-use std::{ops::Deref, sync::Arc};
+use std::{
+    collections::HashMap,
+    ops::Deref,
+    sync::{
+        Arc,
+        atomic::{AtomicI64, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use super::{ChatClient, GenericChatClient};
+use super::{BODY_BLOCK_ID_PREFIX, ChannelInfo, ChatClient, GenericChatClient, MessageOptions, ScheduledMessageInfo, UserGroup, UserInfo, is_text_mime_type, mrkdwn, split};
 
 // Type aliases.
 
 type FullClient = slack_morphism::SlackClient<SlackClientHyperConnector<HttpsConnector<HttpConnector>>>;
 
+// Statics.
+
+/// How long resolved permalinks are cached (they never change, but there is no need to keep them forever).
+const PERMALINK_CACHE_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+
+/// Slack's limit on the text of a section block (a message with blocks carries its first chunk in one section).
+const SECTION_TEXT_LIMIT: usize = 3000;
+
+/// The delay before the first socket mode reconnect (doubled for each consecutive attempt).
+const SOCKET_MODE_BASE_BACKOFF: Duration = Duration::from_secs(2);
+
+/// The longest delay between socket mode reconnects.
+const SOCKET_MODE_MAX_BACKOFF: Duration = Duration::from_secs(5 * 60);
+
+/// How long a socket mode connection must stay up for the reconnect backoff to reset.
+const SOCKET_MODE_STABLE_AFTER: Duration = Duration::from_secs(5 * 60);
+
+/// How long to wait for a termination signal after the socket mode listener stops, to tell a shutdown from a dropped connection.
+const SOCKET_MODE_SHUTDOWN_GRACE: Duration = Duration::from_secs(1);
+
 // Extra methods on `ChatClient` applied by the slack implementation.
 
 impl ChatClient {
-    /// Creates a new Slack chat client.
-    pub async fn slack(config: &Config, db: DbClient, llm: LlmClient, mcp: McpClient) -> Res<Self> {
-        let client = SlackChatClient::new(config, db.clone(), llm.clone(), mcp.clone()).await?;
+    /// Creates a new Slack chat client for one workspace.
+    pub async fn slack(config: &Config, workspace: &SlackWorkspaceConfig, db: DbClient, llm: LlmClient, mcp: McpClient) -> Res<Self> {
+        let client = SlackChatClient::new(config, workspace, db.clone(), llm.clone(), mcp.clone()).await?;
         Ok(Self { inner: Arc::new(client) })
     }
 }
@@ -50,23 +88,54 @@ impl From<SlackChatClient> for ChatClient {
 
 /// User state for the slack socket client.
 struct SlackUserState {
+    config: Config,
     db: DbClient,
     llm: LlmClient,
     chat: ChatClient,
     mcp: McpClient,
     bot_user_id: String,
+    bot_id: Option<String>,
+    channel_namespace: Option<String>,
+    last_event_at: Arc<AtomicI64>,
+}
+
+impl SlackUserState {
+    /// Record that an event was received (now), so that connection staleness can be reported.
+    fn record_event(&self) {
+        self.last_event_at.store(chrono::Utc::now().timestamp(), Ordering::Relaxed);
+    }
+
+    /// Qualify a Slack channel ID from an event with the workspace namespace (if any), for use in the interaction layer.
+    fn channel_id(&self, channel_id: &str) -> String {
+        qualify_channel_id(self.channel_namespace.as_deref(), channel_id)
+    }
+
+    /// Whether a message was sent by the bot itself.
+    fn is_own_message(&self, sender: &SlackMessageSender) -> bool {
+        is_own_message(sender, &self.bot_user_id, self.bot_id.as_deref())
+    }
 }
 
 /// Slack client implementation.
 #[derive(Clone)]
 struct SlackChatClient {
+    pub config: Config,
+    pub workspace: SlackWorkspaceConfig,
     pub app_token: SlackApiToken,
     pub bot_token: SlackApiToken,
     pub bot_user_id: String,
+    pub bot_id: Option<String>,
+    pub channel_namespace: Option<String>,
     pub client: Arc<FullClient>,
     pub db: DbClient,
     pub llm: LlmClient,
     pub mcp: McpClient,
+    pub user_cache: TtlCache<String, UserInfo>,
+    pub permalink_cache: TtlCache<(String, String), String>,
+    pub user_group_cache: TtlCache<(), Vec<UserGroup>>,
+    pub last_event_at: Arc<AtomicI64>,
+    /// The HTTP client for downloading private files.
+    pub http_client: reqwest::Client,
 }
 
 impl Deref for SlackChatClient {
@@ -80,11 +149,11 @@ impl Deref for SlackChatClient {
 impl SlackChatClient {
     /// Create a new Slack chat client.
     #[instrument(name = "SlackChatClient::new", skip_all)]
-    pub async fn new(config: &Config, db: DbClient, llm: LlmClient, mcp: McpClient) -> Res<Self> {
+    pub async fn new(config: &Config, workspace: &SlackWorkspaceConfig, db: DbClient, llm: LlmClient, mcp: McpClient) -> Res<Self> {
         // Initialize tokens.
 
-        let app_token = SlackApiToken::new(SlackApiTokenValue(config.slack_app_token.clone()));
-        let bot_token = SlackApiToken::new(SlackApiTokenValue(config.slack_bot_token.clone()));
+        let app_token = SlackApiToken::new(SlackApiTokenValue(workspace.app_token.clone()));
+        let bot_token = SlackApiToken::new(SlackApiTokenValue(workspace.bot_token.clone()));
 
         // Initialize the Slack client.
 
@@ -95,50 +164,191 @@ impl SlackChatClient {
         // Get the bot's user ID.
 
         let session = client.open_session(&bot_token);
-        let bot_user = session.auth_test().await?;
+        let bot_user = call_with_rate_limit_retry(config.slack_max_retries, || session.auth_test()).await?;
         let bot_user_id = bot_user.user_id.0;
+        let bot_id = bot_user.bot_id.map(|id| id.0);
 
-        info!("Slack bot user ID: {}", bot_user_id);
+        info!("Slack bot user ID for workspace `{}`: {}", workspace.name, bot_user_id);
+
+        // With multiple workspaces, channels are namespaced by team ID, so that they don't collide in the database.
+        let channel_namespace = config.is_multi_workspace().then_some(bot_user.team_id.0);
 
         Ok(Self {
+            config: config.clone(),
+            workspace: workspace.clone(),
             app_token,
             bot_token,
             bot_user_id,
+            bot_id,
+            channel_namespace,
             client,
             db,
             llm,
             mcp,
+            user_cache: TtlCache::new(Duration::from_secs(config.slack_user_cache_ttl_seconds)),
+            permalink_cache: TtlCache::new(PERMALINK_CACHE_TTL),
+            user_group_cache: TtlCache::new(Duration::from_secs(config.slack_user_cache_ttl_seconds)),
+            last_event_at: Arc::new(AtomicI64::new(0)),
+            http_client: reqwest::Client::new(),
         })
     }
 }
 
-#[async_trait]
-impl GenericChatClient for SlackChatClient {
-    fn bot_user_id(&self) -> &str {
-        &self.bot_user_id
+impl SlackChatClient {
+    /// Call a Slack API method, retrying according to the configured rate limit policy.
+    async fn call_with_retry<T, F, Fut>(&self, call: F) -> Result<T, SlackClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, SlackClientError>>,
+    {
+        call_with_rate_limit_retry(self.config.slack_max_retries, call).await
     }
 
-    async fn start(&self) -> Void {
-        // Initialize the socket mode listener.
+    /// Start downloading a private file, which is fetched directly, authorized with the bot token.
+    async fn download_file(&self, url: &str) -> Res<reqwest::Response> {
+        self.http_client
+            .get(url)
+            .bearer_auth(&self.workspace.bot_token)
+            .send()
+            .await?
+            .error_for_status()
+            .map_err(|e| anyhow::anyhow!("Failed to download file: {}", e))
+    }
 
-        let socket_mode_callbacks = SlackSocketModeListenerCallbacks::new()
-            .with_command_events(handle_command_event)
-            .with_interaction_events(handle_interaction_event)
-            .with_push_events(handle_push_event);
+    /// Call a Slack API method in a channel, joining the channel and retrying once if the bot is not in it.
+    ///
+    /// If joining fails, the original error is returned.
+    async fn call_with_join_retry<T, F, Fut>(&self, channel_id: &str, call: F) -> Result<T, SlackClientError>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, SlackClientError>>,
+    {
+        match self.call_with_retry(&call).await {
+            Err(err) if is_retryable_after_join(&err) => {
+                info!("Bot is not in channel `{}`, joining it ...", channel_id);
+
+                if let Err(join_err) = self.join_channel(channel_id).await {
+                    warn!("Failed to join channel `{}`: {}", channel_id, join_err);
+                    return Err(err);
+                }
 
-        // Initialize the socket mode listener environment.
+                self.call_with_retry(&call).await
+            }
+            result => result,
+        }
+    }
+
+    /// Post a single plain text message (that already fits Slack's message length limit).
+    async fn post_text(&self, channel_id: &str, thread_ts: &str, text: &str) -> Res<String> {
+        let message = SlackMessageContent::new().with_text(text.to_string());
+
+        let mut request = SlackApiChatPostMessageRequest::new(slack_channel_id(channel_id), message).with_as_user(true).with_link_names(true);
+
+        // An empty thread posts to the channel itself.
+        if !thread_ts.is_empty() {
+            request = request.with_thread_ts(SlackTs(thread_ts.to_string()));
+        }
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self
+            .call_with_join_retry(channel_id, || session.chat_post_message(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send message: {}", e))?;
+
+        Ok(response.ts.0)
+    }
+
+    /// Post the remaining chunks of a split message to the thread, in order.
+    async fn post_chunks(&self, channel_id: &str, thread_ts: &str, chunks: &[String]) -> Void {
+        for chunk in chunks {
+            self.post_text(channel_id, thread_ts, chunk).await?;
+        }
+
+        Ok(())
+    }
 
-        let listener_environment = Arc::new(SlackClientEventsListenerEnvironment::new(self.client.clone()).with_user_state(SlackUserState {
+    /// Build the listener environment shared by the socket mode and events API listeners.
+    fn listener_environment(&self) -> Arc<SlackHyperListenerEnvironment> {
+        Arc::new(SlackClientEventsListenerEnvironment::new(self.client.clone()).with_user_state(SlackUserState {
+            config: self.config.clone(),
             db: self.db.clone(),
             llm: self.llm.clone(),
             bot_user_id: self.bot_user_id.clone(),
+            bot_id: self.bot_id.clone(),
+            channel_namespace: self.channel_namespace.clone(),
             chat: ChatClient::from(self.clone()),
             mcp: self.mcp.clone(),
-        }));
+            last_event_at: self.last_event_at.clone(),
+        }))
+    }
+
+    /// Start listening for events over Socket Mode, reconnecting (with backoff) whenever the listener stops.
+    ///
+    /// After `SLACK_SOCKET_MAX_RECONNECTS` consecutive failed reconnects, the error is returned (so that the
+    /// process exits and can be restarted), rather than leaving the bot running without a connection.
+    #[instrument(skip_all)]
+    async fn start_socket_mode(&self) -> Void {
+        let mut shutdown = tokio::spawn(shutdown_signal());
+        let mut attempts = 0;
+
+        loop {
+            let connected_at = Instant::now();
+            let result = self.serve_socket_mode().await;
+
+            // The listener also stops on a termination signal, in which case the bot is shutting down.
+            if tokio::time::timeout(SOCKET_MODE_SHUTDOWN_GRACE, &mut shutdown).await.is_ok() {
+                info!("Slack socket mode listener for workspace `{}` stopped.", self.workspace.name);
+
+                return Ok(());
+            }
+
+            // A connection that stayed up for a while resets the backoff.
+            if connected_at.elapsed() >= SOCKET_MODE_STABLE_AFTER {
+                attempts = 0;
+            }
+
+            attempts += 1;
+
+            let reason = match result {
+                Ok(()) => "the connection was dropped".to_string(),
+                Err(err) => err.to_string(),
+            };
+
+            let max_reconnects = self.config.slack_socket_max_reconnects;
+            if max_reconnects > 0 && attempts > max_reconnects {
+                error!(
+                    "Slack socket mode listener for workspace `{}` stopped ({}), and {} reconnects failed; giving up.",
+                    self.workspace.name, reason, max_reconnects
+                );
+
+                return Err(anyhow::anyhow!("Slack socket mode listener for workspace `{}` could not reconnect: {}", self.workspace.name, reason));
+            }
+
+            let delay = socket_mode_backoff(attempts);
+            warn!(
+                "Slack socket mode listener for workspace `{}` stopped ({}); reconnecting in {:?} (attempt {}) ...",
+                self.workspace.name, reason, delay, attempts
+            );
+
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Connect over Socket Mode, and serve events until the listener stops.
+    async fn serve_socket_mode(&self) -> Void {
+        info!("Starting Slack socket mode listener for workspace `{}` ...", self.workspace.name);
+
+        // Initialize the socket mode listener.
+
+        let socket_mode_callbacks = SlackSocketModeListenerCallbacks::new()
+            .with_command_events(handle_command_event)
+            .with_interaction_events(handle_interaction_event)
+            .with_push_events(handle_push_event);
 
         let socket_mode_listener = Arc::new(SlackClientSocketModeListener::new(
             &SlackClientSocketModeConfig::new(),
-            listener_environment.clone(),
+            self.listener_environment(),
             socket_mode_callbacks,
         ));
 
@@ -146,156 +356,1506 @@ impl GenericChatClient for SlackChatClient {
         socket_mode_listener.listen_for(&self.app_token).await?;
 
         // Start WS connections calling Slack API to get WS url for the token,
-        // and wait for Ctrl-C to shutdown.
+        // and wait for the connection to stop (or Ctrl-C to shutdown).
         // There are also `.start()`/`.shutdown()` available to manage manually
         socket_mode_listener.serve().await;
 
         Ok(())
     }
 
+    /// Start listening for events over the HTTP Events API.
+    ///
+    /// Requests are verified with the signing secret before being dispatched.
+    #[instrument(skip_all)]
+    async fn start_events_api(&self) -> Void {
+        info!(
+            "Starting Slack events API listener for workspace `{}` on `{}` ...",
+            self.workspace.name, self.workspace.events_bind_address
+        );
+
+        let listener = SlackEventsAxumListener::<HttpsConnector<HttpConnector>>::new(self.listener_environment());
+        let signing_secret = SlackSigningSecret(self.workspace.signing_secret.clone());
+
+        let app = axum::Router::new()
+            .route(
+                "/push",
+                axum::routing::post(handle_events_api_push_event).layer(listener.events_layer(&signing_secret).with_event_extractor(SlackEventsExtractors::push_event())),
+            )
+            .route(
+                "/command",
+                axum::routing::post(handle_events_api_command_event).layer(listener.events_layer(&signing_secret).with_event_extractor(SlackEventsExtractors::command_event())),
+            )
+            .route(
+                "/interaction",
+                axum::routing::post(handle_events_api_interaction_event).layer(listener.events_layer(&signing_secret).with_event_extractor(SlackEventsExtractors::interaction_event())),
+            );
+
+        let tcp_listener = tokio::net::TcpListener::bind(&self.workspace.events_bind_address).await?;
+
+        axum::serve(tcp_listener, app).await?;
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GenericChatClient for SlackChatClient {
+    fn bot_user_id(&self) -> &str {
+        &self.bot_user_id
+    }
+
+    fn last_event_at(&self) -> Option<i64> {
+        let last_event_at = self.last_event_at.load(Ordering::Relaxed);
+
+        (last_event_at > 0).then_some(last_event_at)
+    }
+
+    fn convert_markdown(&self, text: &str) -> String {
+        mrkdwn::markdown_to_mrkdwn(text)
+    }
+
     #[instrument(skip(self))]
-    async fn send_message(&self, channel_id: &str, thread_ts: &str, text: &str) -> Void {
-        let message = SlackMessageContent::new().with_text(text.to_string());
+    async fn healthy(&self) -> Void {
+        let session = self.client.open_session(&self.bot_token);
+
+        probe_health(self.call_with_retry(|| session.auth_test()).await)
+    }
+
+    async fn start(&self) -> Void {
+        match self.config.slack_mode.as_str() {
+            "events" => self.start_events_api().await,
+            _ => self.start_socket_mode().await,
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn send_message(&self, channel_id: &str, thread_ts: &str, text: &str) -> Res<String> {
+        let chunks = split::split_message(&self.convert_markdown(text), self.config.slack_message_max_chars);
+
+        let ts = self.post_text(channel_id, thread_ts, &chunks[0]).await?;
+        self.post_chunks(channel_id, thread_ts, &chunks[1..]).await?;
+
+        Ok(ts)
+    }
+
+    #[instrument(skip(self, blocks))]
+    async fn send_blocks(&self, channel_id: &str, thread_ts: &str, text: &str, blocks: &[Value], options: &MessageOptions) -> Res<String> {
+        // The first chunk carries the blocks (e.g., the classification context and the buttons), and the rest follow it in the thread.
+        let chunks = split::split_message(&self.convert_markdown(text), self.config.slack_message_max_chars.min(SECTION_TEXT_LIMIT));
+        let blocks: Vec<SlackBlock> = serde_json::from_value(Value::Array(replace_section_text(&convert_block_text(blocks, |text| self.convert_markdown(text)), &chunks[0])))?;
+        let message = SlackMessageContent::new().with_text(chunks[0].clone()).with_blocks(blocks);
 
-        let request = SlackApiChatPostMessageRequest::new(SlackChannelId(channel_id.to_string()), message)
+        let request = SlackApiChatPostMessageRequest::new(slack_channel_id(channel_id), message)
             .with_as_user(true)
             .with_thread_ts(SlackTs(thread_ts.to_string()))
+            .with_link_names(true)
+            .with_reply_broadcast(options.reply_broadcast);
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self
+            .call_with_join_retry(channel_id, || session.chat_post_message(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send blocks: {}", e))?;
+
+        self.post_chunks(channel_id, thread_ts, &chunks[1..]).await?;
+
+        Ok(response.ts.0)
+    }
+
+    #[instrument(skip(self))]
+    async fn update_message(&self, channel_id: &str, ts: &str, text: &str) -> Void {
+        let message = SlackMessageContent::new().with_text(self.convert_markdown(text));
+
+        let request = SlackApiChatUpdateRequest::new(slack_channel_id(channel_id), message, SlackTs(ts.to_string()))
+            .with_as_user(true)
             .with_link_names(true);
 
         let session = self.client.open_session(&self.bot_token);
 
-        let _ = session.chat_post_message(&request).await.map_err(|e| anyhow::anyhow!("Failed to send message: {}", e))?;
+        let _ = self
+            .call_with_retry(|| session.chat_update(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update message: {}", e))?;
 
         Ok(())
     }
 
-    #[instrument(skip(self))]
-    async fn react_to_message(&self, channel_id: &str, thread_ts: &str, emoji: &str) -> Void {
-        let request = SlackApiReactionsAddRequest {
-            channel: SlackChannelId(channel_id.to_string()),
-            name: SlackReactionName(emoji.to_string()),
-            timestamp: SlackTs(thread_ts.to_string()),
-        };
+    #[instrument(skip(self, blocks))]
+    async fn update_blocks(&self, channel_id: &str, ts: &str, text: &str, blocks: &[Value], options: &MessageOptions) -> Void {
+        // As with `send_blocks`, the edited message carries the first chunk, and the rest follow it in the thread.
+        let chunks = split::split_message(&self.convert_markdown(text), self.config.slack_message_max_chars.min(SECTION_TEXT_LIMIT));
+        let blocks: Vec<SlackBlock> = serde_json::from_value(Value::Array(replace_section_text(&convert_block_text(blocks, |text| self.convert_markdown(text)), &chunks[0])))?;
+        let message = SlackMessageContent::new().with_text(chunks[0].clone()).with_blocks(blocks);
+
+        let request = SlackApiChatUpdateRequest::new(slack_channel_id(channel_id), message, SlackTs(ts.to_string()))
+            .with_as_user(true)
+            .with_link_names(true)
+            .with_reply_broadcast(options.reply_broadcast);
 
         let session = self.client.open_session(&self.bot_token);
 
-        let _ = session.reactions_add(&request).await.map_err(|e| anyhow::anyhow!("Failed to react to message: {}", e))?;
+        let response = self
+            .call_with_retry(|| session.chat_update(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update blocks: {}", e))?;
+
+        if chunks.len() > 1 {
+            let thread_ts = response.thread_ts.map(|ts| ts.0).unwrap_or_else(|| ts.to_string());
+            self.post_chunks(channel_id, &thread_ts, &chunks[1..]).await?;
+        }
 
         Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn get_thread_context(&self, channel_id: &str, thread_ts: &str) -> Res<String> {
-        let request = SlackApiConversationsRepliesRequest::new(SlackChannelId(channel_id.to_string()), SlackTs(thread_ts.to_string()));
+    async fn delete_message(&self, channel_id: &str, ts: &str) -> Void {
+        let request = SlackApiChatDeleteRequest::new(slack_channel_id(channel_id), SlackTs(ts.to_string())).with_as_user(true);
+
         let session = self.client.open_session(&self.bot_token);
 
-        let response = session.conversations_replies(&request).await;
+        let _ = self
+            .call_with_retry(|| session.chat_delete(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete message: {}", e))?;
 
-        let response = if let Err(e) = &response
-            && let SlackClientError::ApiError(ae) = e
-            && ae.code == "thread_not_found"
-        {
-            // If the thread is not found (due to this being a top-level message), we can just return an empty string.
-            return Ok("".to_string());
-        } else {
-            response?
-        };
+        Ok(())
+    }
 
-        let messages = serde_json::to_string(&response.messages)?;
+    #[instrument(skip(self))]
+    async fn send_placeholder(&self, channel_id: &str, thread_ts: &str, text: &str) -> Res<Option<String>> {
+        if !self.config.slack_thinking_placeholder_enabled {
+            return Ok(None);
+        }
 
-        Ok(messages)
+        let ts = self.send_message(channel_id, thread_ts, text).await?;
+
+        Ok(Some(ts))
     }
-}
 
-// Socket mode listener callbacks for Slack..
+    #[instrument(skip(self))]
+    async fn send_ephemeral_message(&self, channel_id: &str, user_id: &str, thread_ts: &str, text: &str) -> Void {
+        let message = SlackMessageContent::new().with_text(self.convert_markdown(text));
 
-/// Handles command events from Slack.
-async fn handle_command_event(
-    event: SlackCommandEvent,
-    _client: Arc<SlackHyperClient>,
-    _states: SlackClientEventsUserState,
-) -> Result<SlackCommandEventResponse, Box<dyn std::error::Error + Send + Sync>> {
-    warn!("[COMMAND] {:#?}", event);
-    Ok(SlackCommandEventResponse::new(SlackMessageContent::new().with_text("No app commands are currently supported.".into())))
-}
+        let request = SlackApiChatPostEphemeralRequest::new(slack_channel_id(channel_id), SlackUserId(user_id.to_string()), message)
+            .with_as_user(true)
+            .with_thread_ts(SlackTs(thread_ts.to_string()))
+            .with_link_names(true);
 
-/// Handles interaction events from Slack.
-async fn handle_interaction_event(event: SlackInteractionEvent, _client: Arc<SlackHyperClient>, _states: SlackClientEventsUserState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    warn!("[INTERACTION] {:#?}", event);
-    Ok(())
-}
+        let session = self.client.open_session(&self.bot_token);
 
-/// Handles push events from Slack.
-#[instrument(skip_all)]
-async fn handle_push_event(event_callback: SlackPushEventCallback, _client: Arc<SlackHyperClient>, states: SlackClientEventsUserState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
-    let event = event_callback.event;
-    let states = states.read().await;
-    let user_state = states.get_user_state::<SlackUserState>().ok_or(anyhow::anyhow!("Failed to get user state"))?;
+        let _ = self
+            .call_with_retry(|| session.chat_post_ephemeral(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to send ephemeral message: {}", e))?;
 
-    match event {
-        SlackEventCallbackBody::Message(slack_message_event) => {
-            info!("Received message event ...");
-            let channel_id = slack_message_event.origin.channel.as_ref().ok_or(anyhow::anyhow!("Failed to get channel ID"))?.0.to_owned();
+        Ok(())
+    }
 
-            // No matter what, we are going to store the message in the database for future reference.
-            interaction::message_storage::handle_message_storage(slack_message_event.clone(), channel_id.clone(), user_state.db.clone());
+    #[instrument(skip(self, text))]
+    async fn unfurl_link(&self, channel_id: &str, ts: &str, url: &str, text: &str) -> Void {
+        let unfurls = std::collections::HashMap::from([(url.to_string(), SlackApiChatUnfurlMapItem::new(text.to_string()))]);
 
-            // If the message @mentions the bot, skip, and let the app mention handler take care of it.
-            let text = slack_message_event.content.as_ref().map(|c| c.text.as_deref()).unwrap_or_default().unwrap_or_default();
-            if text.contains(&user_state.bot_user_id) {
-                warn!("Skipping message event because it mentions the bot.");
-                return Ok(());
-            }
+        let request = SlackApiChatUnfurlRequest::new(slack_channel_id(channel_id), SlackTs(ts.to_string()), unfurls);
 
-            // If the message is in a thread, skip, since we don't want the bot to respond unless it is mentioned in a thread.
-            if slack_message_event.origin.thread_ts.is_some() {
-                warn!("Skipping message event because it is in a thread.");
-                return Ok(());
-            }
+        let session = self.client.open_session(&self.bot_token);
 
-            let thread_ts = slack_message_event.origin.thread_ts.clone().unwrap_or(SlackTs("".to_string())).0;
-            interaction::chat_event::handle_chat_event(
-                slack_message_event,
-                channel_id,
-                thread_ts,
-                user_state.db.clone(),
-                user_state.llm.clone(),
-                user_state.chat.clone(),
-                user_state.mcp.clone(),
-            );
+        let _ = self
+            .call_with_retry(|| session.chat_unfurl(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to unfurl link: {}", e))?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn react_to_message(&self, channel_id: &str, thread_ts: &str, emoji: &str) -> Void {
+        let request = SlackApiReactionsAddRequest {
+            channel: slack_channel_id(channel_id),
+            name: SlackReactionName(emoji.to_string()),
+            timestamp: SlackTs(thread_ts.to_string()),
+        };
+
+        let session = self.client.open_session(&self.bot_token);
+
+        match self.call_with_join_retry(channel_id, || session.reactions_add(&request)).await {
+            Ok(_) => Ok(()),
+            // Reacting twice (e.g., when a message is re-evaluated) is not a failure.
+            Err(SlackClientError::ApiError(ae)) if ae.code == "already_reacted" => Ok(()),
+            Err(e) => Err(anyhow::anyhow!("Failed to react to message: {}", e)),
         }
-        SlackEventCallbackBody::AppMention(slack_app_mention_event) => {
-            info!("Received app mention event ...");
+    }
 
-            let channel_id = slack_app_mention_event.channel.0.to_owned();
-            let thread_ts = slack_app_mention_event.origin.thread_ts.clone().unwrap_or(SlackTs("".to_string())).0;
-            interaction::chat_event::handle_chat_event(
-                slack_app_mention_event,
-                channel_id,
-                thread_ts,
-                user_state.db.clone(),
-                user_state.llm.clone(),
-                user_state.chat.clone(),
-                user_state.mcp.clone(),
-            );
+    #[instrument(skip(self, markdown))]
+    async fn publish_channel_directive(&self, channel_id: &str, markdown: &str) -> Void {
+        if !self.config.slack_directive_canvas_enabled {
+            return Ok(());
         }
-        //SlackEventCallbackBody::LinkShared(slack_link_shared_event) => todo!(),
-        //SlackEventCallbackBody::ReactionAdded(slack_reaction_added_event) => todo!(),
-        //SlackEventCallbackBody::ReactionRemoved(slack_reaction_removed_event) => todo!(),
-        //SlackEventCallbackBody::StarAdded(slack_star_added_event) => todo!(),
-        //SlackEventCallbackBody::StarRemoved(slack_star_removed_event) => todo!(),
-        _ => {
-            warn!("Received unhandled push event.")
+
+        let content = SlackCanvasDocumentContent::new(markdown.to_string());
+        let request = SlackApiConversationsCanvasesCreateRequest::new(slack_channel_id(channel_id)).with_document_content(content.clone());
+
+        let session = self.client.open_session(&self.bot_token);
+
+        // Create the channel canvas the first time, and replace its content after that.
+        match self.call_with_retry(|| session.conversations_canvases_create(&request)).await {
+            Ok(_) => return Ok(()),
+            Err(SlackClientError::ApiError(ae)) if ae.code == "channel_canvas_already_exists" => {}
+            Err(e) => return Err(anyhow::anyhow!("Failed to create channel canvas: {}", e)),
         }
+
+        // The canvas ID is only in the channel's properties (which are not modeled), so read them as JSON.
+        let params = vec![("channel", Some(slack_channel_id(channel_id).0))];
+        let info: Value = self
+            .call_with_retry(|| session.http_session_api.http_get("conversations.info", &params, None))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get channel canvas: {}", e))?;
+        let canvas_id = info
+            .pointer("/channel/properties/canvas/file_id")
+            .and_then(Value::as_str)
+            .ok_or(anyhow::anyhow!("Failed to find the canvas of channel `{}`", channel_id))?;
+
+        let change = SlackCanvasChange::new(SlackCanvasOperation::Replace).with_document_content(content);
+        let request = SlackApiCanvasesEditRequest::new(SlackCanvasId(canvas_id.to_string()), vec![change]);
+
+        let _ = self
+            .call_with_retry(|| session.canvases_edit(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to update channel canvas: {}", e))?;
+
+        Ok(())
     }
 
-    Ok(())
-}
+    #[instrument(skip(self))]
+    async fn join_channel(&self, channel_id: &str) -> Void {
+        let request = SlackApiConversationsJoinRequest::new(slack_channel_id(channel_id));
 
-// Tests.
+        let session = self.client.open_session(&self.bot_token);
 
-#[cfg(test)]
-mod tests {
-    // All mocked tests removed as they don't test the actual functionality.
-    // Unit tests should be added for any functionality that gets abstracted out of the client.
+        let _ = self
+            .call_with_retry(|| session.conversations_join(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to join channel: {}", e))?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, text))]
+    async fn schedule_message(&self, channel_id: &str, thread_ts: &str, text: &str, post_at: i64) -> Res<Option<String>> {
+        let post_at = chrono::DateTime::from_timestamp(post_at, 0).ok_or(anyhow::anyhow!("Invalid time to schedule a message for: {}", post_at))?;
+        let message = SlackMessageContent::new().with_text(text.to_string());
+
+        let request = SlackApiChatScheduleMessageRequest::new(slack_channel_id(channel_id), message, SlackDateTime(post_at)).with_thread_ts(SlackTs(thread_ts.to_string()));
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self
+            .call_with_retry(|| session.chat_schedule_message(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to schedule message: {}", e))?;
+
+        Ok(Some(response.scheduled_message_id.0))
+    }
+
+    #[instrument(skip(self))]
+    async fn list_scheduled_messages(&self, channel_id: &str) -> Res<Vec<ScheduledMessageInfo>> {
+        let session = self.client.open_session(&self.bot_token);
+
+        let mut scheduled_messages = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let mut request = SlackApiChatScheduledMessagesListRequest::new().with_channel(slack_channel_id(channel_id));
+
+            if let Some(cursor) = cursor.take() {
+                request = request.with_cursor(cursor);
+            }
+
+            let response = self
+                .call_with_retry(|| session.chat_scheduled_messages_list(&request))
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to list scheduled messages: {}", e))?;
+
+            scheduled_messages.extend(response.scheduled_messages.into_iter().map(|message| ScheduledMessageInfo {
+                id: message.id.0,
+                channel_id: channel_id.to_string(),
+                post_at: message.post_at.0.timestamp(),
+            }));
+
+            cursor = response.response_metadata.and_then(|m| m.next_cursor).filter(|c| !c.0.is_empty());
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        Ok(scheduled_messages)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_scheduled_message(&self, channel_id: &str, scheduled_message_id: &str) -> Void {
+        let request = SlackApiChatDeleteScheduledMessageRequest::new(slack_channel_id(channel_id), SlackScheduledMid(scheduled_message_id.to_string()));
+
+        let session = self.client.open_session(&self.bot_token);
+
+        self.call_with_retry(|| session.chat_delete_scheduled_message(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to delete scheduled message: {}", e))?;
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn pin_message(&self, channel_id: &str, ts: &str) -> Void {
+        let request = SlackApiPinsAddRequest::new(slack_channel_id(channel_id), SlackTs(ts.to_string()));
+
+        let session = self.client.open_session(&self.bot_token);
+
+        match self.call_with_retry(|| session.pins_add(&request)).await {
+            Ok(_) => Ok(()),
+            // Pinning twice is not a failure.
+            Err(SlackClientError::ApiError(ae)) if ae.code == "already_pinned" => Ok(()),
+            Err(e) => Err(anyhow::anyhow!("Failed to pin message: {}", e)),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn remove_reaction(&self, channel_id: &str, ts: &str, emoji: &str) -> Void {
+        let request = SlackApiReactionsRemoveRequest::new(SlackReactionName(emoji.to_string()))
+            .with_channel(slack_channel_id(channel_id))
+            .with_timestamp(SlackTs(ts.to_string()));
+
+        let session = self.client.open_session(&self.bot_token);
+
+        match self.call_with_retry(|| session.reactions_remove(&request)).await {
+            Ok(_) => Ok(()),
+            // The reaction is already gone, which is what we wanted.
+            Err(SlackClientError::ApiError(ae)) if ae.code == "no_reaction" => Ok(()),
+            Err(e) => Err(anyhow::anyhow!("Failed to remove reaction: {}", e)),
+        }
+    }
+
+    #[instrument(skip(self))]
+    async fn get_user_info(&self, user_id: &str) -> Res<UserInfo> {
+        if let Some(user_info) = self.user_cache.get(&user_id.to_string()) {
+            return Ok(user_info);
+        }
+
+        let request = SlackApiUsersInfoRequest::new(SlackUserId(user_id.to_string()));
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self
+            .call_with_retry(|| session.users_info(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get user info: {}", e))?;
+
+        let user = response.user;
+        let profile = user.profile.as_ref();
+        let display_name = [
+            profile.and_then(|p| p.display_name.clone()),
+            profile.and_then(|p| p.real_name.clone()),
+            user.real_name.clone(),
+            user.name.clone(),
+        ]
+        .into_iter()
+        .flatten()
+        .find(|name| !name.is_empty())
+        .unwrap_or_else(|| user_id.to_string());
+
+        let user_info = UserInfo {
+            id: user_id.to_string(),
+            display_name,
+            is_bot: user.flags.is_bot.unwrap_or_default(),
+        };
+
+        self.user_cache.insert(user_id.to_string(), user_info.clone());
+
+        Ok(user_info)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_user_groups(&self) -> Res<Vec<UserGroup>> {
+        if let Some(user_groups) = self.user_group_cache.get(&()) {
+            return Ok(user_groups);
+        }
+
+        let request = SlackApiUserGroupsListRequest::new();
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self
+            .call_with_retry(|| session.usergroups_list(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to list user groups: {}", e))?;
+
+        let user_groups = response
+            .usergroups
+            .into_iter()
+            .map(|group| UserGroup {
+                id: group.id.0,
+                handle: group.handle,
+                name: group.name,
+            })
+            .collect::<Vec<_>>();
+
+        self.user_group_cache.insert((), user_groups.clone());
+
+        Ok(user_groups)
+    }
+
+    #[instrument(skip_all)]
+    async fn resolve_user_group_mentions(&self, text: &str) -> String {
+        let user_groups = match self.get_user_groups().await {
+            Ok(user_groups) => user_groups,
+            Err(err) => {
+                warn!("Failed to resolve user group mentions: {}", err);
+                return text.to_string();
+            }
+        };
+
+        let handles = user_groups.into_iter().map(|group| (group.handle.to_lowercase(), group.id)).collect();
+
+        rewrite_user_group_handles(text, &handles)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_permalink(&self, channel_id: &str, ts: &str) -> Res<String> {
+        let key = (channel_id.to_string(), ts.to_string());
+
+        if let Some(permalink) = self.permalink_cache.get(&key) {
+            return Ok(permalink);
+        }
+
+        let request = SlackApiChatGetPermalinkRequest::new(slack_channel_id(channel_id), SlackTs(ts.to_string()));
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self
+            .call_with_retry(|| session.chat_get_permalink(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get permalink: {}", e))?;
+        let permalink = response.permalink.to_string();
+
+        self.permalink_cache.insert(key, permalink.clone());
+
+        Ok(permalink)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_info(&self, channel_id: &str) -> Res<ChannelInfo> {
+        let request = SlackApiConversationsInfoRequest::new(slack_channel_id(channel_id));
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self
+            .call_with_retry(|| session.conversations_info(&request))
+            .await
+            .map_err(|e| anyhow::anyhow!("Failed to get channel info: {}", e))?;
+        let channel = response.channel;
+
+        Ok(ChannelInfo {
+            id: channel.id.0,
+            name: channel.name.unwrap_or_default(),
+            topic: channel.topic.map(|t| t.value).unwrap_or_default(),
+            purpose: channel.purpose.map(|p| p.value).unwrap_or_default(),
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_history(&self, channel_id: &str, oldest_ts: &str) -> Res<Vec<Value>> {
+        const PAGE_SIZE: u16 = 200;
+
+        let session = self.client.open_session(&self.bot_token);
+
+        let mut messages = Vec::new();
+        let mut cursor = None;
+
+        loop {
+            let mut request = SlackApiConversationsHistoryRequest::new()
+                .with_channel(slack_channel_id(channel_id))
+                .with_oldest(SlackTs(oldest_ts.to_string()))
+                .with_limit(PAGE_SIZE);
+
+            if let Some(cursor) = cursor.take() {
+                request = request.with_cursor(cursor);
+            }
+
+            let response = self
+                .call_with_retry(|| session.conversations_history(&request))
+                .await
+                .map_err(|e| anyhow::anyhow!("Failed to get channel history: {}", e))?;
+
+            for message in &response.messages {
+                messages.push(serde_json::to_value(message)?);
+            }
+
+            info!("Fetched {} history messages for channel `{}` so far ...", messages.len(), channel_id);
+
+            cursor = response.response_metadata.and_then(|m| m.next_cursor).filter(|c| !c.0.is_empty());
+            if cursor.is_none() {
+                break;
+            }
+        }
+
+        // Slack returns the newest messages first.
+        messages.reverse();
+
+        Ok(messages)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_file_text(&self, url: &str) -> Res<String> {
+        let response = self.download_file(url).await?;
+
+        // Without the `files:read` scope, Slack answers with a (successful) sign in page, rather than the file.
+        let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+        if !is_text_mime_type(content_type) || content_type.starts_with("text/html") {
+            return Err(anyhow::anyhow!("The file is not text (`{}`); is the `files:read` scope missing?", content_type));
+        }
+
+        // A character takes at most 4 bytes, so this is enough for the characters that are kept.
+        let (bytes, _) = read_capped(response, self.config.slack_file_max_chars.saturating_mul(4)).await?;
+        let text = String::from_utf8_lossy(&bytes).chars().take(self.config.slack_file_max_chars).collect();
+
+        Ok(text)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_file_bytes(&self, url: &str, max_bytes: usize) -> Res<Vec<u8>> {
+        let response = self.download_file(url).await?;
+
+        // Without the `files:read` scope, Slack answers with a (successful) sign in page, rather than the file.
+        let content_type = response.headers().get(reqwest::header::CONTENT_TYPE).and_then(|value| value.to_str().ok()).unwrap_or_default();
+        if !content_type.starts_with("image/") {
+            return Err(anyhow::anyhow!("The file is not an image (`{}`); is the `files:read` scope missing?", content_type));
+        }
+
+        if response.content_length().is_some_and(|length| length as usize > max_bytes) {
+            return Err(anyhow::anyhow!("The image is larger than {} bytes.", max_bytes));
+        }
+
+        let (bytes, truncated) = read_capped(response, max_bytes).await?;
+        if truncated {
+            return Err(anyhow::anyhow!("The image is larger than {} bytes.", max_bytes));
+        }
+
+        Ok(bytes)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_thread_context(&self, channel_id: &str, thread_ts: &str) -> Res<String> {
+        let request = SlackApiConversationsRepliesRequest::new(slack_channel_id(channel_id), SlackTs(thread_ts.to_string()));
+        let session = self.client.open_session(&self.bot_token);
+
+        let response = self.call_with_retry(|| session.conversations_replies(&request)).await;
+
+        let response = if let Err(e) = &response
+            && let SlackClientError::ApiError(ae) = e
+            && ae.code == "thread_not_found"
+        {
+            // If the thread is not found (due to this being a top-level message), we can just return an empty string.
+            return Ok("".to_string());
+        } else {
+            response?
+        };
+
+        let messages = serde_json::to_string(&response.messages)?;
+
+        Ok(messages)
+    }
+}
+
+// Events API routes for Slack.
+
+/// Handles push events delivered over the HTTP Events API.
+///
+/// Answers the `url_verification` challenge, and dispatches `event_callback` payloads
+/// through the same path as socket mode.
+async fn handle_events_api_push_event(Extension(environment): Extension<Arc<SlackHyperListenerEnvironment>>, Extension(event): Extension<SlackPushEvent>) -> Response {
+    match event {
+        SlackPushEvent::UrlVerification(url_verification) => url_verification.challenge.into_response(),
+        SlackPushEvent::EventCallback(event_callback) => {
+            if let Err(err) = handle_push_event(event_callback, environment.client.clone(), environment.user_state.clone()).await {
+                error!("Error while handling push event: {}", err);
+            }
+
+            StatusCode::OK.into_response()
+        }
+        SlackPushEvent::AppRateLimited(rate_limited) => {
+            warn!("Slack app rate limited: {:?}", rate_limited);
+
+            StatusCode::OK.into_response()
+        }
+    }
+}
+
+/// Handles command events delivered over the HTTP Events API.
+async fn handle_events_api_command_event(Extension(environment): Extension<Arc<SlackHyperListenerEnvironment>>, Extension(event): Extension<SlackCommandEvent>) -> Response {
+    match handle_command_event(event, environment.client.clone(), environment.user_state.clone()).await {
+        Ok(response) => axum::Json(response).into_response(),
+        Err(err) => {
+            error!("Error while handling command event: {}", err);
+
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Handles interaction events delivered over the HTTP Events API.
+async fn handle_events_api_interaction_event(Extension(environment): Extension<Arc<SlackHyperListenerEnvironment>>, Extension(event): Extension<SlackInteractionEvent>) -> Response {
+    if let Err(err) = handle_interaction_event(event, environment.client.clone(), environment.user_state.clone()).await {
+        error!("Error while handling interaction event: {}", err);
+    }
+
+    StatusCode::OK.into_response()
+}
+
+// Listener callbacks for Slack (shared by socket mode and the events API).
+
+/// Handles command events from Slack.
+///
+/// Only `/triage` is supported, and responses are always ephemeral.
+#[instrument(skip_all)]
+async fn handle_command_event(
+    event: SlackCommandEvent,
+    _client: Arc<SlackHyperClient>,
+    states: SlackClientEventsUserState,
+) -> Result<SlackCommandEventResponse, Box<dyn std::error::Error + Send + Sync>> {
+    let states = states.read().await;
+    let user_state = states.get_user_state::<SlackUserState>().ok_or(anyhow::anyhow!("Failed to get user state"))?;
+    user_state.record_event();
+
+    let text = if event.command.0 == "/triage" {
+        info!("Received `/triage` command event ...");
+
+        let subcommand = event.text.as_deref().unwrap_or_default();
+        interaction::command::handle_triage_command(subcommand, &user_state.channel_id(&event.channel_id.0), &user_state.config, &user_state.db).await?
+    } else {
+        warn!("Received unsupported command `{}`.", event.command.0);
+
+        interaction::command::TRIAGE_COMMAND_USAGE.to_string()
+    };
+
+    Ok(SlackCommandEventResponse::new(SlackMessageContent::new().with_text(text)).with_response_type(SlackMessageResponseType::Ephemeral))
+}
+
+/// Handles interaction events from Slack.
+///
+/// Only the buttons on the bot's replies are supported.
+#[instrument(skip_all)]
+async fn handle_interaction_event(event: SlackInteractionEvent, _client: Arc<SlackHyperClient>, states: SlackClientEventsUserState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let states = states.read().await;
+    let user_state = states.get_user_state::<SlackUserState>().ok_or(anyhow::anyhow!("Failed to get user state"))?;
+    user_state.record_event();
+
+    match event {
+        SlackInteractionEvent::BlockActions(_) => {
+            info!("Received block actions event ...");
+
+            // The payload is handed over as JSON, so that the interaction layer stays chat-agnostic.
+            let mut payload = serde_json::to_value(&event)?;
+
+            for pointer in ["/container/channel_id", "/channel/id"] {
+                if let Some(channel_id) = payload.pointer_mut(pointer)
+                    && let Some(id) = channel_id.as_str()
+                {
+                    *channel_id = Value::String(user_state.channel_id(id));
+                }
+            }
+
+            interaction::reply_action::handle_reply_actions(payload, user_state.db.clone(), user_state.chat.clone());
+        }
+        _ => {
+            warn!("Received unhandled interaction event.")
+        }
+    }
+
+    Ok(())
+}
+
+/// Handles push events from Slack.
+#[instrument(skip_all)]
+async fn handle_push_event(event_callback: SlackPushEventCallback, _client: Arc<SlackHyperClient>, states: SlackClientEventsUserState) -> Result<(), Box<dyn std::error::Error + Send + Sync>> {
+    let event = event_callback.event;
+    let states = states.read().await;
+    let user_state = states.get_user_state::<SlackUserState>().ok_or(anyhow::anyhow!("Failed to get user state"))?;
+    user_state.record_event();
+
+    match event {
+        SlackEventCallbackBody::Message(slack_message_event) => {
+            info!("Received message event ...");
+            let channel_id = user_state.channel_id(&slack_message_event.origin.channel.as_ref().ok_or(anyhow::anyhow!("Failed to get channel ID"))?.0);
+
+            // Edits and deletions update the stored history, but are not new messages.
+            match slack_message_event.subtype {
+                Some(SlackMessageEventType::MessageChanged) => {
+                    if let Some(edited) = slack_message_event.message {
+                        info!("Updating edited message ...");
+                        let ts = edited.ts.0.clone();
+                        interaction::message_storage::handle_message_update(
+                            edited.clone(),
+                            channel_id.clone(),
+                            ts,
+                            user_state.config.clone(),
+                            user_state.db.clone(),
+                            user_state.llm.clone(),
+                            user_state.chat.clone(),
+                        );
+
+                        // An edit shortly after the bot replied may change the answer (unless it is in a direct message, and those are disabled).
+                        let is_direct_message = slack_message_event.origin.channel_type.as_ref().is_some_and(|t| t.0 == "im");
+                        if !is_direct_message || user_state.config.slack_direct_messages_enabled {
+                            interaction::message_edit::handle_message_edit(
+                                serde_json::to_value(&edited)?,
+                                channel_id,
+                                user_state.config.clone(),
+                                user_state.db.clone(),
+                                user_state.llm.clone(),
+                                user_state.chat.clone(),
+                                user_state.mcp.clone(),
+                            );
+                        }
+                    }
+
+                    return Ok(());
+                }
+                Some(SlackMessageEventType::MessageDeleted) => {
+                    if let Some(previous) = slack_message_event.previous_message {
+                        info!("Deleting removed message ...");
+                        interaction::message_storage::handle_message_deletion(channel_id, previous.ts.0, user_state.db.clone());
+                    }
+
+                    return Ok(());
+                }
+                _ => {}
+            }
+
+            // Direct messages are always answered (no @-mention needed), unless they are disabled.
+            let is_direct_message = slack_message_event.origin.channel_type.as_ref().is_some_and(|t| t.0 == "im");
+            if is_direct_message {
+                if !user_state.config.slack_direct_messages_enabled {
+                    warn!("Skipping direct message because direct messages are disabled.");
+                    return Ok(());
+                }
+
+                interaction::message_storage::handle_message_storage(
+                    slack_message_event.clone(),
+                    channel_id.clone(),
+                    user_state.config.clone(),
+                    user_state.db.clone(),
+                    user_state.llm.clone(),
+                    user_state.chat.clone(),
+                );
+
+                // Never answer our own messages (which also arrive as message events in the DM), or other bots.
+                if user_state.is_own_message(&slack_message_event.sender) || slack_message_event.sender.bot_id.is_some() {
+                    return Ok(());
+                }
+
+                let thread_ts = thread_root_ts(&slack_message_event.origin);
+                interaction::chat_event::handle_chat_event(
+                    slack_message_event,
+                    channel_id,
+                    thread_ts,
+                    user_state.config.clone(),
+                    user_state.db.clone(),
+                    user_state.llm.clone(),
+                    user_state.chat.clone(),
+                    user_state.mcp.clone(),
+                );
+
+                return Ok(());
+            }
+
+            // No matter what, we are going to store the message in the database for future reference.
+            interaction::message_storage::handle_message_storage(
+                slack_message_event.clone(),
+                channel_id.clone(),
+                user_state.config.clone(),
+                user_state.db.clone(),
+                user_state.llm.clone(),
+                user_state.chat.clone(),
+            );
+
+            // Never answer our own messages (e.g., onboarding messages and reminders come back as events).
+            // The prompt also tells the assistant to ignore them, but this saves a call, and is reliable.
+            if user_state.is_own_message(&slack_message_event.sender) {
+                info!("Skipping message event because it is the bot's own message.");
+                return Ok(());
+            }
+
+            // If the message @mentions the bot, skip, and let the app mention handler take care of it.
+            let text = slack_message_event.content.as_ref().map(|c| c.text.as_deref()).unwrap_or_default().unwrap_or_default();
+            let blocks = slack_message_event.content.as_ref().and_then(|c| c.blocks.as_ref()).and_then(|b| serde_json::to_value(b).ok());
+            if message_mentions_user(text, blocks.as_ref(), &user_state.bot_user_id) {
+                warn!("Skipping message event because it mentions the bot.");
+                return Ok(());
+            }
+
+            // If the message is in a thread, skip, since we don't want the bot to respond unless it is mentioned in a thread.
+            if slack_message_event.origin.thread_ts.is_some() {
+                warn!("Skipping message event because it is in a thread.");
+                return Ok(());
+            }
+
+            let thread_ts = thread_root_ts(&slack_message_event.origin);
+            interaction::chat_event::handle_chat_event(
+                slack_message_event,
+                channel_id,
+                thread_ts,
+                user_state.config.clone(),
+                user_state.db.clone(),
+                user_state.llm.clone(),
+                user_state.chat.clone(),
+                user_state.mcp.clone(),
+            );
+        }
+        SlackEventCallbackBody::AppMention(slack_app_mention_event) => {
+            info!("Received app mention event ...");
+
+            let channel_id = user_state.channel_id(&slack_app_mention_event.channel.0);
+            let thread_ts = thread_root_ts(&slack_app_mention_event.origin);
+            interaction::chat_event::handle_chat_event(
+                slack_app_mention_event,
+                channel_id,
+                thread_ts,
+                user_state.config.clone(),
+                user_state.db.clone(),
+                user_state.llm.clone(),
+                user_state.chat.clone(),
+                user_state.mcp.clone(),
+            );
+        }
+        SlackEventCallbackBody::ReactionAdded(slack_reaction_added_event) => {
+            info!("Received reaction added event ...");
+
+            // Only reactions on the bot's own messages count as feedback.
+            let is_bot_message = slack_reaction_added_event.item_user.as_ref().is_some_and(|u| u.0 == user_state.bot_user_id);
+            let reaction = slack_reaction_added_event.reaction.0;
+
+            if !is_bot_message || !interaction::feedback::is_feedback_reaction(&reaction) {
+                return Ok(());
+            }
+
+            if let SlackReactionsItem::Message(message) = slack_reaction_added_event.item {
+                let channel_id = user_state.channel_id(&message.origin.channel.as_ref().ok_or(anyhow::anyhow!("Failed to get channel ID"))?.0);
+
+                interaction::feedback::handle_feedback(channel_id, message.origin.ts.0, slack_reaction_added_event.user.0, reaction, user_state.db.clone());
+            }
+        }
+        SlackEventCallbackBody::LinkShared(slack_link_shared_event) => {
+            info!("Received link shared event ...");
+
+            // Only unfurl links on allowlisted domains.
+            let urls = slack_link_shared_event
+                .links
+                .into_iter()
+                .filter(|link| user_state.config.is_unfurl_domain_allowed(&link.domain))
+                .map(|link| link.url.to_string())
+                .collect::<Vec<_>>();
+
+            if urls.is_empty() {
+                warn!("Skipping link shared event because no links are on allowlisted domains.");
+                return Ok(());
+            }
+
+            interaction::link_unfurl::handle_link_shared(
+                user_state.channel_id(&slack_link_shared_event.channel.0),
+                slack_link_shared_event.message_ts.0,
+                urls,
+                user_state.llm.clone(),
+                user_state.chat.clone(),
+            );
+        }
+        SlackEventCallbackBody::MemberJoinedChannel(slack_member_joined_channel_event) => {
+            info!("Received member joined channel event ...");
+
+            // Only the bot joining (being invited to) a channel is interesting.
+            if slack_member_joined_channel_event.user.0 != user_state.bot_user_id {
+                return Ok(());
+            }
+
+            let channel_id = user_state.channel_id(&slack_member_joined_channel_event.channel.0);
+            interaction::channel_join::handle_bot_joined_channel(channel_id.clone(), user_state.db.clone(), user_state.chat.clone());
+            interaction::history_backfill::handle_history_backfill(channel_id, user_state.config.slack_history_backfill_days, user_state.db.clone(), user_state.chat.clone());
+        }
+        SlackEventCallbackBody::ChannelArchive(slack_channel_archive_event) => {
+            info!("Received channel archive event ...");
+
+            let channel_id = user_state.channel_id(&slack_channel_archive_event.channel.0);
+            interaction::channel_lifecycle::handle_channel_archived(channel_id, true, user_state.db.clone());
+        }
+        SlackEventCallbackBody::ChannelUnarchive(slack_channel_unarchive_event) => {
+            info!("Received channel unarchive event ...");
+
+            let channel_id = user_state.channel_id(&slack_channel_unarchive_event.channel.0);
+            interaction::channel_lifecycle::handle_channel_archived(channel_id, false, user_state.db.clone());
+        }
+        SlackEventCallbackBody::ChannelRename(slack_channel_rename_event) => {
+            info!("Received channel rename event ...");
+
+            let Some(name) = slack_channel_rename_event.channel.name else {
+                warn!("Skipping channel rename event without a name.");
+                return Ok(());
+            };
+
+            let channel_id = user_state.channel_id(&slack_channel_rename_event.channel.id.0);
+            interaction::channel_lifecycle::handle_channel_renamed(channel_id, name, user_state.db.clone());
+        }
+        //SlackEventCallbackBody::ReactionRemoved(slack_reaction_removed_event) => todo!(),
+        //SlackEventCallbackBody::StarAdded(slack_star_added_event) => todo!(),
+        //SlackEventCallbackBody::StarRemoved(slack_star_removed_event) => todo!(),
+        _ => {
+            warn!("Received unhandled push event.")
+        }
+    }
+
+    Ok(())
+}
+
+// Helpers.
+
+/// Call a Slack API method, retrying when Slack responds with a rate limit error.
+///
+/// The wait honors Slack's `Retry-After` (falling back to exponential backoff if it is missing).
+/// Any other error fails fast.
+async fn call_with_rate_limit_retry<T, F, Fut>(max_retries: u32, call: F) -> Result<T, SlackClientError>
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = Result<T, SlackClientError>>,
+{
+    const RETRY_DELAY_MS: u64 = 1000;
+
+    let mut retries = 0;
+
+    loop {
+        match call().await {
+            Err(SlackClientError::RateLimitError(err)) if retries < max_retries => {
+                retries += 1;
+
+                let delay = err.retry_after.unwrap_or_else(|| Duration::from_millis(RETRY_DELAY_MS * 2_u64.pow(retries - 1)));
+                warn!("Slack API call rate limited, retrying {retries}/{max_retries} in {delay:?}");
+
+                tokio::time::sleep(delay).await;
+            }
+            result => return result,
+        }
+    }
+}
+
+/// Get the root of the thread a message belongs to (a top-level message is the root of its own, future, thread).
+fn thread_root_ts(origin: &SlackMessageOrigin) -> String {
+    origin.thread_ts.as_ref().unwrap_or(&origin.ts).0.clone()
+}
+
+/// Check whether a message mentions a user (e.g., `<@U12345>` or `<@U12345|name>`), comparing the full user ID.
+///
+/// Mentions inside code (inline or fenced) do not count.  Rich text `blocks`, when present, are checked as well
+/// (skipping their preformatted and code-styled elements).
+pub fn message_mentions_user(text: &str, blocks: Option<&Value>, user_id: &str) -> bool {
+    text_mentions_user(text, user_id) || blocks.is_some_and(|blocks| blocks_mention_user(blocks, user_id))
+}
+
+/// Check the `<@…>` tokens in the text (outside of code) for the user.
+fn text_mentions_user(text: &str, user_id: &str) -> bool {
+    // Odd segments between the backtick delimiters are code.
+    let prose = text.split("```").step_by(2).flat_map(|segment| segment.split('`').step_by(2));
+
+    prose.flat_map(|segment| segment.split("<@").skip(1)).any(|token| {
+        let Some((mention, _)) = token.split_once('>') else {
+            return false;
+        };
+
+        mention.split('|').next() == Some(user_id)
+    })
+}
+
+/// Check the rich text elements of the blocks for a mention of the user.
+fn blocks_mention_user(value: &Value, user_id: &str) -> bool {
+    match value {
+        Value::Array(values) => values.iter().any(|v| blocks_mention_user(v, user_id)),
+        Value::Object(object) => {
+            let is_code = object.get("type").and_then(Value::as_str) == Some("rich_text_preformatted") || object.get("style").and_then(|s| s.get("code")).and_then(Value::as_bool) == Some(true);
+            if is_code {
+                return false;
+            }
+
+            if object.get("type").and_then(Value::as_str) == Some("user") {
+                return object.get("user_id").and_then(Value::as_str) == Some(user_id);
+            }
+
+            object.values().any(|v| blocks_mention_user(v, user_id))
+        }
+        _ => false,
+    }
+}
+
+/// Rewrite plain user group handles (e.g., `@payments-oncall`) into live mentions (e.g., `<!subteam^S0123>`).
+///
+/// The `handles` map lowercase handles to user group IDs.  Unknown handles, and anything inside code, are left alone.
+fn rewrite_user_group_handles(text: &str, handles: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+
+    // Odd segments between the backtick delimiters are code.
+    for (i, block) in text.split("```").enumerate() {
+        if i > 0 {
+            result.push_str("```");
+        }
+
+        if i % 2 == 1 {
+            result.push_str(block);
+            continue;
+        }
+
+        for (j, span) in block.split('`').enumerate() {
+            if j > 0 {
+                result.push('`');
+            }
+
+            if j % 2 == 1 {
+                result.push_str(span);
+            } else {
+                result.push_str(&rewrite_prose_user_group_handles(span, handles));
+            }
+        }
+    }
+
+    result
+}
+
+/// Rewrite the user group handles in some prose (text without code).
+fn rewrite_prose_user_group_handles(text: &str, handles: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(at) = rest.find('@') {
+        result.push_str(&rest[..at]);
+        let after = &rest[at + 1..];
+
+        // Skip emails and existing mentions (e.g., `<@U0123>` or `<!subteam^S0123|@payments-oncall>`).
+        let is_embedded = result.chars().last().is_some_and(|c| c.is_alphanumeric() || matches!(c, '<' | '|' | '^' | '!'));
+
+        // Handles can contain dots, dashes, and underscores, but a trailing one is likely punctuation.
+        let len = after.find(|c: char| !(c.is_alphanumeric() || matches!(c, '.' | '-' | '_'))).unwrap_or(after.len());
+        let mut handle = &after[..len];
+        let id = loop {
+            if is_embedded || handle.is_empty() {
+                break None;
+            }
+
+            if let Some(id) = handles.get(&handle.to_lowercase()) {
+                break Some(id);
+            }
+
+            match handle.strip_suffix(['.', '-', '_']) {
+                Some(trimmed) => handle = trimmed,
+                None => break None,
+            }
+        };
+
+        match id {
+            Some(id) => {
+                result.push_str(&format!("<!subteam^{id}>"));
+                rest = &after[handle.len()..];
+            }
+            None => {
+                result.push('@');
+                rest = after;
+            }
+        }
+    }
+
+    result.push_str(rest);
+
+    result
+}
+
+/// Replace the body sections (those whose `block_id` starts with `BODY_BLOCK_ID_PREFIX`, as the message may be spread
+/// over several) with a single section, in place of the first, that holds the first `chunk` of the message.
+///
+/// The other blocks (e.g., the classification context and the buttons) are kept as is.
+fn replace_section_text(blocks: &[Value], chunk: &str) -> Vec<Value> {
+    let is_body = |block: &Value| block.get("block_id").and_then(Value::as_str).is_some_and(|id| id.starts_with(BODY_BLOCK_ID_PREFIX));
+
+    let Some(first) = blocks.iter().position(is_body) else {
+        return blocks.to_vec();
+    };
+
+    let mut section = blocks[first].clone();
+    section["block_id"] = Value::String(format!("{BODY_BLOCK_ID_PREFIX}0"));
+    section["text"]["text"] = Value::String(chunk.to_string());
+
+    let mut result = blocks.iter().filter(|block| !is_body(block)).cloned().collect::<Vec<_>>();
+    result.insert(first, section);
+
+    result
+}
+
+/// Convert the `mrkdwn` text of the blocks (of sections, and of context elements) with `convert` (e.g., the markdown
+/// converter), so that the blocks are formatted like the text of the message.
+fn convert_block_text(blocks: &[Value], convert: impl Fn(&str) -> String) -> Vec<Value> {
+    let convert_text = |text: &mut Value| {
+        if text.get("type").and_then(Value::as_str) == Some("mrkdwn")
+            && let Some(value) = text.get("text").and_then(Value::as_str)
+        {
+            text["text"] = Value::String(convert(value));
+        }
+    };
+
+    blocks
+        .iter()
+        .cloned()
+        .map(|mut block| {
+            if let Some(text) = block.get_mut("text") {
+                convert_text(text);
+            }
+
+            if let Some(elements) = block.get_mut("elements").and_then(Value::as_array_mut) {
+                elements.iter_mut().for_each(&convert_text);
+            }
+
+            block
+        })
+        .collect()
+}
+
+/// Qualify a Slack channel ID with a workspace namespace (e.g., `T0123:C0456`), if there is one.
+fn qualify_channel_id(namespace: Option<&str>, channel_id: &str) -> String {
+    match namespace {
+        Some(namespace) => format!("{namespace}:{channel_id}"),
+        None => channel_id.to_string(),
+    }
+}
+
+/// Whether a message was sent by the bot, by its user ID or (for messages posted as the app) its bot ID.
+fn is_own_message(sender: &SlackMessageSender, bot_user_id: &str, bot_id: Option<&str>) -> bool {
+    let is_bot_user = sender.user.as_ref().is_some_and(|user| user.0 == bot_user_id);
+    let is_bot = bot_id.is_some() && sender.bot_id.as_ref().map(|id| id.0.as_str()) == bot_id;
+
+    is_bot_user || is_bot
+}
+
+/// Whether a failed Slack call can succeed after the bot joins the channel.
+///
+/// Only `not_in_channel` qualifies: private channels (`channel_not_found`) and archived channels (`is_archived`) cannot be joined.
+fn is_retryable_after_join(err: &SlackClientError) -> bool {
+    matches!(err, SlackClientError::ApiError(ae) if ae.code == "not_in_channel")
+}
+
+/// Read the body of a response, stopping after `max_bytes` of it (so that a huge file is never held in memory).
+///
+/// Returns the bytes read, and whether the body was longer.
+async fn read_capped(mut response: reqwest::Response, max_bytes: usize) -> Res<(Vec<u8>, bool)> {
+    let mut bytes = Vec::new();
+
+    while let Some(chunk) = response.chunk().await? {
+        let room = max_bytes - bytes.len();
+        if chunk.len() > room {
+            bytes.extend_from_slice(&chunk[..room]);
+            return Ok((bytes, true));
+        }
+
+        bytes.extend_from_slice(&chunk);
+    }
+
+    Ok((bytes, false))
+}
+
+/// The health of the bot, given the result of the health probe (`auth.test`).
+///
+/// A rate limit that outlasts the retries means that Slack is reachable (and the bot is only busy), so it is healthy.
+fn probe_health<T>(result: Result<T, SlackClientError>) -> Void {
+    match result {
+        Ok(_) | Err(SlackClientError::RateLimitError(_)) => Ok(()),
+        Err(e) => Err(anyhow::anyhow!("Failed to authenticate with Slack: {}", e)),
+    }
+}
+
+/// The delay before the next socket mode reconnect, after `attempts` consecutive attempts.
+fn socket_mode_backoff(attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16);
+
+    (SOCKET_MODE_BASE_BACKOFF * 2_u32.pow(exponent)).min(SOCKET_MODE_MAX_BACKOFF)
+}
+
+/// Wait for a termination signal (Ctrl-C, or `SIGTERM` on unix).
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{SignalKind, signal};
+
+        match signal(SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                tokio::select! {
+                    _ = tokio::signal::ctrl_c() => {}
+                    _ = terminate.recv() => {}
+                }
+            }
+            Err(_) => {
+                let _ = tokio::signal::ctrl_c().await;
+            }
+        }
+    }
+
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+/// Get the Slack channel ID from a (possibly namespaced) channel ID.
+fn slack_channel_id(channel_id: &str) -> SlackChannelId {
+    SlackChannelId(channel_id.rsplit(':').next().unwrap_or(channel_id).to_string())
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    use serde_json::json;
+    use slack_morphism::errors::SlackRateLimitError;
+
+    use super::*;
+    use crate::{base::types::AssistantClassification, interaction::chat_event::render_reply_blocks};
+
+    fn rate_limited() -> SlackClientError {
+        SlackClientError::RateLimitError(SlackRateLimitError::new().with_retry_after(Duration::from_millis(1)))
+    }
+
+    #[tokio::test]
+    async fn test_read_capped() {
+        let response = |body: &str| reqwest::Response::from(axum::http::Response::new(body.to_string()));
+
+        assert_eq!(read_capped(response("short"), 10).await.unwrap(), (b"short".to_vec(), false));
+        assert_eq!(read_capped(response("exactly10!"), 10).await.unwrap(), (b"exactly10!".to_vec(), false));
+        assert_eq!(read_capped(response("much too long"), 10).await.unwrap(), (b"much too l".to_vec(), true));
+    }
+
+    #[test]
+    fn test_is_text_mime_type() {
+        assert!(is_text_mime_type("text/plain; charset=utf-8"));
+        assert!(is_text_mime_type("Application/JSON"));
+        assert!(!is_text_mime_type("image/png"));
+        assert!(!is_text_mime_type("application/octet-stream"));
+    }
+
+    #[test]
+    fn test_probe_health() {
+        assert!(probe_health(Ok(())).is_ok());
+
+        // Being rate limited is not being unhealthy, but a rejected token is.
+        assert!(probe_health::<()>(Err(rate_limited())).is_ok());
+        assert!(probe_health::<()>(Err(SlackClientError::ApiError(SlackClientApiError::new("invalid_auth".to_string())))).is_err());
+    }
+
+    #[test]
+    fn test_channel_id_namespacing() {
+        assert_eq!(qualify_channel_id(None, "C0456"), "C0456");
+        assert_eq!(qualify_channel_id(Some("T0123"), "C0456"), "T0123:C0456");
+
+        assert_eq!(slack_channel_id("C0456").0, "C0456");
+        assert_eq!(slack_channel_id("T0123:C0456").0, "C0456");
+    }
+
+    #[test]
+    fn test_message_mentions_user() {
+        // Full IDs only (lookalikes do not count).
+        assert!(message_mentions_user("hey <@U12345>, help!", None, "U12345"));
+        assert!(message_mentions_user("hey <@U12345|triage-bot>", None, "U12345"));
+        assert!(!message_mentions_user("hey <@U123456>", None, "U12345"));
+        assert!(!message_mentions_user("hey <@U1234>", None, "U12345"));
+        assert!(!message_mentions_user("U12345 is the bot", None, "U12345"));
+
+        // Multiple mentions.
+        assert!(message_mentions_user("<@U99999> and <@U12345>", None, "U12345"));
+        assert!(!message_mentions_user("<@U99999> and <@U88888>", None, "U12345"));
+
+        // Code blocks do not count.
+        assert!(!message_mentions_user("try `<@U12345>`", None, "U12345"));
+        assert!(!message_mentions_user("```\nping <@U12345>\n```", None, "U12345"));
+        assert!(message_mentions_user("```\ncode\n``` then <@U12345>", None, "U12345"));
+
+        // Rich text blocks.
+        let mention = json!([{ "type": "rich_text", "elements": [{ "type": "rich_text_section", "elements": [{ "type": "user", "user_id": "U12345" }] }] }]);
+        let code = json!([{ "type": "rich_text", "elements": [{ "type": "rich_text_preformatted", "elements": [{ "type": "user", "user_id": "U12345" }] }] }]);
+        let lookalike = json!([{ "type": "rich_text", "elements": [{ "type": "rich_text_section", "elements": [{ "type": "user", "user_id": "U123456" }] }] }]);
+        assert!(message_mentions_user("", Some(&mention), "U12345"));
+        assert!(!message_mentions_user("", Some(&code), "U12345"));
+        assert!(!message_mentions_user("", Some(&lookalike), "U12345"));
+    }
+
+    #[test]
+    fn test_rewrite_user_group_handles() {
+        let handles = HashMap::from([("payments-oncall".to_string(), "S0123".to_string()), ("horses".to_string(), "S0456".to_string())]);
+
+        // Known handles are rewritten (case-insensitively, and without trailing punctuation).
+        assert_eq!(rewrite_user_group_handles("Tagging @payments-oncall.", &handles), "Tagging <!subteam^S0123>.");
+        assert_eq!(rewrite_user_group_handles("@Horses, and @payments-oncall!", &handles), "<!subteam^S0456>, and <!subteam^S0123>!");
+
+        // Unknown handles, emails, and existing mentions are left alone.
+        assert_eq!(rewrite_user_group_handles("Ask @cows.", &handles), "Ask @cows.");
+        assert_eq!(rewrite_user_group_handles("Email horses@example.com", &handles), "Email horses@example.com");
+        assert_eq!(rewrite_user_group_handles("<@U0123> and <!subteam^S0456|@horses>", &handles), "<@U0123> and <!subteam^S0456|@horses>");
+
+        // Code is never modified.
+        assert_eq!(
+            rewrite_user_group_handles("Run `page @horses` to page @horses.", &handles),
+            "Run `page @horses` to page <!subteam^S0456>."
+        );
+        assert_eq!(rewrite_user_group_handles("```\n@horses\n```\n@horses", &handles), "```\n@horses\n```\n<!subteam^S0456>");
+    }
+
+    #[test]
+    fn test_is_own_message() {
+        let user = |id: &str| SlackMessageSender::new().with_user(SlackUserId(id.to_string()));
+        let bot = |id: &str| SlackMessageSender::new().with_bot_id(SlackBotId(id.to_string()));
+
+        // By user ID, or by bot ID.
+        assert!(is_own_message(&user("U12345"), "U12345", Some("B12345")));
+        assert!(is_own_message(&bot("B12345"), "U12345", Some("B12345")));
+        assert!(is_own_message(&user("U12345").with_bot_id(SlackBotId("B12345".into())), "U12345", None));
+
+        // Other users and bots are not.
+        assert!(!is_own_message(&user("U99999"), "U12345", Some("B12345")));
+        assert!(!is_own_message(&bot("B99999"), "U12345", Some("B12345")));
+        assert!(!is_own_message(&bot("B12345"), "U12345", None));
+        assert!(!is_own_message(&SlackMessageSender::new(), "U12345", Some("B12345")));
+    }
+
+    #[test]
+    fn test_is_retryable_after_join() {
+        let api_error = |code: &str| SlackClientError::ApiError(SlackClientApiError::new(code.to_string()));
+
+        assert!(is_retryable_after_join(&api_error("not_in_channel")));
+        assert!(!is_retryable_after_join(&api_error("channel_not_found")));
+        assert!(!is_retryable_after_join(&api_error("is_archived")));
+        assert!(!is_retryable_after_join(&api_error("already_reacted")));
+        assert!(!is_retryable_after_join(&rate_limited()));
+    }
+
+    #[test]
+    fn test_socket_mode_backoff() {
+        assert_eq!(socket_mode_backoff(1), Duration::from_secs(2));
+        assert_eq!(socket_mode_backoff(2), Duration::from_secs(4));
+        assert_eq!(socket_mode_backoff(5), Duration::from_secs(32));
+        assert_eq!(socket_mode_backoff(10), SOCKET_MODE_MAX_BACKOFF);
+        assert_eq!(socket_mode_backoff(u32::MAX), SOCKET_MODE_MAX_BACKOFF);
+    }
+
+    #[test]
+    fn test_replace_section_text() {
+        let blocks = vec![
+            json!({ "type": "context", "elements": [{ "type": "mrkdwn", "text": "Bug" }] }),
+            json!({ "type": "section", "block_id": "body_0", "text": { "type": "mrkdwn", "text": "long reply" } }),
+            json!({ "type": "section", "text": { "type": "mrkdwn", "text": "*Links*" } }),
+        ];
+
+        let replaced = replace_section_text(&blocks, "long");
+
+        assert_eq!(replaced.len(), 3);
+        assert_eq!(replaced[0], blocks[0]);
+        assert_eq!(replaced[1]["text"]["text"], "long");
+        assert_eq!(replaced[2], blocks[2]);
+
+        // A message that is spread over several sections is replaced by a single section.
+        let blocks = vec![
+            json!({ "type": "context", "elements": [{ "type": "mrkdwn", "text": "Bug" }] }),
+            json!({ "type": "section", "block_id": "body_0", "text": { "type": "mrkdwn", "text": "long " } }),
+            json!({ "type": "section", "block_id": "body_1", "text": { "type": "mrkdwn", "text": "reply" } }),
+            json!({ "type": "section", "text": { "type": "mrkdwn", "text": "*Links*" } }),
+        ];
+
+        let replaced = replace_section_text(&blocks, "long");
+
+        assert_eq!(replaced.len(), 3);
+        assert_eq!(replaced[1]["block_id"], "body_0");
+        assert_eq!(replaced[1]["text"]["text"], "long");
+        assert_eq!(replaced[2], blocks[3]);
+
+        // Blocks without a body (e.g., a section that merely holds the same text) are kept as is.
+        let blocks = vec![
+            json!({ "type": "context", "elements": [{ "type": "mrkdwn", "text": "Bug" }] }),
+            json!({ "type": "section", "text": { "type": "mrkdwn", "text": "long reply" } }),
+        ];
+
+        assert_eq!(replace_section_text(&blocks, "long"), blocks);
+    }
+
+    #[test]
+    fn test_convert_block_text() {
+        let blocks = vec![
+            json!({ "type": "context", "elements": [{ "type": "mrkdwn", "text": "**Bug**" }, { "type": "plain_text", "text": "**as is**" }] }),
+            json!({ "type": "section", "text": { "type": "mrkdwn", "text": "*Links*\n• [the docs](https://example.com/docs)" } }),
+            json!({ "type": "actions", "elements": [{ "type": "button", "text": { "type": "plain_text", "text": "Reply" } }] }),
+        ];
+
+        let converted = convert_block_text(&blocks, mrkdwn::markdown_to_mrkdwn);
+
+        assert_eq!(converted[0]["elements"][0]["text"], "*Bug*");
+        assert_eq!(converted[0]["elements"][1]["text"], "**as is**");
+        assert_eq!(converted[1]["text"]["text"], "*Links*\n• <https://example.com/docs|the docs>");
+        assert_eq!(converted[2], blocks[2]);
+    }
+
+    #[test]
+    fn test_replace_section_text_long_reply_with_sources() {
+        let message = format!("{}\n\n{}", "a".repeat(2500), "b".repeat(2500));
+        let sources = "*Sources:* <https://example.com/docs|the docs>";
+
+        let mut blocks = render_reply_blocks(&AssistantClassification::Bug, "bug", &message);
+        blocks.push(json!({ "type": "context", "elements": [{ "type": "mrkdwn", "text": sources }] }));
+
+        let chunks = split::split_message(&message, SECTION_TEXT_LIMIT);
+        let replaced = replace_section_text(&blocks, &chunks[0]);
+
+        // The first chunk replaces the body (so the rest, which follows in the thread, is not shown twice), and the sources are shown once.
+        assert_eq!(chunks.len(), 2);
+        assert_eq!(replaced.len(), 3);
+        assert_eq!(replaced[1]["text"]["text"], chunks[0].as_str());
+        assert_eq!(replaced[2]["elements"][0]["text"], sources);
+
+        let shown = replaced.iter().map(Value::to_string).chain(chunks[1..].iter().cloned()).collect::<String>();
+        assert_eq!(shown.matches(&"b".repeat(2500)).count(), 1);
+        assert_eq!(shown.matches("*Sources:*").count(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_call_with_rate_limit_retry_succeeds_after_rate_limit() {
+        let attempts = &AtomicU32::new(0);
+
+        let result = call_with_rate_limit_retry(3, move || async move { if attempts.fetch_add(1, Ordering::SeqCst) < 2 { Err(rate_limited()) } else { Ok("ok") } }).await;
+
+        assert_eq!(result.unwrap(), "ok");
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_call_with_rate_limit_retry_gives_up() {
+        let attempts = &AtomicU32::new(0);
+
+        let result: Result<(), _> = call_with_rate_limit_retry(2, move || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(rate_limited())
+        })
+        .await;
+
+        assert!(matches!(result, Err(SlackClientError::RateLimitError(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_call_with_rate_limit_retry_fails_fast() {
+        let attempts = &AtomicU32::new(0);
+
+        let result: Result<(), _> = call_with_rate_limit_retry(3, move || async move {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            Err(SlackClientError::ApiError(SlackClientApiError::new("channel_not_found".into())))
+        })
+        .await;
+
+        assert!(matches!(result, Err(SlackClientError::ApiError(_))));
+        assert_eq!(attempts.load(Ordering::SeqCst), 1);
+    }
 }
@@ -0,0 +1,301 @@
+//! Splitting of long messages into chunks that fit a chat platform's message length limit.
+
+/// The fence that wraps code blocks.
+const FENCE: &str = "```";
+
+/// The smallest chunk size that is honored (code blocks that are split need room for their fences).
+const MIN_CHUNK_CHARS: usize = 16;
+
+/// Split a message into chunks of at most `max_chars` characters.
+///
+/// Chunks are split at paragraph or code block boundaries when possible, then at line boundaries, and only then
+/// within a line (preferring whitespace).  Fenced code blocks that are too long on their own are split at line
+/// boundaries, and each chunk is re-fenced, so that every chunk renders on its own.  Mentions, links, and other
+/// `<…>` tokens are never split (unless a single token is longer than a chunk).
+pub fn split_message(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(MIN_CHUNK_CHARS);
+
+    let mut chunks = Vec::new();
+    let mut rest = text;
+    let mut in_fence = false;
+
+    loop {
+        // A code block that was split is reopened at the start of the next chunk.
+        let prefix = if in_fence { format!("{FENCE}\n") } else { String::new() };
+        let budget = max_chars - prefix.chars().count();
+
+        if rest.chars().count() <= budget {
+            if !rest.trim().is_empty() {
+                chunks.push(format!("{prefix}{rest}"));
+            }
+
+            break;
+        }
+
+        let split = find_split(rest, budget, in_fence);
+        let (chunk, remainder) = rest.split_at(split.end);
+
+        // A code block that is split is closed at the end of this chunk.
+        let suffix = if split.in_fence { format!("\n{FENCE}") } else { String::new() };
+        let chunk = if split.in_line { chunk.trim_end() } else { chunk.trim_end_matches('\n') };
+
+        if !chunk.trim().is_empty() {
+            chunks.push(format!("{prefix}{chunk}{suffix}"));
+        }
+
+        rest = if split.in_line { remainder.trim_start_matches(' ') } else { remainder.trim_start_matches('\n') };
+        in_fence = split.in_fence;
+    }
+
+    if chunks.is_empty() {
+        chunks.push(text.to_string());
+    }
+
+    chunks
+}
+
+// Helpers.
+
+/// Where (and how) to split the text.
+#[derive(Debug, Clone, Copy)]
+struct Split {
+    /// The byte offset to split at.
+    end: usize,
+    /// Whether the split is inside a fenced code block (which then needs to be closed and reopened).
+    in_fence: bool,
+    /// Whether the split is within a line (rather than at a line boundary).
+    in_line: bool,
+}
+
+/// How good a split is: code block and paragraph boundaries are best, then line boundaries, then lines inside code blocks.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum SplitQuality {
+    CodeLine,
+    Line,
+    Paragraph,
+}
+
+/// Find the best place to split the text, so that the first part fits the budget.
+fn find_split(text: &str, budget: usize, starts_in_fence: bool) -> Split {
+    let fence_suffix_chars = FENCE.chars().count() + 1;
+
+    // Collect the line boundaries that fit, with their quality.
+    let mut candidates: Vec<(SplitQuality, Split)> = Vec::new();
+    let mut in_fence = starts_in_fence;
+    let mut offset = 0;
+    let mut chars = 0;
+    let mut previous_blank = false;
+
+    for line in text.split_inclusive('\n') {
+        let line_start = offset;
+        let is_fence = line.trim_start().starts_with(FENCE);
+
+        // Splitting right before an opening fence is a code block boundary.
+        if is_fence && !in_fence && line_start > 0 {
+            candidates.push((
+                SplitQuality::Paragraph,
+                Split {
+                    end: line_start,
+                    in_fence: false,
+                    in_line: false,
+                },
+            ));
+        }
+
+        let is_single_line_fence = is_fence && line.trim().len() > 2 * FENCE.len() && line.trim().ends_with(FENCE);
+        if is_fence && !is_single_line_fence {
+            in_fence = !in_fence;
+        }
+
+        offset += line.len();
+        chars += line.chars().count();
+
+        let is_blank = line.trim().is_empty();
+        let needed = if in_fence { chars + fence_suffix_chars } else { chars };
+        if needed > budget {
+            break;
+        }
+
+        // Splitting right after an opening fence would leave an empty code block.
+        if is_fence && in_fence {
+            continue;
+        }
+
+        let quality = if in_fence {
+            SplitQuality::CodeLine
+        } else if is_fence || (is_blank && !previous_blank) {
+            SplitQuality::Paragraph
+        } else {
+            SplitQuality::Line
+        };
+
+        candidates.push((quality, Split { end: offset, in_fence, in_line: false }));
+        previous_blank = is_blank && !in_fence;
+    }
+
+    // Prefer the best kind of boundary, as long as it does not make the chunk too small.
+    for quality in [SplitQuality::Paragraph, SplitQuality::Line] {
+        if let Some((_, split)) = candidates.iter().rev().find(|(q, _)| *q == quality)
+            && text[..split.end].chars().count() >= budget / 2
+        {
+            return *split;
+        }
+    }
+
+    // Otherwise, any boundary outside of a code block beats splitting the code block.
+    if let Some((_, split)) = candidates.iter().rev().find(|(q, _)| *q != SplitQuality::CodeLine).or_else(|| candidates.last()) {
+        return *split;
+    }
+
+    // No line fits, so split within the first line (of the code block, if the text opens one).
+    let first_line = text.split_inclusive('\n').next().unwrap_or_default();
+    let opens_fence = !starts_in_fence && first_line.trim_start().starts_with(FENCE) && first_line.trim().len() == FENCE.len() && first_line.len() < text.len();
+
+    if opens_fence {
+        split_in_line(text, budget, true, first_line.len(), fence_suffix_chars)
+    } else {
+        split_in_line(text, budget, starts_in_fence, 0, fence_suffix_chars)
+    }
+}
+
+/// Split within a line (preferring whitespace, and after `start`), never inside a `<…>` token.
+fn split_in_line(text: &str, budget: usize, in_fence: bool, start: usize, fence_suffix_chars: usize) -> Split {
+    let budget = if in_fence { budget - fence_suffix_chars } else { budget };
+
+    let mut last_space = None;
+    let mut last_boundary = None;
+    let mut in_token = false;
+
+    for (chars, (index, c)) in text.char_indices().enumerate() {
+        if chars >= budget {
+            break;
+        }
+
+        if !in_token && index > start {
+            last_boundary = Some(index);
+            if c == ' ' {
+                last_space = Some(index);
+            }
+        }
+
+        match c {
+            '<' if !in_fence => in_token = true,
+            '>' => in_token = false,
+            _ => {}
+        }
+    }
+
+    // The first character (after `start`) always fits, so the split always makes progress.
+    let at_budget = text.char_indices().nth(budget).map(|(index, _)| index).unwrap_or(text.len());
+    let first = start + text[start..].chars().next().map(char::len_utf8).unwrap_or(1);
+    let end = last_space.or(last_boundary).unwrap_or(at_budget).max(first);
+
+    Split { end, in_fence, in_line: true }
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_fits(chunks: &[String], max_chars: usize) {
+        for chunk in chunks {
+            assert!(chunk.chars().count() <= max_chars, "Chunk is too long ({} > {}): {:?}", chunk.chars().count(), max_chars, chunk);
+            assert!(!chunk.trim().is_empty(), "Chunk is empty");
+        }
+    }
+
+    #[test]
+    fn test_short_message() {
+        assert_eq!(split_message("Hello!", 100), vec!["Hello!"]);
+        assert_eq!(split_message("", 100), vec![""]);
+    }
+
+    #[test]
+    fn test_paragraphs() {
+        let text = format!("{}\n\n{}\n\n{}", "a".repeat(40), "b".repeat(40), "c".repeat(40));
+
+        let chunks = split_message(&text, 90);
+
+        assert_fits(&chunks, 90);
+        assert_eq!(chunks, vec![format!("{}\n\n{}", "a".repeat(40), "b".repeat(40)), "c".repeat(40)]);
+    }
+
+    #[test]
+    fn test_code_block_is_kept_whole() {
+        let code = format!("```\n{}\n{}\n```", "x".repeat(30), "y".repeat(30));
+        let text = format!("{}\n{}\n\nDone.", "a".repeat(30), code);
+
+        let chunks = split_message(&text, 80);
+
+        assert_fits(&chunks, 80);
+        assert_eq!(chunks, vec!["a".repeat(30), format!("{code}\n\nDone.")]);
+    }
+
+    #[test]
+    fn test_giant_code_block() {
+        let lines = (0..50).map(|i| format!("line {i:02} of the stack trace")).collect::<Vec<_>>();
+        let text = format!("```\n{}\n```", lines.join("\n"));
+
+        let chunks = split_message(&text, 200);
+
+        assert_fits(&chunks, 200);
+        assert!(chunks.len() > 1);
+
+        // Every chunk is a complete code block, and no line is lost.
+        for chunk in &chunks {
+            assert!(chunk.starts_with("```\n") && chunk.ends_with("\n```"), "Chunk is not fenced: {chunk:?}");
+        }
+
+        let content = chunks.iter().map(|c| c.trim_start_matches("```\n").trim_end_matches("\n```")).collect::<Vec<_>>().join("\n");
+        assert_eq!(content, lines.join("\n"));
+    }
+
+    #[test]
+    fn test_giant_code_line() {
+        let text = format!("```\n{}\n```", "x".repeat(100));
+
+        let chunks = split_message(&text, 40);
+
+        assert_fits(&chunks, 40);
+        for chunk in &chunks {
+            assert!(chunk.starts_with("```\n") && chunk.ends_with("\n```"), "Chunk is not fenced: {chunk:?}");
+        }
+
+        let content = chunks.iter().map(|c| c.trim_start_matches("```\n").trim_end_matches("\n```")).collect::<String>();
+        assert_eq!(content, "x".repeat(100));
+    }
+
+    #[test]
+    fn test_no_newlines() {
+        let text = "word ".repeat(100).trim_end().to_string();
+
+        let chunks = split_message(&text, 64);
+
+        assert_fits(&chunks, 64);
+        assert_eq!(chunks.join(" "), text);
+    }
+
+    #[test]
+    fn test_no_whitespace() {
+        let text = "é".repeat(100);
+
+        let chunks = split_message(&text, 30);
+
+        assert_fits(&chunks, 30);
+        assert_eq!(chunks.concat(), text);
+    }
+
+    #[test]
+    fn test_mentions_are_not_split() {
+        let text = format!("{} <@U12345678> and <https://example.com/a/long/link|the docs> {}", "a".repeat(20), "b".repeat(20));
+
+        for max_chars in 16..text.len() {
+            let chunks = split_message(&text, max_chars);
+
+            assert_fits(&chunks, max_chars.max(MIN_CHUNK_CHARS));
+            assert!(chunks.iter().any(|c| c.contains("<@U12345678>")), "Mention was split at {max_chars}: {chunks:?}");
+        }
+    }
+}
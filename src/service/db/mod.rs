@@ -1,15 +1,39 @@
-use std::{ops::Deref, sync::Arc};
+use std::{collections::BTreeMap, ops::Deref, sync::Arc, time::Duration};
 
 use async_trait::async_trait;
-use serde::{Serialize, de::DeserializeOwned};
+use futures::{StreamExt, stream::BoxStream};
+use serde::{Deserialize, Serialize, de::DeserializeOwned};
 use serde_json::Value;
 use surreal::{SurrealChannel, SurrealLlmContext, SurrealMessage};
-use surrealdb::method::Stream;
+use surrealdb::Action;
+use tracing::{info, warn};
 
-use crate::base::types::Res;
+use crate::base::{
+    config::Config,
+    types::{ChannelMode, Res, TokenUsage, Void},
+};
 
+pub mod sqlite;
 pub mod surreal;
 
+#[cfg(test)]
+mod suite;
+
+// Statics.
+
+/// The number of dimensions of message embeddings (the default for OpenAI's `text-embedding-3-small`), which the
+/// vector index is defined with.
+pub const EMBEDDING_DIMENSIONS: usize = 1536;
+
+/// The number of messages that are stored per transaction by `GenericDbClient::add_channel_messages`, unless configured.
+pub const DEFAULT_MESSAGE_BATCH_SIZE: usize = 500;
+
+/// The longest wait between attempts to re-issue a live query (see `resubscribing_live_query`).
+const RESUBSCRIBE_MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// The number of a channel's most recent messages that a fuzzy search looks through (see `MessageSearchOptions::fuzzy`).
+pub const FUZZY_SEARCH_CANDIDATES: usize = 1000;
+
 // Traits.
 
 /// Generic database client trait that clients must implement.
@@ -23,44 +47,291 @@ pub trait GenericDbClient: Send + Sync + 'static {
     type ChannelType: Channel;
     type MessageType: Message;
 
+    /// Whether the database is reachable.
+    ///
+    /// While it is not (e.g., while reconnecting to a database server that restarted), every operation fails fast with
+    /// `DbUnavailable`, so callers can check this first to skip work whose results could not be stored anyway.
+    fn is_available(&self) -> bool;
+
+    /// Checks that the database is reachable, and answers queries, with a trivial query.
+    ///
+    /// Unlike `is_available`, this makes a round trip, so it is meant for health and readiness checks.
+    async fn healthy(&self) -> Void;
+
     /// Gets the channel from the database by its ID; or, creates a new channel if it doesn't exist.
     ///
     /// This is used to ensure a channel exists before operating on it, and
-    /// to retrieve channel-specific settings.
+    /// to retrieve channel-specific settings.  Concurrent calls for the same new channel all get the one channel that is created.
     async fn get_or_create_channel(&self, channel_id: &str) -> Res<Self::ChannelType>;
 
+    /// Gets the channel's current directive, or `None` if the channel does not exist.
+    ///
+    /// Unlike `get_or_create_channel`, this never creates the channel, so it is safe to use on read-only paths.
+    async fn get_channel_directive(&self, channel_id: &str) -> Res<Option<Self::LlmContextType>>;
+
     /// Updates the channel directive in the database.
     ///
     /// The directive controls how the bot behaves in the specific channel,
-    /// such as which issues to prioritize or which team to notify.
+    /// such as which issues to prioritize or which team to notify.  Every update is also kept as a new version in the
+    /// channel's directive history (with the user that requested it, and when), so that it can be audited and restored.
     async fn update_channel_directive(&self, channel_id: &str, directive: &Self::LlmContextType) -> Res<()>;
 
+    /// Gets the most recent versions of the channel's directive (newest first).
+    async fn get_directive_history(&self, channel_id: &str, limit: usize) -> Res<Vec<DirectiveVersion>>;
+
+    /// Restores a previous version of the channel's directive (which is recorded as a new version).
+    ///
+    /// Returns the restored directive, or `None` if the version is not in the channel's history.
+    async fn restore_channel_directive(&self, channel_id: &str, version_id: &str) -> Res<Option<Self::LlmContextType>>;
+
+    /// Pauses (or resumes) the bot in the channel.
+    ///
+    /// While paused, messages are still stored, but the bot does not respond.
+    async fn set_channel_paused(&self, channel_id: &str, paused: bool) -> Res<()>;
+
+    /// Sets whether the bot's replies to incidents are also broadcast to the channel.
+    async fn set_channel_broadcast_incidents(&self, channel_id: &str, broadcast_incidents: bool) -> Res<()>;
+
+    /// Marks the channel as active (or inactive, when it is archived).
+    ///
+    /// Inactive channels keep their history, but the bot does not respond in them.
+    async fn set_channel_active(&self, channel_id: &str, active: bool) -> Res<()>;
+
+    /// Sets the channel's (human-readable) name, e.g., after it is renamed.
+    async fn set_channel_name(&self, channel_id: &str, name: &str) -> Res<()>;
+
+    /// Sets how long after posting an edited message is re-evaluated in the channel, in seconds.
+    ///
+    /// `None` uses the configured default, and zero disables re-evaluation.
+    async fn set_channel_edit_window(&self, channel_id: &str, window_seconds: Option<u64>) -> Res<()>;
+
+    /// Sets how many days of messages are kept for the channel.
+    ///
+    /// `None` uses the configured default, and zero keeps messages forever.
+    async fn set_channel_retention(&self, channel_id: &str, retention_days: Option<u64>) -> Res<()>;
+
+    /// Sets the channels whose message history may also be searched when answering in a channel.
+    ///
+    /// Channels are isolated unless they are linked, and an empty list unlinks every channel.
+    async fn set_channel_linked_channels(&self, channel_id: &str, linked_channels: &[String]) -> Res<()>;
+
+    /// Sets whether the assistant's replies are moderated in the channel.
+    ///
+    /// `None` uses the configured default.
+    async fn set_channel_moderation(&self, channel_id: &str, moderation_enabled: Option<bool>) -> Res<()>;
+
+    /// Sets the confidence (0-100) below which the assistant's answers are withheld in the channel.
+    ///
+    /// `None` uses the configured default, and zero posts every answer.
+    async fn set_channel_confidence_threshold(&self, channel_id: &str, confidence_threshold: Option<u8>) -> Res<()>;
+
+    /// Sets the language (e.g., `German`) that the assistant replies in, in the channel.
+    ///
+    /// `None` replies in the language of each message (as detected).
+    async fn set_channel_force_language(&self, channel_id: &str, force_language: Option<&str>) -> Res<()>;
+
+    /// Sets the channel's system addendum: operator instructions (e.g., tone, or escalation policy) that are given to the
+    /// assistant ahead of the channel directive, and that channel members cannot change.
+    ///
+    /// `None` removes the addendum.
+    async fn set_channel_system_addendum(&self, channel_id: &str, system_addendum: Option<&str>) -> Res<()>;
+
+    /// Sets what is posted in the channel when the model refuses to respond to a message.
+    ///
+    /// `None` uses the configured default, and an empty reply posts nothing (only a reaction).
+    async fn set_channel_refusal_reply(&self, channel_id: &str, refusal_reply: Option<&str>) -> Res<()>;
+
+    /// Sets how much the bot does in the channel (e.g., only classifying messages).
+    async fn set_channel_mode(&self, channel_id: &str, mode: ChannelMode) -> Res<()>;
+
+    /// Sets whether the channel's messages are searched for on the web.
+    ///
+    /// `None` uses the configured default.
+    async fn set_channel_web_search(&self, channel_id: &str, web_search_enabled: Option<bool>) -> Res<()>;
+
     /// Adds a context JSON to the channel via a `has_context` edge.
     ///
     /// This stores additional contextual information that the bot can use
     /// when responding to messages in the channel.
     async fn add_channel_context(&self, channel_id: &str, context: &Self::LlmContextType) -> Res<()>;
 
+    /// Adds a context JSON about a user (e.g., "Alice owns the billing service") to the channel via a `has_user_context` edge.
+    ///
+    /// User contexts are kept per channel, since what someone owns in one channel may not matter in another.
+    async fn add_user_context(&self, channel_id: &str, user_id: &str, context: &Self::LlmContextType) -> Res<()>;
+
+    /// Gets the contexts stored about a user in the channel (oldest first).
+    async fn get_user_context(&self, channel_id: &str, user_id: &str) -> Res<Vec<Self::LlmContextType>>;
+
     /// Adds a message to the database that can then be retrieved by the bot.
     ///
-    /// This creates a searchable history of messages in the channel.
-    async fn add_channel_message(&self, channel_id: &str, message: &Value) -> Res<()>;
+    /// This creates a searchable history of messages in the channel.  Messages are keyed by their channel and `ts`,
+    /// so that storing the same message twice (e.g., a retried event delivery) is a no-op, and returns `false`.
+    async fn add_channel_message(&self, channel_id: &str, message: &Value) -> Res<bool>;
+
+    /// Adds many messages to the channel at once (e.g., for a backfill or an import), and returns how many were added.
+    ///
+    /// The messages are stored in transactions of up to `message_batch_size` messages each (see `with_message_batch_size`),
+    /// rather than one transaction per message.  Like `add_channel_message`, messages that are already stored (by their `ts`) are skipped.
+    async fn add_channel_messages(&self, channel_id: &str, messages: &[Value]) -> Res<u64>;
+
+    /// Updates a stored message (matched by channel and message timestamp).
+    ///
+    /// The new raw content is merged over the stored message, so that edits are
+    /// reflected in search results instead of creating duplicates.
+    async fn update_channel_message(&self, channel_id: &str, ts: &str, message: &Value) -> Res<()>;
+
+    /// Deletes a stored message (matched by channel and message timestamp).
+    ///
+    /// This removes the message and its `has_message` edge, so deleted text no longer
+    /// surfaces in search results.
+    async fn delete_channel_message(&self, channel_id: &str, ts: &str) -> Res<()>;
+
+    /// Gets a stored message (matched by channel and message timestamp), if it exists.
+    async fn get_channel_message(&self, channel_id: &str, ts: &str) -> Res<Option<Self::MessageType>>;
+
+    /// Gets the stored messages of a thread (the root, whose `ts` is `thread_ts`, and its replies), ordered by `ts`.
+    ///
+    /// This lets the thread context be read without a chat API call, although threads that were (partly) posted
+    /// before the bot was in the channel may be incomplete.
+    async fn get_channel_thread_messages(&self, channel_id: &str, thread_ts: &str) -> Res<Vec<Self::MessageType>>;
+
+    /// Gets the channel's thread roots that were posted since `since` (unix seconds), newest first, with their stored reply counts.
+    ///
+    /// Every top-level message is the root of its own thread, so a root without replies is a thread that nobody (not even
+    /// the bot) has answered.  Messages without a `ts` are never roots, nor replies.
+    async fn get_thread_roots(&self, channel_id: &str, since: i64) -> Res<Vec<ThreadRoot>>;
+
+    /// Gets the channel's most recent stored messages (newest first, by `ts`), at most `limit` of them.
+    ///
+    /// This gives the assistant the channel's ambient context (e.g., that an incident is underway), beyond what search finds.
+    async fn get_recent_channel_messages(&self, channel_id: &str, limit: usize) -> Res<Vec<Self::MessageType>>;
+
+    /// Adds user feedback on one of the bot's replies to the channel via a `has_feedback` edge.
+    async fn add_feedback(&self, channel_id: &str, feedback: &Feedback) -> Res<()>;
+
+    /// Gets all of the feedback stored for the channel.
+    async fn get_channel_feedback(&self, channel_id: &str) -> Res<Vec<Feedback>>;
+
+    /// Adds one of the bot's own responses (e.g., a reply and its classification, a `NoAction`, or a tool call and its
+    /// outcome) to a thread of the channel via a `has_response` edge, so that its decisions can be audited later.
+    async fn add_assistant_response(&self, channel_id: &str, thread_ts: &str, response: &Value) -> Void;
+
+    /// Gets the bot's `limit` most recent responses in a thread of the channel, oldest first.
+    async fn get_assistant_responses(&self, channel_id: &str, thread_ts: &str, limit: usize) -> Res<Vec<Value>>;
+
+    /// Gets the number of messages stored for the channel.
+    async fn get_channel_message_count(&self, channel_id: &str) -> Res<u64>;
+
+    /// Gets statistics about the channel's stored data, and its activity since `since` (unix seconds).
+    ///
+    /// Messages are counted by when they were posted, and responses by when they were stored.
+    async fn get_channel_stats(&self, channel_id: &str, since: i64) -> Res<ChannelStats>;
+
+    /// Gets the IDs of all of the channels in the database.
+    async fn list_channel_ids(&self) -> Res<Vec<String>>;
+
+    /// Deletes the channel's messages that were posted before `cutoff` (unix seconds), along with their `has_message` edges.
+    ///
+    /// Messages that a stored context was created from are kept.  Returns the number of messages that were deleted.
+    async fn prune_old_messages(&self, channel_id: &str, cutoff: i64) -> Res<u64>;
+
+    /// Deletes the channel's contexts that expired before `cutoff` (unix seconds), along with their `has_context` edges.
+    ///
+    /// Expired contexts are already left out of the channel's context, so this only cleans them up.  Returns the
+    /// number of contexts that were deleted.
+    async fn prune_expired_contexts(&self, channel_id: &str, cutoff: i64) -> Res<u64>;
+
+    /// Adds an outbound message to the outbox (e.g., one that failed to send, so that it can be retried).
+    ///
+    /// Returns the database ID of the message.
+    async fn add_pending_message(&self, message: &PendingMessage) -> Res<String>;
+
+    /// Gets the outbox messages that are due for a retry (`next_attempt_at <= now`), oldest first.
+    async fn get_due_pending_messages(&self, now: i64) -> Res<Vec<PendingMessage>>;
+
+    /// Updates the retry bookkeeping (`attempts` and `next_attempt_at`) of an outbox message.
+    async fn update_pending_message(&self, message: &PendingMessage) -> Res<()>;
+
+    /// Deletes an outbox message (e.g., once it has been sent).
+    async fn delete_pending_message(&self, id: &str) -> Res<()>;
+
+    /// Adds a scheduled message (e.g., a reminder) for a thread, so that it can be found (and cancelled) later.
+    async fn add_scheduled_message(&self, message: &ScheduledMessage) -> Res<()>;
+
+    /// Gets the scheduled messages for a thread.
+    async fn get_thread_scheduled_messages(&self, channel_id: &str, thread_ts: &str) -> Res<Vec<ScheduledMessage>>;
+
+    /// Deletes a scheduled message record (e.g., once it has been cancelled).
+    async fn delete_scheduled_message(&self, id: &str) -> Res<()>;
 
     /// Gets additional context for the channel.
     ///
-    /// This retrieves all contextual information that has been stored for the channel,
+    /// This retrieves all (unexpired) contextual information that has been stored for the channel,
     /// which helps the bot generate more relevant responses.
     async fn get_channel_context(&self, channel_id: &str) -> Res<String>;
 
+    /// Retrieves the most recent (unexpired) context for a channel, newest first, as a JSON string.
+    ///
+    /// At most `limit` contexts (and `max_chars` characters of them) are returned, so that a channel that has
+    /// accumulated a lot of context does not blow the assistant's token budget.  When older context is left out,
+    /// a one-line note saying so is appended (see `format_channel_context`).
+    async fn get_channel_context_paged(&self, channel_id: &str, limit: usize, max_chars: usize) -> Res<String>;
+
+    /// Lists the contexts stored for a channel (newest first), as `(id, summary)` pairs.
+    ///
+    /// The IDs can be passed to `remove_channel_context` (e.g., when a user asks the bot to forget something).
+    async fn list_channel_contexts(&self, channel_id: &str) -> Res<Vec<(String, String)>>;
+
+    /// Removes a stored context from a channel (the context itself, and its link to the channel).
+    ///
+    /// Returns whether the context was found in the channel, so that unknown (or another channel's) IDs are not an error.
+    async fn remove_channel_context(&self, channel_id: &str, context_id: &str) -> Res<bool>;
+
+    /// Sets the embedding of a stored message (by channel, and `ts`), so that it can be found by semantic search.
+    ///
+    /// The embedding must have `EMBEDDING_DIMENSIONS` dimensions.
+    async fn set_channel_message_embedding(&self, channel_id: &str, ts: &str, embedding: &[f32]) -> Res<()>;
+
+    /// Searches for the `k` messages in the channel that are closest in meaning to a query (given as its embedding).
+    ///
+    /// Only messages with an embedding are searched, and the result is a JSON array of messages (like `search_channel_messages`),
+    /// most similar first.
+    async fn search_channel_messages_semantic(&self, channel_id: &str, query: &[f32], k: usize) -> Res<String>;
+
     /// Searches for messages in the channel that match the search string.
     ///
     /// This allows the bot to find relevant past discussions when responding to new questions.
     /// The search_terms parameter should contain comma-separated keywords.
     async fn search_channel_messages(&self, channel_id: &str, search_terms: &str) -> Res<String>;
+
+    /// Searches for messages in the channel (like `search_channel_messages`), only within a time range.
+    ///
+    /// The range is half-open: messages posted at or after `after_ts`, and strictly before `before_ts` (both are
+    /// Slack timestamps, and either may be omitted).  Messages without a `ts` never match a range.
+    async fn search_channel_messages_filtered(&self, channel_id: &str, search_terms: &str, after_ts: Option<&str>, before_ts: Option<&str>) -> Res<String>;
+
+    /// Searches for messages in several channels at once (like `search_channel_messages_filtered`), ranked together.
+    ///
+    /// The result is a JSON array of `CrossChannelMessage`s (so that each hit is labelled with the channel it came from),
+    /// and the result cap applies to all of the channels combined.
+    async fn search_messages_across_channels(&self, channel_ids: &[String], search_terms: &str, after_ts: Option<&str>, before_ts: Option<&str>) -> Res<String>;
+
+    /// Exports a channel's directive, contexts, contexts about users, and messages (with their embeddings).
+    ///
+    /// The export is backend-agnostic, so that a channel can be moved between databases (see `import_channel`).
+    /// It fails if the channel does not exist.
+    async fn export_channel(&self, channel_id: &str) -> Res<ChannelExport>;
+
+    /// Imports a channel export into a channel (which need not be the channel that was exported), and returns how many messages were added.
+    ///
+    /// Messages that the channel already has (by their `ts`) are skipped, so re-importing an export does not duplicate them.
+    async fn import_channel(&self, channel_id: &str, export: &ChannelExport) -> Res<u64>;
+
     /// Starts a stream of a live query for channels.
-    async fn get_channel_live_query(&self) -> Res<Stream<Vec<Self::ChannelType>>>;
+    async fn get_channel_live_query(&self) -> Res<LiveQuery<Self::ChannelType>>;
     /// Starts a stream of a live query for contexts.
-    async fn get_context_live_query(&self) -> Res<Stream<Vec<Self::LlmContextType>>>;
+    async fn get_context_live_query(&self) -> Res<LiveQuery<Self::LlmContextType>>;
 }
 
 /// Database client for triage-bot.
@@ -90,6 +361,319 @@ where
     }
 }
 
+impl DbClient {
+    /// Create a new database client, with the backend selected by `db_endpoint`.
+    ///
+    /// `sqlite://path` uses a SQLite database (which needs no server), and anything else uses SurrealDB (see `surreal`).
+    pub async fn new(config: &Config) -> Res<Self> {
+        match config.db_endpoint.strip_prefix("sqlite://") {
+            Some(path) => Self::sqlite_from_config(path, config).await,
+            None => Self::surreal(config).await,
+        }
+    }
+}
+
+impl<L, C, M> DbClient<L, C, M>
+where
+    L: LlmContext,
+    C: Channel,
+    M: Message,
+{
+    /// Starts a stream of changes to channels, which (unlike `get_channel_live_query`) does not end when the live query does.
+    ///
+    /// The live query is re-issued whenever it ends or fails (e.g., when the connection to the database is lost), and
+    /// each time it is, a `Change::Resynced` tells consumers that changes may have been missed (so they should refetch).
+    pub async fn get_channel_changes(&self) -> Res<ChangeStream<C>> {
+        let inner = self.inner.clone();
+
+        resubscribing_live_query(move || {
+            let inner = inner.clone();
+            async move { inner.get_channel_live_query().await }
+        })
+        .await
+    }
+}
+
+// Data types.
+
+/// How message searches are capped and ranked.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MessageSearchOptions {
+    /// The maximum number of messages returned by a search.
+    pub limit: usize,
+    /// How much newer messages are boosted: a message's relevance is scaled by `1 + recency_boost / (1 + age in days)`.
+    ///
+    /// Zero ranks by relevance alone.
+    pub recency_boost: f32,
+    /// Whether a channel's searches also match likely typos of the (unquoted) terms (e.g., `kafak` for `kafka`).
+    ///
+    /// Fuzzy matches are ranked after the exact matches, and only the channel's most recent messages are looked through
+    /// (see `FUZZY_SEARCH_CANDIDATES`).
+    pub fuzzy: bool,
+}
+
+impl MessageSearchOptions {
+    /// The message search options from the configuration.
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            limit: config.message_search_limit,
+            recency_boost: config.message_search_recency_boost,
+            fuzzy: config.message_search_fuzzy,
+        }
+    }
+}
+
+impl Default for MessageSearchOptions {
+    fn default() -> Self {
+        Self {
+            limit: 20,
+            recency_boost: 0.0,
+            fuzzy: false,
+        }
+    }
+}
+
+/// A term of a message search (see `parse_search_terms`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchTerm {
+    /// The text of the term (without its quotes, and with its whitespace collapsed).
+    pub text: String,
+    /// Whether the term was quoted, so that it only matches its words in order (e.g., `"connection reset by peer"`).
+    ///
+    /// Unquoted terms match messages that have all of their words, in any order.
+    pub phrase: bool,
+}
+
+/// The error returned by database operations while the database is unreachable (see `GenericDbClient::is_available`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DbUnavailable;
+
+impl std::fmt::Display for DbUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The database is unavailable (the connection is being re-established).")
+    }
+}
+
+impl std::error::Error for DbUnavailable {}
+
+/// A stream of live query events, which ends when the database goes away.
+pub type LiveQuery<T> = BoxStream<'static, Res<LiveEvent<T>>>;
+
+/// A change seen by a resubscribing live query (see `resubscribing_live_query`).
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change<T> {
+    /// A change to a record.
+    Event(LiveEvent<T>),
+    /// The live query was re-issued (after it ended, or failed), so changes may have been missed in between.
+    Resynced,
+}
+
+/// A stream of changes, which outlives the live queries behind it.
+pub type ChangeStream<T> = BoxStream<'static, Change<T>>;
+
+/// A change to a record, as seen by a live query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct LiveEvent<T> {
+    /// What happened to the record.
+    pub action: Action,
+    /// The record, as of the change.
+    pub data: T,
+}
+
+/// A version of a channel's directive, kept so that directive changes can be audited and rolled back.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct DirectiveVersion {
+    /// The database ID of the version (set once stored).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The version number within the channel (starting at 1).
+    pub version: u64,
+    /// The user that requested the directive (if known).
+    #[serde(default)]
+    pub user_id: Option<String>,
+    /// When the directive was set (unix seconds).
+    pub created_at: i64,
+    /// The message that set the directive.
+    pub user_message: Value,
+    /// The directive itself (the assistant's notes).
+    pub your_notes: String,
+}
+
+impl DirectiveVersion {
+    /// A new (unstored) version of a directive, set now by the user that sent its message.
+    pub fn new<L: LlmContext>(directive: &L, version: u64, created_at: i64) -> Self {
+        Self {
+            id: None,
+            version,
+            user_id: directive.user_message().get("user").and_then(Value::as_str).map(str::to_string),
+            created_at,
+            user_message: directive.user_message().clone(),
+            your_notes: directive.your_notes().to_string(),
+        }
+    }
+}
+
+/// A message found by a search across channels (see `GenericDbClient::search_messages_across_channels`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct CrossChannelMessage {
+    /// The channel that the message belongs to.
+    pub channel_id: String,
+    /// The raw message, as received from the chat platform.
+    pub raw: Value,
+    /// The thread that the message belongs to.
+    #[serde(default)]
+    pub thread_ts: Option<String>,
+}
+
+/// Statistics about a channel (see `GenericDbClient::get_channel_stats`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Default)]
+pub struct ChannelStats {
+    /// The channel that the statistics are for.
+    pub channel_id: String,
+    /// The start of the window that activity is counted over (unix seconds).
+    pub since: i64,
+    /// The number of messages stored for the channel.
+    pub messages: u64,
+    /// The number of messages posted since the start of the window.
+    pub messages_since: u64,
+    /// The number of contexts stored for the channel.
+    pub contexts: u64,
+    /// The number of the bot's responses (replies, `NoAction`s, and tool calls) since the start of the window.
+    pub responses_since: u64,
+    /// The number of the bot's classified replies since the start of the window, by classification (e.g., `Bug`).
+    pub classifications: BTreeMap<String, u64>,
+    /// The token usage (and estimated cost) of the bot's responses since the start of the window.
+    pub usage: TokenUsage,
+}
+
+/// The root of a thread, with how many replies it has (see `GenericDbClient::get_thread_roots`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct ThreadRoot {
+    /// The `ts` of the root message (which is also the `thread_ts` of its replies).
+    pub ts: String,
+    /// The raw root message.
+    pub raw: Value,
+    /// The number of stored replies (from anyone, including the bot).
+    pub reply_count: u64,
+}
+
+/// A backend-agnostic copy of a channel's data (see `GenericDbClient::export_channel`).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ChannelExport {
+    /// The channel that was exported.
+    pub channel_id: String,
+    /// When the channel was exported (unix seconds).
+    pub exported_at: i64,
+    /// The channel's directive.
+    pub directive: ExportedContext,
+    /// The channel's contexts (oldest first).
+    #[serde(default)]
+    pub contexts: Vec<ExportedContext>,
+    /// The contexts about users in the channel (oldest first).
+    #[serde(default)]
+    pub user_contexts: Vec<ExportedUserContext>,
+    /// The channel's messages (oldest first).
+    ///
+    /// Their relations (to the channel, and to their threads) are restored from the raw messages on import.
+    #[serde(default)]
+    pub messages: Vec<ExportedMessage>,
+}
+
+/// A context (or directive) in a channel export.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExportedContext {
+    /// The message that the context was created from.
+    pub user_message: Value,
+    /// The context itself (the assistant's notes).
+    pub your_notes: String,
+    /// When the context expires (in unix seconds), if it does.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+}
+
+/// A context about a user in a channel export.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExportedUserContext {
+    /// The user that the context is about.
+    pub user_id: String,
+    /// The message that the context was created from.
+    pub user_message: Value,
+    /// The context itself (the assistant's notes).
+    pub your_notes: String,
+}
+
+/// A message in a channel export.
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ExportedMessage {
+    /// The raw message, as received from the chat platform.
+    pub raw: Value,
+    /// The message's embedding (if semantic search is enabled).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub embedding: Option<Vec<f32>>,
+}
+
+/// User feedback on one of the bot's replies (e.g., a 👍 / 👎 reaction).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq, Eq)]
+pub struct Feedback {
+    /// The thread that the bot's reply belongs to.
+    pub thread_ts: String,
+    /// The timestamp of the bot's reply that received the feedback.
+    pub message_ts: String,
+    /// The user who gave the feedback.
+    pub user_id: String,
+    /// The emoji (reaction name) used to give the feedback.
+    pub emoji: String,
+    /// The classification of the original reply, if known.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub classification: Option<String>,
+}
+
+/// An outbound chat message that is waiting in the outbox (e.g., to be retried after a failed send, or to be posted later).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct PendingMessage {
+    /// The database ID of the message (set once stored).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The bot user that should send the message (identifies the workspace).
+    pub bot_user_id: String,
+    /// The channel to send the message to.
+    pub channel_id: String,
+    /// The thread to send the message to.
+    pub thread_ts: String,
+    /// The plain-text version of the message.
+    pub text: String,
+    /// The Block Kit blocks of the message (if any).
+    #[serde(default)]
+    pub blocks: Vec<Value>,
+    /// Whether the threaded reply is also surfaced to the channel.
+    #[serde(default)]
+    pub reply_broadcast: bool,
+    /// When the message was first due to be sent (unix seconds).
+    pub created_at: i64,
+    /// How many sends have failed so far.
+    pub attempts: u32,
+    /// When the next retry is due (unix seconds).
+    pub next_attempt_at: i64,
+}
+
+/// A message that is scheduled to be posted to a thread later (e.g., a follow-up reminder).
+#[derive(Debug, Serialize, Deserialize, Clone, PartialEq)]
+pub struct ScheduledMessage {
+    /// The database ID of the record (set once stored).
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+    /// The channel the message is posted to.
+    pub channel_id: String,
+    /// The thread the message is posted to.
+    pub thread_ts: String,
+    /// The ID of the scheduled message: the chat platform's ID if it was scheduled natively, and the outbox message ID otherwise.
+    pub scheduled_message_id: String,
+    /// Whether the chat platform scheduled the message natively (rather than the outbox).
+    pub native: bool,
+    /// When the message is posted (unix seconds).
+    pub post_at: i64,
+}
+
 // Data type traits.
 
 /// Generic trait for an LLM context in a generic database.
@@ -102,6 +686,14 @@ pub trait LlmContext: std::fmt::Debug + Serialize + DeserializeOwned + Clone + P
     fn user_message(&self) -> &Value;
     /// Get the notes.
     fn your_notes(&self) -> &str;
+    /// Set when the context expires (in unix seconds), after which it is no longer provided to the assistant.
+    fn with_expires_at(self, expires_at: Option<i64>) -> Self;
+    /// Get when the context expires (in unix seconds), if it does.
+    fn expires_at(&self) -> Option<i64>;
+    /// Get when the context was stored (in unix seconds), if it has been.
+    fn created_at(&self) -> Option<i64>;
+    /// Get when the context was last written (in unix seconds), if it has been stored.
+    fn updated_at(&self) -> Option<i64>;
 }
 
 /// Generic trait for a channel in a generic database.
@@ -110,6 +702,38 @@ pub trait Channel: std::fmt::Debug + Serialize + DeserializeOwned + Clone + Part
     fn id(&self) -> Option<String>;
     /// Get the channel directive.
     fn channel_directive(&self) -> &impl LlmContext;
+    /// Get whether the bot is paused in the channel.
+    fn is_paused(&self) -> bool;
+    /// Get whether replies to incidents are also broadcast to the channel.
+    fn broadcasts_incidents(&self) -> bool;
+    /// Get whether the channel is active (i.e., not archived).
+    fn is_active(&self) -> bool;
+    /// Get the channel's (human-readable) name, if it is known.
+    fn name(&self) -> Option<&str>;
+    /// Get the channel's override of the edit re-evaluation window (in seconds), if it has one.
+    fn edit_window_seconds(&self) -> Option<u64>;
+    /// Get the channel's override of the message retention (in days), if it has one.
+    fn retention_days(&self) -> Option<u64>;
+    /// Get the channels whose message history may also be searched (none, unless the channel was linked to others).
+    fn linked_channels(&self) -> &[String];
+    /// Get the channel's override of whether replies are moderated, if it has one.
+    fn moderation_enabled(&self) -> Option<bool>;
+    /// Get the channel's override of the reply confidence threshold (0-100), if it has one.
+    fn confidence_threshold(&self) -> Option<u8>;
+    /// Get the language that the channel's replies are always in (rather than the detected language), if it has one.
+    fn force_language(&self) -> Option<&str>;
+    /// Get the channel's system addendum (set by an operator, not by the channel's members), if it has one.
+    fn system_addendum(&self) -> Option<&str>;
+    /// Get the channel's override of what is posted when the model refuses to respond, if it has one.
+    fn refusal_reply(&self) -> Option<&str>;
+    /// Get how much the bot does in the channel (`Full`, unless it has been set).
+    fn mode(&self) -> ChannelMode;
+    /// Get the channel's override of whether its messages are searched for on the web, if it has one.
+    fn web_search_enabled(&self) -> Option<bool>;
+    /// Get when the channel was stored (in unix seconds), if it has been.
+    fn created_at(&self) -> Option<i64>;
+    /// Get when the channel (e.g., its directive, or settings) was last written (in unix seconds), if it has been stored.
+    fn updated_at(&self) -> Option<i64>;
 }
 
 /// Generic trait for a message in a generic database.
@@ -118,4 +742,344 @@ pub trait Message: std::fmt::Debug + Serialize + DeserializeOwned + Clone + Part
     fn id(&self) -> Option<String>;
     /// Get the raw message content.
     fn raw(&self) -> &Value;
+    /// Get the message timestamp (its `ts`), if it has one.
+    fn ts(&self) -> Option<&str>;
+    /// Get the thread that the message belongs to (its `thread_ts`, or its own `ts` if it is top-level), if it has one.
+    fn thread_ts(&self) -> Option<&str>;
+    /// Get when the message was stored (in unix seconds), if it has been.
+    fn created_at(&self) -> Option<i64>;
+    /// Get when the message was last written (e.g., edited), in unix seconds, if it has been stored.
+    fn updated_at(&self) -> Option<i64>;
+}
+
+// Helpers.
+
+/// The note appended to a channel's context when older context was left out.
+pub const OMITTED_CONTEXT_NOTE: &str = "[Older channel context was omitted to fit the context budget.]";
+
+/// Format a channel's contexts (newest first) as a JSON array of at most `limit` contexts and `max_chars` characters.
+///
+/// Backends fetch one more context than `limit`, so that it is known whether any were left out, in which case
+/// `OMITTED_CONTEXT_NOTE` is appended on its own line.
+pub fn format_channel_context<T: Serialize>(contexts: &[T], limit: usize, max_chars: usize) -> Res<String> {
+    let mut kept = Vec::new();
+    let mut chars = 2; // The brackets.
+
+    for context in contexts.iter().take(limit) {
+        let json = serde_json::to_string(context)?;
+        let needed = json.chars().count() + usize::from(!kept.is_empty());
+
+        if chars + needed > max_chars {
+            break;
+        }
+
+        chars += needed;
+        kept.push(json);
+    }
+
+    let result = format!("[{}]", kept.join(","));
+
+    if kept.len() < contexts.len() {
+        return Ok(format!("{result}\n{OMITTED_CONTEXT_NOTE}"));
+    }
+
+    Ok(result)
+}
+
+/// Import a channel export into a channel through the client's regular writes (so that the full-text search index,
+/// and deduplication of messages, apply as they do to live data), and return how many messages were added.
+///
+/// Backends implement `GenericDbClient::import_channel` with this.
+pub(crate) async fn import_channel_export<D>(db: &D, channel_id: &str, export: &ChannelExport) -> Res<u64>
+where
+    D: GenericDbClient + ?Sized,
+{
+    db.get_or_create_channel(channel_id).await?;
+
+    // A channel that never had a directive exports an empty one, which would only clutter the directive history.
+    if !export.directive.your_notes.is_empty() {
+        let directive = D::LlmContextType::new(export.directive.user_message.clone(), export.directive.your_notes.clone());
+        db.update_channel_directive(channel_id, &directive).await?;
+    }
+
+    for context in &export.contexts {
+        let llm_context = D::LlmContextType::new(context.user_message.clone(), context.your_notes.clone()).with_expires_at(context.expires_at);
+        db.add_channel_context(channel_id, &llm_context).await?;
+    }
+
+    for context in &export.user_contexts {
+        let llm_context = D::LlmContextType::new(context.user_message.clone(), context.your_notes.clone());
+        db.add_user_context(channel_id, &context.user_id, &llm_context).await?;
+    }
+
+    let raws = export.messages.iter().map(|message| message.raw.clone()).collect::<Vec<_>>();
+    let added = db.add_channel_messages(channel_id, &raws).await?;
+
+    // The exported embeddings are (re-)applied to every exported message, since the batch does not say which ones were skipped.
+    for message in &export.messages {
+        if let (Some(ts), Some(embedding)) = (message_ts(&message.raw), &message.embedding) {
+            db.set_channel_message_embedding(channel_id, ts, embedding).await?;
+        }
+    }
+
+    Ok(added)
+}
+
+/// Wrap the live query started by `subscribe`, so that it is re-issued whenever it ends or fails.
+///
+/// The first subscription's error is returned, while later ones are retried (with backoff) until they succeed, and a
+/// `Change::Resynced` is emitted each time the live query is re-issued.
+pub async fn resubscribing_live_query<T, F, Fut>(mut subscribe: F) -> Res<ChangeStream<T>>
+where
+    T: Send + 'static,
+    F: FnMut() -> Fut + Send + 'static,
+    Fut: Future<Output = Res<LiveQuery<T>>> + Send,
+{
+    let live_query = subscribe().await?;
+
+    let changes = futures::stream::unfold((Some(live_query), subscribe), |(mut live_query, mut subscribe)| async move {
+        let mut failures = 0;
+
+        loop {
+            let Some(current) = live_query.as_mut() else {
+                match subscribe().await {
+                    Ok(resubscribed) => return Some((Change::Resynced, (Some(resubscribed), subscribe))),
+                    Err(err) => {
+                        failures += 1;
+                        let backoff = Duration::from_secs(2u64.pow(failures.min(5))).min(RESUBSCRIBE_MAX_BACKOFF);
+
+                        warn!("Failed to re-issue a live query (retrying in {:?}): {}", backoff, err);
+                        tokio::time::sleep(backoff).await;
+
+                        continue;
+                    }
+                }
+            };
+
+            match current.next().await {
+                Some(Ok(event)) => return Some((Change::Event(event), (live_query, subscribe))),
+                Some(Err(err)) => warn!("A live query failed, re-issuing it: {}", err),
+                None => info!("A live query ended, re-issuing it."),
+            }
+
+            live_query = None;
+        }
+    });
+
+    Ok(changes.boxed())
+}
+
+/// Whether an error is (or was caused by) the database being unavailable.
+pub fn is_db_unavailable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<DbUnavailable>())
+}
+
+/// The maximum number of characters in a context summary (see `summarize_context`).
+const CONTEXT_SUMMARY_CHARS: usize = 200;
+
+/// A short, single-line summary of a stored context: the assistant's notes, or else the text of the message it was created from.
+pub fn summarize_context<T: LlmContext>(context: &T) -> String {
+    let text = match context.your_notes().trim() {
+        "" => context.user_message().get("text").and_then(Value::as_str).unwrap_or_default(),
+        notes => notes,
+    };
+
+    let line = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    if line.chars().count() > CONTEXT_SUMMARY_CHARS {
+        format!("{}…", line.chars().take(CONTEXT_SUMMARY_CHARS).collect::<String>())
+    } else {
+        line
+    }
+}
+
+/// The (Slack-style) timestamp of a unix time, for comparing against the `ts` of stored messages.
+///
+/// Timestamps compare correctly as strings, since their seconds all have the same number of digits.
+pub fn unix_to_ts(seconds: i64) -> String {
+    format!("{seconds}.000000")
+}
+
+/// The terms of a message search (comma-separated, e.g., `deploy, rollback, "connection reset by peer"`).
+///
+/// Quoted terms (with straight or curly quotes) are phrases, and commas inside of quotes do not split them.  Terms are
+/// only ever bound as query parameters (never formatted into a query), and terms without any letters or digits
+/// (e.g., `';`) are dropped, since they cannot match any message.
+pub fn parse_search_terms(search_terms: &str) -> Vec<SearchTerm> {
+    let mut terms = Vec::new();
+    let mut current = String::new();
+    let mut quoted = false;
+    let mut in_quotes = false;
+
+    let mut push_term = |current: &mut String, quoted: bool| {
+        let text = current.split_whitespace().collect::<Vec<_>>().join(" ");
+        if text.chars().any(char::is_alphanumeric) {
+            let phrase = quoted && text.contains(' ');
+            terms.push(SearchTerm { text, phrase });
+        }
+
+        current.clear();
+    };
+
+    for c in search_terms.chars() {
+        match c {
+            '"' | '“' | '”' => {
+                in_quotes = !in_quotes;
+                quoted = true;
+            }
+            ',' if !in_quotes => {
+                push_term(&mut current, quoted);
+                quoted = false;
+            }
+            _ => current.push(c),
+        }
+    }
+
+    // An unterminated quote runs to the end of the terms.
+    push_term(&mut current, quoted);
+
+    terms
+}
+
+/// The words of a search term (or of a message's text), lowercased, and without any surrounding punctuation.
+fn search_words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric() && c != '\'' && c != '_' && c != '-')
+        .map(|word| word.trim_matches(|c: char| !c.is_alphanumeric()).to_lowercase())
+        .filter(|word| !word.is_empty())
+        .collect()
+}
+
+/// Whether `text` approximately matches any of the unquoted `terms`: every word of the term is within a small edit
+/// distance of a word of the text (none for words of up to three characters, one for up to six, and two otherwise).
+///
+/// Phrases are never matched approximately, since they are meant to be matched exactly.
+pub fn fuzzy_matches(text: &str, terms: &[SearchTerm]) -> bool {
+    let words = search_words(text);
+
+    terms.iter().filter(|term| !term.phrase).any(|term| {
+        let term_words = search_words(&term.text);
+
+        !term_words.is_empty()
+            && term_words.iter().all(|term_word| {
+                let max_distance = match term_word.chars().count() {
+                    0..=3 => 0,
+                    4..=6 => 1,
+                    _ => 2,
+                };
+
+                words.iter().any(|word| edit_distance(word, term_word) <= max_distance)
+            })
+    })
+}
+
+/// Add the `candidates` that approximately match `terms` (see `fuzzy_matches`) to a search's `messages`, up to `limit`.
+///
+/// Candidates that are already in the results, or outside of the time range (`after_ts` inclusive, `before_ts`
+/// exclusive), are skipped.
+pub fn add_fuzzy_matches<M: Message>(messages: &mut Vec<M>, candidates: Vec<M>, terms: &[SearchTerm], after_ts: Option<&str>, before_ts: Option<&str>, limit: usize) {
+    for candidate in candidates {
+        if messages.len() >= limit {
+            break;
+        }
+
+        let in_range = candidate.ts().is_some_and(|ts| after_ts.is_none_or(|after| ts >= after) && before_ts.is_none_or(|before| ts < before));
+        let text = candidate.raw().get("text").and_then(Value::as_str).unwrap_or_default();
+
+        if in_range && !messages.iter().any(|m| m.id() == candidate.id()) && fuzzy_matches(text, terms) {
+            messages.push(candidate);
+        }
+    }
+}
+
+/// The number of single-character insertions, deletions, substitutions, and swaps of adjacent characters that turn `a` into `b`.
+fn edit_distance(a: &str, b: &str) -> usize {
+    let a = a.chars().collect::<Vec<_>>();
+    let b = b.chars().collect::<Vec<_>>();
+
+    // `distances[i][j]` is the distance between the first `i` characters of `a`, and the first `j` characters of `b`.
+    let mut distances = vec![vec![0; b.len() + 1]; a.len() + 1];
+    for (i, row) in distances.iter_mut().enumerate() {
+        row[0] = i;
+    }
+    for (j, distance) in distances[0].iter_mut().enumerate() {
+        *distance = j;
+    }
+
+    for i in 1..=a.len() {
+        for j in 1..=b.len() {
+            let substitution = distances[i - 1][j - 1] + usize::from(a[i - 1] != b[j - 1]);
+            distances[i][j] = substitution.min(distances[i - 1][j] + 1).min(distances[i][j - 1] + 1);
+
+            if i > 1 && j > 1 && a[i - 1] == b[j - 2] && a[i - 2] == b[j - 1] {
+                distances[i][j] = distances[i][j].min(distances[i - 2][j - 2] + 1);
+            }
+        }
+    }
+
+    distances[a.len()][b.len()]
+}
+
+/// The timestamp of a raw message, which identifies it within its channel.
+pub fn message_ts(message: &Value) -> Option<&str> {
+    message.get("ts").and_then(Value::as_str)
+}
+
+/// The thread that a raw message belongs to: its `thread_ts`, or its own `ts` (a top-level message is the root of its own thread).
+pub fn message_thread_ts(message: &Value) -> Option<&str> {
+    message.get("thread_ts").or_else(|| message.get("ts")).and_then(Value::as_str)
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn term(text: &str, phrase: bool) -> SearchTerm {
+        SearchTerm { text: text.to_string(), phrase }
+    }
+
+    #[test]
+    fn test_parse_search_terms() {
+        assert_eq!(parse_search_terms("deploy, rollback"), vec![term("deploy", false), term("rollback", false)]);
+
+        // Quoted terms are phrases (even with commas in them), and can be mixed with unquoted terms.
+        assert_eq!(
+            parse_search_terms(r#"kafka, "connection reset by peer", payment  timeout, “failed, retrying”"#),
+            vec![
+                term("kafka", false),
+                term("connection reset by peer", true),
+                term("payment timeout", false),
+                term("failed, retrying", true),
+            ]
+        );
+
+        // A quoted single word is just a word, an unterminated quote runs to the end, and empty terms are dropped.
+        assert_eq!(parse_search_terms(r#""502", "bad gateway"#), vec![term("502", false), term("bad gateway", true)]);
+        assert!(parse_search_terms(r#", "", ';, --"#).is_empty());
+    }
+
+    #[test]
+    fn test_edit_distance() {
+        assert_eq!(edit_distance("kafka", "kafka"), 0);
+        assert_eq!(edit_distance("kafak", "kafka"), 1);
+        assert_eq!(edit_distance("kakfa", "kafka"), 1);
+        assert_eq!(edit_distance("kafk", "kafka"), 1);
+        assert_eq!(edit_distance("déploiment", "déploiement"), 1);
+        assert_eq!(edit_distance("", "abc"), 3);
+    }
+
+    #[test]
+    fn test_fuzzy_matches() {
+        let text = "The Kubernetes deployment of kafka-consumer failed: connection reset by peer.";
+
+        assert!(fuzzy_matches(text, &[term("kuberentes", false)]));
+        assert!(fuzzy_matches(text, &[term("deploymnet kafka-consumer", false)]));
+        assert!(fuzzy_matches(text, &[term("nope", false), term("kubernets", false)]));
+
+        // Short words must match exactly, every word of a term must match, and phrases are never fuzzy.
+        assert!(!fuzzy_matches(text, &[term("byt", false)]));
+        assert!(!fuzzy_matches(text, &[term("kubernetes rollback", false)]));
+        assert!(!fuzzy_matches(text, &[term("connection reset by pear", true)]));
+    }
 }
@@ -0,0 +1,1919 @@
+//! SQLite implementation of the database client, for local deployments that do not need a database server.
+//!
+//! Raw messages and contexts are stored as JSON columns, message search uses an FTS5 index, and the live
+//! queries are emulated with broadcast channels that emit the rows written through the client itself (so
+//! writes from other processes are not seen).
+//!
+//! The data types are shared with the surreal implementation, so that the rest of the bot is agnostic to the backend.
+
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::StreamExt;
+use rusqlite::{Connection, OptionalExtension, Row, params, types::Type};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+use surrealdb::{Action, RecordId};
+use tokio::sync::broadcast;
+use tracing::{info, instrument};
+
+use crate::base::{
+    config::Config,
+    types::{ChannelMode, Res, TokenUsage, Void},
+};
+
+use super::{
+    ChannelExport, ChannelStats, CrossChannelMessage, DEFAULT_MESSAGE_BATCH_SIZE, DbClient, DirectiveVersion, ExportedContext, ExportedMessage, ExportedUserContext, FUZZY_SEARCH_CANDIDATES, Feedback,
+    GenericDbClient, LiveEvent, LiveQuery, LlmContext, MessageSearchOptions, PendingMessage, ScheduledMessage, SearchTerm, ThreadRoot, add_fuzzy_matches, format_channel_context,
+    import_channel_export, message_thread_ts, message_ts, parse_search_terms, summarize_context,
+    surreal::{SurrealChannel, SurrealLlmContext, SurrealMessage},
+    unix_to_ts,
+};
+
+// Statics.
+
+/// How many live query events are buffered for slow consumers before they start skipping events.
+const LIVE_QUERY_CAPACITY: usize = 256;
+
+/// How search hits are ranked: by relevance (`bm25`, where lower is better), scaled up for newer messages (see `MessageSearchOptions`).
+const SEARCH_RANK: &str = "bm25(message_fts) * (1.0 + ?6 / (1.0 + max(0.0, (CAST(strftime('%s', 'now') AS REAL) - CAST(COALESCE(message.ts, '0') AS REAL)) / 86400.0)))";
+
+/// The columns of the channel table, in the order that `read_channel` expects.
+const CHANNEL_COLUMNS: &str = "id, channel_directive, paused, broadcast_incidents, active, name, edit_window_seconds, retention_days, linked_channels, moderation_enabled, confidence_threshold, force_language, system_addendum, refusal_reply, mode, web_search_enabled, created_at, updated_at";
+
+/// The columns of the context tables, in the order that `read_context` expects.
+const CONTEXT_COLUMNS: &str = "id, user_message, your_notes, expires_at, created_at, updated_at";
+
+/// The columns of the message table, in the order that `read_message` expects.
+const MESSAGE_COLUMNS: &str = "id, raw, ts, thread_ts, created_at, updated_at";
+
+/// The current time (in unix seconds), in SQL.
+const SQL_NOW: &str = "CAST(strftime('%s', 'now') AS INTEGER)";
+
+/// The condition that a context has not expired (contexts without an expiry never do).
+const SQL_UNEXPIRED: &str = "(expires_at IS NULL OR expires_at > CAST(strftime('%s', 'now') AS INTEGER))";
+
+// Extra methods on `DbClient` applied by the sqlite implementation.
+
+impl DbClient {
+    /// Create a new database client, backed by a SQLite database at `path` (`:memory:` for an in-memory database).
+    pub async fn sqlite(path: &str) -> Res<Self> {
+        Self::sqlite_with_search_options(path, MessageSearchOptions::default()).await
+    }
+
+    /// Create a new database client, backed by a SQLite database at `path`, with the message search and batch options from the configuration.
+    #[instrument(skip_all)]
+    pub async fn sqlite_from_config(path: &str, config: &Config) -> Res<Self> {
+        info!("Using a SQLite database at `{}`.", path);
+
+        let client = SqliteDbClient::open(path)
+            .await?
+            .with_search_options(MessageSearchOptions::from_config(config))
+            .with_message_batch_size(config.message_batch_size);
+
+        Ok(Self { inner: Arc::new(client) })
+    }
+
+    /// Create a new database client, backed by a SQLite database at `path`, that caps and ranks message searches with `search`.
+    #[instrument(skip_all)]
+    pub async fn sqlite_with_search_options(path: &str, search: MessageSearchOptions) -> Res<Self> {
+        info!("Using a SQLite database at `{}`.", path);
+
+        Ok(Self {
+            inner: Arc::new(SqliteDbClient::open(path).await?.with_search_options(search)),
+        })
+    }
+}
+
+// SQLite client implementation.
+
+/// Database client for SQLite.
+pub struct SqliteDbClient {
+    /// The connection (which is only ever used from blocking tasks).
+    conn: Arc<Mutex<Connection>>,
+    /// The live query events for channels.
+    channels: broadcast::Sender<LiveEvent<SurrealChannel>>,
+    /// The live query events for contexts.
+    contexts: broadcast::Sender<LiveEvent<SurrealLlmContext>>,
+    /// How message searches are capped and ranked.
+    search: MessageSearchOptions,
+    /// How many messages are stored per transaction by `add_channel_messages`.
+    message_batch_size: usize,
+}
+
+impl SqliteDbClient {
+    /// Open (or create) the SQLite database at `path`, and set up its schema.
+    #[instrument(name = "SqliteDbClient::open", skip_all)]
+    pub async fn open(path: &str) -> Res<Self> {
+        let path = path.to_string();
+
+        let conn = tokio::task::spawn_blocking(move || -> Res<Connection> {
+            let conn = Connection::open(path)?;
+            setup_sqlite_db(&conn)?;
+
+            Ok(conn)
+        })
+        .await??;
+
+        let (channels, _) = broadcast::channel(LIVE_QUERY_CAPACITY);
+        let (contexts, _) = broadcast::channel(LIVE_QUERY_CAPACITY);
+
+        info!("Database initialized successfully.");
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            channels,
+            contexts,
+            search: MessageSearchOptions::default(),
+            message_batch_size: DEFAULT_MESSAGE_BATCH_SIZE,
+        })
+    }
+
+    /// Cap and rank message searches with `search` (rather than the defaults).
+    pub fn with_search_options(mut self, search: MessageSearchOptions) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// Store up to `message_batch_size` messages per transaction in `add_channel_messages` (rather than the default).
+    pub fn with_message_batch_size(mut self, message_batch_size: usize) -> Self {
+        self.message_batch_size = message_batch_size.max(1);
+        self
+    }
+
+    /// Run `f` with the connection on a blocking task, so that the runtime is not blocked by the disk.
+    async fn with_conn<T, F>(&self, f: F) -> Res<T>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Res<T> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+
+        tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().map_err(|_| anyhow!("The SQLite connection is poisoned."))?;
+
+            f(&mut conn)
+        })
+        .await?
+    }
+
+    /// Set a column of a channel, and emit the updated channel to the live queries.
+    async fn set_channel_column(&self, channel_id: &str, column: &'static str, value: rusqlite::types::Value) -> Void {
+        let channel_id = channel_id.to_string();
+
+        let channel = self
+            .with_conn(move |conn| {
+                conn.execute(&format!("UPDATE channel SET {column} = ?1 WHERE id = ?2"), params![value, channel_id])?;
+
+                read_channel(conn, &channel_id)
+            })
+            .await?;
+
+        if let Some(channel) = channel {
+            let _ = self.channels.send(LiveEvent { action: Action::Update, data: channel });
+        }
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl GenericDbClient for SqliteDbClient {
+    type ChannelType = SurrealChannel;
+    type LlmContextType = SurrealLlmContext;
+    type MessageType = SurrealMessage;
+
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    #[instrument(skip(self))]
+    async fn healthy(&self) -> Void {
+        self.with_conn(|conn| {
+            conn.query_row("SELECT 1", [], |row| row.get::<_, i64>(0))?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_or_create_channel(&self, channel_id: &str) -> Res<Self::ChannelType> {
+        let id = channel_id.to_string();
+
+        let (channel, created) = self
+            .with_conn(move |conn| {
+                let directive = serde_json::to_string(&SurrealLlmContext::new(Value::Object(Default::default()), "".into()))?;
+
+                // Creating is idempotent, so that concurrent callers get the same channel.
+                let created = conn.execute("INSERT OR IGNORE INTO channel (id, channel_directive) VALUES (?1, ?2)", params![id, directive])? > 0;
+                let channel = read_channel(conn, &id)?.ok_or(anyhow!("Failed to create channel"))?;
+
+                Ok((channel, created))
+            })
+            .await?;
+
+        if created {
+            info!("Channel `{}` not found, created a new one.", channel_id);
+
+            let _ = self.channels.send(LiveEvent {
+                action: Action::Create,
+                data: channel.clone(),
+            });
+        } else {
+            info!("Channel `{}` found.", channel_id);
+        }
+
+        Ok(channel)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_directive(&self, channel_id: &str) -> Res<Option<Self::LlmContextType>> {
+        let id = channel_id.to_string();
+
+        let channel = self.with_conn(move |conn| read_channel(conn, &id)).await?;
+
+        Ok(channel.map(|channel| channel.channel_directive))
+    }
+
+    #[instrument(skip(self, directive))]
+    async fn update_channel_directive(&self, channel_id: &str, directive: &Self::LlmContextType) -> Void {
+        let version = DirectiveVersion::new(directive, 0, chrono::Utc::now().timestamp());
+        let directive = serde_json::to_string(&SurrealLlmContext { id: None, ..directive.clone() })?;
+
+        self.set_channel_column(channel_id, "channel_directive", directive.into()).await?;
+
+        // The new version is numbered after the channel's existing versions.
+        let id = channel_id.to_string();
+        self.with_conn(move |conn| {
+            conn.execute(
+                r#"
+                    INSERT INTO directive_version (channel_id, version, user_id, created_at, user_message, your_notes)
+                    VALUES (?1, (SELECT COALESCE(MAX(version), 0) + 1 FROM directive_version WHERE channel_id = ?1), ?2, ?3, ?4, ?5)
+                "#,
+                params![id, version.user_id, version.created_at, serde_json::to_string(&version.user_message)?, version.your_notes],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Channel `{}` updated.", channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_directive_history(&self, channel_id: &str, limit: usize) -> Res<Vec<DirectiveVersion>> {
+        let id = channel_id.to_string();
+
+        self.with_conn(move |conn| {
+            let versions = conn
+                .prepare("SELECT id, version, user_id, created_at, user_message, your_notes FROM directive_version WHERE channel_id = ?1 ORDER BY version DESC LIMIT ?2")?
+                .query_map(params![id, limit as i64], read_directive_version)?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(versions)
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn restore_channel_directive(&self, channel_id: &str, version_id: &str) -> Res<Option<Self::LlmContextType>> {
+        let Ok(rowid) = version_id.parse::<i64>() else {
+            return Ok(None);
+        };
+        let id = channel_id.to_string();
+
+        let version = self
+            .with_conn(move |conn| {
+                let version = conn
+                    .query_row(
+                        "SELECT id, version, user_id, created_at, user_message, your_notes FROM directive_version WHERE id = ?1 AND channel_id = ?2",
+                        params![rowid, id],
+                        read_directive_version,
+                    )
+                    .optional()?;
+
+                Ok(version)
+            })
+            .await?;
+
+        let Some(version) = version else {
+            return Ok(None);
+        };
+
+        let directive = SurrealLlmContext::new(version.user_message, version.your_notes);
+        self.update_channel_directive(channel_id, &directive).await?;
+
+        info!("Restored version {} of the directive of channel `{}`.", version.version, channel_id);
+
+        Ok(Some(directive))
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_paused(&self, channel_id: &str, paused: bool) -> Res<()> {
+        self.set_channel_column(channel_id, "paused", i64::from(paused).into()).await?;
+
+        info!("Channel `{}` paused: {}.", channel_id, paused);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_broadcast_incidents(&self, channel_id: &str, broadcast_incidents: bool) -> Res<()> {
+        self.set_channel_column(channel_id, "broadcast_incidents", i64::from(broadcast_incidents).into()).await?;
+
+        info!("Channel `{}` broadcasts incidents: {}.", channel_id, broadcast_incidents);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_active(&self, channel_id: &str, active: bool) -> Res<()> {
+        self.set_channel_column(channel_id, "active", i64::from(active).into()).await?;
+
+        info!("Channel `{}` active: {}.", channel_id, active);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_name(&self, channel_id: &str, name: &str) -> Res<()> {
+        self.set_channel_column(channel_id, "name", name.to_string().into()).await?;
+
+        info!("Channel `{}` named `{}`.", channel_id, name);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_edit_window(&self, channel_id: &str, window_seconds: Option<u64>) -> Res<()> {
+        let value = match window_seconds {
+            Some(window_seconds) => i64::try_from(window_seconds)?.into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "edit_window_seconds", value).await?;
+
+        info!("Channel `{}` edit window: {:?}.", channel_id, window_seconds);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_retention(&self, channel_id: &str, retention_days: Option<u64>) -> Res<()> {
+        let value = match retention_days {
+            Some(retention_days) => i64::try_from(retention_days)?.into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "retention_days", value).await?;
+
+        info!("Channel `{}` retention: {:?} days.", channel_id, retention_days);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_moderation(&self, channel_id: &str, moderation_enabled: Option<bool>) -> Res<()> {
+        let value = match moderation_enabled {
+            Some(moderation_enabled) => moderation_enabled.into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "moderation_enabled", value).await?;
+
+        info!("Channel `{}` moderation: {:?}.", channel_id, moderation_enabled);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_confidence_threshold(&self, channel_id: &str, confidence_threshold: Option<u8>) -> Res<()> {
+        let value = match confidence_threshold {
+            Some(confidence_threshold) => i64::from(confidence_threshold).into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "confidence_threshold", value).await?;
+
+        info!("Channel `{}` confidence threshold: {:?}.", channel_id, confidence_threshold);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_force_language(&self, channel_id: &str, force_language: Option<&str>) -> Res<()> {
+        let value = match force_language {
+            Some(force_language) => force_language.to_string().into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "force_language", value).await?;
+
+        info!("Channel `{}` language: {:?}.", channel_id, force_language);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, system_addendum))]
+    async fn set_channel_system_addendum(&self, channel_id: &str, system_addendum: Option<&str>) -> Res<()> {
+        let value = match system_addendum {
+            Some(system_addendum) => system_addendum.to_string().into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "system_addendum", value).await?;
+
+        info!("Channel `{}` system addendum {}.", channel_id, if system_addendum.is_some() { "set" } else { "removed" });
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_refusal_reply(&self, channel_id: &str, refusal_reply: Option<&str>) -> Res<()> {
+        let value = match refusal_reply {
+            Some(refusal_reply) => refusal_reply.to_string().into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "refusal_reply", value).await?;
+
+        info!("Channel `{}` refusal reply: {:?}.", channel_id, refusal_reply);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_mode(&self, channel_id: &str, mode: ChannelMode) -> Res<()> {
+        self.set_channel_column(channel_id, "mode", mode.label().to_string().into()).await?;
+
+        info!("Channel `{}` mode: {}.", channel_id, mode.label());
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_web_search(&self, channel_id: &str, web_search_enabled: Option<bool>) -> Res<()> {
+        let value = match web_search_enabled {
+            Some(web_search_enabled) => web_search_enabled.into(),
+            None => rusqlite::types::Value::Null,
+        };
+
+        self.set_channel_column(channel_id, "web_search_enabled", value).await?;
+
+        info!("Channel `{}` web search: {:?}.", channel_id, web_search_enabled);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_linked_channels(&self, channel_id: &str, linked_channels: &[String]) -> Res<()> {
+        let value = serde_json::to_string(linked_channels)?.into();
+
+        self.set_channel_column(channel_id, "linked_channels", value).await?;
+
+        info!("Channel `{}` linked channels: {:?}.", channel_id, linked_channels);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, context))]
+    async fn add_channel_context(&self, channel_id: &str, context: &Self::LlmContextType) -> Res<()> {
+        let id = channel_id.to_string();
+        let user_message = serde_json::to_string(&context.user_message)?;
+        let your_notes = context.your_notes.clone();
+        let expires_at = context.expires_at;
+
+        let context = self
+            .with_conn(move |conn| {
+                conn.execute(
+                    "INSERT INTO context (channel_id, user_message, your_notes, expires_at) VALUES (?1, ?2, ?3, ?4)",
+                    params![id, user_message, your_notes, expires_at],
+                )?;
+
+                let context = conn.query_row(&format!("SELECT {CONTEXT_COLUMNS} FROM context WHERE id = ?1"), params![conn.last_insert_rowid()], read_context)?;
+
+                Ok(context)
+            })
+            .await?;
+
+        let _ = self.contexts.send(LiveEvent { action: Action::Create, data: context });
+
+        info!("Added context for channel `{}`.", channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, context))]
+    async fn add_user_context(&self, channel_id: &str, user_id: &str, context: &Self::LlmContextType) -> Res<()> {
+        let id = channel_id.to_string();
+        let user = user_id.to_string();
+        let user_message = serde_json::to_string(&context.user_message)?;
+        let your_notes = context.your_notes.clone();
+
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO user_context (channel_id, user_id, user_message, your_notes) VALUES (?1, ?2, ?3, ?4)",
+                params![id, user, user_message, your_notes],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Added context about user `{}` for channel `{}`.", user_id, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_user_context(&self, channel_id: &str, user_id: &str) -> Res<Vec<Self::LlmContextType>> {
+        let id = channel_id.to_string();
+        let user = user_id.to_string();
+
+        let context = self
+            .with_conn(move |conn| {
+                let context = conn
+                    .prepare(&format!("SELECT {CONTEXT_COLUMNS} FROM user_context WHERE channel_id = ?1 AND user_id = ?2 ORDER BY id"))?
+                    .query_map(params![id, user], |row| {
+                        Ok(SurrealLlmContext {
+                            id: Some(RecordId::from(("user_context", row.get::<_, i64>(0)?))),
+                            ..read_context(row)?
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(context)
+            })
+            .await?;
+
+        info!("Retrieved {} contexts about user `{}` for channel `{}`.", context.len(), user_id, channel_id);
+
+        Ok(context)
+    }
+
+    #[instrument(skip(self))]
+    async fn add_channel_message(&self, channel_id: &str, message: &Value) -> Res<bool> {
+        let id = channel_id.to_string();
+        let message = message.clone();
+
+        // A message that is already stored (by channel and `ts`) is ignored by the unique index.
+        let added = self
+            .with_conn(move |conn| {
+                let tx = conn.transaction()?;
+
+                let added = tx.execute(
+                    "INSERT OR IGNORE INTO message (channel_id, ts, thread_ts, raw) VALUES (?1, ?2, ?3, ?4)",
+                    params![id, message_ts(&message), message_thread_ts(&message), serde_json::to_string(&message)?],
+                )? > 0;
+
+                if added {
+                    tx.execute("INSERT INTO message_fts (rowid, text) VALUES (?1, ?2)", params![tx.last_insert_rowid(), message_text(&message)])?;
+                }
+
+                tx.commit()?;
+
+                Ok(added)
+            })
+            .await?;
+
+        if added {
+            info!("Added message for channel `{}`.", channel_id);
+        } else {
+            info!("Skipped duplicate message for channel `{}`.", channel_id);
+        }
+
+        Ok(added)
+    }
+
+    #[instrument(skip(self, messages))]
+    async fn add_channel_messages(&self, channel_id: &str, messages: &[Value]) -> Res<u64> {
+        let mut added = 0;
+
+        for chunk in messages.chunks(self.message_batch_size) {
+            let id = channel_id.to_string();
+            let chunk = chunk.to_vec();
+
+            // Like `add_channel_message`, messages that are already stored are ignored by the unique index.
+            added += self
+                .with_conn(move |conn| {
+                    let tx = conn.transaction()?;
+                    let mut added: u64 = 0;
+
+                    for message in &chunk {
+                        let inserted = tx.execute(
+                            "INSERT OR IGNORE INTO message (channel_id, ts, thread_ts, raw) VALUES (?1, ?2, ?3, ?4)",
+                            params![id, message_ts(message), message_thread_ts(message), serde_json::to_string(message)?],
+                        )? > 0;
+
+                        if inserted {
+                            tx.execute("INSERT INTO message_fts (rowid, text) VALUES (?1, ?2)", params![tx.last_insert_rowid(), message_text(message)])?;
+                            added += 1;
+                        }
+                    }
+
+                    tx.commit()?;
+
+                    Ok(added)
+                })
+                .await?;
+        }
+
+        info!("Added {} of {} messages for channel `{}`.", added, messages.len(), channel_id);
+
+        Ok(added)
+    }
+
+    #[instrument(skip(self, message))]
+    async fn update_channel_message(&self, channel_id: &str, ts: &str, message: &Value) -> Res<()> {
+        let id = channel_id.to_string();
+        let ts_owned = ts.to_string();
+        let patch = message.clone();
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+
+            let messages = tx
+                .prepare("SELECT id, raw FROM message WHERE channel_id = ?1 AND ts = ?2")?
+                .query_map(params![id, ts_owned], |row| Ok((row.get::<_, i64>(0)?, json_column::<Value>(row, 1)?)))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for (rowid, mut raw) in messages {
+                merge_json(&mut raw, &patch);
+
+                tx.execute("UPDATE message SET ts = ?1, raw = ?2 WHERE id = ?3", params![message_ts(&raw), serde_json::to_string(&raw)?, rowid])?;
+                tx.execute("UPDATE message_fts SET text = ?1 WHERE rowid = ?2", params![message_text(&raw), rowid])?;
+            }
+
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Updated message `{}` for channel `{}`.", ts, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_channel_message(&self, channel_id: &str, ts: &str) -> Res<()> {
+        let id = channel_id.to_string();
+        let ts_owned = ts.to_string();
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+
+            tx.execute(
+                "DELETE FROM message_fts WHERE rowid IN (SELECT id FROM message WHERE channel_id = ?1 AND ts = ?2)",
+                params![id, ts_owned],
+            )?;
+            tx.execute("DELETE FROM message WHERE channel_id = ?1 AND ts = ?2", params![id, ts_owned])?;
+
+            tx.commit()?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Deleted message `{}` for channel `{}`.", ts, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_message(&self, channel_id: &str, ts: &str) -> Res<Option<Self::MessageType>> {
+        let id = channel_id.to_string();
+        let ts = ts.to_string();
+
+        self.with_conn(move |conn| {
+            let message = conn
+                .query_row(
+                    &format!("SELECT {MESSAGE_COLUMNS} FROM message WHERE channel_id = ?1 AND ts = ?2 ORDER BY id LIMIT 1"),
+                    params![id, ts],
+                    read_message,
+                )
+                .optional()?;
+
+            Ok(message)
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_thread_messages(&self, channel_id: &str, thread_ts: &str) -> Res<Vec<Self::MessageType>> {
+        let id = channel_id.to_string();
+        let thread_ts_owned = thread_ts.to_string();
+
+        let messages = self
+            .with_conn(move |conn| {
+                let messages = conn
+                    .prepare(&format!("SELECT {MESSAGE_COLUMNS} FROM message WHERE channel_id = ?1 AND thread_ts = ?2 ORDER BY ts ASC, id ASC"))?
+                    .query_map(params![id, thread_ts_owned], read_message)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(messages)
+            })
+            .await?;
+
+        info!("Retrieved {} stored messages for thread `{}` in channel `{}`.", messages.len(), thread_ts, channel_id);
+
+        Ok(messages)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_thread_roots(&self, channel_id: &str, since: i64) -> Res<Vec<ThreadRoot>> {
+        let id = channel_id.to_string();
+
+        let roots = self
+            .with_conn(move |conn| {
+                let roots = conn
+                    .prepare(
+                        r#"
+                            SELECT root.ts, root.raw, (
+                                SELECT COUNT(*) FROM message reply
+                                WHERE reply.channel_id = root.channel_id AND reply.thread_ts = root.ts AND reply.ts IS NOT NULL AND reply.ts != root.ts
+                            )
+                            FROM message root
+                            WHERE root.channel_id = ?1 AND root.ts >= ?2 AND root.thread_ts = root.ts
+                            ORDER BY root.ts DESC
+                        "#,
+                    )?
+                    .query_map(params![id, unix_to_ts(since)], |row| {
+                        Ok(ThreadRoot {
+                            ts: row.get(0)?,
+                            raw: json_column(row, 1)?,
+                            reply_count: row.get::<_, i64>(2)? as u64,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(roots)
+            })
+            .await?;
+
+        info!("Retrieved {} thread roots for channel `{}`.", roots.len(), channel_id);
+
+        Ok(roots)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_recent_channel_messages(&self, channel_id: &str, limit: usize) -> Res<Vec<Self::MessageType>> {
+        let id = channel_id.to_string();
+
+        let messages = self
+            .with_conn(move |conn| {
+                let messages = conn
+                    .prepare(&format!("SELECT {MESSAGE_COLUMNS} FROM message WHERE channel_id = ?1 AND ts IS NOT NULL ORDER BY ts DESC LIMIT ?2"))?
+                    .query_map(params![id, limit as i64], read_message)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(messages)
+            })
+            .await?;
+
+        info!("Retrieved {} recent messages for channel `{}`.", messages.len(), channel_id);
+
+        Ok(messages)
+    }
+
+    #[instrument(skip(self))]
+    async fn add_feedback(&self, channel_id: &str, feedback: &Feedback) -> Res<()> {
+        let id = channel_id.to_string();
+        let feedback = feedback.clone();
+
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO feedback (channel_id, thread_ts, message_ts, user_id, emoji, classification) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                params![id, feedback.thread_ts, feedback.message_ts, feedback.user_id, feedback.emoji, feedback.classification],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Added feedback for channel `{}`.", channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_feedback(&self, channel_id: &str) -> Res<Vec<Feedback>> {
+        let id = channel_id.to_string();
+
+        let feedback = self
+            .with_conn(move |conn| {
+                let feedback = conn
+                    .prepare("SELECT thread_ts, message_ts, user_id, emoji, classification FROM feedback WHERE channel_id = ?1 ORDER BY id")?
+                    .query_map(params![id], |row| {
+                        Ok(Feedback {
+                            thread_ts: row.get(0)?,
+                            message_ts: row.get(1)?,
+                            user_id: row.get(2)?,
+                            emoji: row.get(3)?,
+                            classification: row.get(4)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(feedback)
+            })
+            .await?;
+
+        info!("Retrieved feedback for channel `{}`.", channel_id);
+
+        Ok(feedback)
+    }
+
+    #[instrument(skip(self, response))]
+    async fn add_assistant_response(&self, channel_id: &str, thread_ts: &str, response: &Value) -> Void {
+        let id = channel_id.to_string();
+        let thread = thread_ts.to_string();
+        let response = serde_json::to_string(response)?;
+
+        self.with_conn(move |conn| {
+            conn.execute("INSERT INTO response (channel_id, thread_ts, response) VALUES (?1, ?2, ?3)", params![id, thread, response])?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Added response to thread `{}` in channel `{}`.", thread_ts, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_assistant_responses(&self, channel_id: &str, thread_ts: &str, limit: usize) -> Res<Vec<Value>> {
+        let id = channel_id.to_string();
+        let thread = thread_ts.to_string();
+
+        let mut responses = self
+            .with_conn(move |conn| {
+                let responses = conn
+                    .prepare("SELECT response FROM response WHERE channel_id = ?1 AND thread_ts = ?2 ORDER BY id DESC LIMIT ?3")?
+                    .query_map(params![id, thread, limit as i64], |row| json_column(row, 0))?
+                    .collect::<Result<Vec<Value>, _>>()?;
+
+                Ok(responses)
+            })
+            .await?;
+
+        responses.reverse();
+
+        Ok(responses)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_message_count(&self, channel_id: &str) -> Res<u64> {
+        let id = channel_id.to_string();
+
+        self.with_conn(move |conn| {
+            let count: i64 = conn.query_row("SELECT COUNT(*) FROM message WHERE channel_id = ?1", params![id], |row| row.get(0))?;
+
+            Ok(u64::try_from(count)?)
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_stats(&self, channel_id: &str, since: i64) -> Res<ChannelStats> {
+        let id = channel_id.to_string();
+
+        self.with_conn(move |conn| {
+            let count = |sql: &str, params: &[&dyn rusqlite::ToSql]| -> Res<u64> {
+                let count: i64 = conn.query_row(sql, params, |row| row.get(0))?;
+
+                Ok(u64::try_from(count)?)
+            };
+
+            let messages = count("SELECT COUNT(*) FROM message WHERE channel_id = ?1", params![id])?;
+            let messages_since = count("SELECT COUNT(*) FROM message WHERE channel_id = ?1 AND ts >= ?2", params![id, unix_to_ts(since)])?;
+            let contexts = count("SELECT COUNT(*) FROM context WHERE channel_id = ?1", params![id])?;
+            let responses_since = count(
+                "SELECT COUNT(*) FROM response WHERE channel_id = ?1 AND json_extract(response, '$.created_at') >= ?2",
+                params![id, since],
+            )?;
+
+            let classifications = conn
+                .prepare(
+                    r#"
+                        SELECT json_extract(response, '$.response.classification') AS classification, COUNT(*) FROM response
+                        WHERE channel_id = ?1 AND json_extract(response, '$.created_at') >= ?2 AND json_extract(response, '$.response.classification') IS NOT NULL
+                        GROUP BY classification
+                    "#,
+                )?
+                .query_map(params![id, since], |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)? as u64)))?
+                .collect::<Result<_, _>>()?;
+
+            let mut usage = TokenUsage::default();
+            let usages = conn
+                .prepare(
+                    r#"
+                        SELECT json_extract(response, '$.usage') FROM response
+                        WHERE channel_id = ?1 AND json_extract(response, '$.created_at') >= ?2 AND json_extract(response, '$.usage') IS NOT NULL
+                    "#,
+                )?
+                .query_map(params![id, since], |row| row.get::<_, String>(0))?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            for response_usage in usages {
+                usage.add(&serde_json::from_str(&response_usage)?);
+            }
+
+            Ok(ChannelStats {
+                channel_id: id,
+                since,
+                messages,
+                messages_since,
+                contexts,
+                responses_since,
+                classifications,
+                usage,
+            })
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn list_channel_ids(&self) -> Res<Vec<String>> {
+        self.with_conn(move |conn| {
+            let ids = conn
+                .prepare("SELECT id FROM channel ORDER BY id")?
+                .query_map([], |row| row.get(0))?
+                .collect::<Result<Vec<String>, _>>()?;
+
+            Ok(ids)
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn prune_old_messages(&self, channel_id: &str, cutoff: i64) -> Res<u64> {
+        let id = channel_id.to_string();
+
+        let pruned = self
+            .with_conn(move |conn| {
+                let tx = conn.transaction()?;
+
+                // Messages without a timestamp are never pruned, since their age is unknown.
+                let messages = tx
+                    .prepare(
+                        r#"
+                            SELECT id FROM message
+                            WHERE channel_id = ?1
+                                AND ts IS NOT NULL
+                                AND ts < ?2
+                                AND ts NOT IN (
+                                    SELECT json_extract(user_message, '$.ts') FROM context
+                                    WHERE channel_id = ?1 AND json_extract(user_message, '$.ts') IS NOT NULL
+                                )
+                        "#,
+                    )?
+                    .query_map(params![id, unix_to_ts(cutoff)], |row| row.get(0))?
+                    .collect::<Result<Vec<i64>, _>>()?;
+
+                for rowid in &messages {
+                    tx.execute("DELETE FROM message_fts WHERE rowid = ?1", params![rowid])?;
+                    tx.execute("DELETE FROM message WHERE id = ?1", params![rowid])?;
+                }
+
+                tx.commit()?;
+
+                Ok(messages.len() as u64)
+            })
+            .await?;
+
+        if pruned > 0 {
+            info!("Pruned {} messages from channel `{}`.", pruned, channel_id);
+        }
+
+        Ok(pruned)
+    }
+
+    #[instrument(skip(self))]
+    async fn prune_expired_contexts(&self, channel_id: &str, cutoff: i64) -> Res<u64> {
+        let id = channel_id.to_string();
+
+        let contexts = self
+            .with_conn(move |conn| {
+                let tx = conn.transaction()?;
+
+                let contexts = tx
+                    .prepare(&format!("SELECT {CONTEXT_COLUMNS} FROM context WHERE channel_id = ?1 AND expires_at IS NOT NULL AND expires_at < ?2"))?
+                    .query_map(params![id, cutoff], read_context)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                tx.execute("DELETE FROM context WHERE channel_id = ?1 AND expires_at IS NOT NULL AND expires_at < ?2", params![id, cutoff])?;
+                tx.commit()?;
+
+                Ok(contexts)
+            })
+            .await?;
+
+        let pruned = contexts.len() as u64;
+        for context in contexts {
+            let _ = self.contexts.send(LiveEvent { action: Action::Delete, data: context });
+        }
+
+        if pruned > 0 {
+            info!("Pruned {} expired contexts from channel `{}`.", pruned, channel_id);
+        }
+
+        Ok(pruned)
+    }
+
+    #[instrument(skip(self, message))]
+    async fn add_pending_message(&self, message: &PendingMessage) -> Res<String> {
+        let message = message.clone();
+        let channel_id = message.channel_id.clone();
+
+        let id = self
+            .with_conn(move |conn| {
+                conn.execute(
+                    "INSERT INTO pending_message (bot_user_id, channel_id, thread_ts, text, blocks, reply_broadcast, created_at, attempts, next_attempt_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                    params![
+                        message.bot_user_id,
+                        message.channel_id,
+                        message.thread_ts,
+                        message.text,
+                        serde_json::to_string(&message.blocks)?,
+                        message.reply_broadcast,
+                        message.created_at,
+                        message.attempts,
+                        message.next_attempt_at
+                    ],
+                )?;
+
+                Ok(conn.last_insert_rowid().to_string())
+            })
+            .await?;
+
+        info!("Added pending message for channel `{}` to the outbox.", channel_id);
+
+        Ok(id)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_due_pending_messages(&self, now: i64) -> Res<Vec<PendingMessage>> {
+        self.with_conn(move |conn| {
+            let messages = conn
+                .prepare(
+                    "SELECT id, bot_user_id, channel_id, thread_ts, text, blocks, reply_broadcast, created_at, attempts, next_attempt_at FROM pending_message WHERE next_attempt_at <= ?1 ORDER BY created_at ASC, id ASC",
+                )?
+                .query_map(params![now], |row| {
+                    Ok(PendingMessage {
+                        id: Some(row.get::<_, i64>(0)?.to_string()),
+                        bot_user_id: row.get(1)?,
+                        channel_id: row.get(2)?,
+                        thread_ts: row.get(3)?,
+                        text: row.get(4)?,
+                        blocks: json_column(row, 5)?,
+                        reply_broadcast: row.get(6)?,
+                        created_at: row.get(7)?,
+                        attempts: row.get(8)?,
+                        next_attempt_at: row.get(9)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(messages)
+        })
+        .await
+    }
+
+    #[instrument(skip(self, message))]
+    async fn update_pending_message(&self, message: &PendingMessage) -> Res<()> {
+        let id = parse_row_id(message.id.as_deref().ok_or(anyhow!("Cannot update a pending message without an ID."))?)?;
+        let (attempts, next_attempt_at) = (message.attempts, message.next_attempt_at);
+
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE pending_message SET attempts = ?1, next_attempt_at = ?2 WHERE id = ?3", params![attempts, next_attempt_at, id])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_pending_message(&self, id: &str) -> Res<()> {
+        let rowid = parse_row_id(id)?;
+
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM pending_message WHERE id = ?1", params![rowid])?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Deleted pending message `{}` from the outbox.", id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, message))]
+    async fn add_scheduled_message(&self, message: &ScheduledMessage) -> Res<()> {
+        let message = message.clone();
+        let thread_ts = message.thread_ts.clone();
+
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT INTO scheduled_message (channel_id, thread_ts, scheduled_message_id, native, post_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+                params![message.channel_id, message.thread_ts, message.scheduled_message_id, message.native, message.post_at],
+            )?;
+
+            Ok(())
+        })
+        .await?;
+
+        info!("Added scheduled message for thread `{}`.", thread_ts);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_thread_scheduled_messages(&self, channel_id: &str, thread_ts: &str) -> Res<Vec<ScheduledMessage>> {
+        let id = channel_id.to_string();
+        let thread_ts = thread_ts.to_string();
+
+        self.with_conn(move |conn| {
+            let messages = conn
+                .prepare("SELECT id, channel_id, thread_ts, scheduled_message_id, native, post_at FROM scheduled_message WHERE channel_id = ?1 AND thread_ts = ?2 ORDER BY post_at ASC")?
+                .query_map(params![id, thread_ts], |row| {
+                    Ok(ScheduledMessage {
+                        id: Some(row.get::<_, i64>(0)?.to_string()),
+                        channel_id: row.get(1)?,
+                        thread_ts: row.get(2)?,
+                        scheduled_message_id: row.get(3)?,
+                        native: row.get(4)?,
+                        post_at: row.get(5)?,
+                    })
+                })?
+                .collect::<Result<Vec<_>, _>>()?;
+
+            Ok(messages)
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_scheduled_message(&self, id: &str) -> Res<()> {
+        let rowid = parse_row_id(id)?;
+
+        self.with_conn(move |conn| {
+            conn.execute("DELETE FROM scheduled_message WHERE id = ?1", params![rowid])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_context(&self, channel_id: &str) -> Res<String> {
+        let id = channel_id.to_string();
+
+        let context = self
+            .with_conn(move |conn| {
+                let context = conn
+                    .prepare(&format!("SELECT {CONTEXT_COLUMNS} FROM context WHERE channel_id = ?1 AND {SQL_UNEXPIRED} ORDER BY id"))?
+                    .query_map(params![id], read_context)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(context)
+            })
+            .await?;
+
+        let result = serde_json::to_string(&context)?;
+
+        info!("Retrieved context for channel `{}`.", channel_id);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_context_paged(&self, channel_id: &str, limit: usize, max_chars: usize) -> Res<String> {
+        let id = channel_id.to_string();
+
+        // One more than the limit is fetched, so that it is known whether older context was left out.
+        let context = self
+            .with_conn(move |conn| {
+                let context = conn
+                    .prepare(&format!(
+                        "SELECT {CONTEXT_COLUMNS} FROM context WHERE channel_id = ?1 AND {SQL_UNEXPIRED} ORDER BY json_extract(user_message, '$.ts') DESC, id DESC LIMIT ?2"
+                    ))?
+                    .query_map(params![id, limit as i64 + 1], read_context)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(context)
+            })
+            .await?;
+
+        let result = format_channel_context(&context, limit, max_chars)?;
+
+        info!("Retrieved recent context for channel `{}`.", channel_id);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn list_channel_contexts(&self, channel_id: &str) -> Res<Vec<(String, String)>> {
+        let id = channel_id.to_string();
+
+        let contexts = self
+            .with_conn(move |conn| {
+                let contexts = conn
+                    .prepare(&format!(
+                        "SELECT {CONTEXT_COLUMNS} FROM context WHERE channel_id = ?1 ORDER BY json_extract(user_message, '$.ts') DESC, id DESC"
+                    ))?
+                    .query_map(params![id], read_context)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(contexts)
+            })
+            .await?;
+
+        Ok(contexts.iter().filter_map(|context| Some((context.id()?, summarize_context(context)))).collect())
+    }
+
+    #[instrument(skip(self))]
+    async fn remove_channel_context(&self, channel_id: &str, context_id: &str) -> Res<bool> {
+        // IDs are handed out as `context:<rowid>` (see `list_channel_contexts`), but the bare row ID is accepted too.
+        let Ok(rowid) = context_id.strip_prefix("context:").unwrap_or(context_id).parse::<i64>() else {
+            return Ok(false);
+        };
+        let id = channel_id.to_string();
+
+        let removed = self
+            .with_conn(move |conn| {
+                let context = conn
+                    .query_row(&format!("SELECT {CONTEXT_COLUMNS} FROM context WHERE id = ?1 AND channel_id = ?2"), params![rowid, id], read_context)
+                    .optional()?;
+
+                if context.is_some() {
+                    conn.execute("DELETE FROM context WHERE id = ?1", params![rowid])?;
+                }
+
+                Ok(context)
+            })
+            .await?;
+
+        let Some(context) = removed else {
+            return Ok(false);
+        };
+
+        let _ = self.contexts.send(LiveEvent { action: Action::Delete, data: context });
+
+        info!("Removed context `{}` from channel `{}`.", context_id, channel_id);
+
+        Ok(true)
+    }
+
+    #[instrument(skip(self))]
+    async fn search_channel_messages(&self, channel_id: &str, search_terms: &str) -> Res<String> {
+        self.search_channel_messages_filtered(channel_id, search_terms, None, None).await
+    }
+
+    #[instrument(skip(self))]
+    async fn search_channel_messages_filtered(&self, channel_id: &str, search_terms: &str, after_ts: Option<&str>, before_ts: Option<&str>) -> Res<String> {
+        let terms = parse_search_terms(search_terms);
+
+        if terms.is_empty() {
+            return Ok("[]".to_string()); // Return empty array if no terms
+        }
+
+        let query = fts_query(&terms);
+        let id = channel_id.to_string();
+        let after = after_ts.map(str::to_string);
+        let before = before_ts.map(str::to_string);
+        let MessageSearchOptions { limit, recency_boost, .. } = self.search;
+
+        // Slack timestamps have fixed-width seconds, so they compare correctly as text (and exactly, unlike as floats).
+        let mut messages = self
+            .with_conn(move |conn| {
+                let messages = conn
+                    .prepare(&format!(
+                        r#"
+                            SELECT message.id, message.raw, message.ts, message.thread_ts, message.created_at, message.updated_at
+                            FROM message_fts JOIN message ON message.id = message_fts.rowid
+                            WHERE message_fts MATCH ?1 AND message.channel_id = ?2
+                                AND (?4 IS NULL OR message.ts >= ?4)
+                                AND (?5 IS NULL OR message.ts < ?5)
+                            ORDER BY {SEARCH_RANK}
+                            LIMIT ?3
+                        "#,
+                    ))?
+                    .query_map(params![query, id, limit as i64, after, before, recency_boost as f64], read_message)?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(messages)
+            })
+            .await?;
+
+        // With fuzzy search, likely typos of the terms also match (after the exact matches).
+        if self.search.fuzzy && messages.len() < limit {
+            let candidates = self.get_recent_channel_messages(channel_id, FUZZY_SEARCH_CANDIDATES).await?;
+            add_fuzzy_matches(&mut messages, candidates, &terms, after_ts, before_ts, limit);
+        }
+
+        let result = serde_json::to_string(&messages)?;
+
+        info!("Retrieved {} ranked messages for channel `{}` matching search terms: {}", messages.len(), channel_id, search_terms);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn search_messages_across_channels(&self, channel_ids: &[String], search_terms: &str, after_ts: Option<&str>, before_ts: Option<&str>) -> Res<String> {
+        let terms = parse_search_terms(search_terms);
+
+        if terms.is_empty() || channel_ids.is_empty() {
+            return Ok("[]".to_string());
+        }
+
+        let query = fts_query(&terms);
+        let ids = serde_json::to_string(channel_ids)?;
+        let after_ts = after_ts.map(str::to_string);
+        let before_ts = before_ts.map(str::to_string);
+        let MessageSearchOptions { limit, recency_boost, .. } = self.search;
+
+        // All of the channels are ranked (and capped) together.
+        let messages = self
+            .with_conn(move |conn| {
+                let messages = conn
+                    .prepare(&format!(
+                        r#"
+                            SELECT message.channel_id, message.raw, message.thread_ts
+                            FROM message_fts JOIN message ON message.id = message_fts.rowid
+                            WHERE message_fts MATCH ?1 AND message.channel_id IN (SELECT value FROM json_each(?2))
+                                AND (?4 IS NULL OR message.ts >= ?4)
+                                AND (?5 IS NULL OR message.ts < ?5)
+                            ORDER BY {SEARCH_RANK}
+                            LIMIT ?3
+                        "#,
+                    ))?
+                    .query_map(params![query, ids, limit as i64, after_ts, before_ts, recency_boost as f64], |row| {
+                        Ok(CrossChannelMessage {
+                            channel_id: row.get(0)?,
+                            raw: json_column(row, 1)?,
+                            thread_ts: row.get(2)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(messages)
+            })
+            .await?;
+
+        let result = serde_json::to_string(&messages)?;
+
+        info!(
+            "Retrieved {} ranked messages across {} channels matching search terms: {}",
+            messages.len(),
+            channel_ids.len(),
+            search_terms
+        );
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self, embedding))]
+    async fn set_channel_message_embedding(&self, channel_id: &str, ts: &str, embedding: &[f32]) -> Res<()> {
+        let id = channel_id.to_string();
+        let ts = ts.to_string();
+        let embedding = embedding_to_blob(embedding);
+
+        self.with_conn(move |conn| {
+            conn.execute("UPDATE message SET embedding = ?1 WHERE channel_id = ?2 AND ts = ?3", params![embedding, id, ts])?;
+
+            Ok(())
+        })
+        .await
+    }
+
+    #[instrument(skip(self, query))]
+    async fn search_channel_messages_semantic(&self, channel_id: &str, query: &[f32], k: usize) -> Res<String> {
+        let id = channel_id.to_string();
+
+        // SQLite has no vector index, so the channel's embedded messages are ranked here.
+        let candidates = self
+            .with_conn(move |conn| {
+                let candidates = conn
+                    .prepare(&format!("SELECT {MESSAGE_COLUMNS}, embedding FROM message WHERE channel_id = ?1 AND embedding IS NOT NULL"))?
+                    .query_map(params![id], |row| Ok((read_message(row)?, blob_to_embedding(&row.get::<_, Vec<u8>>(6)?))))?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(candidates)
+            })
+            .await?;
+
+        let mut ranked = candidates.into_iter().map(|(message, embedding)| (cosine_similarity(query, &embedding), message)).collect::<Vec<_>>();
+        ranked.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+        let messages = ranked.into_iter().take(k.max(1)).map(|(_, message)| message).collect::<Vec<_>>();
+
+        let result = serde_json::to_string(&messages)?;
+
+        info!("Retrieved {} semantically similar messages for channel `{}`.", messages.len(), channel_id);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn export_channel(&self, channel_id: &str) -> Res<ChannelExport> {
+        let id = channel_id.to_string();
+
+        let export = self
+            .with_conn(move |conn| {
+                let Some(channel) = read_channel(conn, &id)? else {
+                    return Err(anyhow!("Channel `{}` does not exist.", id));
+                };
+
+                let contexts = conn
+                    .prepare("SELECT user_message, your_notes, expires_at FROM context WHERE channel_id = ?1 ORDER BY id")?
+                    .query_map(params![id], |row| {
+                        Ok(ExportedContext {
+                            user_message: json_column(row, 0)?,
+                            your_notes: row.get(1)?,
+                            expires_at: row.get(2)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let user_contexts = conn
+                    .prepare("SELECT user_id, user_message, your_notes FROM user_context WHERE channel_id = ?1 ORDER BY id")?
+                    .query_map(params![id], |row| {
+                        Ok(ExportedUserContext {
+                            user_id: row.get(0)?,
+                            user_message: json_column(row, 1)?,
+                            your_notes: row.get(2)?,
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let messages = conn
+                    .prepare("SELECT raw, embedding FROM message WHERE channel_id = ?1 ORDER BY ts, id")?
+                    .query_map(params![id], |row| {
+                        Ok(ExportedMessage {
+                            raw: json_column(row, 0)?,
+                            embedding: row.get::<_, Option<Vec<u8>>>(1)?.map(|blob| blob_to_embedding(&blob)),
+                        })
+                    })?
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                Ok(ChannelExport {
+                    channel_id: id,
+                    exported_at: chrono::Utc::now().timestamp(),
+                    directive: ExportedContext {
+                        user_message: channel.channel_directive.user_message,
+                        your_notes: channel.channel_directive.your_notes,
+                        expires_at: None,
+                    },
+                    contexts,
+                    user_contexts,
+                    messages,
+                })
+            })
+            .await?;
+
+        info!("Exported channel `{}` ({} contexts, {} messages).", channel_id, export.contexts.len(), export.messages.len());
+
+        Ok(export)
+    }
+
+    #[instrument(skip(self, export))]
+    async fn import_channel(&self, channel_id: &str, export: &ChannelExport) -> Res<u64> {
+        let added = import_channel_export(self, channel_id, export).await?;
+
+        info!("Imported channel `{}` into channel `{}` ({} new messages).", export.channel_id, channel_id, added);
+
+        Ok(added)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_live_query(&self) -> Res<LiveQuery<Self::ChannelType>> {
+        Ok(live_query(self.channels.subscribe()))
+    }
+
+    #[instrument(skip(self))]
+    async fn get_context_live_query(&self) -> Res<LiveQuery<Self::LlmContextType>> {
+        Ok(live_query(self.contexts.subscribe()))
+    }
+}
+
+// Helpers.
+
+/// Turn a broadcast receiver into a live query stream.
+///
+/// A consumer that falls too far behind gets an error for the events that it missed, and then continues.
+fn live_query<T>(receiver: broadcast::Receiver<LiveEvent<T>>) -> LiveQuery<T>
+where
+    T: Clone + Send + 'static,
+{
+    futures::stream::unfold(receiver, |mut receiver| async move {
+        match receiver.recv().await {
+            Ok(event) => Some((Ok(event), receiver)),
+            Err(broadcast::error::RecvError::Lagged(skipped)) => Some((Err(anyhow!("The live query fell behind, and skipped {} events.", skipped)), receiver)),
+            Err(broadcast::error::RecvError::Closed) => None,
+        }
+    })
+    .boxed()
+}
+
+/// Read a channel by its ID.
+fn read_channel(conn: &Connection, channel_id: &str) -> Res<Option<SurrealChannel>> {
+    let channel = conn
+        .query_row(&format!("SELECT {CHANNEL_COLUMNS} FROM channel WHERE id = ?1"), params![channel_id], |row| {
+            Ok(SurrealChannel {
+                id: Some(RecordId::from(("channel", row.get::<_, String>(0)?))),
+                channel_directive: json_column(row, 1)?,
+                paused: row.get(2)?,
+                broadcast_incidents: row.get(3)?,
+                active: row.get(4)?,
+                name: row.get(5)?,
+                edit_window_seconds: row.get::<_, Option<i64>>(6)?.map(|seconds| seconds.max(0) as u64),
+                retention_days: row.get::<_, Option<i64>>(7)?.map(|days| days.max(0) as u64),
+                linked_channels: json_column(row, 8)?,
+                moderation_enabled: row.get(9)?,
+                confidence_threshold: row.get::<_, Option<i64>>(10)?.map(|threshold| threshold.clamp(0, 100) as u8),
+                force_language: row.get(11)?,
+                system_addendum: row.get(12)?,
+                refusal_reply: row.get(13)?,
+                mode: row.get::<_, Option<String>>(14)?.as_deref().and_then(ChannelMode::parse),
+                web_search_enabled: row.get(15)?,
+                created_at: row.get(16)?,
+                updated_at: row.get(17)?,
+            })
+        })
+        .optional()?;
+
+    Ok(channel)
+}
+
+/// Read a directive version from a row of `id, version, user_id, created_at, user_message, your_notes`.
+fn read_directive_version(row: &Row) -> rusqlite::Result<DirectiveVersion> {
+    Ok(DirectiveVersion {
+        id: Some(row.get::<_, i64>(0)?.to_string()),
+        version: row.get::<_, i64>(1)? as u64,
+        user_id: row.get(2)?,
+        created_at: row.get(3)?,
+        user_message: json_column(row, 4)?,
+        your_notes: row.get(5)?,
+    })
+}
+
+/// Read a context from a row of `CONTEXT_COLUMNS`.
+fn read_context(row: &Row) -> rusqlite::Result<SurrealLlmContext> {
+    Ok(SurrealLlmContext {
+        id: Some(RecordId::from(("context", row.get::<_, i64>(0)?))),
+        user_message: json_column(row, 1)?,
+        your_notes: row.get(2)?,
+        expires_at: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+/// Read a message from a row of `MESSAGE_COLUMNS`.
+fn read_message(row: &Row) -> rusqlite::Result<SurrealMessage> {
+    Ok(SurrealMessage {
+        id: Some(RecordId::from(("message", row.get::<_, i64>(0)?))),
+        raw: json_column(row, 1)?,
+        ts: row.get(2)?,
+        thread_ts: row.get(3)?,
+        created_at: row.get(4)?,
+        updated_at: row.get(5)?,
+    })
+}
+
+/// Read a JSON column.
+fn json_column<T: DeserializeOwned>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    let text: String = row.get(index)?;
+
+    serde_json::from_str(&text).map_err(|err| rusqlite::Error::FromSqlConversionFailure(index, Type::Text, Box::new(err)))
+}
+
+/// Parse a row ID that was handed out as a string (e.g., the ID of a pending message).
+fn parse_row_id(id: &str) -> Res<i64> {
+    id.parse().map_err(|_| anyhow!("Invalid row ID `{}`.", id))
+}
+
+/// The FTS5 query for any of `terms`, with every string quoted (so that it is never parsed as FTS5 syntax).
+///
+/// A phrase is matched as one quoted string (i.e., its words in order), and any other term matches all of its words.
+fn fts_query(terms: &[SearchTerm]) -> String {
+    let quote = |text: &str| format!("\"{}\"", text.replace('"', "\"\""));
+
+    terms
+        .iter()
+        .filter_map(|term| {
+            if term.phrase {
+                return Some(quote(&term.text));
+            }
+
+            // Words without any letters or digits (e.g., `--`) have no tokens, and would match nothing.
+            let words = term.text.split_whitespace().filter(|word| word.chars().any(char::is_alphanumeric)).map(quote).collect::<Vec<_>>();
+
+            (!words.is_empty()).then(|| format!("({})", words.join(" AND ")))
+        })
+        .collect::<Vec<_>>()
+        .join(" OR ")
+}
+
+/// The searchable text of a raw message.
+fn message_text(message: &Value) -> &str {
+    message.get("text").and_then(Value::as_str).unwrap_or_default()
+}
+
+/// Encode an embedding as a blob (of little-endian floats).
+fn embedding_to_blob(embedding: &[f32]) -> Vec<u8> {
+    embedding.iter().flat_map(|x| x.to_le_bytes()).collect()
+}
+
+/// Decode an embedding from a blob (of little-endian floats).
+fn blob_to_embedding(blob: &[u8]) -> Vec<f32> {
+    blob.chunks_exact(4).map(|bytes| f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]])).collect()
+}
+
+/// The cosine similarity of two embeddings (zero if either is empty, or they differ in length).
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+
+    let dot = a.iter().zip(b).map(|(x, y)| x * y).sum::<f32>();
+    let norms = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+
+    if norms == 0.0 { 0.0 } else { dot / norms }
+}
+
+/// Merge the top-level fields of `patch` over `target`.
+fn merge_json(target: &mut Value, patch: &Value) {
+    match (target.as_object_mut(), patch.as_object()) {
+        (Some(target), Some(patch)) => {
+            for (key, value) in patch {
+                target.insert(key.clone(), value.clone());
+            }
+        }
+        _ => *target = patch.clone(),
+    }
+}
+
+/// Set up the sqlite database.
+fn setup_sqlite_db(conn: &Connection) -> Void {
+    conn.execute_batch(
+        r#"
+            -- Schema for list of channels that the bot has been "added to" (@-mentioned).
+            CREATE TABLE IF NOT EXISTS channel (
+                id TEXT PRIMARY KEY,
+                channel_directive TEXT NOT NULL,
+                paused INTEGER NOT NULL DEFAULT 0,
+                broadcast_incidents INTEGER NOT NULL DEFAULT 0,
+                active INTEGER NOT NULL DEFAULT 1,
+                name TEXT,
+                edit_window_seconds INTEGER,
+                retention_days INTEGER,
+                linked_channels TEXT NOT NULL DEFAULT '[]',
+                moderation_enabled INTEGER,
+                confidence_threshold INTEGER,
+                force_language TEXT,
+                system_addendum TEXT,
+                refusal_reply TEXT,
+                mode TEXT,
+                web_search_enabled INTEGER,
+                created_at INTEGER,
+                updated_at INTEGER
+            );
+
+            -- Schema for contexts.
+            CREATE TABLE IF NOT EXISTS context (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                user_message TEXT NOT NULL,
+                your_notes TEXT NOT NULL,
+                expires_at INTEGER,
+                created_at INTEGER,
+                updated_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS context_channel ON context (channel_id);
+
+            -- Schema for contexts about users.
+            CREATE TABLE IF NOT EXISTS user_context (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                user_message TEXT NOT NULL,
+                your_notes TEXT NOT NULL,
+                expires_at INTEGER,
+                created_at INTEGER,
+                updated_at INTEGER
+            );
+            CREATE INDEX IF NOT EXISTS user_context_channel_user ON user_context (channel_id, user_id);
+
+            -- Schema for the versions of channel directives.
+            CREATE TABLE IF NOT EXISTS directive_version (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                version INTEGER NOT NULL,
+                user_id TEXT,
+                created_at INTEGER NOT NULL,
+                user_message TEXT NOT NULL,
+                your_notes TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS directive_version_channel ON directive_version (channel_id, version);
+
+            -- Schema for messages (and their full-text search index, keyed by the message's row ID).
+            CREATE TABLE IF NOT EXISTS message (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                ts TEXT,
+                thread_ts TEXT,
+                raw TEXT NOT NULL,
+                embedding BLOB,
+                created_at INTEGER,
+                updated_at INTEGER
+            );
+            DROP INDEX IF EXISTS message_channel_ts;
+            CREATE UNIQUE INDEX IF NOT EXISTS message_channel_ts_unique ON message (channel_id, ts);
+            CREATE INDEX IF NOT EXISTS message_channel_thread_ts ON message (channel_id, thread_ts);
+            CREATE VIRTUAL TABLE IF NOT EXISTS message_fts USING fts5(text, tokenize = 'porter unicode61');
+
+            -- Schema for feedback on the bot's replies.
+            CREATE TABLE IF NOT EXISTS feedback (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                thread_ts TEXT NOT NULL,
+                message_ts TEXT NOT NULL,
+                user_id TEXT NOT NULL,
+                emoji TEXT NOT NULL,
+                classification TEXT
+            );
+            CREATE INDEX IF NOT EXISTS feedback_channel ON feedback (channel_id);
+
+            -- Schema for the bot's own responses.
+            CREATE TABLE IF NOT EXISTS response (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                thread_ts TEXT NOT NULL,
+                response TEXT NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS response_channel_thread ON response (channel_id, thread_ts);
+
+            -- Schema for the outbox of messages that failed to send.
+            CREATE TABLE IF NOT EXISTS pending_message (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                bot_user_id TEXT NOT NULL,
+                channel_id TEXT NOT NULL,
+                thread_ts TEXT NOT NULL,
+                text TEXT NOT NULL,
+                blocks TEXT NOT NULL,
+                reply_broadcast INTEGER NOT NULL DEFAULT 0,
+                created_at INTEGER NOT NULL,
+                attempts INTEGER NOT NULL,
+                next_attempt_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS pending_message_next_attempt_at ON pending_message (next_attempt_at);
+
+            -- Schema for messages that are scheduled to be posted to threads later.
+            CREATE TABLE IF NOT EXISTS scheduled_message (
+                id INTEGER PRIMARY KEY AUTOINCREMENT,
+                channel_id TEXT NOT NULL,
+                thread_ts TEXT NOT NULL,
+                scheduled_message_id TEXT NOT NULL,
+                native INTEGER NOT NULL,
+                post_at INTEGER NOT NULL
+            );
+            CREATE INDEX IF NOT EXISTS scheduled_message_thread ON scheduled_message (channel_id, thread_ts);
+        "#,
+    )?;
+
+    // Columns added since the tables were first created are added to existing databases.
+    add_missing_column(conn, "channel", "linked_channels", "TEXT NOT NULL DEFAULT '[]'")?;
+    add_missing_column(conn, "channel", "moderation_enabled", "INTEGER")?;
+    add_missing_column(conn, "channel", "confidence_threshold", "INTEGER")?;
+    add_missing_column(conn, "channel", "force_language", "TEXT")?;
+    add_missing_column(conn, "channel", "system_addendum", "TEXT")?;
+    add_missing_column(conn, "channel", "refusal_reply", "TEXT")?;
+    add_missing_column(conn, "channel", "mode", "TEXT")?;
+    add_missing_column(conn, "channel", "web_search_enabled", "INTEGER")?;
+    add_missing_column(conn, "context", "expires_at", "INTEGER")?;
+    add_missing_column(conn, "user_context", "expires_at", "INTEGER")?;
+
+    // Messages from before the timestamps existed were (roughly) stored when they were posted.
+    setup_timestamps(conn, "channel", SQL_NOW)?;
+    setup_timestamps(conn, "context", SQL_NOW)?;
+    setup_timestamps(conn, "user_context", SQL_NOW)?;
+    setup_timestamps(conn, "message", &format!("COALESCE(CAST(ts AS INTEGER), {SQL_NOW})"))?;
+
+    Ok(())
+}
+
+/// Keep the `created_at` and `updated_at` columns (in unix seconds) of a table up to date on every write.
+///
+/// Triggers set them, so that no write can forget to, and rows from before the columns existed get `backfill`.
+fn setup_timestamps(conn: &Connection, table: &str, backfill: &str) -> Void {
+    add_missing_column(conn, table, "created_at", "INTEGER")?;
+    add_missing_column(conn, table, "updated_at", "INTEGER")?;
+
+    conn.execute(&format!("UPDATE {table} SET created_at = {backfill}, updated_at = {backfill} WHERE created_at IS NULL"), [])?;
+
+    // Writes that set `updated_at` themselves (including these triggers) do not re-trigger.
+    conn.execute_batch(&format!(
+        r#"
+            CREATE TRIGGER IF NOT EXISTS {table}_created_at AFTER INSERT ON {table}
+            BEGIN
+                UPDATE {table} SET created_at = COALESCE(NEW.created_at, {SQL_NOW}), updated_at = {SQL_NOW} WHERE rowid = NEW.rowid;
+            END;
+
+            CREATE TRIGGER IF NOT EXISTS {table}_updated_at AFTER UPDATE ON {table} WHEN NEW.updated_at IS OLD.updated_at
+            BEGIN
+                UPDATE {table} SET updated_at = {SQL_NOW} WHERE rowid = NEW.rowid;
+            END;
+        "#
+    ))?;
+
+    Ok(())
+}
+
+/// Add a column to a table, unless the table already has it.
+fn add_missing_column(conn: &Connection, table: &str, column: &str, definition: &str) -> Void {
+    let exists = conn.prepare(&format!("SELECT 1 FROM pragma_table_info('{table}') WHERE name = ?1"))?.exists(params![column])?;
+
+    if !exists {
+        conn.execute(&format!("ALTER TABLE {table} ADD COLUMN {column} {definition}"), [])?;
+    }
+
+    Ok(())
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn setup_test_db() -> Res<DbClient> {
+        DbClient::sqlite(":memory:").await
+    }
+
+    super::super::suite::db_test_suite!(setup_test_db);
+
+    #[tokio::test]
+    async fn test_search_recency_boost() {
+        let client = DbClient::sqlite_with_search_options(":memory:", super::super::suite::RECENCY_BOOSTED_SEARCH).await.unwrap();
+
+        super::super::suite::test_search_recency_boost(client).await;
+    }
+
+    #[tokio::test]
+    async fn test_search_fuzzy() {
+        let client = DbClient::sqlite_with_search_options(":memory:", super::super::suite::FUZZY_SEARCH).await.unwrap();
+
+        super::super::suite::test_search_fuzzy(client).await;
+    }
+
+    #[tokio::test]
+    async fn test_sqlite_persists_across_restarts() {
+        let path = std::env::temp_dir().join(format!("triage-bot-test-{}-{}.db", std::process::id(), chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()));
+        let path = path.to_str().unwrap().to_string();
+
+        {
+            let client = DbClient::sqlite(&path).await.unwrap();
+
+            client.get_or_create_channel("C1").await.unwrap();
+            client.set_channel_paused("C1", true).await.unwrap();
+            client.add_channel_message("C1", &serde_json::json!({ "text": "The build is broken.", "ts": "1.0" })).await.unwrap();
+        }
+
+        let client = DbClient::sqlite(&path).await.unwrap();
+
+        assert!(client.get_or_create_channel("C1").await.unwrap().paused);
+        assert_eq!(client.get_channel_message_count("C1").await.unwrap(), 1);
+        assert!(client.search_channel_messages("C1", "broken").await.unwrap().contains("The build is broken."));
+
+        let _ = std::fs::remove_file(&path);
+    }
+
+    #[test]
+    fn test_merge_json() {
+        let mut raw = serde_json::json!({ "text": "Original", "ts": "1.0", "user": "U1" });
+
+        merge_json(&mut raw, &serde_json::json!({ "text": "Edited", "edited": { "ts": "2.0" } }));
+
+        assert_eq!(raw, serde_json::json!({ "text": "Edited", "ts": "1.0", "user": "U1", "edited": { "ts": "2.0" } }));
+    }
+
+    #[test]
+    fn test_embeddings() {
+        let embedding = vec![0.5, -1.25, 3.0];
+
+        assert_eq!(blob_to_embedding(&embedding_to_blob(&embedding)), embedding);
+
+        assert!((cosine_similarity(&[1.0, 0.0], &[2.0, 0.0]) - 1.0).abs() < 1e-6);
+        assert!(cosine_similarity(&[1.0, 0.0], &[0.0, 1.0]).abs() < 1e-6);
+        assert_eq!(cosine_similarity(&[0.0, 0.0], &[1.0, 0.0]), 0.0);
+        assert_eq!(cosine_similarity(&[1.0], &[1.0, 0.0]), 0.0);
+    }
+}
@@ -8,34 +8,153 @@
 //! It defines the `GenericDbClient` trait that can be implemented for different
 //! database backends, with a default implementation for SurrealDB.
 
-use std::{ops::Deref, sync::Arc};
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{
+        Arc, RwLock, Weak,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
 use crate::base::{
     config::Config,
-    types::{Res, Void},
+    types::{ChannelMode, Res, TokenUsage, Void},
 };
 use anyhow::{Ok, anyhow};
 use async_trait::async_trait;
+use futures::StreamExt;
+use rustls::pki_types::{CertificateDer, pem::PemObject};
 use serde::{Deserialize, Serialize};
 use serde_json::{Value, json};
 use surrealdb::{
     Connection, RecordId, Surreal,
-    engine::remote::ws::{Client, Ws},
-    method::Stream,
-    opt::auth::Root,
+    engine::{
+        local::{Mem, RocksDb},
+        remote::ws::{Client, Ws, Wss},
+    },
+    opt::auth::{Database, Namespace, Root},
+};
+use tracing::{info, instrument, warn};
+
+use super::{
+    Channel, ChannelExport, ChannelStats, CrossChannelMessage, DEFAULT_MESSAGE_BATCH_SIZE, DbClient, DbUnavailable, DirectiveVersion, EMBEDDING_DIMENSIONS, ExportedContext, FUZZY_SEARCH_CANDIDATES,
+    Feedback, GenericDbClient, LiveEvent, LiveQuery, LlmContext, Message, MessageSearchOptions, PendingMessage, ScheduledMessage, SearchTerm, ThreadRoot, add_fuzzy_matches, format_channel_context,
+    import_channel_export, message_thread_ts, message_ts, parse_search_terms, summarize_context, unix_to_ts,
 };
-use tracing::{info, instrument};
 
-use super::{Channel, DbClient, GenericDbClient, LlmContext, Message};
+// Statics.
+
+/// The namespace that embedded databases use (servers use `db_namespace`).
+const DEFAULT_NAMESPACE: &str = "triage";
+
+/// The database that embedded databases use (servers use `db_database`).
+const DEFAULT_DATABASE: &str = "bot";
+
+/// How often the connection to a SurrealDB server is checked.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(5);
+
+/// How long a health check (or a reconnect attempt) may take before the connection is considered lost.
+const HEALTH_CHECK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// The delay before the first reconnect attempt, which doubles with each failed attempt.
+const RECONNECT_BASE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// The maximum delay between reconnect attempts.
+const RECONNECT_MAX_BACKOFF: Duration = Duration::from_secs(60);
+
+/// How much a search hit's relevance is scaled up for being new (see `MessageSearchOptions`).
+const RECENCY_FACTOR: &str = "(1 + $recency_boost / (1 + math::max([0, (time::unix(time::now()) - <float> (raw.ts ?? '0')) / 86400])))";
+
+/// The condition that a context has not expired (contexts without an expiry never do).
+const UNEXPIRED: &str = "(expires_at IS NONE OR expires_at > time::unix(time::now()))";
 
 // Extra methods on `DbClient` applied by the surreal implementation.
 
 impl DbClient {
     /// Create a new database client.
+    ///
+    /// The engine is selected by `db_endpoint`: `memory` uses an embedded in-memory database, `rocksdb://path`
+    /// (or `file://path`) uses an embedded persistent database, and anything else connects to a SurrealDB server
+    /// (with the configured credentials).
     #[instrument(skip_all)]
     pub async fn surreal(config: &Config) -> Res<Self> {
-        let db = SurrealDbClient::new(config).await?;
-        Ok(Self { inner: Arc::new(db) })
+        let search = MessageSearchOptions::from_config(config);
+        let message_batch_size = config.message_batch_size;
+
+        let inner: Arc<dyn GenericDbClient<LlmContextType = SurrealLlmContext, ChannelType = SurrealChannel, MessageType = SurrealMessage>> = match SurrealEngine::parse(&config.db_endpoint) {
+            SurrealEngine::Memory => {
+                info!("Using an embedded in-memory database.");
+                Arc::new(
+                    SurrealDbClient::from(Surreal::new::<Mem>(()).await?)
+                        .await?
+                        .with_search_options(search)
+                        .with_message_batch_size(message_batch_size),
+                )
+            }
+            SurrealEngine::RocksDb(path) => {
+                info!("Using an embedded database at `{}`.", path);
+                Arc::new(
+                    SurrealDbClient::from(Surreal::new::<RocksDb>(path).await?)
+                        .await?
+                        .with_search_options(search)
+                        .with_message_batch_size(message_batch_size),
+                )
+            }
+            SurrealEngine::Remote => Arc::new(SurrealDbClient::new(config).await?),
+        };
+
+        Ok(Self { inner })
+    }
+}
+
+/// The surreal engines that `db_endpoint` can select.
+#[derive(Debug, PartialEq, Eq)]
+enum SurrealEngine<'a> {
+    /// An embedded in-memory database (`memory`).
+    Memory,
+    /// An embedded persistent database at a path (`rocksdb://path` or `file://path`).
+    RocksDb(&'a str),
+    /// A SurrealDB server (anything else).
+    Remote,
+}
+
+impl<'a> SurrealEngine<'a> {
+    /// Select the engine for a `db_endpoint`.
+    fn parse(endpoint: &'a str) -> Self {
+        if endpoint == "memory" || endpoint == "mem://" {
+            return Self::Memory;
+        }
+
+        match endpoint.strip_prefix("rocksdb://").or_else(|| endpoint.strip_prefix("file://")) {
+            Some(path) => Self::RocksDb(path),
+            None => Self::Remote,
+        }
+    }
+}
+
+/// The address of a SurrealDB server (from `db_endpoint`), and whether it is connected to over TLS.
+#[derive(Debug, PartialEq, Eq)]
+struct RemoteEndpoint<'a> {
+    /// The server's address, without a scheme (e.g., `db.internal:8000`).
+    address: &'a str,
+    /// Whether the connection uses TLS (`wss`).
+    tls: bool,
+}
+
+impl<'a> RemoteEndpoint<'a> {
+    /// Parse a server's `db_endpoint`.
+    ///
+    /// `wss://` (or `https://`) always uses TLS, `ws://` (or `http://`) never does, and an endpoint without a scheme uses TLS if `tls` is set.
+    fn parse(endpoint: &'a str, tls: bool) -> Self {
+        if let Some(address) = endpoint.strip_prefix("wss://").or_else(|| endpoint.strip_prefix("https://")) {
+            return Self { address, tls: true };
+        }
+
+        match endpoint.strip_prefix("ws://").or_else(|| endpoint.strip_prefix("http://")) {
+            Some(address) => Self { address, tls: false },
+            None => Self { address: endpoint, tls },
+        }
     }
 }
 
@@ -48,11 +167,24 @@ pub struct SurrealLlmContext {
     pub id: Option<RecordId>,
     pub user_message: Value,
     pub your_notes: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub expires_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<i64>,
 }
 
 impl LlmContext for SurrealLlmContext {
     fn new(user_message: Value, your_notes: String) -> Self {
-        Self { id: None, user_message, your_notes }
+        Self {
+            id: None,
+            user_message,
+            your_notes,
+            expires_at: None,
+            created_at: None,
+            updated_at: None,
+        }
     }
 
     fn id(&self) -> Option<String> {
@@ -66,6 +198,22 @@ impl LlmContext for SurrealLlmContext {
     fn your_notes(&self) -> &str {
         &self.your_notes
     }
+
+    fn with_expires_at(self, expires_at: Option<i64>) -> Self {
+        Self { expires_at, ..self }
+    }
+
+    fn expires_at(&self) -> Option<i64> {
+        self.expires_at
+    }
+
+    fn created_at(&self) -> Option<i64> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> Option<i64> {
+        self.updated_at
+    }
 }
 
 /// A channel in a surreal database.
@@ -74,6 +222,38 @@ pub struct SurrealChannel {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<RecordId>,
     pub channel_directive: SurrealLlmContext,
+    #[serde(default)]
+    pub paused: bool,
+    #[serde(default)]
+    pub broadcast_incidents: bool,
+    #[serde(default = "default_channel_active")]
+    pub active: bool,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub edit_window_seconds: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub retention_days: Option<u64>,
+    #[serde(default)]
+    pub linked_channels: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub moderation_enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub confidence_threshold: Option<u8>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub force_language: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub system_addendum: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub refusal_reply: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<ChannelMode>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub web_search_enabled: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<i64>,
 }
 
 impl Channel for SurrealChannel {
@@ -84,6 +264,70 @@ impl Channel for SurrealChannel {
     fn channel_directive(&self) -> &impl LlmContext {
         &self.channel_directive
     }
+
+    fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    fn broadcasts_incidents(&self) -> bool {
+        self.broadcast_incidents
+    }
+
+    fn is_active(&self) -> bool {
+        self.active
+    }
+
+    fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    fn edit_window_seconds(&self) -> Option<u64> {
+        self.edit_window_seconds
+    }
+
+    fn retention_days(&self) -> Option<u64> {
+        self.retention_days
+    }
+
+    fn linked_channels(&self) -> &[String] {
+        &self.linked_channels
+    }
+
+    fn moderation_enabled(&self) -> Option<bool> {
+        self.moderation_enabled
+    }
+
+    fn confidence_threshold(&self) -> Option<u8> {
+        self.confidence_threshold
+    }
+
+    fn force_language(&self) -> Option<&str> {
+        self.force_language.as_deref()
+    }
+
+    fn system_addendum(&self) -> Option<&str> {
+        self.system_addendum.as_deref()
+    }
+
+    fn refusal_reply(&self) -> Option<&str> {
+        self.refusal_reply.as_deref()
+    }
+
+    fn mode(&self) -> ChannelMode {
+        self.mode.unwrap_or_default()
+    }
+
+    fn web_search_enabled(&self) -> Option<bool> {
+        self.web_search_enabled
+    }
+
+    fn created_at(&self) -> Option<i64> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> Option<i64> {
+        self.updated_at
+    }
 }
 
 /// A message in a surreal database.
@@ -92,6 +336,14 @@ pub struct SurrealMessage {
     #[serde(skip_serializing_if = "Option::is_none")]
     pub id: Option<RecordId>,
     pub raw: Value,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub ts: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub thread_ts: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub created_at: Option<i64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<i64>,
 }
 
 impl Message for SurrealMessage {
@@ -102,6 +354,22 @@ impl Message for SurrealMessage {
     fn raw(&self) -> &Value {
         &self.raw
     }
+
+    fn ts(&self) -> Option<&str> {
+        self.ts.as_deref()
+    }
+
+    fn thread_ts(&self) -> Option<&str> {
+        self.thread_ts.as_deref()
+    }
+
+    fn created_at(&self) -> Option<i64> {
+        self.created_at
+    }
+
+    fn updated_at(&self) -> Option<i64> {
+        self.updated_at
+    }
 }
 
 // SurrealDB client implementation.
@@ -111,41 +379,38 @@ pub struct SurrealDbClient<C>
 where
     C: Connection,
 {
-    pub db: Surreal<C>,
-}
-
-impl<C> Deref for SurrealDbClient<C>
-where
-    C: Connection,
-{
-    type Target = Surreal<C>;
-
-    fn deref(&self) -> &Self::Target {
-        &self.db
-    }
+    /// The connection, which is replaced when the connection to a server is re-established.
+    db: Arc<RwLock<Surreal<C>>>,
+    /// Whether the database is reachable (embedded engines always are).
+    available: Arc<AtomicBool>,
+    /// How message searches are capped and ranked.
+    search: MessageSearchOptions,
+    /// How many messages are stored per transaction by `add_channel_messages`.
+    message_batch_size: usize,
 }
 
 impl SurrealDbClient<Client> {
-    /// Create a new database client.
+    /// Create a new database client, connected to a SurrealDB server over a websocket.
     ///
-    /// This creates an in-memory database instance. For production, you would
-    /// want to connect to a persistent database.
+    /// The connection is supervised: if the server goes away (e.g., it restarts), the client is marked unavailable
+    /// (so that queries fail fast with `DbUnavailable`), and the connection is re-established (and re-authenticated)
+    /// in the background.  Embedded engines (which need no server, and no sign in) are created with `from`.
     #[instrument(name = "SurrealDbClient::new", skip_all)]
-    #[allow(unused_variables)]
     pub async fn new(config: &Config) -> Res<Self> {
-        let db = Surreal::new::<Ws>(&config.db_endpoint).await?;
+        let db = connect_surreal_server(config).await?;
 
-        db.signin(Root {
-            username: &config.db_username,
-            password: &config.db_password,
-        })
-        .await?;
+        let client = Self {
+            db: Arc::new(RwLock::new(db)),
+            available: Arc::new(AtomicBool::new(true)),
+            search: MessageSearchOptions::from_config(config),
+            message_batch_size: config.message_batch_size,
+        };
 
-        setup_surreal_db(&db).await?;
+        tokio::spawn(supervise_surreal_connection(config.clone(), Arc::downgrade(&client.db), client.available.clone()));
 
         info!("Database initialized successfully.");
 
-        Ok(Self { db })
+        Ok(client)
     }
 }
 
@@ -153,12 +418,48 @@ impl<C> SurrealDbClient<C>
 where
     C: Connection,
 {
+    /// Create a client for an (embedded) database, in the default namespace and database.
     pub async fn from(db: Surreal<C>) -> Res<Self> {
-        setup_surreal_db(&db).await?;
+        setup_surreal_db(&db, DEFAULT_NAMESPACE, DEFAULT_DATABASE).await?;
 
         info!("Database initialized successfully.");
 
-        Ok(Self { db })
+        Ok(Self {
+            db: Arc::new(RwLock::new(db)),
+            available: Arc::new(AtomicBool::new(true)),
+            search: MessageSearchOptions::default(),
+            message_batch_size: DEFAULT_MESSAGE_BATCH_SIZE,
+        })
+    }
+
+    /// Cap and rank message searches with `search` (rather than the defaults).
+    pub fn with_search_options(mut self, search: MessageSearchOptions) -> Self {
+        self.search = search;
+        self
+    }
+
+    /// Store up to `message_batch_size` messages per transaction in `add_channel_messages` (rather than the default).
+    pub fn with_message_batch_size(mut self, message_batch_size: usize) -> Self {
+        self.message_batch_size = message_batch_size.max(1);
+        self
+    }
+
+    /// The current connection, or a `DbUnavailable` error (without waiting on the connection) while it is being re-established.
+    fn db(&self) -> Res<Surreal<C>> {
+        if !self.available.load(Ordering::Relaxed) {
+            return Err(DbUnavailable.into());
+        }
+
+        let db = self.db.read().map_err(|_| anyhow!("The SurrealDB connection is poisoned."))?;
+
+        Ok(db.clone())
+    }
+
+    /// Whether a message with this key (its channel, and `ts`) is already stored.
+    async fn message_exists(&self, key: &[String]) -> Res<bool> {
+        let ids: Vec<RecordId> = self.db()?.query("SELECT VALUE id FROM type::thing('message', $key);").bind(("key", key.to_vec())).await?.take(0)?;
+
+        Ok(!ids.is_empty())
     }
 }
 
@@ -171,9 +472,25 @@ where
     type LlmContextType = SurrealLlmContext;
     type MessageType = SurrealMessage;
 
+    fn is_available(&self) -> bool {
+        self.available.load(Ordering::Relaxed)
+    }
+
+    #[instrument(skip(self))]
+    async fn healthy(&self) -> Void {
+        let query = async { Result::<Option<i64>, anyhow::Error>::Ok(self.db()?.query("RETURN 1;").await?.take(0)?) };
+        let result = tokio::time::timeout(HEALTH_CHECK_TIMEOUT, query).await.map_err(|_| anyhow!("The database health check timed out."))??;
+
+        if result != Some(1) {
+            return Err(anyhow!("The database health check returned `{:?}`.", result));
+        }
+
+        Ok(())
+    }
+
     #[instrument(skip(self))]
     async fn get_or_create_channel(&self, channel_id: &str) -> Res<Self::ChannelType> {
-        let channel: Option<Self::ChannelType> = self.select(("channel", channel_id)).await?;
+        let channel: Option<Self::ChannelType> = self.db()?.select(("channel", channel_id)).await?;
 
         if let Some(channel) = channel {
             info!("Channel `{}` found.", channel_id);
@@ -184,427 +501,2121 @@ where
 
             let new_channel = Self::ChannelType {
                 id: None,
-                channel_directive: Self::LlmContextType {
-                    id: None,
-                    user_message: json!({}),
-                    your_notes: "".into(),
-                },
+                channel_directive: Self::LlmContextType::new(json!({}), "".into()),
+                paused: false,
+                broadcast_incidents: false,
+                active: true,
+                name: None,
+                edit_window_seconds: None,
+                retention_days: None,
+                linked_channels: Vec::new(),
+                moderation_enabled: None,
+                confidence_threshold: None,
+                force_language: None,
+                system_addendum: None,
+                refusal_reply: None,
+                mode: None,
+                web_search_enabled: None,
+                created_at: None,
+                updated_at: None,
             };
 
-            let channel: Self::ChannelType = self.create(("channel", channel_id)).content(new_channel).await?.ok_or(anyhow!("Failed to create channel"))?;
+            let created: Result<Option<Self::ChannelType>, _> = self.db()?.create(("channel", channel_id)).content(new_channel).await;
 
-            Ok(channel)
+            match created {
+                Result::Ok(channel) => channel.ok_or(anyhow!("Failed to create channel")),
+                Err(err) => {
+                    // A concurrent event for the same (new) channel may have created it first, in which case that channel is used.
+                    let channel: Option<Self::ChannelType> = self.db()?.select(("channel", channel_id)).await?;
+
+                    match channel {
+                        Some(channel) => {
+                            info!("Channel `{}` was created concurrently.", channel_id);
+
+                            Ok(channel)
+                        }
+                        None => Err(err.into()),
+                    }
+                }
+            }
         }
     }
 
-    #[instrument(skip(self, directive))]
-    async fn update_channel_directive(&self, channel_id: &str, directive: &Self::LlmContextType) -> Void {
-        let _: Option<Self::ChannelType> = self.update(("channel", channel_id)).merge(json!({ "channel_directive": directive })).await?;
-
-        info!("Channel `{}` updated.", channel_id);
+    #[instrument(skip(self))]
+    async fn get_channel_directive(&self, channel_id: &str) -> Res<Option<Self::LlmContextType>> {
+        let channel: Option<Self::ChannelType> = self.db()?.select(("channel", channel_id)).await?;
 
-        Ok(())
+        Ok(channel.map(|channel| channel.channel_directive))
     }
 
-    #[instrument(skip(self, context))]
-    async fn add_channel_context(&self, channel_id: &str, context: &Self::LlmContextType) -> Res<()> {
+    #[instrument(skip(self, directive))]
+    async fn update_channel_directive(&self, channel_id: &str, directive: &Self::LlmContextType) -> Void {
+        // The new version is numbered after the channel's existing versions (in the same transaction).
+        let version = DirectiveVersion::new(directive, 0, chrono::Utc::now().timestamp());
+
         let mut response = self
-            .db
+            .db()?
             .query("BEGIN TRANSACTION;")
             .query("LET $channel = type::thing('channel', $channel_id);")
-            .query("LET $context = (CREATE context CONTENT $context_content).id;")
-            .query("RELATE $channel->has_context->$context;")
+            .query("UPDATE $channel MERGE { channel_directive: $directive };")
+            .query("LET $number = array::len((SELECT VALUE id FROM directive_history WHERE in = $channel)) + 1;")
+            .query("LET $version = (CREATE directive_version CONTENT $version_content).id;")
+            .query("UPDATE $version SET version = $number;")
+            .query("RELATE $channel->directive_history->$version;")
             .query("COMMIT;")
-            .bind(("context_content", context.clone()))
+            .bind(("directive", directive.clone()))
+            .bind(("version_content", version))
             .bind(("channel_id", channel_id.to_string()))
             .await?;
 
         let errors = response.take_errors();
         if !errors.is_empty() {
-            return Err(anyhow!("Failed to add message to channel `{}`: {:#?}.", channel_id, errors));
+            return Err(anyhow!("Failed to update the directive of channel `{}`: {:#?}.", channel_id, errors));
         }
 
-        info!("Added context for channel `{}`.", channel_id);
+        info!("Channel `{}` updated.", channel_id);
 
         Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn add_channel_message(&self, channel_id: &str, message: &Value) -> Res<()> {
-        let message = Self::MessageType { id: None, raw: message.clone() };
-
-        let mut response = self
-            .db
-            .query("BEGIN TRANSACTION;")
-            .query("LET $channel = type::thing('channel', $channel_id);")
-            .query("LET $message = (CREATE message CONTENT $message_content).id;")
-            .query("RELATE $channel->has_message->$message;")
-            .query("COMMIT;")
-            .bind(("message_content", message))
+    async fn get_directive_history(&self, channel_id: &str, limit: usize) -> Res<Vec<DirectiveVersion>> {
+        let versions: Vec<DirectiveVersion> = self
+            .db()?
+            .query(
+                r#"
+                    SELECT *, record::id(id) AS id FROM directive_version
+                    WHERE id IN (SELECT VALUE out FROM directive_history WHERE in = type::thing('channel', $channel_id))
+                    ORDER BY version DESC
+                    LIMIT $limit;
+                "#,
+            )
             .bind(("channel_id", channel_id.to_string()))
-            .await?;
-
-        let errors = response.take_errors();
-        if !errors.is_empty() {
-            return Err(anyhow!("Failed to add message to channel `{}`: {:#?}.", channel_id, errors));
-        }
-
-        info!("Added message for channel `{}`.", channel_id);
+            .bind(("limit", limit as i64))
+            .await?
+            .take(0)?;
 
-        Ok(())
+        Ok(versions)
     }
 
     #[instrument(skip(self))]
-    async fn get_channel_context(&self, channel_id: &str) -> Res<String> {
-        let context: Vec<Self::LlmContextType> = self
-            .db
-            .query("SELECT * FROM type::thing('channel', $channel_id)->has_context->context;")
+    async fn restore_channel_directive(&self, channel_id: &str, version_id: &str) -> Res<Option<Self::LlmContextType>> {
+        let versions: Vec<DirectiveVersion> = self
+            .db()?
+            .query(
+                r#"
+                    SELECT *, record::id(id) AS id FROM directive_version
+                    WHERE id = type::thing('directive_version', $version_id)
+                        AND id IN (SELECT VALUE out FROM directive_history WHERE in = type::thing('channel', $channel_id));
+                "#,
+            )
+            .bind(("version_id", version_id.to_string()))
             .bind(("channel_id", channel_id.to_string()))
             .await?
             .take(0)?;
 
-        let result = serde_json::to_string(&context)?;
+        let Some(version) = versions.into_iter().next() else {
+            return Ok(None);
+        };
 
-        info!("Retrieved context for channel `{}`.", channel_id);
+        let directive = Self::LlmContextType::new(version.user_message, version.your_notes);
+        self.update_channel_directive(channel_id, &directive).await?;
 
-        Ok(result)
+        info!("Restored version {} of the directive of channel `{}`.", version.version, channel_id);
+
+        Ok(Some(directive))
     }
 
     #[instrument(skip(self))]
-    async fn search_channel_messages(&self, channel_id: &str, search_terms: &str) -> Res<String> {
-        let terms: Vec<String> = search_terms.split(',').map(|s| s.trim().to_string()).filter(|s| !s.is_empty()).collect();
+    async fn set_channel_paused(&self, channel_id: &str, paused: bool) -> Res<()> {
+        let _: Option<Self::ChannelType> = self.db()?.update(("channel", channel_id)).merge(json!({ "paused": paused })).await?;
 
-        if terms.is_empty() {
-            return Ok("[]".to_string()); // Return empty array if no terms
-        }
-
-        // Generate the query parts.
+        info!("Channel `{}` paused: {}.", channel_id, paused);
 
-        let mut score_list = vec![];
-        let mut filter_list = vec![];
-        for (k, term) in terms.iter().enumerate() {
-            score_list.push(format!("search::score({k})"));
-            filter_list.push(format!("raw.text @{k}@ '{term}'"));
-        }
-
-        let score = score_list.join(" + ");
-        let filter = filter_list.join(" OR ");
+        Ok(())
+    }
 
-        // Format the search terms for SurrealDB full-text search
-        // Convert each term to a quoted string and join with OR
-        let query_str = terms.iter().map(|term| format!("\"{term}\"")).collect::<Vec<String>>().join(" OR ");
+    #[instrument(skip(self))]
+    async fn set_channel_broadcast_incidents(&self, channel_id: &str, broadcast_incidents: bool) -> Res<()> {
+        let _: Option<Self::ChannelType> = self.db()?.update(("channel", channel_id)).merge(json!({ "broadcast_incidents": broadcast_incidents })).await?;
 
-        // Get messages from the channel that match the search terms
-        // Use the full-text search capabilities
-        let messages: Vec<SurrealMessage> = self
-            .db
-            .query(format!(
-                r####"
-                    let $messages = SELECT id FROM type::thing('channel', $channel_id)->has_message.out.id;
-                    let $messages = array::flatten($messages[*].id);
+        info!("Channel `{}` broadcasts incidents: {}.", channel_id, broadcast_incidents);
 
-                    SELECT *, {score} AS score
-                    FROM message
-                    WHERE id in $messages AND ({filter})
-                    ORDER BY score DESC
-                    LIMIT 50;
-                "####,
-            ))
-            .bind(("channel_id", channel_id.to_string()))
-            .bind(("query_str", query_str))
-            .await?
-            .take(2)?;
+        Ok(())
+    }
 
-        let result = serde_json::to_string(&messages)?;
+    #[instrument(skip(self))]
+    async fn set_channel_active(&self, channel_id: &str, active: bool) -> Res<()> {
+        let _: Option<Self::ChannelType> = self.db()?.update(("channel", channel_id)).merge(json!({ "active": active })).await?;
 
-        info!("Retrieved {} ranked messages for channel `{}` matching search terms: {}", messages.len(), channel_id, search_terms);
+        info!("Channel `{}` active: {}.", channel_id, active);
 
-        Ok(result)
+        Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn get_channel_live_query(&self) -> Res<Stream<Vec<Self::ChannelType>>> {
-        let stream = self.db.select("channel").live().await?;
+    async fn set_channel_name(&self, channel_id: &str, name: &str) -> Res<()> {
+        let _: Option<Self::ChannelType> = self.db()?.update(("channel", channel_id)).merge(json!({ "name": name })).await?;
+
+        info!("Channel `{}` named `{}`.", channel_id, name);
 
-        Ok(stream)
+        Ok(())
     }
 
     #[instrument(skip(self))]
-    async fn get_context_live_query(&self) -> Res<Stream<Vec<Self::LlmContextType>>> {
-        let stream = self.db.select("context").live().await?;
-
-        Ok(stream)
-    }
-}
+    async fn set_channel_edit_window(&self, channel_id: &str, window_seconds: Option<u64>) -> Res<()> {
+        // Clearing the override removes the field (rather than setting it to `NULL`).
+        let query = match window_seconds {
+            Some(_) => "UPDATE type::thing('channel', $id) SET edit_window_seconds = $window_seconds;",
+            None => "UPDATE type::thing('channel', $id) SET edit_window_seconds = NONE;",
+        };
 
-// Helpers.
+        let mut response = self.db()?.query(query).bind(("id", channel_id.to_string())).bind(("window_seconds", window_seconds)).await?;
 
-/// Set up the surreal database.
-async fn setup_surreal_db<C: Connection>(db: &Surreal<C>) -> Void {
-    // Use a specific namespace and database
-    db.use_ns("triage").use_db("bot").await?;
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the edit window for channel `{}`: {:#?}.", channel_id, errors));
+        }
 
-    // Schema for contexts.
-    db.query("DEFINE TABLE context SCHEMAFULL").await?;
-    db.query("DEFINE FIELD user_message ON context FLEXIBLE TYPE object;").await?;
-    db.query("DEFINE FIELD your_notes ON context TYPE string;").await?;
+        info!("Channel `{}` edit window: {:?}.", channel_id, window_seconds);
 
-    // Schema for messages.
-    db.query("DEFINE TABLE message SCHEMAFULL").await?;
-    db.query("DEFINE FIELD raw ON message FLEXIBLE TYPE object;").await?;
-    db.query("DEFINE FIELD raw.text ON message TYPE string;").await?;
+        Ok(())
+    }
 
-    // Define analyzer for full-text search
-    db.query("DEFINE ANALYZER en TOKENIZERS class FILTERS lowercase, snowball(english);").await?;
+    #[instrument(skip(self))]
+    async fn set_channel_retention(&self, channel_id: &str, retention_days: Option<u64>) -> Res<()> {
+        // Clearing the override removes the field (rather than setting it to `NULL`).
+        let query = match retention_days {
+            Some(_) => "UPDATE type::thing('channel', $id) SET retention_days = $retention_days;",
+            None => "UPDATE type::thing('channel', $id) SET retention_days = NONE;",
+        };
 
-    // Define full-text search index for message text
-    db.query("DEFINE INDEX rawTextFts ON TABLE message FIELDS raw.text SEARCH ANALYZER en BM25;").await?;
+        let mut response = self.db()?.query(query).bind(("id", channel_id.to_string())).bind(("retention_days", retention_days)).await?;
 
-    // Schema for list of channels that the bot has been "added to" (@-mentioned).
-    db.query("DEFINE TABLE channel SCHEMAFULL").await?;
-    db.query("DEFINE FIELD channel_directive ON channel TYPE object;").await?;
-    db.query("DEFINE FIELD channel_directive.user_message ON channel FLEXIBLE TYPE object;").await?;
-    db.query("DEFINE FIELD channel_directive.your_notes ON channel TYPE string;").await?;
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the retention for channel `{}`: {:#?}.", channel_id, errors));
+        }
 
-    // Schema for the relation between channels and contexts.
-    db.query("DEFINE TABLE has_context TYPE RELATION IN channel OUT context;").await?;
+        info!("Channel `{}` retention: {:?} days.", channel_id, retention_days);
 
-    // Schema for the relation between channels and messages.
-    db.query("DEFINE TABLE has_message TYPE RELATION IN channel OUT message;").await?;
+        Ok(())
+    }
 
-    Ok(())
-}
+    #[instrument(skip(self))]
+    async fn set_channel_moderation(&self, channel_id: &str, moderation_enabled: Option<bool>) -> Res<()> {
+        // Clearing the override removes the field (rather than setting it to `NULL`).
+        let query = match moderation_enabled {
+            Some(_) => "UPDATE type::thing('channel', $id) SET moderation_enabled = $moderation_enabled;",
+            None => "UPDATE type::thing('channel', $id) SET moderation_enabled = NONE;",
+        };
 
-#[cfg(test)]
-mod tests {
-    use surrealdb::engine::local::Mem;
+        let mut response = self.db()?.query(query).bind(("id", channel_id.to_string())).bind(("moderation_enabled", moderation_enabled)).await?;
 
-    use super::*;
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the moderation for channel `{}`: {:#?}.", channel_id, errors));
+        }
 
-    async fn setup_test_db() -> Res<DbClient> {
-        let surreal = Surreal::new::<Mem>(()).await?;
-        let db = SurrealDbClient::from(surreal).await?;
-        let client = DbClient { inner: Arc::new(db) };
+        info!("Channel `{}` moderation: {:?}.", channel_id, moderation_enabled);
 
-        Ok(client)
+        Ok(())
     }
 
-    #[tokio::test]
-    async fn test_get_or_create_channel() {
-        let client = setup_test_db().await.unwrap();
+    #[instrument(skip(self))]
+    async fn set_channel_confidence_threshold(&self, channel_id: &str, confidence_threshold: Option<u8>) -> Res<()> {
+        // Clearing the override removes the field (rather than setting it to `NULL`).
+        let query = match confidence_threshold {
+            Some(_) => "UPDATE type::thing('channel', $id) SET confidence_threshold = $confidence_threshold;",
+            None => "UPDATE type::thing('channel', $id) SET confidence_threshold = NONE;",
+        };
 
-        // Test channel creation
-        let channel = client.get_or_create_channel("C1").await.unwrap();
-        assert_eq!(serde_json::to_string(&channel.channel_directive).unwrap(), "{\"user_message\":{},\"your_notes\":\"\"}");
+        let mut response = self
+            .db()?
+            .query(query)
+            .bind(("id", channel_id.to_string()))
+            .bind(("confidence_threshold", confidence_threshold))
+            .await?;
 
-        // Test getting existing channel
-        let existing_channel = client.get_or_create_channel("C1").await.unwrap();
-        assert_eq!(channel.channel_directive, existing_channel.channel_directive);
-    }
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the confidence threshold for channel `{}`: {:#?}.", channel_id, errors));
+        }
 
-    #[tokio::test]
-    async fn test_update_channel_directive() {
-        let client = setup_test_db().await.unwrap();
+        info!("Channel `{}` confidence threshold: {:?}.", channel_id, confidence_threshold);
 
-        // Create a channel first
-        client.get_or_create_channel("C1").await.unwrap();
+        Ok(())
+    }
 
-        // Update the directive
-        let new_directive = SurrealLlmContext {
-            id: None,
-            user_message: json!({ "directive": "new channel directive" }),
-            your_notes: "Updated notes.".into(),
+    #[instrument(skip(self))]
+    async fn set_channel_force_language(&self, channel_id: &str, force_language: Option<&str>) -> Res<()> {
+        // Clearing the override removes the field (rather than setting it to `NULL`).
+        let query = match force_language {
+            Some(_) => "UPDATE type::thing('channel', $id) SET force_language = $force_language;",
+            None => "UPDATE type::thing('channel', $id) SET force_language = NONE;",
         };
 
-        client.update_channel_directive("C1", &new_directive).await.unwrap();
-
-        // Verify the update - the directive should be completely replaced
-        let updated = client.get_or_create_channel("C1").await.unwrap();
+        let mut response = self
+            .db()?
+            .query(query)
+            .bind(("id", channel_id.to_string()))
+            .bind(("force_language", force_language.map(str::to_string)))
+            .await?;
 
-        assert_eq!(updated.channel_directive.your_notes, "Updated notes.");
-        assert!(updated.channel_directive.user_message.get("directive").is_some());
-    }
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the language for channel `{}`: {:#?}.", channel_id, errors));
+        }
 
-    #[tokio::test]
-    async fn test_add_channel_context() {
-        let client = setup_test_db().await.unwrap();
+        info!("Channel `{}` language: {:?}.", channel_id, force_language);
 
-        // Create a channel first
-        client.get_or_create_channel("C1").await.unwrap();
+        Ok(())
+    }
 
-        // Add context
-        let context = SurrealLlmContext {
-            id: None,
-            user_message: json!({ "context": "some context data" }),
-            your_notes: "Context notes.".into(),
+    #[instrument(skip(self, system_addendum))]
+    async fn set_channel_system_addendum(&self, channel_id: &str, system_addendum: Option<&str>) -> Res<()> {
+        // Clearing the addendum removes the field (rather than setting it to `NULL`).
+        let query = match system_addendum {
+            Some(_) => "UPDATE type::thing('channel', $id) SET system_addendum = $system_addendum;",
+            None => "UPDATE type::thing('channel', $id) SET system_addendum = NONE;",
         };
 
-        client.add_channel_context("C1", &context).await.unwrap();
+        let mut response = self
+            .db()?
+            .query(query)
+            .bind(("id", channel_id.to_string()))
+            .bind(("system_addendum", system_addendum.map(str::to_string)))
+            .await?;
 
-        // Verify context was added by getting channel context
-        let retrieved_context = client.get_channel_context("C1").await.unwrap();
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the system addendum for channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Channel `{}` system addendum {}.", channel_id, if system_addendum.is_some() { "set" } else { "removed" });
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_refusal_reply(&self, channel_id: &str, refusal_reply: Option<&str>) -> Res<()> {
+        // Clearing the override removes the field (rather than setting it to `NULL`).
+        let query = match refusal_reply {
+            Some(_) => "UPDATE type::thing('channel', $id) SET refusal_reply = $refusal_reply;",
+            None => "UPDATE type::thing('channel', $id) SET refusal_reply = NONE;",
+        };
+
+        let mut response = self
+            .db()?
+            .query(query)
+            .bind(("id", channel_id.to_string()))
+            .bind(("refusal_reply", refusal_reply.map(str::to_string)))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the refusal reply for channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Channel `{}` refusal reply: {:?}.", channel_id, refusal_reply);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_mode(&self, channel_id: &str, mode: ChannelMode) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query("UPDATE type::thing('channel', $id) SET mode = $mode;")
+            .bind(("id", channel_id.to_string()))
+            .bind(("mode", mode))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the mode for channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Channel `{}` mode: {}.", channel_id, mode.label());
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_web_search(&self, channel_id: &str, web_search_enabled: Option<bool>) -> Res<()> {
+        let query = match web_search_enabled {
+            Some(_) => "UPDATE type::thing('channel', $id) SET web_search_enabled = $web_search_enabled;",
+            None => "UPDATE type::thing('channel', $id) SET web_search_enabled = NONE;",
+        };
+
+        let mut response = self.db()?.query(query).bind(("id", channel_id.to_string())).bind(("web_search_enabled", web_search_enabled)).await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the web search for channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Channel `{}` web search: {:?}.", channel_id, web_search_enabled);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn set_channel_linked_channels(&self, channel_id: &str, linked_channels: &[String]) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query("UPDATE type::thing('channel', $id) SET linked_channels = $linked_channels;")
+            .bind(("id", channel_id.to_string()))
+            .bind(("linked_channels", linked_channels.to_vec()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the linked channels for channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Channel `{}` linked channels: {:?}.", channel_id, linked_channels);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, context))]
+    async fn add_channel_context(&self, channel_id: &str, context: &Self::LlmContextType) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("LET $channel = type::thing('channel', $channel_id);")
+            .query("LET $context = (CREATE context CONTENT $context_content).id;")
+            .query("RELATE $channel->has_context->$context;")
+            .query("COMMIT;")
+            .bind(("context_content", context.clone()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to add message to channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Added context for channel `{}`.", channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, context))]
+    async fn add_user_context(&self, channel_id: &str, user_id: &str, context: &Self::LlmContextType) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("LET $channel = type::thing('channel', $channel_id);")
+            .query("LET $context = (CREATE user_context CONTENT $context_content).id;")
+            .query("RELATE $channel->has_user_context->$context SET user_id = $user_id, created_at = time::now();")
+            .query("COMMIT;")
+            .bind(("context_content", context.clone()))
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("user_id", user_id.to_string()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to add user context to channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Added context about user `{}` for channel `{}`.", user_id, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_user_context(&self, channel_id: &str, user_id: &str) -> Res<Vec<Self::LlmContextType>> {
+        let context: Vec<Self::LlmContextType> = self
+            .db()?
+            .query(
+                r#"
+                    SELECT VALUE out.* FROM (
+                        SELECT out, created_at FROM has_user_context
+                        WHERE in = type::thing('channel', $channel_id) AND user_id = $user_id
+                        ORDER BY created_at ASC
+                    );
+                "#,
+            )
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("user_id", user_id.to_string()))
+            .await?
+            .take(0)?;
+
+        info!("Retrieved {} contexts about user `{}` for channel `{}`.", context.len(), user_id, channel_id);
+
+        Ok(context)
+    }
+
+    #[instrument(skip(self))]
+    async fn add_channel_message(&self, channel_id: &str, message: &Value) -> Res<bool> {
+        // Messages are keyed by their channel and `ts` (when they have one), so that a message is only stored once.
+        let key = message_ts(message).map(|ts| vec![channel_id.to_string(), ts.to_string()]);
+
+        if let Some(key) = &key
+            && self.message_exists(key).await?
+        {
+            info!("Skipped duplicate message for channel `{}`.", channel_id);
+            return Ok(false);
+        }
+
+        let record = if key.is_some() { "type::thing('message', $key)" } else { "message" };
+        let message = Self::MessageType {
+            id: None,
+            raw: message.clone(),
+            ts: message_ts(message).map(str::to_string),
+            thread_ts: message_thread_ts(message).map(str::to_string),
+            created_at: None,
+            updated_at: None,
+        };
+
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("LET $channel = type::thing('channel', $channel_id);")
+            .query(format!("LET $message = (CREATE {record} CONTENT $message_content).id;"))
+            .query("RELATE $channel->has_message->$message;")
+            .query("COMMIT;")
+            .bind(("message_content", message))
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("key", key.clone()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            // A concurrent delivery of the same message may have stored it first.
+            if let Some(key) = &key
+                && self.message_exists(key).await?
+            {
+                info!("Skipped duplicate message for channel `{}`.", channel_id);
+                return Ok(false);
+            }
+
+            return Err(anyhow!("Failed to add message to channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Added message for channel `{}`.", channel_id);
+
+        Ok(true)
+    }
+
+    #[instrument(skip(self, messages))]
+    async fn add_channel_messages(&self, channel_id: &str, messages: &[Value]) -> Res<u64> {
+        /// A message to create, keyed like in `add_channel_message` (when it has a `ts`).
+        #[derive(Serialize)]
+        struct NewMessage {
+            #[serde(skip_serializing_if = "Option::is_none")]
+            key: Option<Vec<String>>,
+            content: SurrealMessage,
+        }
+
+        let mut added = 0;
+
+        for chunk in messages.chunks(self.message_batch_size) {
+            // Messages that are already stored (or that appear earlier in the batch) are skipped.
+            let ts = chunk.iter().filter_map(message_ts).map(str::to_string).collect::<Vec<_>>();
+            let stored: Vec<String> = self
+                .db()?
+                .query("SELECT VALUE out.raw.ts FROM has_message WHERE in = type::thing('channel', $channel_id) AND out.raw.ts INSIDE $ts;")
+                .bind(("channel_id", channel_id.to_string()))
+                .bind(("ts", ts))
+                .await?
+                .take(0)?;
+
+            let mut seen = stored.into_iter().collect::<HashSet<_>>();
+            let new_messages = chunk
+                .iter()
+                .filter(|message| message_ts(message).is_none_or(|ts| seen.insert(ts.to_string())))
+                .map(|message| NewMessage {
+                    key: message_ts(message).map(|ts| vec![channel_id.to_string(), ts.to_string()]),
+                    content: SurrealMessage {
+                        id: None,
+                        raw: message.clone(),
+                        ts: message_ts(message).map(str::to_string),
+                        thread_ts: message_thread_ts(message).map(str::to_string),
+                        created_at: None,
+                        updated_at: None,
+                    },
+                })
+                .collect::<Vec<_>>();
+
+            if new_messages.is_empty() {
+                continue;
+            }
+
+            let count = new_messages.len() as u64;
+            let mut response = self
+                .db()?
+                .query("BEGIN TRANSACTION;")
+                .query("LET $channel = type::thing('channel', $channel_id);")
+                .query(
+                    r#"
+                        FOR $message IN $messages {
+                            IF $message.key IS NONE {
+                                LET $record = (CREATE message CONTENT $message.content).id;
+                                RELATE $channel->has_message->$record;
+                            } ELSE {
+                                LET $record = (CREATE type::thing('message', $message.key) CONTENT $message.content).id;
+                                RELATE $channel->has_message->$record;
+                            };
+                        };
+                    "#,
+                )
+                .query("COMMIT;")
+                .bind(("messages", new_messages))
+                .bind(("channel_id", channel_id.to_string()))
+                .await?;
+
+            let errors = response.take_errors();
+            if errors.is_empty() {
+                added += count;
+                continue;
+            }
+
+            // A concurrent delivery of one of the messages may have stored it first, so add this batch one message at a time.
+            warn!("Failed to add a batch of messages to channel `{}`, so adding them one at a time: {:#?}.", channel_id, errors);
+
+            for message in chunk {
+                if self.add_channel_message(channel_id, message).await? {
+                    added += 1;
+                }
+            }
+        }
+
+        info!("Added {} of {} messages for channel `{}`.", added, messages.len(), channel_id);
+
+        Ok(added)
+    }
+
+    #[instrument(skip(self, message))]
+    async fn update_channel_message(&self, channel_id: &str, ts: &str, message: &Value) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query(
+                r#"
+                    UPDATE message MERGE { raw: $raw }
+                    WHERE raw.ts = $ts AND id IN (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id));
+                "#,
+            )
+            .bind(("raw", message.clone()))
+            .bind(("ts", ts.to_string()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to update message `{}` in channel `{}`: {:#?}.", ts, channel_id, errors));
+        }
+
+        info!("Updated message `{}` for channel `{}`.", ts, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_channel_message(&self, channel_id: &str, ts: &str) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("LET $channel = type::thing('channel', $channel_id);")
+            .query("LET $messages = (SELECT VALUE out FROM has_message WHERE in = $channel AND out.raw.ts = $ts);")
+            .query("DELETE has_message WHERE in = $channel AND out IN $messages;")
+            .query("DELETE $messages;")
+            .query("COMMIT;")
+            .bind(("ts", ts.to_string()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to delete message `{}` from channel `{}`: {:#?}.", ts, channel_id, errors));
+        }
+
+        info!("Deleted message `{}` for channel `{}`.", ts, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_message(&self, channel_id: &str, ts: &str) -> Res<Option<Self::MessageType>> {
+        let messages: Vec<Self::MessageType> = self
+            .db()?
+            .query("SELECT * FROM message WHERE ts = $ts AND id IN (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id)) LIMIT 1;")
+            .bind(("ts", ts.to_string()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?
+            .take(0)?;
+
+        Ok(messages.into_iter().next())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_thread_messages(&self, channel_id: &str, thread_ts: &str) -> Res<Vec<Self::MessageType>> {
+        let messages: Vec<Self::MessageType> = self
+            .db()?
+            .query("SELECT * FROM message WHERE thread_ts = $thread_ts AND id IN (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id)) ORDER BY ts ASC;")
+            .bind(("thread_ts", thread_ts.to_string()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?
+            .take(0)?;
+
+        info!("Retrieved {} stored messages for thread `{}` in channel `{}`.", messages.len(), thread_ts, channel_id);
+
+        Ok(messages)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_thread_roots(&self, channel_id: &str, since: i64) -> Res<Vec<ThreadRoot>> {
+        #[derive(Deserialize)]
+        struct ReplyCount {
+            thread_ts: String,
+            count: u64,
+        }
+
+        let mut result = self
+            .db()?
+            .query("LET $messages = (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id));")
+            .query("SELECT ts, raw, 0 AS reply_count FROM message WHERE id IN $messages AND ts >= $since_ts AND thread_ts = ts ORDER BY ts DESC;")
+            .query(
+                r#"
+                    SELECT thread_ts, count() AS count FROM message
+                    WHERE id IN $messages AND thread_ts >= $since_ts AND ts IS NOT NONE AND ts != thread_ts
+                    GROUP BY thread_ts;
+                "#,
+            )
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("since_ts", unix_to_ts(since)))
+            .await?;
+
+        let mut roots: Vec<ThreadRoot> = result.take(1)?;
+        let counts: Vec<ReplyCount> = result.take(2)?;
+        let counts = counts.into_iter().map(|c| (c.thread_ts, c.count)).collect::<HashMap<_, _>>();
+
+        for root in &mut roots {
+            root.reply_count = counts.get(&root.ts).copied().unwrap_or_default();
+        }
+
+        info!("Retrieved {} thread roots for channel `{}`.", roots.len(), channel_id);
+
+        Ok(roots)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_recent_channel_messages(&self, channel_id: &str, limit: usize) -> Res<Vec<Self::MessageType>> {
+        if limit == 0 {
+            return Ok(Vec::new());
+        }
+
+        let messages: Vec<Self::MessageType> = self
+            .db()?
+            .query(
+                r#"
+                    SELECT * FROM message
+                    WHERE ts IS NOT NONE AND id IN (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id))
+                    ORDER BY ts DESC
+                    LIMIT $limit;
+                "#,
+            )
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("limit", limit as i64))
+            .await?
+            .take(0)?;
+
+        info!("Retrieved {} recent messages for channel `{}`.", messages.len(), channel_id);
+
+        Ok(messages)
+    }
+
+    #[instrument(skip(self))]
+    async fn add_feedback(&self, channel_id: &str, feedback: &Feedback) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("LET $channel = type::thing('channel', $channel_id);")
+            .query("LET $feedback = (CREATE feedback CONTENT $feedback_content).id;")
+            .query("RELATE $channel->has_feedback->$feedback;")
+            .query("COMMIT;")
+            .bind(("feedback_content", feedback.clone()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to add feedback to channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Added feedback for channel `{}`.", channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_feedback(&self, channel_id: &str) -> Res<Vec<Feedback>> {
+        let feedback: Vec<Feedback> = self
+            .db()?
+            .query("SELECT * FROM type::thing('channel', $channel_id)->has_feedback->feedback;")
+            .bind(("channel_id", channel_id.to_string()))
+            .await?
+            .take(0)?;
+
+        info!("Retrieved feedback for channel `{}`.", channel_id);
+
+        Ok(feedback)
+    }
+
+    #[instrument(skip(self, response))]
+    async fn add_assistant_response(&self, channel_id: &str, thread_ts: &str, response: &Value) -> Void {
+        // Responses are numbered within their thread, so that they can be read back in order.
+        let mut result = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("LET $channel = type::thing('channel', $channel_id);")
+            .query("LET $sequence = array::len((SELECT VALUE id FROM response WHERE thread_ts = $thread_ts AND id IN (SELECT VALUE out FROM has_response WHERE in = $channel))) + 1;")
+            .query("LET $response = (CREATE response CONTENT { thread_ts: $thread_ts, sequence: $sequence, response: $response_content }).id;")
+            .query("RELATE $channel->has_response->$response;")
+            .query("COMMIT;")
+            .bind(("response_content", response.clone()))
+            .bind(("thread_ts", thread_ts.to_string()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?;
+
+        let errors = result.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to add a response to channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Added response to thread `{}` in channel `{}`.", thread_ts, channel_id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_assistant_responses(&self, channel_id: &str, thread_ts: &str, limit: usize) -> Res<Vec<Value>> {
+        let mut responses: Vec<Value> = self
+            .db()?
+            .query(
+                r#"
+                    SELECT VALUE response FROM (
+                        SELECT response, sequence FROM response
+                        WHERE thread_ts = $thread_ts AND id IN (SELECT VALUE out FROM has_response WHERE in = type::thing('channel', $channel_id))
+                        ORDER BY sequence DESC
+                        LIMIT $limit
+                    );
+                "#,
+            )
+            .bind(("thread_ts", thread_ts.to_string()))
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("limit", limit as i64))
+            .await?
+            .take(0)?;
+
+        responses.reverse();
+
+        Ok(responses)
+    }
+
+    #[instrument(skip(self, message))]
+    async fn add_pending_message(&self, message: &PendingMessage) -> Res<String> {
+        let mut response = self
+            .db()?
+            .query("CREATE pending_message CONTENT $message_content RETURN VALUE record::id(id);")
+            .bind(("message_content", message.clone()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to add pending message for channel `{}`: {:#?}.", message.channel_id, errors));
+        }
+
+        let ids: Vec<String> = response.take(0)?;
+        let id = ids
+            .into_iter()
+            .next()
+            .ok_or(anyhow!("Failed to get the ID of the pending message for channel `{}`.", message.channel_id))?;
+
+        info!("Added pending message for channel `{}` to the outbox.", message.channel_id);
+
+        Ok(id)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_due_pending_messages(&self, now: i64) -> Res<Vec<PendingMessage>> {
+        let messages: Vec<PendingMessage> = self
+            .db()?
+            .query("SELECT *, record::id(id) AS id FROM pending_message WHERE next_attempt_at <= $now ORDER BY created_at ASC;")
+            .bind(("now", now))
+            .await?
+            .take(0)?;
+
+        Ok(messages)
+    }
+
+    #[instrument(skip(self, message))]
+    async fn update_pending_message(&self, message: &PendingMessage) -> Res<()> {
+        let id = message.id.as_deref().ok_or(anyhow!("Cannot update a pending message without an ID."))?;
+
+        let mut response = self
+            .db()?
+            .query("UPDATE type::thing('pending_message', $id) MERGE { attempts: $attempts, next_attempt_at: $next_attempt_at };")
+            .bind(("id", id.to_string()))
+            .bind(("attempts", message.attempts))
+            .bind(("next_attempt_at", message.next_attempt_at))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to update pending message `{}`: {:#?}.", id, errors));
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_pending_message(&self, id: &str) -> Res<()> {
+        let mut response = self.db()?.query("DELETE type::thing('pending_message', $id);").bind(("id", id.to_string())).await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to delete pending message `{}`: {:#?}.", id, errors));
+        }
+
+        info!("Deleted pending message `{}` from the outbox.", id);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, message))]
+    async fn add_scheduled_message(&self, message: &ScheduledMessage) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query("CREATE scheduled_message CONTENT $message_content;")
+            .bind(("message_content", message.clone()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to add scheduled message for thread `{}`: {:#?}.", message.thread_ts, errors));
+        }
+
+        info!("Added scheduled message for thread `{}`.", message.thread_ts);
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_thread_scheduled_messages(&self, channel_id: &str, thread_ts: &str) -> Res<Vec<ScheduledMessage>> {
+        let messages: Vec<ScheduledMessage> = self
+            .db()?
+            .query("SELECT *, record::id(id) AS id FROM scheduled_message WHERE channel_id = $channel_id AND thread_ts = $thread_ts ORDER BY post_at ASC;")
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("thread_ts", thread_ts.to_string()))
+            .await?
+            .take(0)?;
+
+        Ok(messages)
+    }
+
+    #[instrument(skip(self))]
+    async fn delete_scheduled_message(&self, id: &str) -> Res<()> {
+        let mut response = self.db()?.query("DELETE type::thing('scheduled_message', $id);").bind(("id", id.to_string())).await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to delete scheduled message `{}`: {:#?}.", id, errors));
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_message_count(&self, channel_id: &str) -> Res<u64> {
+        let count: Option<u64> = self
+            .db()?
+            .query("SELECT count() AS count FROM has_message WHERE in = type::thing('channel', $channel_id) GROUP ALL;")
+            .bind(("channel_id", channel_id.to_string()))
+            .await?
+            .take((0, "count"))?;
+
+        Ok(count.unwrap_or_default())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_stats(&self, channel_id: &str, since: i64) -> Res<ChannelStats> {
+        #[derive(Deserialize)]
+        struct ClassificationCount {
+            classification: String,
+            count: u64,
+        }
+
+        let mut result = self
+            .db()?
+            .query("LET $channel = type::thing('channel', $channel_id);")
+            .query("RETURN array::len((SELECT VALUE id FROM has_message WHERE in = $channel));")
+            .query("RETURN array::len((SELECT VALUE id FROM has_message WHERE in = $channel AND out.raw.ts >= $since_ts));")
+            .query("RETURN array::len((SELECT VALUE id FROM has_context WHERE in = $channel));")
+            .query("LET $responses = (SELECT VALUE out FROM has_response WHERE in = $channel);")
+            .query("RETURN array::len((SELECT VALUE id FROM response WHERE response.created_at >= $since AND id IN $responses));")
+            .query(
+                r#"
+                    SELECT response.response.classification AS classification, count() AS count FROM response
+                    WHERE response.created_at >= $since AND response.response.classification IS NOT NONE AND id IN $responses
+                    GROUP BY classification;
+                "#,
+            )
+            .query("SELECT VALUE response.usage FROM response WHERE response.created_at >= $since AND response.usage IS NOT NONE AND id IN $responses;")
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("since", since))
+            .bind(("since_ts", unix_to_ts(since)))
+            .await?;
+
+        let messages: Option<u64> = result.take(1)?;
+        let messages_since: Option<u64> = result.take(2)?;
+        let contexts: Option<u64> = result.take(3)?;
+        let responses_since: Option<u64> = result.take(5)?;
+        let classifications: Vec<ClassificationCount> = result.take(6)?;
+        let usages: Vec<TokenUsage> = result.take(7)?;
+
+        let mut usage = TokenUsage::default();
+        for response_usage in &usages {
+            usage.add(response_usage);
+        }
+
+        Ok(ChannelStats {
+            channel_id: channel_id.to_string(),
+            since,
+            messages: messages.unwrap_or_default(),
+            messages_since: messages_since.unwrap_or_default(),
+            contexts: contexts.unwrap_or_default(),
+            responses_since: responses_since.unwrap_or_default(),
+            classifications: classifications.into_iter().map(|c| (c.classification, c.count)).collect(),
+            usage,
+        })
+    }
+
+    #[instrument(skip(self))]
+    async fn list_channel_ids(&self) -> Res<Vec<String>> {
+        let ids: Vec<String> = self.db()?.query("SELECT VALUE record::id(id) FROM channel;").await?.take(0)?;
+
+        Ok(ids)
+    }
+
+    #[instrument(skip(self))]
+    async fn prune_old_messages(&self, channel_id: &str, cutoff: i64) -> Res<u64> {
+        // Messages without a timestamp are never pruned, since their age is unknown.
+        let messages: Vec<RecordId> = self
+            .db()?
+            .query(
+                r#"
+                    SELECT VALUE out FROM has_message
+                    WHERE in = type::thing('channel', $channel_id)
+                        AND out.raw.ts IS NOT NONE
+                        AND out.raw.ts < $cutoff_ts
+                        AND out.raw.ts NOTINSIDE (SELECT VALUE user_message.ts FROM type::thing('channel', $channel_id)->has_context->context);
+                "#,
+            )
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("cutoff_ts", unix_to_ts(cutoff)))
+            .await?
+            .take(0)?;
+
+        if messages.is_empty() {
+            return Ok(0);
+        }
+
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("DELETE has_message WHERE out IN $messages;")
+            .query("DELETE $messages;")
+            .query("COMMIT;")
+            .bind(("messages", messages.clone()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to prune messages from channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Pruned {} messages from channel `{}`.", messages.len(), channel_id);
+
+        Ok(messages.len() as u64)
+    }
+
+    #[instrument(skip(self))]
+    async fn prune_expired_contexts(&self, channel_id: &str, cutoff: i64) -> Res<u64> {
+        let contexts: Vec<RecordId> = self
+            .db()?
+            .query("SELECT VALUE out FROM has_context WHERE in = type::thing('channel', $channel_id) AND out.expires_at IS NOT NONE AND out.expires_at < $cutoff;")
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("cutoff", cutoff))
+            .await?
+            .take(0)?;
+
+        if contexts.is_empty() {
+            return Ok(0);
+        }
+
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("DELETE has_context WHERE out IN $contexts;")
+            .query("DELETE $contexts;")
+            .query("COMMIT;")
+            .bind(("contexts", contexts.clone()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to prune expired contexts from channel `{}`: {:#?}.", channel_id, errors));
+        }
+
+        info!("Pruned {} expired contexts from channel `{}`.", contexts.len(), channel_id);
+
+        Ok(contexts.len() as u64)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_context(&self, channel_id: &str) -> Res<String> {
+        let context: Vec<Self::LlmContextType> = self
+            .db()?
+            .query(format!("SELECT * FROM type::thing('channel', $channel_id)->has_context->context WHERE {UNEXPIRED};"))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?
+            .take(0)?;
+
+        let result = serde_json::to_string(&context)?;
+
+        info!("Retrieved context for channel `{}`.", channel_id);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_context_paged(&self, channel_id: &str, limit: usize, max_chars: usize) -> Res<String> {
+        // One more than the limit is fetched, so that it is known whether older context was left out.
+        let context: Vec<Self::LlmContextType> = self
+            .db()?
+            .query(format!(
+                "SELECT * FROM type::thing('channel', $channel_id)->has_context->context WHERE {UNEXPIRED} ORDER BY user_message.ts DESC LIMIT $limit;"
+            ))
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("limit", limit as i64 + 1))
+            .await?
+            .take(0)?;
+
+        let result = format_channel_context(&context, limit, max_chars)?;
+
+        info!("Retrieved recent context for channel `{}`.", channel_id);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn list_channel_contexts(&self, channel_id: &str) -> Res<Vec<(String, String)>> {
+        let contexts: Vec<Self::LlmContextType> = self
+            .db()?
+            .query("SELECT * FROM type::thing('channel', $channel_id)->has_context->context ORDER BY user_message.ts DESC;")
+            .bind(("channel_id", channel_id.to_string()))
+            .await?
+            .take(0)?;
+
+        Ok(contexts.iter().filter_map(|context| Some((context.id()?, summarize_context(context)))).collect())
+    }
+
+    #[instrument(skip(self))]
+    async fn remove_channel_context(&self, channel_id: &str, context_id: &str) -> Res<bool> {
+        // IDs are handed out as `context:<key>` (see `list_channel_contexts`), but the bare key is accepted too.
+        let key = context_id.strip_prefix("context:").unwrap_or(context_id).trim_start_matches('⟨').trim_end_matches('⟩');
+
+        let contexts: Vec<RecordId> = self
+            .db()?
+            .query("SELECT VALUE out FROM has_context WHERE in = type::thing('channel', $channel_id) AND out = type::thing('context', $key);")
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("key", key.to_string()))
+            .await?
+            .take(0)?;
+
+        if contexts.is_empty() {
+            return Ok(false);
+        }
+
+        let mut response = self
+            .db()?
+            .query("BEGIN TRANSACTION;")
+            .query("DELETE has_context WHERE out IN $contexts;")
+            .query("DELETE $contexts;")
+            .query("COMMIT;")
+            .bind(("contexts", contexts))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to remove context `{}` from channel `{}`: {:#?}.", context_id, channel_id, errors));
+        }
 
-        assert!(!retrieved_context.is_empty());
-        assert!(retrieved_context.contains("some context data"));
+        info!("Removed context `{}` from channel `{}`.", context_id, channel_id);
+
+        Ok(true)
     }
 
-    #[tokio::test]
-    async fn test_add_channel_message() {
-        let client = setup_test_db().await.unwrap();
+    #[instrument(skip(self))]
+    async fn search_channel_messages(&self, channel_id: &str, search_terms: &str) -> Res<String> {
+        self.search_channel_messages_filtered(channel_id, search_terms, None, None).await
+    }
 
-        // Create a channel first
-        client.get_or_create_channel("C1").await.unwrap();
+    #[instrument(skip(self))]
+    async fn search_channel_messages_filtered(&self, channel_id: &str, search_terms: &str, after_ts: Option<&str>, before_ts: Option<&str>) -> Res<String> {
+        let terms = parse_search_terms(search_terms);
+
+        if terms.is_empty() {
+            return Ok("[]".to_string()); // Return empty array if no terms
+        }
+
+        // Generate the query parts (the terms themselves are bound as parameters).
+
+        let (score, filter) = full_text_search_parts(&terms);
+
+        // Get messages from the channel that match the search terms
+        // Use the full-text search capabilities
+        let mut messages: Vec<SurrealMessage> = self
+            .db()?
+            .query(format!(
+                r####"
+                    let $messages = SELECT id FROM type::thing('channel', $channel_id)->has_message.out.id;
+                    let $messages = array::flatten($messages[*].id);
+
+                    SELECT *, ({score}) * {RECENCY_FACTOR} AS score
+                    FROM message
+                    WHERE id in $messages AND ({filter})
+                        AND ($after_ts IS NONE OR raw.ts >= $after_ts)
+                        AND ($before_ts IS NONE OR raw.ts < $before_ts)
+                    ORDER BY score DESC
+                    LIMIT $limit;
+                "####,
+            ))
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(full_text_search_terms(&terms))
+            .bind(("after_ts", after_ts.map(str::to_string)))
+            .bind(("before_ts", before_ts.map(str::to_string)))
+            .bind(("limit", self.search.limit as i64))
+            .bind(("recency_boost", self.search.recency_boost))
+            .await?
+            .take(2)?;
+
+        // With fuzzy search, likely typos of the terms also match (after the exact matches).
+        if self.search.fuzzy && messages.len() < self.search.limit {
+            let candidates = self.get_recent_channel_messages(channel_id, FUZZY_SEARCH_CANDIDATES).await?;
+            add_fuzzy_matches(&mut messages, candidates, &terms, after_ts, before_ts, self.search.limit);
+        }
+
+        let result = serde_json::to_string(&messages)?;
+
+        info!("Retrieved {} ranked messages for channel `{}` matching search terms: {}", messages.len(), channel_id, search_terms);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn search_messages_across_channels(&self, channel_ids: &[String], search_terms: &str, after_ts: Option<&str>, before_ts: Option<&str>) -> Res<String> {
+        let terms = parse_search_terms(search_terms);
+
+        if terms.is_empty() || channel_ids.is_empty() {
+            return Ok("[]".to_string());
+        }
+
+        let (score, filter) = full_text_search_parts(&terms);
+        let channels = channel_ids.iter().map(|id| RecordId::from(("channel", id.as_str()))).collect::<Vec<_>>();
+
+        // Each message is labelled with the channel that it belongs to, and all of the channels are ranked (and capped) together.
+        let messages: Vec<CrossChannelMessage> = self
+            .db()?
+            .query(format!(
+                r####"
+                    LET $messages = (SELECT VALUE out FROM has_message WHERE in IN $channels);
+
+                    SELECT raw, thread_ts, record::id(array::first(<-has_message<-channel)) AS channel_id, ({score}) * {RECENCY_FACTOR} AS score
+                    FROM message
+                    WHERE id IN $messages AND ({filter})
+                        AND ($after_ts IS NONE OR raw.ts >= $after_ts)
+                        AND ($before_ts IS NONE OR raw.ts < $before_ts)
+                    ORDER BY score DESC
+                    LIMIT $limit;
+                "####,
+            ))
+            .bind(("channels", channels))
+            .bind(full_text_search_terms(&terms))
+            .bind(("after_ts", after_ts.map(str::to_string)))
+            .bind(("before_ts", before_ts.map(str::to_string)))
+            .bind(("limit", self.search.limit as i64))
+            .bind(("recency_boost", self.search.recency_boost))
+            .await?
+            .take(1)?;
+
+        let result = serde_json::to_string(&messages)?;
+
+        info!(
+            "Retrieved {} ranked messages across {} channels matching search terms: {}",
+            messages.len(),
+            channel_ids.len(),
+            search_terms
+        );
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self, embedding))]
+    async fn set_channel_message_embedding(&self, channel_id: &str, ts: &str, embedding: &[f32]) -> Res<()> {
+        let mut response = self
+            .db()?
+            .query(
+                r#"
+                    UPDATE message SET embedding = $embedding
+                    WHERE raw.ts = $ts AND id IN (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id));
+                "#,
+            )
+            .bind(("embedding", embedding.to_vec()))
+            .bind(("ts", ts.to_string()))
+            .bind(("channel_id", channel_id.to_string()))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to set the embedding of message `{}` in channel `{}`: {:#?}.", ts, channel_id, errors));
+        }
+
+        Ok(())
+    }
+
+    #[instrument(skip(self, query))]
+    async fn search_channel_messages_semantic(&self, channel_id: &str, query: &[f32], k: usize) -> Res<String> {
+        // The number of neighbours is part of the KNN operator (and is a number, so it is safe to format in).
+        let k = k.max(1);
+
+        let messages: Vec<SurrealMessage> = self
+            .db()?
+            .query(format!(
+                r#"
+                    SELECT id, raw, ts, thread_ts, vector::distance::knn() AS distance
+                    FROM message
+                    WHERE id IN (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id)) AND embedding <|{k},40|> $query
+                    ORDER BY distance;
+                "#
+            ))
+            .bind(("channel_id", channel_id.to_string()))
+            .bind(("query", query.to_vec()))
+            .await?
+            .take(0)?;
+
+        let result = serde_json::to_string(&messages)?;
+
+        info!("Retrieved {} semantically similar messages for channel `{}`.", messages.len(), channel_id);
+
+        Ok(result)
+    }
+
+    #[instrument(skip(self))]
+    async fn export_channel(&self, channel_id: &str) -> Res<ChannelExport> {
+        let mut response = self
+            .db()?
+            .query("SELECT VALUE channel_directive FROM ONLY type::thing('channel', $channel_id);")
+            .query("SELECT user_message, your_notes, expires_at FROM type::thing('channel', $channel_id)->has_context->context ORDER BY user_message.ts ASC;")
+            .query(
+                r#"
+                    SELECT user_id, out.user_message AS user_message, out.your_notes AS your_notes, created_at
+                    FROM has_user_context
+                    WHERE in = type::thing('channel', $channel_id)
+                    ORDER BY created_at ASC;
+                "#,
+            )
+            .query("SELECT raw, embedding FROM message WHERE id IN (SELECT VALUE out FROM has_message WHERE in = type::thing('channel', $channel_id)) ORDER BY raw.ts ASC;")
+            .bind(("channel_id", channel_id.to_string()))
+            .await?;
+
+        let Some(directive) = response.take::<Option<ExportedContext>>(0)? else {
+            return Err(anyhow!("Channel `{}` does not exist.", channel_id));
+        };
+
+        let export = ChannelExport {
+            channel_id: channel_id.to_string(),
+            exported_at: chrono::Utc::now().timestamp(),
+            directive,
+            contexts: response.take(1)?,
+            user_contexts: response.take(2)?,
+            messages: response.take(3)?,
+        };
+
+        info!("Exported channel `{}` ({} contexts, {} messages).", channel_id, export.contexts.len(), export.messages.len());
+
+        Ok(export)
+    }
+
+    #[instrument(skip(self, export))]
+    async fn import_channel(&self, channel_id: &str, export: &ChannelExport) -> Res<u64> {
+        let added = import_channel_export(self, channel_id, export).await?;
+
+        info!("Imported channel `{}` into channel `{}` ({} new messages).", export.channel_id, channel_id, added);
+
+        Ok(added)
+    }
+
+    #[instrument(skip(self))]
+    async fn get_channel_live_query(&self) -> Res<LiveQuery<Self::ChannelType>> {
+        let stream = self.db()?.select("channel").live().await?;
+
+        Ok(stream
+            .map(|notification| notification.map(|n| LiveEvent { action: n.action, data: n.data }).map_err(anyhow::Error::from))
+            .boxed())
+    }
+
+    #[instrument(skip(self))]
+    async fn get_context_live_query(&self) -> Res<LiveQuery<Self::LlmContextType>> {
+        let stream = self.db()?.select("context").live().await?;
+
+        Ok(stream
+            .map(|notification| notification.map(|n| LiveEvent { action: n.action, data: n.data }).map_err(anyhow::Error::from))
+            .boxed())
+    }
+}
+
+// Helpers.
+
+/// Channels are active unless they are archived (records from before the field existed are active).
+fn default_channel_active() -> bool {
+    true
+}
+
+/// Load a TLS configuration that trusts the CA certificates in a PEM file (e.g., those of an internal PKI).
+fn load_tls_config(ca_path: &str) -> Res<rustls::ClientConfig> {
+    let certs = CertificateDer::pem_file_iter(ca_path)
+        .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+        .map_err(|e| anyhow!("Failed to load the SurrealDB CA certificates from `{}`: {}", ca_path, e))?;
+
+    let mut roots = rustls::RootCertStore::empty();
+    let (added, _) = roots.add_parsable_certificates(certs);
+
+    if added == 0 {
+        return Err(anyhow!("No valid CA certificates were found in `{}`.", ca_path));
+    }
+
+    Ok(rustls::ClientConfig::builder().with_root_certificates(roots).with_no_client_auth())
+}
+
+/// Connect (and sign in) to a SurrealDB server, and set up the database.
+async fn connect_surreal_server(config: &Config) -> Res<Surreal<Client>> {
+    let endpoint = RemoteEndpoint::parse(&config.db_endpoint, config.db_tls);
+
+    // Connection (and TLS) failures are reported apart from sign in failures, since they are fixed in different places.
+    let db = if endpoint.tls {
+        let options = match &config.db_tls_ca_path {
+            Some(path) => surrealdb::opt::Config::new().rustls(load_tls_config(path)?),
+            None => surrealdb::opt::Config::new(),
+        };
 
-        // Add messages
-        let message1 = json!({"text": "Hello world", "user": "U123", "ts": "1234567890.123"});
-        let message2 = json!({"text": "Another message", "user": "U456", "ts": "1234567890.456"});
+        Surreal::new::<Wss>((endpoint.address, options)).await.map_err(|e| {
+            anyhow!(
+                "Failed to connect to SurrealDB at `{}` over TLS (check the server's certificate, and `db_tls_ca_path`): {}",
+                endpoint.address,
+                e
+            )
+        })?
+    } else {
+        Surreal::new::<Ws>(endpoint.address)
+            .await
+            .map_err(|e| anyhow!("Failed to connect to SurrealDB at `{}`: {}", endpoint.address, e))?
+    };
+
+    let (namespace, database, username, password) = (config.db_namespace.as_str(), config.db_database.as_str(), config.db_username.as_str(), config.db_password.as_str());
+
+    match config.db_auth.as_str() {
+        "namespace" => db.signin(Namespace { namespace, username, password }).await.map(drop),
+        "database" => db.signin(Database { namespace, database, username, password }).await.map(drop),
+        "token" => db.authenticate(config.db_token.clone()).await,
+        _ => db.signin(Root { username, password }).await.map(drop),
+    }
+    .map_err(|e| anyhow!("Failed to sign in to SurrealDB with `{}` auth (check the `db_auth` credentials): {}", config.db_auth, e))?;
+
+    setup_surreal_db(&db, &config.db_namespace, &config.db_database).await?;
+
+    Ok(db)
+}
+
+/// Check the connection to a SurrealDB server periodically, and re-establish it when it is lost.
+///
+/// While the connection is down, the client is marked unavailable, and reconnects are attempted with an exponential
+/// backoff.  The supervisor stops once the client is dropped.
+async fn supervise_surreal_connection(config: Config, db: Weak<RwLock<Surreal<Client>>>, available: Arc<AtomicBool>) {
+    loop {
+        tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+
+        let Some(current) = current_connection(&db) else {
+            return;
+        };
+
+        let error = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, current.health()).await {
+            Result::Ok(Result::Ok(())) => continue,
+            Result::Ok(Err(err)) => err.to_string(),
+            Err(_) => "the health check timed out".to_string(),
+        };
+
+        available.store(false, Ordering::Relaxed);
+        warn!("Lost the connection to the database ({}); queries will fail until it is re-established.", error);
+
+        let degraded_at = Instant::now();
+        let mut attempts = 0;
+
+        loop {
+            attempts += 1;
+
+            let connected = match tokio::time::timeout(HEALTH_CHECK_TIMEOUT, connect_surreal_server(&config)).await {
+                Result::Ok(connected) => connected,
+                Err(_) => Err(anyhow!("The connection attempt timed out.")),
+            };
+
+            match connected {
+                Result::Ok(connection) => {
+                    let Some(db) = db.upgrade() else {
+                        return;
+                    };
+
+                    match db.write() {
+                        Result::Ok(mut db) => *db = connection,
+                        Err(_) => return,
+                    }
+
+                    available.store(true, Ordering::Relaxed);
+                    info!("Reconnected to the database after {:?} ({} attempts).", degraded_at.elapsed(), attempts);
+
+                    break;
+                }
+                Err(err) => {
+                    let delay = reconnect_backoff(attempts);
+                    warn!(
+                        "Failed to reconnect to the database (attempt {}, down for {:?}), retrying in {:?}: {}",
+                        attempts,
+                        degraded_at.elapsed(),
+                        delay,
+                        err
+                    );
+
+                    tokio::time::sleep(delay).await;
+                }
+            }
+        }
+    }
+}
+
+/// The current connection of a supervised client (if the client still exists).
+fn current_connection(db: &Weak<RwLock<Surreal<Client>>>) -> Option<Surreal<Client>> {
+    let db = db.upgrade()?;
+    let current = db.read().ok()?.clone();
+
+    Some(current)
+}
+
+/// The delay before the next reconnect attempt, after `attempts` consecutive failed attempts.
+fn reconnect_backoff(attempts: u32) -> Duration {
+    let exponent = attempts.saturating_sub(1).min(16);
+
+    (RECONNECT_BASE_BACKOFF * 2_u32.pow(exponent)).min(RECONNECT_MAX_BACKOFF)
+}
+
+/// Set up the surreal database in a namespace and database (bringing its schema up to date).
+async fn setup_surreal_db<C: Connection>(db: &Surreal<C>, namespace: &str, database: &str) -> Void {
+    db.use_ns(namespace).use_db(database).await?;
+
+    migrate_surreal_db(db, &surreal_migrations()).await
+}
+
+/// Apply the migrations that the database is missing, in order, each in its own transaction.
+///
+/// The schema version is stored in the `meta:schema` record.  A database whose schema is newer than the migrations
+/// (e.g., after rolling back a deploy) is an error, rather than something to silently run against.
+async fn migrate_surreal_db<C: Connection>(db: &Surreal<C>, migrations: &[SchemaMigration]) -> Void {
+    let current = get_schema_version(db).await?;
+    let latest = migrations.last().map_or(0, |m| m.version);
+
+    if current > latest {
+        return Err(anyhow!(
+            "The database schema is at version {}, which is newer than this build supports (version {}); refusing to downgrade.",
+            current,
+            latest
+        ));
+    }
+
+    for migration in migrations.iter().filter(|m| m.version > current) {
+        let mut response = db
+            .query("BEGIN TRANSACTION;")
+            .query(migration.statements.as_str())
+            .query("UPSERT meta:schema SET version = $version, migrated_at = time::now();")
+            .query("COMMIT;")
+            .bind(("version", migration.version))
+            .await?;
+
+        let errors = response.take_errors();
+        if !errors.is_empty() {
+            return Err(anyhow!("Failed to apply schema migration {} ({}): {:#?}.", migration.version, migration.description, errors));
+        }
+
+        info!("Applied schema migration {}: {}.", migration.version, migration.description);
+    }
+
+    Ok(())
+}
+
+/// Get the schema version of the database (0 for a new database).
+async fn get_schema_version<C: Connection>(db: &Surreal<C>) -> Res<u64> {
+    let version: Option<u64> = db.query("SELECT VALUE version FROM ONLY meta:schema;").await?.take(0)?;
+
+    Ok(version.unwrap_or_default())
+}
+
+// Migrations.
+
+/// A step in the evolution of the surreal schema.
+///
+/// Migrations that have shipped must never change: any change to the schema goes into a new migration (with the next
+/// version).  Definitions use `IF NOT EXISTS`, so that databases created before the schema was versioned (which
+/// already have some of them) migrate cleanly.
+struct SchemaMigration {
+    /// The schema version that the migration brings the database to.
+    version: u64,
+    /// What the migration does (logged when it is applied).
+    description: &'static str,
+    /// The SurrealQL statements of the migration.
+    statements: String,
+}
+
+/// The score and filter expressions of a full-text search for any of `terms` in the text of messages.
+///
+/// Only the terms' positions are formatted into the expressions: each term is referred to as `$term{k}` (and each
+/// phrase also as `$phrase{k}`), and bound with `full_text_search_terms`, so that no term (e.g., one with a quote) can
+/// change the query.  The full-text index matches a term's words in any order, so phrases are also filtered on their
+/// (case-insensitive) text.
+fn full_text_search_parts(terms: &[SearchTerm]) -> (String, String) {
+    let mut score_list = vec![];
+    let mut filter_list = vec![];
+    for (k, term) in terms.iter().enumerate() {
+        score_list.push(format!("search::score({k})"));
+
+        if term.phrase {
+            filter_list.push(format!("(raw.text @{k}@ $term{k} AND string::contains(string::lowercase(raw.text), $phrase{k}))"));
+        } else {
+            filter_list.push(format!("raw.text @{k}@ $term{k}"));
+        }
+    }
+
+    (score_list.join(" + "), filter_list.join(" OR "))
+}
+
+/// The query parameters of the terms in `full_text_search_parts` (`$term0`, `$term1`, …, and `$phrase{k}` for phrases).
+fn full_text_search_terms(terms: &[SearchTerm]) -> HashMap<String, String> {
+    let mut bindings = HashMap::new();
+    for (k, term) in terms.iter().enumerate() {
+        bindings.insert(format!("term{k}"), term.text.clone());
+
+        if term.phrase {
+            bindings.insert(format!("phrase{k}"), term.text.to_lowercase());
+        }
+    }
+
+    bindings
+}
+
+/// The migrations of the surreal schema, in order.
+fn surreal_migrations() -> Vec<SchemaMigration> {
+    vec![
+        SchemaMigration {
+            version: 1,
+            description: "create the channel, context, and message tables",
+            statements: r#"
+                -- Schema for contexts.
+                DEFINE TABLE IF NOT EXISTS context SCHEMAFULL;
+                DEFINE FIELD IF NOT EXISTS user_message ON context FLEXIBLE TYPE object;
+                DEFINE FIELD IF NOT EXISTS your_notes ON context TYPE string;
+
+                -- Schema for messages.
+                DEFINE TABLE IF NOT EXISTS message SCHEMAFULL;
+                DEFINE FIELD IF NOT EXISTS raw ON message FLEXIBLE TYPE object;
+                DEFINE FIELD IF NOT EXISTS raw.text ON message TYPE string;
+
+                -- Define analyzer for full-text search
+                DEFINE ANALYZER IF NOT EXISTS en TOKENIZERS class FILTERS lowercase, snowball(english);
+
+                -- Define full-text search index for message text
+                DEFINE INDEX IF NOT EXISTS rawTextFts ON TABLE message FIELDS raw.text SEARCH ANALYZER en BM25;
+
+                -- Schema for list of channels that the bot has been "added to" (@-mentioned).
+                DEFINE TABLE IF NOT EXISTS channel SCHEMAFULL;
+                DEFINE FIELD IF NOT EXISTS channel_directive ON channel TYPE object;
+                DEFINE FIELD IF NOT EXISTS channel_directive.user_message ON channel FLEXIBLE TYPE object;
+                DEFINE FIELD IF NOT EXISTS channel_directive.your_notes ON channel TYPE string;
+
+                -- Schema for the relation between channels and contexts.
+                DEFINE TABLE IF NOT EXISTS has_context TYPE RELATION IN channel OUT context;
+
+                -- Schema for the relation between channels and messages.
+                DEFINE TABLE IF NOT EXISTS has_message TYPE RELATION IN channel OUT message;
+            "#
+            .to_string(),
+        },
+        SchemaMigration {
+            version: 2,
+            description: "add threads, embeddings, feedback, the outbox, scheduled messages, channel settings, user context, directive history, and responses",
+            statements: format!(
+                r#"
+                    -- Schema for message threads (and timestamps, for time range search).
+                    DEFINE FIELD IF NOT EXISTS thread_ts ON message TYPE option<string>;
+                    DEFINE INDEX IF NOT EXISTS messageThread ON TABLE message FIELDS thread_ts;
+                    DEFINE INDEX IF NOT EXISTS messageTs ON TABLE message FIELDS raw.ts;
+
+                    -- Define vector index for semantic search (messages only have an embedding when semantic search is enabled).
+                    DEFINE FIELD IF NOT EXISTS embedding ON message TYPE option<array<float>>;
+                    DEFINE INDEX IF NOT EXISTS messageEmbedding ON TABLE message FIELDS embedding HNSW DIMENSION {EMBEDDING_DIMENSIONS} DIST COSINE;
+
+                    -- Schema for feedback on the bot's replies.
+                    DEFINE TABLE IF NOT EXISTS feedback SCHEMAFULL;
+                    DEFINE FIELD IF NOT EXISTS thread_ts ON feedback TYPE string;
+                    DEFINE FIELD IF NOT EXISTS message_ts ON feedback TYPE string;
+                    DEFINE FIELD IF NOT EXISTS user_id ON feedback TYPE string;
+                    DEFINE FIELD IF NOT EXISTS emoji ON feedback TYPE string;
+                    DEFINE FIELD IF NOT EXISTS classification ON feedback TYPE option<string>;
+
+                    -- Schema for the outbox of messages that failed to send.
+                    DEFINE TABLE IF NOT EXISTS pending_message SCHEMAFULL;
+                    DEFINE FIELD IF NOT EXISTS bot_user_id ON pending_message TYPE string;
+                    DEFINE FIELD IF NOT EXISTS channel_id ON pending_message TYPE string;
+                    DEFINE FIELD IF NOT EXISTS thread_ts ON pending_message TYPE string;
+                    DEFINE FIELD IF NOT EXISTS text ON pending_message TYPE string;
+                    DEFINE FIELD IF NOT EXISTS blocks ON pending_message TYPE array;
+                    DEFINE FIELD IF NOT EXISTS blocks.* ON pending_message FLEXIBLE TYPE object;
+                    DEFINE FIELD IF NOT EXISTS reply_broadcast ON pending_message TYPE bool DEFAULT false;
+                    DEFINE FIELD IF NOT EXISTS created_at ON pending_message TYPE int;
+                    DEFINE FIELD IF NOT EXISTS attempts ON pending_message TYPE int;
+                    DEFINE FIELD IF NOT EXISTS next_attempt_at ON pending_message TYPE int;
+                    DEFINE INDEX IF NOT EXISTS pendingMessageNextAttemptAt ON TABLE pending_message FIELDS next_attempt_at;
+
+                    -- Schema for messages that are scheduled to be posted to threads later.
+                    DEFINE TABLE IF NOT EXISTS scheduled_message SCHEMAFULL;
+                    DEFINE FIELD IF NOT EXISTS channel_id ON scheduled_message TYPE string;
+                    DEFINE FIELD IF NOT EXISTS thread_ts ON scheduled_message TYPE string;
+                    DEFINE FIELD IF NOT EXISTS scheduled_message_id ON scheduled_message TYPE string;
+                    DEFINE FIELD IF NOT EXISTS native ON scheduled_message TYPE bool;
+                    DEFINE FIELD IF NOT EXISTS post_at ON scheduled_message TYPE int;
+                    DEFINE INDEX IF NOT EXISTS scheduledMessageThread ON TABLE scheduled_message FIELDS channel_id, thread_ts;
+
+                    -- Schema for the channel settings.
+                    DEFINE FIELD IF NOT EXISTS paused ON channel TYPE bool DEFAULT false;
+                    DEFINE FIELD IF NOT EXISTS broadcast_incidents ON channel TYPE bool DEFAULT false;
+                    DEFINE FIELD IF NOT EXISTS active ON channel TYPE bool DEFAULT true;
+                    DEFINE FIELD IF NOT EXISTS name ON channel TYPE option<string>;
+                    DEFINE FIELD IF NOT EXISTS edit_window_seconds ON channel TYPE option<int>;
+                    DEFINE FIELD IF NOT EXISTS retention_days ON channel TYPE option<int>;
+                    DEFINE FIELD IF NOT EXISTS moderation_enabled ON channel TYPE option<bool>;
+                    DEFINE FIELD IF NOT EXISTS confidence_threshold ON channel TYPE option<int>;
+                    DEFINE FIELD IF NOT EXISTS force_language ON channel TYPE option<string>;
+                    DEFINE FIELD IF NOT EXISTS system_addendum ON channel TYPE option<string>;
+                    DEFINE FIELD IF NOT EXISTS refusal_reply ON channel TYPE option<string>;
+                    DEFINE FIELD IF NOT EXISTS mode ON channel TYPE option<string>;
+                    DEFINE FIELD IF NOT EXISTS web_search_enabled ON channel TYPE option<bool>;
+
+                    -- Schema for contexts about users, and their relation to channels (the edge records who the context is about).
+                    DEFINE TABLE IF NOT EXISTS user_context SCHEMAFULL;
+                    DEFINE FIELD IF NOT EXISTS user_message ON user_context FLEXIBLE TYPE object;
+                    DEFINE FIELD IF NOT EXISTS your_notes ON user_context TYPE string;
+                    DEFINE TABLE IF NOT EXISTS has_user_context TYPE RELATION IN channel OUT user_context;
+                    DEFINE INDEX IF NOT EXISTS hasUserContextUser ON TABLE has_user_context FIELDS in, user_id;
+
+                    -- Schema for the versions of channel directives, and their relation to channels.
+                    DEFINE TABLE IF NOT EXISTS directive_version SCHEMAFULL;
+                    DEFINE FIELD IF NOT EXISTS version ON directive_version TYPE int;
+                    DEFINE FIELD IF NOT EXISTS user_id ON directive_version TYPE option<string>;
+                    DEFINE FIELD IF NOT EXISTS created_at ON directive_version TYPE int;
+                    DEFINE FIELD IF NOT EXISTS user_message ON directive_version FLEXIBLE TYPE object;
+                    DEFINE FIELD IF NOT EXISTS your_notes ON directive_version TYPE string;
+                    DEFINE TABLE IF NOT EXISTS directive_history TYPE RELATION IN channel OUT directive_version;
+
+                    -- Each message is linked to its channel once.
+                    DEFINE INDEX IF NOT EXISTS hasMessageUnique ON TABLE has_message FIELDS in, out UNIQUE;
+
+                    -- Schema for the relation between channels and feedback.
+                    DEFINE TABLE IF NOT EXISTS has_feedback TYPE RELATION IN channel OUT feedback;
+
+                    -- Schema for the bot's own responses, and their relation to channels.
+                    DEFINE TABLE IF NOT EXISTS response SCHEMAFULL;
+                    DEFINE FIELD IF NOT EXISTS thread_ts ON response TYPE string;
+                    DEFINE FIELD IF NOT EXISTS sequence ON response TYPE int;
+                    DEFINE FIELD IF NOT EXISTS response ON response FLEXIBLE TYPE object;
+                    DEFINE INDEX IF NOT EXISTS responseThread ON TABLE response FIELDS thread_ts;
+                    DEFINE TABLE IF NOT EXISTS has_response TYPE RELATION IN channel OUT response;
+                "#
+            ),
+        },
+        SchemaMigration {
+            version: 3,
+            description: "add linked channels",
+            statements: r#"
+                -- Schema for the channels whose message history may also be searched.
+                DEFINE FIELD IF NOT EXISTS linked_channels ON channel TYPE array<string> DEFAULT [];
+            "#
+            .to_string(),
+        },
+        SchemaMigration {
+            version: 4,
+            description: "add created and updated timestamps",
+            statements: ["channel", "context", "user_context", "message"]
+                .iter()
+                .map(|table| {
+                    // Messages from before the timestamps existed were (roughly) stored when they were posted.
+                    let backfill = if *table == "message" {
+                        "IF raw.ts IS NOT NONE THEN <int> <float> raw.ts ELSE time::unix(time::now()) END"
+                    } else {
+                        "time::unix(time::now())"
+                    };
+
+                    format!(
+                        r#"
+                            -- Timestamps (in unix seconds): `created_at` is set once, and `updated_at` on every write.
+                            DEFINE FIELD IF NOT EXISTS created_at ON {table} TYPE int DEFAULT time::unix(time::now());
+                            DEFINE FIELD IF NOT EXISTS updated_at ON {table} TYPE int VALUE time::unix(time::now());
+                            UPDATE {table} SET created_at = {backfill} WHERE created_at IS NONE;
+                        "#
+                    )
+                })
+                .collect(),
+        },
+        SchemaMigration {
+            version: 5,
+            description: "add message timestamps for thread-aware retrieval",
+            statements: r#"
+                -- The `ts` of messages (alongside their `thread_ts`), so that threads can be read without scanning raw messages.
+                DEFINE FIELD IF NOT EXISTS ts ON message TYPE option<string>;
+                DEFINE INDEX IF NOT EXISTS messageTsField ON TABLE message FIELDS ts;
+                DEFINE INDEX IF NOT EXISTS messageThreadTs ON TABLE message FIELDS thread_ts, ts;
+
+                -- Messages from before the field existed get theirs from the raw message.
+                UPDATE message SET ts = raw.ts, thread_ts = thread_ts ?? raw.thread_ts ?? raw.ts WHERE ts IS NONE AND raw.ts IS NOT NONE;
+            "#
+            .to_string(),
+        },
+        SchemaMigration {
+            version: 6,
+            description: "add context expiry",
+            statements: r#"
+                -- When a context expires (in unix seconds), after which it is no longer provided to the assistant.
+                DEFINE FIELD IF NOT EXISTS expires_at ON context TYPE option<int>;
+                DEFINE FIELD IF NOT EXISTS expires_at ON user_context TYPE option<int>;
+                DEFINE INDEX IF NOT EXISTS contextExpiresAt ON TABLE context FIELDS expires_at;
+            "#
+            .to_string(),
+        },
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use surrealdb::engine::local::Db;
 
-        client.add_channel_message("C1", &message1).await.unwrap();
-        client.add_channel_message("C1", &message2).await.unwrap();
+    use super::*;
+    use crate::service::db::is_db_unavailable;
+
+    async fn setup_test_db() -> Res<DbClient> {
+        let surreal = Surreal::new::<Mem>(()).await?;
+        let db = SurrealDbClient::from(surreal).await?;
+        let client = DbClient { inner: Arc::new(db) };
+
+        Ok(client)
+    }
 
-        // Messages should be stored and retrievable via search
-        let search_result = client.search_channel_messages("C1", "Hello").await.unwrap();
+    /// Like `setup_test_db`, but also returns the raw surreal handle for direct assertions.
+    async fn setup_test_db_with_handle() -> Res<(DbClient, Surreal<Db>)> {
+        let surreal = Surreal::new::<Mem>(()).await?;
+        let db = SurrealDbClient::from(surreal.clone()).await?;
+        let client = DbClient { inner: Arc::new(db) };
 
-        assert!(!search_result.is_empty());
+        Ok((client, surreal))
     }
 
+    super::super::suite::db_test_suite!(setup_test_db);
+
     #[tokio::test]
-    async fn test_get_channel_context() {
-        let client = setup_test_db().await.unwrap();
+    async fn test_add_channel_messages_in_batches() {
+        let db = SurrealDbClient::from(Surreal::new::<Mem>(()).await.unwrap()).await.unwrap();
+        let client = DbClient {
+            inner: Arc::new(db.with_message_batch_size(100)),
+        };
 
-        // Create a channel first
         client.get_or_create_channel("C1").await.unwrap();
 
-        // Initially should return empty context
-        let context = client.get_channel_context("C1").await.unwrap();
-        assert_eq!(context, "[]");
+        let messages = (0..1000).map(|i| json!({ "text": format!("Message {i}."), "ts": format!("1700000000.{i:06}") })).collect::<Vec<_>>();
 
-        // Add some context
-        let context1 = SurrealLlmContext {
-            id: None,
-            user_message: json!({ "context": "first context" }),
-            your_notes: "First notes.".into(),
+        // A thousand messages are stored in ten transactions, which should take well under the time of a thousand.
+        let started_at = Instant::now();
+        let added = client.add_channel_messages("C1", &messages).await.unwrap();
+        let elapsed = started_at.elapsed();
+
+        assert_eq!(added, 1000);
+        assert_eq!(client.get_channel_message_count("C1").await.unwrap(), 1000);
+        assert!(elapsed < Duration::from_secs(30), "Adding 1000 messages took {elapsed:?}.");
+
+        // Every message is already stored the second time around.
+        assert_eq!(client.add_channel_messages("C1", &messages).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_search_recency_boost() {
+        let db = SurrealDbClient::from(Surreal::new::<Mem>(()).await.unwrap()).await.unwrap();
+        let client = DbClient {
+            inner: Arc::new(db.with_search_options(super::super::suite::RECENCY_BOOSTED_SEARCH)),
         };
-        let context2 = SurrealLlmContext {
-            id: None,
-            user_message: json!({ "context": "second context" }),
-            your_notes: "Second notes.".into(),
+
+        super::super::suite::test_search_recency_boost(client).await;
+    }
+
+    #[tokio::test]
+    async fn test_search_fuzzy() {
+        let db = SurrealDbClient::from(Surreal::new::<Mem>(()).await.unwrap()).await.unwrap();
+        let client = DbClient {
+            inner: Arc::new(db.with_search_options(super::super::suite::FUZZY_SEARCH)),
         };
 
-        client.add_channel_context("C1", &context1).await.unwrap();
-        client.add_channel_context("C1", &context2).await.unwrap();
+        super::super::suite::test_search_fuzzy(client).await;
+    }
 
-        // Should now return the contexts
-        let retrieved_context = client.get_channel_context("C1").await.unwrap();
+    #[tokio::test]
+    async fn test_migrate_from_v1() {
+        let surreal = Surreal::new::<Mem>(()).await.unwrap();
+        surreal.use_ns("triage").use_db("bot").await.unwrap();
+
+        // Start from a v1 database, with some data in it.
+        let migrations = surreal_migrations();
+        migrate_surreal_db(&surreal, &migrations[..1]).await.unwrap();
+        assert_eq!(get_schema_version(&surreal).await.unwrap(), 1);
+
+        surreal
+            .query(
+                r#"
+                    CREATE channel:C1 CONTENT { channel_directive: { user_message: { text: "be terse" }, your_notes: "Be terse." } };
+                    LET $context = (CREATE context CONTENT { user_message: { ts: "1700000000.000001" }, your_notes: "FooService owns bar-api." }).id;
+                    RELATE channel:C1->has_context->$context;
+                    LET $message = (CREATE message CONTENT { raw: { text: "The build is broken.", ts: "1700000000.000100" } }).id;
+                    RELATE channel:C1->has_message->$message;
+                "#,
+            )
+            .await
+            .unwrap()
+            .check()
+            .unwrap();
+
+        // Migrate to the current version.
+        let client = DbClient {
+            inner: Arc::new(SurrealDbClient::from(surreal.clone()).await.unwrap()),
+        };
+        assert_eq!(get_schema_version(&surreal).await.unwrap(), migrations.last().unwrap().version);
 
-        assert!(!retrieved_context.is_empty());
-        assert_ne!(retrieved_context, "[]");
-        assert!(retrieved_context.contains("first context"));
-        assert!(retrieved_context.contains("second context"));
+        // The data is still there (and the new fields have their defaults).
+        let channel = client.get_or_create_channel("C1").await.unwrap();
+        assert_eq!(channel.channel_directive().your_notes(), "Be terse.");
+        assert!(channel.is_active());
+        assert!(!channel.is_paused());
+
+        assert!(client.get_channel_context("C1").await.unwrap().contains("FooService owns bar-api."));
+        assert_eq!(client.get_channel_message_count("C1").await.unwrap(), 1);
+        assert!(client.search_channel_messages("C1", "build").await.unwrap().contains("The build is broken."));
+
+        // Existing records get timestamps (messages from when they were posted).
+        assert!(channel.created_at().is_some() && channel.updated_at().is_some());
+        let message = client.get_channel_message("C1", "1700000000.000100").await.unwrap().unwrap();
+        assert_eq!(message.created_at(), Some(1700000000));
+
+        // Existing messages get their `ts` and `thread_ts` (a top-level message is the root of its own thread).
+        assert_eq!(message.ts(), Some("1700000000.000100"));
+        assert_eq!(message.thread_ts(), Some("1700000000.000100"));
+        assert_eq!(client.get_thread_roots("C1", 1700000000).await.unwrap().len(), 1);
+
+        // Migrating again is a no-op.
+        SurrealDbClient::from(surreal.clone()).await.unwrap();
+        assert_eq!(get_schema_version(&surreal).await.unwrap(), migrations.last().unwrap().version);
+        assert_eq!(client.get_channel_message_count("C1").await.unwrap(), 1);
     }
 
     #[tokio::test]
-    async fn test_search_channel_messages() {
-        let client = setup_test_db().await.unwrap();
+    async fn test_unavailable_database_fails_fast() {
+        let client = SurrealDbClient::from(Surreal::new::<Mem>(()).await.unwrap()).await.unwrap();
+        assert!(client.is_available());
 
-        // Create a channel
-        client.get_or_create_channel("C1").await.unwrap();
+        // While the connection is being re-established, queries fail with a distinct error.
+        client.available.store(false, Ordering::Relaxed);
 
-        // Add messages with different content
-        client.add_channel_message("C1", &json!({"text": "Hello world"})).await.unwrap();
-        client.add_channel_message("C1", &json!({"text": "Test message with important keyword"})).await.unwrap();
-        client.add_channel_message("C1", &json!({"text": "Another test without the keyword"})).await.unwrap();
-        client.add_channel_message("C1", &json!({"text": "important important important"})).await.unwrap();
+        assert!(!client.is_available());
+        let err = client.get_or_create_channel("C1").await.err().expect("Queries should fail while the database is unavailable");
+        assert!(is_db_unavailable(&err));
+        assert!(is_db_unavailable(&err.context("Failed to handle the event.")));
+        assert!(!is_db_unavailable(&anyhow!("Some other error.")));
 
-        // Test that search doesn't error - the indexing may not work in memory mode
-        let result = client.search_channel_messages("C1", "important").await;
-        assert!(result.is_ok(), "Search should not error");
+        client.available.store(true, Ordering::Relaxed);
+        assert!(client.get_or_create_channel("C1").await.is_ok());
+    }
 
-        // Test searching with multiple terms
-        let _ = client.search_channel_messages("C1", "Hello, test").await.unwrap();
+    #[test]
+    fn test_reconnect_backoff() {
+        assert_eq!(reconnect_backoff(1), Duration::from_secs(1));
+        assert_eq!(reconnect_backoff(2), Duration::from_secs(2));
+        assert_eq!(reconnect_backoff(5), Duration::from_secs(16));
+        assert_eq!(reconnect_backoff(10), RECONNECT_MAX_BACKOFF);
+        assert_eq!(reconnect_backoff(u32::MAX), RECONNECT_MAX_BACKOFF);
+    }
 
-        // Test searching with no matches
-        let _ = client.search_channel_messages("C1", "nonexistent").await.unwrap();
+    #[test]
+    fn test_full_text_search_parts_bind_terms() {
+        let terms = parse_search_terms(r#"it's, x'; REMOVE TABLE message; --, "Connection RESET by peer""#);
+
+        let (score, filter) = full_text_search_parts(&terms);
+        let bindings = full_text_search_terms(&terms);
+
+        // The terms are only ever bound, never formatted into the query.
+        assert_eq!(score, "search::score(0) + search::score(1) + search::score(2)");
+        assert_eq!(
+            filter,
+            "raw.text @0@ $term0 OR raw.text @1@ $term1 OR (raw.text @2@ $term2 AND string::contains(string::lowercase(raw.text), $phrase2))"
+        );
+        assert_eq!(bindings["term1"], "x'; REMOVE TABLE message; --");
+        assert_eq!(bindings["phrase2"], "connection reset by peer");
+        assert!(!bindings.contains_key("phrase0"));
     }
 
     #[tokio::test]
-    async fn test_search_messages_empty_terms() {
-        let client = setup_test_db().await.unwrap();
-        client.get_or_create_channel("C1").await.unwrap();
+    async fn test_migrate_refuses_downgrade() {
+        let (_, surreal) = setup_test_db_with_handle().await.unwrap();
 
-        // Test searching with empty terms
-        let result = client.search_channel_messages("C1", "").await.unwrap();
-        assert_eq!(result, "[]");
+        surreal.query("UPSERT meta:schema SET version = 999;").await.unwrap().check().unwrap();
 
-        // Test searching with only commas and spaces
-        let result = client.search_channel_messages("C1", " , , ").await.unwrap();
-        assert_eq!(result, "[]");
+        let err = SurrealDbClient::from(surreal).await.err().expect("A newer schema should not be downgraded");
+        assert!(err.to_string().contains("refusing to downgrade"));
     }
 
     #[tokio::test]
-    async fn test_operations_on_nonexistent_channel() {
-        let client = setup_test_db().await.unwrap();
+    async fn test_update_channel_message_in_place() {
+        let (client, surreal) = setup_test_db_with_handle().await.unwrap();
+        client.get_or_create_channel("C1").await.unwrap();
 
-        // These operations should not fail even on nonexistent channels
-        let context = client.get_channel_context("NONEXISTENT").await.unwrap();
-        assert_eq!(context, "[]");
+        client.add_channel_message("C1", &json!({"text": "Original text", "ts": "1234567890.123"})).await.unwrap();
+        client.add_channel_message("C1", &json!({"text": "Untouched text", "ts": "1234567890.456"})).await.unwrap();
 
-        let search_result = client.search_channel_messages("NONEXISTENT", "test").await.unwrap();
-        assert_eq!(search_result, "[]");
+        client
+            .update_channel_message("C1", "1234567890.123", &json!({"text": "Edited text", "ts": "1234567890.123"}))
+            .await
+            .unwrap();
 
-        // Adding context/messages to nonexistent channel should create the channel implicitly
-        let context_obj = SurrealLlmContext {
-            id: None,
-            user_message: json!({ "test": "value" }),
-            your_notes: "Test notes.".into(),
-        };
+        let messages: Vec<SurrealMessage> = surreal.query("SELECT * FROM message ORDER BY raw.ts").await.unwrap().take(0).unwrap();
 
-        // This should succeed (channel gets created implicitly by the relation)
-        client.add_channel_context("NONEXISTENT2", &context_obj).await.unwrap();
-        let retrieved = client.get_channel_context("NONEXISTENT2").await.unwrap();
-        assert!(!retrieved.is_empty());
+        assert_eq!(messages.len(), 2, "An edit should not create a duplicate");
+        assert_eq!(messages[0].raw["text"], "Edited text");
+        assert_eq!(messages[1].raw["text"], "Untouched text");
     }
 
     #[tokio::test]
-    async fn test_multiple_channels_isolation() {
-        let client = setup_test_db().await.unwrap();
+    async fn test_delete_channel_message_removes_edge() {
+        let (client, surreal) = setup_test_db_with_handle().await.unwrap();
+        client.get_or_create_channel("C1").await.unwrap();
+
+        client.add_channel_message("C1", &json!({"text": "Secret deleted text", "ts": "1234567890.123"})).await.unwrap();
+        client.add_channel_message("C1", &json!({"text": "Remaining text", "ts": "1234567890.456"})).await.unwrap();
+
+        client.delete_channel_message("C1", "1234567890.123").await.unwrap();
+
+        let messages: Vec<SurrealMessage> = surreal.query("SELECT * FROM message").await.unwrap().take(0).unwrap();
+        let edges: Vec<Value> = surreal.query("SELECT * FROM has_message").await.unwrap().take(0).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].raw["text"], "Remaining text");
+        assert_eq!(edges.len(), 1, "The edge to the deleted message should be removed");
+    }
 
-        // Create two channels
+    #[tokio::test]
+    async fn test_prune_old_messages_removes_edges() {
+        let (client, surreal) = setup_test_db_with_handle().await.unwrap();
         client.get_or_create_channel("C1").await.unwrap();
-        client.get_or_create_channel("C2").await.unwrap();
 
-        // Add different content to each channel
-        client.add_channel_message("C1", &json!({"text": "Channel 1 message"})).await.unwrap();
-        client.add_channel_message("C2", &json!({"text": "Channel 2 message"})).await.unwrap();
+        client.add_channel_message("C1", &json!({"text": "Old text", "ts": "1000000000.000100"})).await.unwrap();
+        client.add_channel_message("C1", &json!({"text": "New text", "ts": "2000000000.000100"})).await.unwrap();
 
-        let context1 = SurrealLlmContext {
-            id: None,
-            user_message: json!({ "channel": "first" }),
-            your_notes: "Channel 1 context.".into(),
-        };
-        let context2 = SurrealLlmContext {
-            id: None,
-            user_message: json!({ "channel": "second" }),
-            your_notes: "Channel 2 context.".into(),
-        };
+        assert_eq!(client.prune_old_messages("C1", 1500000000).await.unwrap(), 1);
+
+        let messages: Vec<SurrealMessage> = surreal.query("SELECT * FROM message").await.unwrap().take(0).unwrap();
+        let edges: Vec<Value> = surreal.query("SELECT * FROM has_message").await.unwrap().take(0).unwrap();
+
+        assert_eq!(messages.len(), 1);
+        assert_eq!(messages[0].raw["text"], "New text");
+        assert_eq!(edges.len(), 1, "The edge to the pruned message should be removed");
+    }
+
+    #[test]
+    fn test_remote_endpoint_parse() {
+        let endpoint = |address, tls| RemoteEndpoint { address, tls };
+
+        assert_eq!(RemoteEndpoint::parse("localhost:8000", false), endpoint("localhost:8000", false));
+        assert_eq!(RemoteEndpoint::parse("localhost:8000", true), endpoint("localhost:8000", true));
+        assert_eq!(RemoteEndpoint::parse("ws://db.internal:8000", false), endpoint("db.internal:8000", false));
+        assert_eq!(RemoteEndpoint::parse("http://db.internal:8000", false), endpoint("db.internal:8000", false));
+        assert_eq!(RemoteEndpoint::parse("wss://db.internal", false), endpoint("db.internal", true));
+        assert_eq!(RemoteEndpoint::parse("https://db.internal", false), endpoint("db.internal", true));
+    }
+
+    #[test]
+    fn test_load_tls_config() {
+        let err = load_tls_config("does/not/exist.pem").unwrap_err();
+        assert!(err.to_string().contains("Failed to load the SurrealDB CA certificates"), "{err}");
+
+        // A file without any certificates is not a valid CA bundle.
+        let err = load_tls_config("Cargo.toml").unwrap_err();
+        assert!(err.to_string().contains("No valid CA certificates"), "{err}");
+    }
+
+    #[test]
+    fn test_surreal_engine_parse() {
+        assert_eq!(SurrealEngine::parse("memory"), SurrealEngine::Memory);
+        assert_eq!(SurrealEngine::parse("rocksdb:///var/lib/triage-bot"), SurrealEngine::RocksDb("/var/lib/triage-bot"));
+        assert_eq!(SurrealEngine::parse("file://data/triage.db"), SurrealEngine::RocksDb("data/triage.db"));
+        assert_eq!(SurrealEngine::parse("localhost:8000"), SurrealEngine::Remote);
+        assert_eq!(SurrealEngine::parse("ws://db.internal:8000"), SurrealEngine::Remote);
+    }
+
+    #[tokio::test]
+    async fn test_rocksdb_persists_across_restarts() {
+        let path = std::env::temp_dir().join(format!("triage-bot-test-{}-{}", std::process::id(), chrono::Utc::now().timestamp_nanos_opt().unwrap_or_default()));
+        let path = path.to_str().unwrap().to_string();
+
+        {
+            let client = DbClient {
+                inner: Arc::new(SurrealDbClient::from(Surreal::new::<RocksDb>(path.as_str()).await.unwrap()).await.unwrap()),
+            };
 
-        client.add_channel_context("C1", &context1).await.unwrap();
-        client.add_channel_context("C2", &context2).await.unwrap();
+            client.get_or_create_channel("C1").await.unwrap();
+            client.set_channel_paused("C1", true).await.unwrap();
+            client.add_channel_message("C1", &json!({ "text": "The build is broken.", "ts": "1.0" })).await.unwrap();
+        }
 
-        // Verify context isolation
-        let c1_context = client.get_channel_context("C1").await.unwrap();
-        let c2_context = client.get_channel_context("C2").await.unwrap();
+        // The database is locked until the first instance shuts down, which happens in the background.
+        let mut reopened = None;
+        for _ in 0..50 {
+            match Surreal::new::<RocksDb>(path.as_str()).await {
+                std::result::Result::Ok(surreal) => {
+                    reopened = Some(surreal);
+                    break;
+                }
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(100)).await,
+            }
+        }
 
-        assert!(c1_context.contains("first"));
-        assert!(!c1_context.contains("second"));
-        assert!(c2_context.contains("second"));
-        assert!(!c2_context.contains("first"));
+        let client = DbClient {
+            inner: Arc::new(SurrealDbClient::from(reopened.expect("Failed to reopen the database")).await.unwrap()),
+        };
 
-        // Test that search operations don't error (search functionality may be limited in memory mode)
-        let c1_search = client.search_channel_messages("C1", "Channel").await;
-        let c2_search = client.search_channel_messages("C2", "Channel").await;
+        assert!(client.get_or_create_channel("C1").await.unwrap().is_paused());
+        assert_eq!(client.get_channel_message_count("C1").await.unwrap(), 1);
 
-        assert!(c1_search.is_ok());
-        assert!(c2_search.is_ok());
+        let _ = std::fs::remove_dir_all(&path);
     }
 }
@@ -0,0 +1,221 @@
+//! What each model supports (e.g., a temperature, or a reasoning effort), and how large its context window is.
+//!
+//! Known models are looked up in a built-in table (by the longest name that they start with, so that dated snapshots
+//! match their model), other names fall back to their family's defaults, and any of it can be overridden in the config
+//! (under `[openai_model_capabilities]`).
+
+use std::collections::HashMap;
+
+use crate::base::config::ModelCapabilitiesOverride;
+
+// Statics.
+
+/// The context window of models that are not in the table.
+pub const DEFAULT_CONTEXT_WINDOW: usize = 128_000;
+
+/// The capabilities of known models, by the start of their names.
+const BUILT_IN_CAPABILITIES: &[(&str, ModelCapabilities)] = &[
+    ("gpt-5", ModelCapabilities::reasoning(400_000)),
+    ("gpt-5-chat", ModelCapabilities::gpt(128_000)),
+    ("gpt-4.1", ModelCapabilities::gpt(1_047_576)),
+    ("gpt-4.5", ModelCapabilities::gpt(128_000)),
+    ("gpt-4o", ModelCapabilities::gpt(128_000)),
+    ("gpt-4-turbo", ModelCapabilities::gpt(128_000).without_json_schema()),
+    ("gpt-4", ModelCapabilities::gpt(8_192).without_json_schema().without_vision()),
+    ("gpt-3.5-turbo", ModelCapabilities::gpt(16_385).without_json_schema().without_vision()),
+    ("o1", ModelCapabilities::reasoning(200_000)),
+    ("o1-mini", ModelCapabilities::reasoning(128_000).without_reasoning_effort().without_json_schema().without_vision()),
+    ("o3", ModelCapabilities::reasoning(200_000)),
+    ("o3-mini", ModelCapabilities::reasoning(200_000).without_vision()),
+    ("o4-mini", ModelCapabilities::reasoning(200_000)),
+];
+
+// Types.
+
+/// What a model supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ModelCapabilities {
+    /// Whether the model accepts a `temperature`.
+    pub supports_temperature: bool,
+    /// Whether the model accepts a reasoning effort (and reasoning summaries).
+    pub supports_reasoning_effort: bool,
+    /// The model's context window, in tokens.
+    pub max_context_tokens: usize,
+    /// Whether the model supports structured outputs (a strict JSON schema), rather than only JSON mode.
+    pub supports_json_schema: bool,
+    /// Whether the model can see images (e.g., screenshots attached to a message).
+    pub supports_vision: bool,
+}
+
+impl ModelCapabilities {
+    /// The capabilities of a GPT model, which supports a temperature.
+    const fn gpt(max_context_tokens: usize) -> Self {
+        Self {
+            supports_temperature: true,
+            supports_reasoning_effort: false,
+            max_context_tokens,
+            supports_json_schema: true,
+            supports_vision: true,
+        }
+    }
+
+    /// The capabilities of a reasoning model, which supports a reasoning effort.
+    const fn reasoning(max_context_tokens: usize) -> Self {
+        Self {
+            supports_temperature: false,
+            supports_reasoning_effort: true,
+            max_context_tokens,
+            supports_json_schema: true,
+            supports_vision: true,
+        }
+    }
+
+    /// The capabilities of any other model (e.g., a local or self-hosted one), which may support neither sampling parameter
+    /// (nor images).
+    ///
+    /// The JSON schema is still sent, since the prompts expect it, and it can be turned off in the config.
+    const fn unknown() -> Self {
+        Self {
+            supports_temperature: false,
+            supports_reasoning_effort: false,
+            max_context_tokens: DEFAULT_CONTEXT_WINDOW,
+            supports_json_schema: true,
+            supports_vision: false,
+        }
+    }
+
+    const fn without_reasoning_effort(self) -> Self {
+        Self { supports_reasoning_effort: false, ..self }
+    }
+
+    const fn without_json_schema(self) -> Self {
+        Self { supports_json_schema: false, ..self }
+    }
+
+    const fn without_vision(self) -> Self {
+        Self { supports_vision: false, ..self }
+    }
+
+    /// Resolve the capabilities of a model, by its name (ignoring any provider prefix, e.g., `openai/gpt-4.1`).
+    ///
+    /// The configured overrides (matched like the table, by the longest name that the model starts with) replace
+    /// whichever capabilities they set.
+    pub fn resolve(model: &str, overrides: &HashMap<String, ModelCapabilitiesOverride>) -> Self {
+        let name = model.rsplit('/').next().unwrap_or(model).to_lowercase();
+
+        let mut capabilities = longest_prefix_match(&name, BUILT_IN_CAPABILITIES.iter().map(|(prefix, capabilities)| (*prefix, capabilities)))
+            .copied()
+            .unwrap_or_else(|| family_capabilities(&name));
+
+        if let Some(overrides) = longest_prefix_match(&name, overrides.iter().map(|(prefix, overrides)| (prefix.as_str(), overrides))) {
+            capabilities.supports_temperature = overrides.supports_temperature.unwrap_or(capabilities.supports_temperature);
+            capabilities.supports_reasoning_effort = overrides.supports_reasoning_effort.unwrap_or(capabilities.supports_reasoning_effort);
+            capabilities.max_context_tokens = overrides.max_context_tokens.unwrap_or(capabilities.max_context_tokens);
+            capabilities.supports_json_schema = overrides.supports_json_schema.unwrap_or(capabilities.supports_json_schema);
+            capabilities.supports_vision = overrides.supports_vision.unwrap_or(capabilities.supports_vision);
+        }
+
+        capabilities
+    }
+}
+
+// Helpers.
+
+/// The capabilities of a model that is not in the table, by its family (e.g., a new `gpt-` or `o`-series model).
+fn family_capabilities(name: &str) -> ModelCapabilities {
+    if name.starts_with("gpt-") {
+        ModelCapabilities::gpt(DEFAULT_CONTEXT_WINDOW)
+    } else if name.strip_prefix('o').is_some_and(|rest| rest.starts_with(|c: char| c.is_ascii_digit())) {
+        ModelCapabilities::reasoning(200_000)
+    } else {
+        ModelCapabilities::unknown()
+    }
+}
+
+/// The value of the longest (case-insensitive) prefix that a (lowercase) name starts with.
+fn longest_prefix_match<'a, T>(name: &str, entries: impl Iterator<Item = (&'a str, T)>) -> Option<T> {
+    entries
+        .filter(|(prefix, _)| name.starts_with(&prefix.to_lowercase()))
+        .max_by_key(|(prefix, _)| prefix.len())
+        .map(|(_, value)| value)
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_resolve_capabilities() {
+        let resolve = |model| ModelCapabilities::resolve(model, &HashMap::new());
+
+        assert_eq!(resolve("gpt-4.1"), ModelCapabilities::gpt(1_047_576));
+        assert_eq!(resolve("gpt-4.1-mini-2025-04-14"), ModelCapabilities::gpt(1_047_576));
+        assert_eq!(resolve("openai/gpt-4.1"), ModelCapabilities::gpt(1_047_576));
+        assert_eq!(resolve("gpt-4o"), ModelCapabilities::gpt(128_000));
+        assert_eq!(resolve("gpt-4o-mini"), ModelCapabilities::gpt(128_000));
+        assert_eq!(resolve("o3"), ModelCapabilities::reasoning(200_000));
+        assert_eq!(resolve("o4-mini"), ModelCapabilities::reasoning(200_000));
+        assert_eq!(resolve("openai/o4-mini"), ModelCapabilities::reasoning(200_000));
+
+        // `o1-mini` is not `o1`.
+        assert!(!resolve("o1-mini").supports_reasoning_effort);
+        assert!(resolve("o1").supports_reasoning_effort);
+
+        // Only some models can see images.
+        assert!(resolve("gpt-4.1-mini").supports_vision);
+        assert!(resolve("o4-mini").supports_vision);
+        assert!(!resolve("o3-mini").supports_vision);
+        assert!(!resolve("gpt-3.5-turbo").supports_vision);
+        assert!(!resolve("llama3.1:8b").supports_vision);
+
+        // New models of a known family get its defaults.
+        assert_eq!(resolve("gpt-6"), ModelCapabilities::gpt(DEFAULT_CONTEXT_WINDOW));
+        assert_eq!(resolve("o7"), ModelCapabilities::reasoning(200_000));
+
+        // Arbitrary models (that happen to start with `o` or `gpt`) get no sampling parameters.
+        for model in ["llama3.1:8b", "olmo-2", "openchat", "gptq-mistral-7b", "my-custom-model"] {
+            assert_eq!(resolve(model), ModelCapabilities::unknown(), "{model}");
+        }
+    }
+
+    #[test]
+    fn test_resolve_capabilities_overrides() {
+        let overrides = HashMap::from([
+            (
+                "my-custom-model".to_string(),
+                ModelCapabilitiesOverride {
+                    supports_temperature: Some(true),
+                    max_context_tokens: Some(32_768),
+                    supports_json_schema: Some(false),
+                    supports_vision: Some(true),
+                    ..Default::default()
+                },
+            ),
+            (
+                "GPT-4.1".to_string(),
+                ModelCapabilitiesOverride {
+                    max_context_tokens: Some(200_000),
+                    ..Default::default()
+                },
+            ),
+        ]);
+
+        let custom = ModelCapabilities::resolve("my-custom-model-v2", &overrides);
+        assert_eq!(
+            custom,
+            ModelCapabilities {
+                supports_temperature: true,
+                supports_reasoning_effort: false,
+                max_context_tokens: 32_768,
+                supports_json_schema: false,
+                supports_vision: true,
+            }
+        );
+
+        // Overrides only replace what they set.
+        assert_eq!(ModelCapabilities::resolve("gpt-4.1-mini", &overrides), ModelCapabilities::gpt(200_000));
+        assert_eq!(ModelCapabilities::resolve("o3", &overrides), ModelCapabilities::reasoning(200_000));
+    }
+}
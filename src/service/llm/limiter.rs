@@ -0,0 +1,286 @@
+//! Limits on the calls to the LLM provider.
+//!
+//! A flood of messages (e.g., everyone reporting the same outage) would otherwise make dozens of simultaneous calls,
+//! tripping the provider's rate limits, so only so many calls are in flight at once (and the rest wait their turn).  A
+//! circuit breaker stops calling for a while after consecutive failures, so that an outage of the provider fails fast,
+//! rather than every call retrying.
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use tokio::sync::Semaphore;
+use tracing::{info, warn};
+
+use super::LlmUnavailable;
+use crate::base::{config::Config, types::Res};
+
+// Statics.
+
+/// How long a call may wait for its turn before the wait is logged as a warning.
+const QUEUE_WAIT_WARNING: Duration = Duration::from_secs(5);
+
+// Types.
+
+/// The state of a circuit breaker.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BreakerState {
+    /// Calls are made (and the consecutive failures counted).
+    Closed { failures: u32 },
+    /// Calls fail fast until the cool-down is over.
+    Open { until: Instant },
+    /// The cool-down is over, and a trial call is in flight: it closes the breaker if it succeeds, and opens it again if
+    /// it fails.  Other calls fail fast until then (or until another cool-down is over, in case the trial never ends).
+    HalfOpen { until: Instant },
+}
+
+/// A circuit breaker, which opens after some consecutive failures, and lets a trial call through after a cool-down.
+#[derive(Debug)]
+pub struct CircuitBreaker {
+    state: Mutex<BreakerState>,
+    /// How many consecutive failures open the breaker (zero never opens it).
+    failure_threshold: u32,
+    /// How long the breaker stays open before a trial call.
+    cooldown: Duration,
+}
+
+impl CircuitBreaker {
+    /// Create a (closed) circuit breaker.
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self {
+            state: Mutex::new(BreakerState::Closed { failures: 0 }),
+            failure_threshold,
+            cooldown,
+        }
+    }
+
+    /// The current state of the breaker.
+    pub fn state(&self) -> BreakerState {
+        *self.state.lock().unwrap()
+    }
+
+    /// Whether a call would be let through at `now`.
+    pub fn is_available(&self, now: Instant) -> bool {
+        match self.state() {
+            BreakerState::Closed { .. } => true,
+            BreakerState::Open { until } | BreakerState::HalfOpen { until } => now >= until,
+        }
+    }
+
+    /// Let a call through at `now`, or fail with `LlmUnavailable` while the breaker is open (or a trial call is in flight).
+    pub fn try_acquire(&self, now: Instant) -> Result<(), LlmUnavailable> {
+        let mut state = self.state.lock().unwrap();
+
+        match *state {
+            BreakerState::Closed { .. } => Ok(()),
+            BreakerState::Open { until } | BreakerState::HalfOpen { until } if now < until => Err(LlmUnavailable),
+            BreakerState::Open { .. } | BreakerState::HalfOpen { .. } => {
+                info!("The LLM circuit breaker is half-open, so a trial call is made.");
+
+                *state = BreakerState::HalfOpen { until: now + self.cooldown };
+                Ok(())
+            }
+        }
+    }
+
+    /// Record the outcome of a call that was let through, at `now`.
+    pub fn record(&self, success: bool, now: Instant) {
+        let mut state = self.state.lock().unwrap();
+
+        *state = match (*state, success) {
+            (BreakerState::Closed { .. }, true) => BreakerState::Closed { failures: 0 },
+            (_, true) => {
+                info!("The LLM circuit breaker is closed, since a call succeeded.");
+                BreakerState::Closed { failures: 0 }
+            }
+            (BreakerState::Closed { failures }, false) if self.failure_threshold == 0 || failures + 1 < self.failure_threshold => BreakerState::Closed { failures: failures + 1 },
+            // A call that was let through before the breaker opened does not extend the cool-down.
+            (open @ BreakerState::Open { .. }, false) => open,
+            (_, false) => {
+                warn!(
+                    "The LLM circuit breaker is open for {}s, after {} consecutive failures.",
+                    self.cooldown.as_secs(),
+                    self.failure_threshold
+                );
+                BreakerState::Open { until: now + self.cooldown }
+            }
+        };
+    }
+}
+
+/// The limits on the calls to the LLM provider, which are shared by (clones of) its client.
+#[derive(Debug, Clone)]
+pub struct CallLimiter {
+    semaphore: Arc<Semaphore>,
+    breaker: Arc<CircuitBreaker>,
+}
+
+impl CallLimiter {
+    /// Create the limits, with at most `max_concurrent` calls in flight (zero is unlimited).
+    pub fn new(max_concurrent: usize, breaker: CircuitBreaker) -> Self {
+        let permits = if max_concurrent == 0 { Semaphore::MAX_PERMITS } else { max_concurrent };
+
+        Self {
+            semaphore: Arc::new(Semaphore::new(permits)),
+            breaker: Arc::new(breaker),
+        }
+    }
+
+    /// Create the limits from the application configuration.
+    pub fn from_config(config: &Config) -> Self {
+        let breaker = CircuitBreaker::new(config.openai_circuit_breaker_failures, Duration::from_secs(config.openai_circuit_breaker_cooldown_secs));
+
+        Self::new(config.openai_max_concurrent_requests, breaker)
+    }
+
+    /// Whether calls are being made (i.e., the circuit breaker is not open).
+    pub fn is_available(&self) -> bool {
+        self.breaker.is_available(Instant::now())
+    }
+
+    /// Make a call (with all of its retries) within the limits: once it is its turn, and only if the circuit breaker lets
+    /// it through (failing with `LlmUnavailable` otherwise).
+    pub async fn run<T, F, Fut>(&self, call: F) -> Res<T>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Res<T>>,
+    {
+        let queued = self.semaphore.available_permits() == 0;
+        let queued_at = Instant::now();
+
+        let _permit = self.semaphore.acquire().await?;
+
+        if queued {
+            let waited = queued_at.elapsed();
+
+            if waited >= QUEUE_WAIT_WARNING {
+                warn!("An LLM call waited {}ms for its turn (too many calls are in flight).", waited.as_millis());
+            } else {
+                info!("An LLM call waited {}ms for its turn.", waited.as_millis());
+            }
+        }
+
+        // The breaker is checked once it is the call's turn, so that queued calls fail fast if it opened in the meantime.
+        self.breaker.try_acquire(Instant::now())?;
+
+        let result = call().await;
+        self.breaker.record(result.is_ok(), Instant::now());
+
+        result
+    }
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use super::*;
+    use crate::service::llm::is_llm_unavailable;
+
+    #[test]
+    fn test_circuit_breaker_transitions() {
+        let breaker = CircuitBreaker::new(3, Duration::from_secs(60));
+        let now = Instant::now();
+
+        // Failures below the threshold (or broken up by a success) keep it closed.
+        breaker.record(false, now);
+        breaker.record(false, now);
+        breaker.record(true, now);
+        breaker.record(false, now);
+        breaker.record(false, now);
+        assert_eq!(breaker.state(), BreakerState::Closed { failures: 2 });
+        assert!(breaker.try_acquire(now).is_ok());
+
+        // The third consecutive failure opens it, and calls fail fast until the cool-down is over.
+        breaker.record(false, now);
+        assert_eq!(breaker.state(), BreakerState::Open { until: now + Duration::from_secs(60) });
+        assert!(!breaker.is_available(now + Duration::from_secs(59)));
+        assert_eq!(breaker.try_acquire(now + Duration::from_secs(59)), Err(LlmUnavailable));
+
+        // Then a single trial call is let through, and a failure opens it again.
+        let later = now + Duration::from_secs(60);
+        assert!(breaker.is_available(later));
+        assert!(breaker.try_acquire(later).is_ok());
+        assert_eq!(breaker.state(), BreakerState::HalfOpen { until: later + Duration::from_secs(60) });
+        assert_eq!(breaker.try_acquire(later), Err(LlmUnavailable));
+
+        breaker.record(false, later);
+        assert_eq!(breaker.state(), BreakerState::Open { until: later + Duration::from_secs(60) });
+
+        // A successful trial call closes it.
+        let much_later = later + Duration::from_secs(60);
+        assert!(breaker.try_acquire(much_later).is_ok());
+        breaker.record(true, much_later);
+        assert_eq!(breaker.state(), BreakerState::Closed { failures: 0 });
+    }
+
+    #[test]
+    fn test_circuit_breaker_disabled() {
+        let breaker = CircuitBreaker::new(0, Duration::from_secs(60));
+        let now = Instant::now();
+
+        for _ in 0..100 {
+            breaker.record(false, now);
+        }
+
+        assert_eq!(breaker.state(), BreakerState::Closed { failures: 100 });
+        assert!(breaker.try_acquire(now).is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_call_limiter_breaker() {
+        let limiter = CallLimiter::new(4, CircuitBreaker::new(2, Duration::from_secs(60)));
+        let calls = AtomicUsize::new(0);
+        let failing_call = || async {
+            calls.fetch_add(1, Ordering::SeqCst);
+            Err::<(), _>(anyhow::anyhow!("OpenAI API call failed after 3 retries: 503 Service Unavailable"))
+        };
+
+        // The failures are passed on, until they open the breaker.
+        for _ in 0..2 {
+            let err = limiter.run(failing_call).await.unwrap_err();
+            assert!(!is_llm_unavailable(&err));
+        }
+        assert!(!limiter.is_available());
+
+        // Then calls fail fast, without being made.
+        let err = limiter.run(failing_call).await.unwrap_err();
+        assert!(is_llm_unavailable(&err));
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_call_limiter_recovers() {
+        // Without a cool-down, the next call is the trial call.
+        let limiter = CallLimiter::new(4, CircuitBreaker::new(1, Duration::ZERO));
+
+        limiter.run(|| async { Err::<(), _>(anyhow::anyhow!("503 Service Unavailable")) }).await.unwrap_err();
+        assert!(matches!(limiter.breaker.state(), BreakerState::Open { .. }));
+
+        assert_eq!(limiter.run(|| async { Ok(42) }).await.unwrap(), 42);
+        assert_eq!(limiter.breaker.state(), BreakerState::Closed { failures: 0 });
+    }
+
+    #[tokio::test]
+    async fn test_call_limiter_concurrency() {
+        let limiter = CallLimiter::new(2, CircuitBreaker::new(0, Duration::ZERO));
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+
+        let call = || async {
+            let count = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            max_in_flight.fetch_max(count, Ordering::SeqCst);
+            tokio::time::sleep(Duration::from_millis(10)).await;
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            Ok(())
+        };
+
+        futures::future::try_join_all((0..8).map(|_| limiter.run(call))).await.unwrap();
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+}
@@ -1,6 +1,12 @@
+pub mod capabilities;
+pub mod limiter;
 pub mod openai;
+pub mod stream;
+pub mod tokens;
 
-use crate::base::types::{AssistantContext, AssistantResponse, MessageSearchContext, Res, Void, WebSearchContext};
+use crate::base::types::{
+    AssistantContext, AssistantIntent, AssistantResponse, IntentContext, LinkSummaryContext, MessageSearchContext, Moderation, Res, SummaryContext, Void, WebSearchContext, WebSearchResponse,
+};
 use async_trait::async_trait;
 use serde_json::Value;
 use std::sync::Arc;
@@ -9,6 +15,12 @@ use std::{ops::Deref, pin::Pin};
 // Types.
 
 pub type BoxedCallback = Box<dyn Fn(Vec<AssistantResponse>) -> Pin<Box<dyn Future<Output = Res<Vec<Value>>> + Send>> + Send + Sync>;
+pub type BoxedProgressCallback = Box<dyn Fn(String) -> Pin<Box<dyn Future<Output = Void> + Send>> + Send + Sync>;
+
+// Statics.
+
+/// The web search context when web search is disabled (for the deployment, or the channel).
+pub const WEB_SEARCH_DISABLED: &str = "Web search is disabled, so there are no web search results.";
 
 // Traits.
 
@@ -21,8 +33,8 @@ pub trait GenericLlmClient: Send + Sync + 'static {
     /// Execute a web search using the search agent.
     ///
     /// This method takes search context about a user message and returns
-    /// relevant information from web searches to help answer the query.
-    async fn get_web_search_agent_response(&self, context: WebSearchContext) -> Res<String>;
+    /// relevant information from web searches to help answer the query, along with the web pages that it cites.
+    async fn get_web_search_agent_response(&self, context: WebSearchContext) -> Res<WebSearchResponse>;
 
     /// Generate search terms for message search using the message search agent.
     ///
@@ -30,6 +42,24 @@ pub trait GenericLlmClient: Send + Sync + 'static {
     /// can be used to find relevant past messages in the channel history.
     async fn get_message_search_agent_response(&self, context: MessageSearchContext) -> Res<String>;
 
+    /// Summarize the content of a shared link using the search agent model.
+    ///
+    /// This is a lightweight call (no tools) that produces a short summary
+    /// suitable for a link unfurl.
+    async fn get_link_summary_agent_response(&self, context: LinkSummaryContext) -> Res<String>;
+
+    /// Summarize the messages of a (very long) thread using the search agent model.
+    ///
+    /// This is a lightweight call (no tools) that condenses the thread into its key facts,
+    /// attempted fixes, and open questions, so that the assistant agent gets a shorter thread context.
+    async fn get_summary_agent_response(&self, context: SummaryContext) -> Res<String>;
+
+    /// Label what a message asks for (e.g., a directive change) using the search agent model.
+    ///
+    /// This is a lightweight call (no tools) whose label decides which tools the assistant agent is offered, so that
+    /// it only updates its directive or context (or calls an MCP tool) when a message asks for that.
+    async fn get_intent_agent_response(&self, context: IntentContext) -> Res<AssistantIntent>;
+
     /// Generate a response from the primary assistant model.
     ///
     /// This method takes a comprehensive context about the user's message,
@@ -42,10 +72,83 @@ pub trait GenericLlmClient: Send + Sync + 'static {
     /// The response callback should return a `Value` that represents any "message" back
     /// to the model.
     async fn get_assistant_agent_response(&self, context: AssistantContext, response_callback: BoxedCallback) -> Void;
+
+    /// Generate a response from the primary assistant model, streaming replies as they are generated.
+    ///
+    /// The progress callback is called with the message of a thread reply so far, each time that it grows, so that
+    /// the reply can be shown before it is complete.  The responses (including the complete reply) still go through
+    /// the response callback.  Backends that cannot stream keep the default (no progress).
+    async fn get_assistant_agent_response_streaming(&self, context: AssistantContext, response_callback: BoxedCallback, _progress_callback: BoxedProgressCallback) -> Void {
+        self.get_assistant_agent_response(context, response_callback).await
+    }
+
+    /// Get the embedding of some text (e.g., a message), for semantic search.
+    ///
+    /// Embeddings have `EMBEDDING_DIMENSIONS` dimensions (see `service::db`), so that they fit the database's vector index.
+    async fn get_embedding(&self, text: &str) -> Res<Vec<f32>>;
+
+    /// Get the embeddings of several texts (in order), batching the requests as the backend allows.
+    ///
+    /// Embeddings have the same dimensions as those from `get_embedding`.  Backends without batched embeddings keep
+    /// the default, which fails with `UnsupportedOperation` (so that callers can fall back, or skip the feature).
+    async fn get_embeddings(&self, _texts: &[String]) -> Res<Vec<Vec<f32>>> {
+        Err(UnsupportedOperation("get_embeddings").into())
+    }
+
+    /// Check some text (e.g., a reply, before it is posted) with the provider's moderation model.
+    ///
+    /// Backends without a moderation model keep the default, which fails with `UnsupportedOperation`.
+    async fn moderate(&self, _text: &str) -> Res<Moderation> {
+        Err(UnsupportedOperation("moderate").into())
+    }
+
+    /// Whether calls are being made right now (e.g., not while a circuit breaker is open, after repeated failures).
+    ///
+    /// This does not make a call, so callers can check it first to skip work that would fail anyway.  Backends without
+    /// a circuit breaker keep the default (always available).
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Check that the LLM provider is reachable, and that the credentials are valid.
+    ///
+    /// Used for health and readiness checks.  Backends that cannot check this keep the default (always healthy).
+    async fn healthy(&self) -> Void {
+        Ok(())
+    }
 }
 
 // Structs.
 
+/// The error returned by optional operations that a backend does not support (e.g., batched embeddings).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct UnsupportedOperation(pub &'static str);
+
+impl std::fmt::Display for UnsupportedOperation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The LLM backend does not support `{}`.", self.0)
+    }
+}
+
+impl std::error::Error for UnsupportedOperation {}
+
+/// The error returned by calls while the LLM provider is unavailable (see `GenericLlmClient::is_available`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct LlmUnavailable;
+
+impl std::fmt::Display for LlmUnavailable {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The LLM is unavailable (calls are paused for a while, after repeated failures).")
+    }
+}
+
+impl std::error::Error for LlmUnavailable {}
+
+/// Whether an error is (or was caused by) the LLM provider being unavailable.
+pub fn is_llm_unavailable(err: &anyhow::Error) -> bool {
+    err.chain().any(|cause| cause.is::<LlmUnavailable>())
+}
+
 /// LLM client for the application.
 ///
 /// This is trivially cloneable and can be passed around without the need for `Arc` or `Mutex`.
@@ -54,6 +157,12 @@ pub struct LlmClient {
     inner: Arc<dyn GenericLlmClient>,
 }
 
+impl LlmClient {
+    pub fn new(inner: Arc<dyn GenericLlmClient>) -> Self {
+        Self { inner }
+    }
+}
+
 impl Deref for LlmClient {
     type Target = dyn GenericLlmClient;
 
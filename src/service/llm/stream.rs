@@ -0,0 +1,317 @@
+//! Incremental parsing of streamed LLM responses.
+//!
+//! Streamed responses arrive as server-sent events, in arbitrary chunks, and the assistant's reply arrives as
+//! (incomplete) JSON text, so both are parsed as far as they have arrived.
+
+/// Incremental parser of server-sent events, which may arrive split across (or several to a) chunk.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+}
+
+impl SseParser {
+    /// Add a chunk of the stream, returning the data of every event that it completes.
+    pub fn push(&mut self, chunk: &[u8]) -> Vec<String> {
+        // Carriage returns never appear inside a (UTF-8) character, so line endings can be normalized per chunk.
+        self.buffer.extend(chunk.iter().filter(|b| **b != b'\r'));
+
+        let mut events = Vec::new();
+
+        while let Some(end) = self.buffer.windows(2).position(|w| w == b"\n\n") {
+            let event = self.buffer.drain(..end + 2).collect::<Vec<_>>();
+            let event = String::from_utf8_lossy(&event);
+
+            let data = event
+                .lines()
+                .filter_map(|line| line.strip_prefix("data:"))
+                .map(|data| data.strip_prefix(' ').unwrap_or(data))
+                .collect::<Vec<_>>();
+
+            if !data.is_empty() {
+                events.push(data.join("\n"));
+            }
+        }
+
+        events
+    }
+}
+
+/// Read a top-level string field of a (possibly incomplete) JSON object, as far as it has arrived.
+///
+/// Returns the (unescaped) value so far, and whether it is complete, or `None` if the field has not started yet (or
+/// is not a string).
+pub fn partial_string_field(json: &str, field: &str) -> Option<(String, bool)> {
+    let mut scanner = Scanner {
+        chars: json.chars().collect(),
+        position: 0,
+    };
+
+    scanner.skip_whitespace();
+    if !scanner.eat('{') {
+        return None;
+    }
+
+    loop {
+        scanner.skip_whitespace();
+        if !scanner.eat('"') {
+            return None;
+        }
+
+        let (key, complete) = scanner.string();
+        if !complete {
+            return None;
+        }
+
+        scanner.skip_whitespace();
+        if !scanner.eat(':') {
+            return None;
+        }
+        scanner.skip_whitespace();
+
+        if key == field {
+            return scanner.eat('"').then(|| scanner.string());
+        }
+
+        if !scanner.skip_value() {
+            return None;
+        }
+
+        scanner.skip_whitespace();
+        if !scanner.eat(',') {
+            return None;
+        }
+    }
+}
+
+/// Read the message of a (possibly incomplete) thread reply, as far as it has arrived.
+///
+/// Returns `None` until the response's type is known to be a (non-ephemeral) thread reply, and its message has started.
+pub fn partial_reply_message(json: &str) -> Option<String> {
+    match partial_string_field(json, "type") {
+        Some((kind, true)) if kind == "ReplyToThread" => partial_string_field(json, "message").map(|(message, _)| message),
+        _ => None,
+    }
+}
+
+// Helpers.
+
+/// A cursor over (possibly incomplete) JSON text.
+struct Scanner {
+    chars: Vec<char>,
+    position: usize,
+}
+
+impl Scanner {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.position).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        let matches = self.peek() == Some(c);
+        if matches {
+            self.position += 1;
+        }
+
+        matches
+    }
+
+    fn skip_whitespace(&mut self) {
+        while self.peek().is_some_and(char::is_whitespace) {
+            self.position += 1;
+        }
+    }
+
+    /// Read the four hex digits of a `\u` escape.
+    fn hex4(&mut self) -> Option<u16> {
+        let digits = self.chars.get(self.position..self.position + 4)?.iter().collect::<String>();
+        let value = u16::from_str_radix(&digits, 16).ok()?;
+        self.position += 4;
+
+        Some(value)
+    }
+
+    /// Read a string (after its opening quote), returning its value so far, and whether it is complete.
+    ///
+    /// An escape that has not fully arrived is left out of the value.
+    fn string(&mut self) -> (String, bool) {
+        let mut value = String::new();
+
+        while let Some(c) = self.peek() {
+            self.position += 1;
+
+            match c {
+                '"' => return (value, true),
+                '\\' => {
+                    let Some(escape) = self.peek() else {
+                        break;
+                    };
+                    self.position += 1;
+
+                    match escape {
+                        'n' => value.push('\n'),
+                        't' => value.push('\t'),
+                        'r' => value.push('\r'),
+                        'b' => value.push('\u{8}'),
+                        'f' => value.push('\u{c}'),
+                        'u' => {
+                            let Some(unit) = self.hex4() else {
+                                break;
+                            };
+
+                            // A high surrogate needs its low surrogate (the next escape) to make a character.
+                            let decoded = if (0xD800..0xDC00).contains(&unit) {
+                                if self.chars.get(self.position..self.position + 2) != Some(&['\\', 'u'][..]) {
+                                    break;
+                                }
+                                self.position += 2;
+
+                                let Some(low) = self.hex4() else {
+                                    break;
+                                };
+
+                                char::decode_utf16([unit, low]).next().and_then(Result::ok)
+                            } else {
+                                char::from_u32(unit as u32)
+                            };
+
+                            value.push(decoded.unwrap_or(char::REPLACEMENT_CHARACTER));
+                        }
+                        other => value.push(other),
+                    }
+                }
+                c => value.push(c),
+            }
+        }
+
+        (value, false)
+    }
+
+    /// Skip over a complete value, returning whether it was complete.
+    fn skip_value(&mut self) -> bool {
+        match self.peek() {
+            Some('"') => {
+                self.position += 1;
+                self.string().1
+            }
+            Some('{' | '[') => {
+                let mut depth = 0;
+
+                while let Some(c) = self.peek() {
+                    self.position += 1;
+
+                    match c {
+                        '"' => {
+                            if !self.string().1 {
+                                return false;
+                            }
+                        }
+                        '{' | '[' => depth += 1,
+                        '}' | ']' => {
+                            depth -= 1;
+                            if depth == 0 {
+                                return true;
+                            }
+                        }
+                        _ => {}
+                    }
+                }
+
+                false
+            }
+            Some(_) => {
+                // A literal (number, boolean, or null) is complete once something follows it.
+                while self.peek().is_some_and(|c| !matches!(c, ',' | '}') && !c.is_whitespace()) {
+                    self.position += 1;
+                }
+
+                self.peek().is_some()
+            }
+            None => false,
+        }
+    }
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A (shortened) recording of a streamed response.
+    const RECORDED_STREAM: &str = concat!(
+        "event: response.created\n",
+        "data: {\"type\":\"response.created\",\"response\":{\"id\":\"resp_1\",\"status\":\"in_progress\"}}\n\n",
+        "event: response.output_text.delta\n",
+        "data: {\"type\":\"response.output_text.delta\",\"output_index\":0,\"delta\":\"{\\\"type\\\":\\\"Reply\"}\n\n",
+        "event: response.output_text.delta\n",
+        "data: {\"type\":\"response.output_text.delta\",\"output_index\":0,\"delta\":\"ToThread\\\",\\\"message\\\":\\\"Héllo\"}\r\n\r\n",
+        "event: response.completed\n",
+        "data: {\"type\":\"response.completed\",\"response\":{\"id\":\"resp_1\",\"status\":\"completed\"}}\n\n",
+    );
+
+    #[test]
+    fn test_sse_parser() {
+        // However the stream is chunked, the same events come out.
+        for chunk_size in [1, 2, 7, 64, RECORDED_STREAM.len()] {
+            let mut parser = SseParser::default();
+            let events = RECORDED_STREAM.as_bytes().chunks(chunk_size).flat_map(|chunk| parser.push(chunk)).collect::<Vec<_>>();
+
+            assert_eq!(events.len(), 4, "Chunk size {chunk_size}");
+
+            let deltas = events
+                .iter()
+                .map(|event| serde_json::from_str::<serde_json::Value>(event).unwrap())
+                .filter_map(|event| event["delta"].as_str().map(str::to_string))
+                .collect::<String>();
+            assert_eq!(deltas, r#"{"type":"ReplyToThread","message":"Héllo"#);
+        }
+
+        // Comments (and other fields) carry no data.
+        assert!(SseParser::default().push(b": keep-alive\n\n").is_empty());
+    }
+
+    #[test]
+    fn test_partial_string_field() {
+        let response = r#"{"type":"ReplyToThread","thread_ts":"1.0","classification":"Question","message":"Try \"restarting\"\nthe pod. 🚀 Done."}"#;
+
+        // Every prefix of the response reads as far as it has arrived.
+        let mut previous = String::new();
+        for end in (0..=response.len()).filter(|end| response.is_char_boundary(*end)) {
+            let prefix = &response[..end];
+
+            if let Some((message, _)) = partial_string_field(prefix, "message") {
+                assert!(message.starts_with(&previous), "{message:?} does not extend {previous:?}");
+                previous = message;
+            }
+        }
+
+        assert_eq!(partial_string_field(response, "message"), Some(("Try \"restarting\"\nthe pod. 🚀 Done.".to_string(), true)));
+        assert_eq!(partial_string_field(response, "type"), Some(("ReplyToThread".to_string(), true)));
+
+        // A type is only known once it is complete.
+        assert_eq!(partial_string_field(r#"{"type":"Repl"#, "type"), Some(("Repl".to_string(), false)));
+        assert_eq!(partial_string_field(r#"{"type"#, "type"), None);
+        assert_eq!(partial_string_field(r#"{"type":"NoAction"}"#, "message"), None);
+
+        // Escapes that have not fully arrived are left out.
+        assert_eq!(partial_string_field(r#"{"message":"a\"#, "message"), Some(("a".to_string(), false)));
+        assert_eq!(partial_string_field(r#"{"message":"a\u00"#, "message"), Some(("a".to_string(), false)));
+        assert_eq!(partial_string_field(r#"{"message":"a\ud83d"#, "message"), Some(("a".to_string(), false)));
+
+        // Other values are skipped, even when they look like the field.
+        let nested = r#"{"note":"\"message\":\"no\"","extra":{"message":"no"},"count":3,"message":"yes"}"#;
+        assert_eq!(partial_string_field(nested, "message"), Some(("yes".to_string(), true)));
+        assert_eq!(partial_string_field(r#"{"count":3"#, "message"), None);
+    }
+
+    #[test]
+    fn test_partial_reply_message() {
+        assert_eq!(partial_reply_message(r#"{"type":"ReplyTo"#), None);
+        assert_eq!(partial_reply_message(r#"{"type":"ReplyToThread","thread_ts":"1.0""#), None);
+        assert_eq!(partial_reply_message(r#"{"type":"ReplyToThread","thread_ts":"1.0","message":"Che"#), Some("Che".to_string()));
+
+        // Nothing is shown for other responses.
+        assert_eq!(partial_reply_message(r#"{"type":"NoAction"}"#), None);
+        assert_eq!(partial_reply_message(r#"{"type":"EphemeralReplyToThread","thread_ts":"1.0","message":"Psst"#), None);
+    }
+}
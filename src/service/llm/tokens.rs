@@ -0,0 +1,365 @@
+//! Token estimation, and truncation of agent inputs to fit a model's context window (and to share it among their
+//! sections).
+//!
+//! Tokens are counted with the `o200k_base` encoding (used by the GPT-4o, GPT-4.1, and `o`-series models), which is a
+//! close enough estimate for other models.
+
+use std::sync::OnceLock;
+
+use serde_json::Value;
+use tiktoken_rs::CoreBPE;
+use tracing::info;
+
+// Statics.
+
+/// The marker that replaces truncated text.
+pub const TRUNCATED_MARKER: &str = "…truncated…";
+
+static ENCODING: OnceLock<CoreBPE> = OnceLock::new();
+
+fn encoding() -> &'static CoreBPE {
+    ENCODING.get_or_init(|| tiktoken_rs::o200k_base().expect("The `o200k_base` encoding is built in."))
+}
+
+// Types.
+
+/// How a section of an input is truncated.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Truncation {
+    /// Keep the start of the text (e.g., search results, which are in order of relevance).
+    KeepStart,
+    /// Drop the oldest messages of a JSON array of messages (or keep the end of the text, if it is not one).
+    DropOldest,
+}
+
+/// A section of an input, with its share of the input's token budget (see `allocate_budget`).
+#[derive(Debug)]
+pub struct BudgetedSection<'a> {
+    /// The section's name (only used for logging).
+    pub name: &'static str,
+    pub text: &'a mut String,
+    pub truncation: Truncation,
+    /// The section's share of the budget, in percent.
+    pub share: u8,
+}
+
+// Functions.
+
+/// Estimate the number of tokens in some text.
+pub fn count_tokens(text: &str) -> usize {
+    encoding().encode_with_special_tokens(text).len()
+}
+
+/// Fit an input into a token budget, by truncating its sections (in order) until it fits.
+///
+/// `fixed` is the rest of the input, which is never truncated.  Each section is only truncated as far as it needs to
+/// be, so later sections are only truncated once the earlier ones are gone.
+pub fn fit_to_budget(fixed: &[&str], sections: &mut [(&mut String, Truncation)], budget: usize) {
+    let fixed_tokens = fixed.iter().map(|text| count_tokens(text)).sum::<usize>();
+    let mut section_tokens = sections.iter().map(|(text, _)| count_tokens(text)).collect::<Vec<_>>();
+
+    for (index, (text, truncation)) in sections.iter_mut().enumerate() {
+        let total = fixed_tokens + section_tokens.iter().sum::<usize>();
+        if total <= budget {
+            break;
+        }
+
+        let max_tokens = section_tokens[index].saturating_sub(total - budget);
+        **text = truncate(text, max_tokens, *truncation);
+        let truncated_tokens = count_tokens(text);
+
+        info!("Truncated an input section from {} to {} tokens (budget: {budget}).", section_tokens[index], truncated_tokens);
+        section_tokens[index] = truncated_tokens;
+    }
+}
+
+/// Trim each section of an input to its share of a token budget, returning the sections' sizes (in tokens) afterwards.
+///
+/// The shares that sections do not use (e.g., there are no web search results) go to the sections that need more, in
+/// order, so that the earlier sections are trimmed last.
+pub fn allocate_budget(sections: &mut [BudgetedSection], budget: usize) -> Vec<usize> {
+    let tokens = sections.iter().map(|section| count_tokens(section.text)).collect::<Vec<_>>();
+    let mut allocations = sections.iter().map(|section| budget * section.share as usize / 100).collect::<Vec<_>>();
+
+    // Hand out what the smaller sections leave over.
+    let mut unused = tokens.iter().zip(&allocations).map(|(tokens, allocation)| allocation.saturating_sub(*tokens)).sum::<usize>();
+    for (allocation, tokens) in allocations.iter_mut().zip(&tokens) {
+        let extra = tokens.saturating_sub(*allocation).min(unused);
+        *allocation += extra;
+        unused -= extra;
+    }
+
+    sections
+        .iter_mut()
+        .zip(tokens)
+        .zip(allocations)
+        .map(|((section, tokens), allocation)| {
+            if tokens <= allocation {
+                return tokens;
+            }
+
+            *section.text = truncate(section.text, allocation, section.truncation);
+            let truncated_tokens = count_tokens(section.text);
+
+            info!(
+                "Trimmed the {} from {} to {} tokens (its share: {}%, or {} tokens).",
+                section.name, tokens, truncated_tokens, section.share, allocation
+            );
+            truncated_tokens
+        })
+        .collect()
+}
+
+/// Truncate some text to (at most, roughly) a number of tokens, marking where it was truncated.
+pub fn truncate(text: &str, max_tokens: usize, truncation: Truncation) -> String {
+    let tokens = encoding().encode_with_special_tokens(text);
+    if tokens.len() <= max_tokens {
+        return text.to_string();
+    }
+
+    let marker_tokens = count_tokens(TRUNCATED_MARKER) + 1;
+    if max_tokens <= marker_tokens {
+        return TRUNCATED_MARKER.to_string();
+    }
+
+    let keep = max_tokens - marker_tokens;
+
+    match truncation {
+        Truncation::KeepStart => format!("{}\n{TRUNCATED_MARKER}", decode_prefix(&tokens, keep)),
+        Truncation::DropOldest => drop_oldest_messages(text, max_tokens).unwrap_or_else(|| format!("{TRUNCATED_MARKER}\n{}", decode_suffix(&tokens, keep))),
+    }
+}
+
+// Helpers.
+
+/// Drop the oldest messages of a JSON array of messages, so that it fits a number of tokens.
+///
+/// Returns `None` if the text is not a JSON array, or if even its newest message does not fit.
+fn drop_oldest_messages(text: &str, max_tokens: usize) -> Option<String> {
+    let messages = serde_json::from_str::<Vec<Value>>(text).ok()?;
+
+    // Each message also costs (roughly) a token for its separator.
+    let message_tokens = messages.iter().map(|m| count_tokens(&m.to_string()) + 1).collect::<Vec<_>>();
+    let mut total = count_tokens(&Value::from(TRUNCATED_MARKER).to_string()) + 2 + message_tokens.iter().sum::<usize>();
+
+    let mut dropped = 0;
+    while total > max_tokens && dropped < messages.len() {
+        total -= message_tokens[dropped];
+        dropped += 1;
+    }
+
+    if dropped == messages.len() {
+        return None;
+    }
+
+    let kept = std::iter::once(Value::from(TRUNCATED_MARKER)).chain(messages.into_iter().skip(dropped)).collect::<Vec<_>>();
+
+    serde_json::to_string(&kept).ok()
+}
+
+/// Decode the first `count` tokens (or slightly fewer, so that no character is cut in half).
+fn decode_prefix(tokens: &[tiktoken_rs::Rank], count: usize) -> String {
+    (0..4)
+        .filter_map(|shorter| encoding().decode(tokens[..count.saturating_sub(shorter)].to_vec()).ok())
+        .next()
+        .unwrap_or_default()
+}
+
+/// Decode the last `count` tokens (or slightly fewer, so that no character is cut in half).
+fn decode_suffix(tokens: &[tiktoken_rs::Rank], count: usize) -> String {
+    (0..4)
+        .filter_map(|shorter| encoding().decode(tokens[tokens.len() - count.saturating_sub(shorter)..].to_vec()).ok())
+        .next()
+        .unwrap_or_default()
+}
+
+// Tests.
+
+#[cfg(test)]
+mod tests {
+    use serde_json::json;
+
+    use super::*;
+
+    #[test]
+    fn test_truncate_keep_start() {
+        let text = (0..1000).map(|i| format!("result {i}")).collect::<Vec<_>>().join("\n");
+
+        let truncated = truncate(&text, 100, Truncation::KeepStart);
+
+        assert!(count_tokens(&truncated) <= 100);
+        assert!(truncated.starts_with("result 0\nresult 1\n"));
+        assert!(truncated.ends_with(TRUNCATED_MARKER));
+
+        // Truncation is deterministic, and text that fits is untouched.
+        assert_eq!(truncated, truncate(&text, 100, Truncation::KeepStart));
+        assert_eq!(truncate("short", 100, Truncation::KeepStart), "short");
+        assert_eq!(truncate(&text, 2, Truncation::KeepStart), TRUNCATED_MARKER);
+    }
+
+    #[test]
+    fn test_truncate_drop_oldest() {
+        let messages = (0..200)
+            .map(|i| json!({ "user": "U1", "ts": format!("{i}.0"), "text": format!("message number {i}") }))
+            .collect::<Vec<_>>();
+        let text = serde_json::to_string(&messages).unwrap();
+
+        let truncated = truncate(&text, 300, Truncation::DropOldest);
+        let kept = serde_json::from_str::<Vec<Value>>(&truncated).unwrap();
+
+        // The newest messages are kept (whole), after a marker.
+        assert!(count_tokens(&truncated) <= 300);
+        assert_eq!(kept[0], json!(TRUNCATED_MARKER));
+        assert_eq!(kept.last().unwrap(), messages.last().unwrap());
+        assert!(kept.len() > 2 && kept.len() < messages.len());
+
+        // Text that is not a list of messages keeps its end.
+        let plain = "word ".repeat(1000);
+        let truncated = truncate(&plain, 50, Truncation::DropOldest);
+        assert!(truncated.starts_with(TRUNCATED_MARKER));
+        assert!(count_tokens(&truncated) <= 50);
+    }
+
+    #[test]
+    fn test_fit_to_budget() {
+        let mut message_results = "message result ".repeat(2000);
+        let mut web_results = "web result ".repeat(2000);
+        let mut thread = serde_json::to_string(&(0..100).map(|i| json!({ "ts": format!("{i}.0"), "text": "thread message" })).collect::<Vec<_>>()).unwrap();
+        let original_thread = thread.clone();
+        let fixed = "the user message ".repeat(100);
+
+        // Message results are truncated first (entirely, if need be), then web results, and the thread last.
+        fit_to_budget(
+            &[&fixed],
+            &mut [
+                (&mut message_results, Truncation::KeepStart),
+                (&mut web_results, Truncation::KeepStart),
+                (&mut thread, Truncation::DropOldest),
+            ],
+            3000,
+        );
+
+        assert_eq!(message_results, TRUNCATED_MARKER);
+        assert!(web_results.ends_with(TRUNCATED_MARKER) && web_results.len() > 1000);
+        assert_eq!(thread, original_thread);
+        assert!(count_tokens(&fixed) + count_tokens(&message_results) + count_tokens(&web_results) + count_tokens(&thread) <= 3000);
+
+        // Inputs that fit are untouched.
+        let mut small = "small".to_string();
+        fit_to_budget(&[&fixed], &mut [(&mut small, Truncation::KeepStart)], 3000);
+        assert_eq!(small, "small");
+    }
+
+    #[test]
+    fn test_allocate_budget() {
+        let mut thread = serde_json::to_string(&(0..500).map(|i| json!({ "ts": format!("{i}.0"), "text": "thread message" })).collect::<Vec<_>>()).unwrap();
+        let mut web_results = "web result ".repeat(2000);
+        let mut message_results = "message result ".repeat(2000);
+        let mut directive = "Be nice.".to_string();
+
+        let sizes = allocate_budget(
+            &mut [
+                BudgetedSection {
+                    name: "thread",
+                    text: &mut thread,
+                    truncation: Truncation::DropOldest,
+                    share: 40,
+                },
+                BudgetedSection {
+                    name: "directive",
+                    text: &mut directive,
+                    truncation: Truncation::KeepStart,
+                    share: 20,
+                },
+                BudgetedSection {
+                    name: "message results",
+                    text: &mut message_results,
+                    truncation: Truncation::KeepStart,
+                    share: 20,
+                },
+                BudgetedSection {
+                    name: "web results",
+                    text: &mut web_results,
+                    truncation: Truncation::KeepStart,
+                    share: 20,
+                },
+            ],
+            4000,
+        );
+
+        // Every section is trimmed to (roughly) its share, but the directive's unused share goes to the thread first.
+        assert_eq!(sizes, vec![count_tokens(&thread), count_tokens(&directive), count_tokens(&message_results), count_tokens(&web_results)]);
+        assert_eq!(directive, "Be nice.");
+        assert!(sizes[0] > 1600 + 600 && sizes[0] <= 1600 + 800 - sizes[1], "{sizes:?}");
+        assert!(sizes[2] <= 800 && sizes[3] <= 800, "{sizes:?}");
+        assert!(web_results.ends_with(TRUNCATED_MARKER) && thread.contains("499.0") && !thread.contains("\"0.0\""));
+        assert!(sizes.iter().sum::<usize>() <= 4000);
+
+        // Sections that fit their shares are untouched.
+        let mut small = "small".to_string();
+        let sizes = allocate_budget(
+            &mut [BudgetedSection {
+                name: "small",
+                text: &mut small,
+                truncation: Truncation::KeepStart,
+                share: 1,
+            }],
+            4000,
+        );
+        assert_eq!((small.as_str(), sizes), ("small", vec![count_tokens("small")]));
+    }
+
+    #[test]
+    fn test_allocate_budget_priority() {
+        // With nothing left over, the thread keeps its share, while the web results (over theirs) are trimmed.
+        let mut thread = serde_json::to_string(&(0..100).map(|i| json!({ "ts": format!("{i}.0"), "text": "thread message" })).collect::<Vec<_>>()).unwrap();
+        let original_thread = thread.clone();
+        let mut web_results = "web result ".repeat(2000);
+        let thread_tokens = count_tokens(&thread);
+
+        let sizes = allocate_budget(
+            &mut [
+                BudgetedSection {
+                    name: "thread",
+                    text: &mut thread,
+                    truncation: Truncation::DropOldest,
+                    share: 50,
+                },
+                BudgetedSection {
+                    name: "web results",
+                    text: &mut web_results,
+                    truncation: Truncation::KeepStart,
+                    share: 50,
+                },
+            ],
+            thread_tokens * 2,
+        );
+
+        assert_eq!(thread, original_thread);
+        assert_eq!(sizes[0], thread_tokens);
+        assert!(sizes[1] <= thread_tokens && web_results.ends_with(TRUNCATED_MARKER));
+
+        // A small thread leaves its share to the web results.
+        let mut thread = "[]".to_string();
+        let mut web_results = "web result ".repeat(2000);
+        let sizes = allocate_budget(
+            &mut [
+                BudgetedSection {
+                    name: "thread",
+                    text: &mut thread,
+                    truncation: Truncation::DropOldest,
+                    share: 50,
+                },
+                BudgetedSection {
+                    name: "web results",
+                    text: &mut web_results,
+                    truncation: Truncation::KeepStart,
+                    share: 50,
+                },
+            ],
+            thread_tokens * 2,
+        );
+
+        assert!(sizes[1] > thread_tokens && sizes[1] <= thread_tokens * 2 - sizes[0], "{sizes:?}");
+    }
+}
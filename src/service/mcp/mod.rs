@@ -17,7 +17,10 @@ use serde_json::{Map, Value};
 use tokio::process::Command;
 use tracing::instrument;
 
-use crate::base::types::{AssistantTool, Res};
+use crate::base::{
+    redact::Redactor,
+    types::{AssistantTool, Res},
+};
 
 // Statics.
 
@@ -77,11 +80,15 @@ impl Deref for McpClient {
 /// Inner implementation of the MCP client.
 pub struct McpClientInner {
     pub mcps: Vec<Mcp>,
+    /// Redacts the logged tool calls, if they are logged at all (see `log_llm_payloads`).
+    pub payload_redactor: Option<Arc<Redactor>>,
 }
 
 impl McpClient {
     /// Creates a new MCP client.
-    pub async fn new(path: &str) -> Res<Self> {
+    ///
+    /// With a payload redactor, the arguments and results of the tool calls are logged (redacted) at the `TRACE` level.
+    pub async fn new(path: &str, payload_redactor: Option<Arc<Redactor>>) -> Res<Self> {
         // Load the MCP JSON configuration.
         let json_servers = load_mcp_json(path);
 
@@ -92,7 +99,7 @@ impl McpClient {
         let mcps = hydrate_mcps(servers.iter()).await?;
 
         // Create the inner MCP client.
-        let inner = Arc::new(McpClientInner { mcps });
+        let inner = Arc::new(McpClientInner { mcps, payload_redactor });
 
         Ok(Self { inner })
     }
@@ -133,6 +140,10 @@ impl McpClientInner {
         // Find the MCP by name (maybe refactor to a `Map`, but, at this scale, it shouldn't matter).
         let mcp = self.mcps.iter().find(|m| m.name == mcp_name).ok_or_else(|| anyhow::anyhow!("MCP not found: {}", mcp_name))?;
 
+        if let Some(redactor) = &self.payload_redactor {
+            redactor.trace_payload(&format!("MCP tool call (`{name}`) arguments"), arguments);
+        }
+
         // Call the tool with the provided arguments.
         let tool_result = mcp
             .client
@@ -152,9 +163,14 @@ impl McpClientInner {
             .content
             .into_iter()
             .map(|content| content.as_text().map(|text| text.text.clone()).unwrap_or_default())
-            .collect::<Vec<_>>();
+            .collect::<Vec<_>>()
+            .join("\n\n");
+
+        if let Some(redactor) = &self.payload_redactor {
+            redactor.trace_payload(&format!("MCP tool call (`{name}`) result"), &result);
+        }
 
-        Ok(result.join("\n\n"))
+        Ok(result)
     }
 }
 
@@ -375,7 +391,7 @@ mod tests {
 
     #[tokio::test]
     async fn test_create_mcp_client() {
-        let client = McpClient::new("tests/mcp.json").await.unwrap();
+        let client = McpClient::new("tests/mcp.json", None).await.unwrap();
 
         assert!(!client.mcps.is_empty());
 
@@ -12,13 +12,19 @@ use tracing_subscriber::fmt::format::FmtSpan;
 use triage_bot::{
     base::{
         config::Config,
-        types::{Res, Void},
+        types::{
+            AssistantContext, AssistantIntent, ChannelMode, IntentContext, LinkSummaryContext, MessageSearchContext, Moderation, Res, SummaryContext, UsageTracker, Void, WebSearchContext,
+            WebSearchResponse,
+        },
     },
     runtime::Runtime,
     service::{
-        chat::{ChatClient, GenericChatClient},
-        db::{DbClient, surreal::SurrealDbClient},
-        llm::LlmClient,
+        chat::{ChannelInfo, ChatClient, GenericChatClient, UserInfo},
+        db::{
+            DbClient, EMBEDDING_DIMENSIONS, LlmContext,
+            surreal::{SurrealDbClient, SurrealLlmContext},
+        },
+        llm::{BoxedCallback, BoxedProgressCallback, GenericLlmClient, LlmClient, WEB_SEARCH_DISABLED},
         mcp::McpClient,
     },
 };
@@ -34,20 +40,97 @@ mock! {
     impl GenericChatClient for Chat {
         fn bot_user_id(&self) -> &str;
         async fn start(&self) -> triage_bot::base::types::Void;
-        async fn send_message(&self, channel_id: &str, thread_ts: &str, text: &str) -> Void;
+        async fn send_message(&self, channel_id: &str, thread_ts: &str, text: &str) -> Res<String>;
+        async fn update_message(&self, channel_id: &str, ts: &str, text: &str) -> Void;
+        async fn delete_message(&self, channel_id: &str, ts: &str) -> Void;
+        async fn send_ephemeral_message(&self, channel_id: &str, user_id: &str, thread_ts: &str, text: &str) -> Void;
         async fn react_to_message(&self, channel_id: &str, thread_ts: &str, emoji: &str) -> Void;
+        async fn remove_reaction(&self, channel_id: &str, ts: &str, emoji: &str) -> Void;
+        async fn pin_message(&self, channel_id: &str, ts: &str) -> Void;
+        async fn join_channel(&self, channel_id: &str) -> Void;
+        async fn get_user_info(&self, user_id: &str) -> Res<UserInfo>;
+        async fn get_permalink(&self, channel_id: &str, ts: &str) -> Res<String>;
+        async fn get_channel_info(&self, channel_id: &str) -> Res<ChannelInfo>;
+        async fn get_channel_history(&self, channel_id: &str, oldest_ts: &str) -> Res<Vec<serde_json::Value>>;
+        async fn get_file_text(&self, url: &str) -> Res<String>;
+        async fn get_file_bytes(&self, url: &str, max_bytes: usize) -> Res<Vec<u8>>;
         async fn get_thread_context(&self, channel_id: &str, thread_ts: &str) -> Res<String>;
+        async fn healthy(&self) -> Void;
     }
 }
 
+// Mock LLM client for testing (e.g., with stubbed embeddings).
+
+mock! {
+    pub Llm {}
+
+    #[async_trait]
+    impl GenericLlmClient for Llm {
+        async fn get_web_search_agent_response(&self, context: WebSearchContext) -> Res<WebSearchResponse>;
+        async fn get_message_search_agent_response(&self, context: MessageSearchContext) -> Res<String>;
+        async fn get_link_summary_agent_response(&self, context: LinkSummaryContext) -> Res<String>;
+        async fn get_summary_agent_response(&self, context: SummaryContext) -> Res<String>;
+        async fn get_intent_agent_response(&self, context: IntentContext) -> Res<AssistantIntent>;
+        async fn get_assistant_agent_response(&self, context: AssistantContext, response_callback: BoxedCallback) -> Void;
+        async fn get_assistant_agent_response_streaming(&self, context: AssistantContext, response_callback: BoxedCallback, progress_callback: BoxedProgressCallback) -> Void;
+        async fn get_embedding(&self, text: &str) -> Res<Vec<f32>>;
+        async fn get_embeddings(&self, texts: &[String]) -> Res<Vec<Vec<f32>>>;
+        async fn moderate(&self, text: &str) -> Res<Moderation>;
+        async fn healthy(&self) -> Void;
+    }
+}
+
+/// A stubbed embedding, which puts messages about deploys and messages about lunch on different axes.
+fn stub_embedding(text: &str) -> Vec<f32> {
+    let text = text.to_lowercase();
+    let mut embedding = vec![0.0; EMBEDDING_DIMENSIONS];
+
+    embedding[0] = if text.contains("deploy") || text.contains("pipeline") { 1.0 } else { 0.0 };
+    embedding[1] = if text.contains("lunch") { 1.0 } else { 0.0 };
+    embedding[2] = 0.01; // No embedding is all zeros.
+
+    embedding
+}
+
+fn mock_user_info(user_id: &str) -> UserInfo {
+    UserInfo {
+        id: user_id.to_string(),
+        display_name: format!("user-{user_id}"),
+        is_bot: user_id == "U12345",
+    }
+}
+
+fn mock_channel_info(channel_id: &str) -> ChannelInfo {
+    ChannelInfo {
+        id: channel_id.to_string(),
+        name: "help-horses".to_string(),
+        topic: "Horse questions, answered.".to_string(),
+        purpose: "Ask for help with horses; @horse-oncall is the oncall.".to_string(),
+    }
+}
+
+fn mock_permalink(channel_id: &str, ts: &str) -> String {
+    format!("https://example.slack.com/archives/{}/p{}", channel_id, ts.replace('.', ""))
+}
+
 fn get_mock_chat() -> MockChat {
     let mut mock = MockChat::new();
 
     mock.expect_bot_user_id().return_const("U12345".to_string());
     mock.expect_start().returning(|| Ok(()));
-    mock.expect_send_message().returning(|_, _, _| Ok(()));
+    mock.expect_send_message().returning(|_, _, _| Ok("1234567890.999999".to_string()));
+    mock.expect_update_message().returning(|_, _, _| Ok(()));
+    mock.expect_send_ephemeral_message().returning(|_, _, _, _| Ok(()));
     mock.expect_react_to_message().returning(|_, _, _| Ok(()));
+    mock.expect_remove_reaction().returning(|_, _, _| Ok(()));
+    mock.expect_pin_message().returning(|_, _| Ok(()));
+    mock.expect_join_channel().returning(|_| Ok(()));
+    mock.expect_get_user_info().returning(|id| Ok(mock_user_info(id)));
+    mock.expect_get_permalink().returning(|c, ts| Ok(mock_permalink(c, ts)));
+    mock.expect_get_channel_info().returning(|id| Ok(mock_channel_info(id)));
+    mock.expect_get_channel_history().returning(|_, _| Ok(vec![]));
     mock.expect_get_thread_context().returning(|_, _| Ok("Some context.".to_string()));
+    mock.expect_healthy().returning(|| Ok(()));
 
     mock
 }
@@ -84,6 +167,8 @@ async fn setup_test_environment() -> Runtime {
         "openai_search_agent_model": "gpt-4.1-mini",
         "openai_assistant_agent_model": "gpt-4.1-mini",
         "openai_search_agent_temperature": 0.1,
+        "openai_message_search_agent_model": "gpt-4.1-mini",
+        "openai_message_search_agent_temperature": 0.1,
         "openai_assistant_agent_temperature": 0.1,
         "openai_max_tokens": 500,
         "slack_app_token": "xapp-test",
@@ -109,9 +194,9 @@ async fn setup_test_environment() -> Runtime {
     let chat = ChatClient::new(Arc::new(get_mock_chat()));
 
     // Create an MCP client from the test version.
-    let mcp = McpClient::new(&config.mcp_config_path).await.expect("Failed to create MCP client");
+    let mcp = McpClient::new(&config.mcp_config_path, None).await.expect("Failed to create MCP client");
 
-    Runtime { config, db, llm, chat, mcp }
+    Runtime { config, db, llm, chats: vec![chat], mcp }
 }
 
 #[tokio::test]
@@ -144,7 +229,10 @@ async fn test_app_mention_integration() {
     // Override the chat mock to expect a message send.
     let mut chat_mock = MockChat::new();
     chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock.expect_get_user_info().returning(|id| Ok(mock_user_info(id)));
+    chat_mock.expect_get_permalink().returning(|c, ts| Ok(mock_permalink(c, ts)));
     chat_mock.expect_get_thread_context().returning(move |_, _| Ok("Test context".to_string()));
+    chat_mock.expect_get_channel_info().returning(|id| Ok(mock_channel_info(id)));
     chat_mock.expect_react_to_message().returning(move |_, _, _| Ok(()));
     chat_mock.expect_send_message().withf(move |c, t, _| c == channel_id && t == thread_ts).returning(move |_, _, m| {
         let m = m.to_string();
@@ -154,18 +242,19 @@ async fn test_app_mention_integration() {
             tx.send(m).await.expect("Failed to send message");
         });
 
-        Ok(())
+        Ok("1234567890.999999".to_string())
     });
-    runtime.chat = ChatClient::new(Arc::new(chat_mock));
+    runtime.chats = vec![ChatClient::new(Arc::new(chat_mock))];
 
     // Call the handler directly
     triage_bot::interaction::chat_event::handle_chat_event(
         test_message,
         channel_id.to_string(),
         thread_ts.to_string(),
+        runtime.config.clone(),
         runtime.db.clone(),
         runtime.llm.clone(),
-        runtime.chat.clone(),
+        runtime.chats[0].clone(),
         runtime.mcp.clone(),
     );
 
@@ -206,9 +295,10 @@ async fn test_directive_update_integration() {
         context_update_message,
         channel_id.to_string(),
         thread_ts.to_string(),
+        runtime.config.clone(),
         runtime.db.clone(),
         runtime.llm.clone(),
-        runtime.chat.clone(),
+        runtime.chats[0].clone(),
         runtime.mcp.clone(),
     );
 
@@ -255,9 +345,10 @@ async fn test_add_context_integration() {
         add_context_message,
         channel_id.to_string(),
         thread_ts.to_string(),
+        runtime.config.clone(),
         runtime.db.clone(),
         runtime.llm.clone(),
-        runtime.chat.clone(),
+        runtime.chats[0].clone(),
         runtime.mcp.clone(),
     );
 
@@ -311,7 +402,10 @@ async fn test_message_search_integration() {
     // Override the chat mock to expect a message send.
     let mut chat_mock = MockChat::new();
     chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock.expect_get_user_info().returning(|id| Ok(mock_user_info(id)));
+    chat_mock.expect_get_permalink().returning(|c, ts| Ok(mock_permalink(c, ts)));
     chat_mock.expect_get_thread_context().returning(move |_, _| Ok("Test context".to_string()));
+    chat_mock.expect_get_channel_info().returning(|id| Ok(mock_channel_info(id)));
     chat_mock.expect_react_to_message().returning(move |_, _, _| Ok(()));
     chat_mock.expect_send_message().withf(move |c, t, _| c == channel_id && t == thread_ts).returning(move |_, _, m| {
         let m = m.to_string();
@@ -321,9 +415,9 @@ async fn test_message_search_integration() {
             tx.send(m).await.expect("Failed to send message");
         });
 
-        Ok(())
+        Ok("1234567890.999999".to_string())
     });
-    runtime.chat = ChatClient::new(Arc::new(chat_mock));
+    runtime.chats = vec![ChatClient::new(Arc::new(chat_mock))];
 
     // Create a message that would trigger message search
     let search_message = serde_json::json!({
@@ -340,9 +434,10 @@ async fn test_message_search_integration() {
         search_message,
         channel_id.to_string(),
         thread_ts.to_string(),
+        runtime.config.clone(),
         runtime.db.clone(),
         runtime.llm.clone(),
-        runtime.chat.clone(),
+        runtime.chats[0].clone(),
         runtime.mcp.clone(),
     );
 
@@ -388,18 +483,20 @@ async fn test_multiple_channel_isolation_integration() {
         message1,
         channel1.to_string(),
         thread_ts.to_string(),
+        runtime.config.clone(),
         runtime.db.clone(),
         runtime.llm.clone(),
-        runtime.chat.clone(),
+        runtime.chats[0].clone(),
         runtime.mcp.clone(),
     );
     triage_bot::interaction::chat_event::handle_chat_event(
         message2,
         channel2.to_string(),
         thread_ts.to_string(),
+        runtime.config.clone(),
         runtime.db.clone(),
         runtime.llm.clone(),
-        runtime.chat.clone(),
+        runtime.chats[0].clone(),
         runtime.mcp.clone(),
     );
 
@@ -429,7 +526,10 @@ async fn test_mcp_access() {
     // Override the chat mock to expect a message send.
     let mut chat_mock = MockChat::new();
     chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock.expect_get_user_info().returning(|id| Ok(mock_user_info(id)));
+    chat_mock.expect_get_permalink().returning(|c, ts| Ok(mock_permalink(c, ts)));
     chat_mock.expect_get_thread_context().returning(move |_, _| Ok("Test context".to_string()));
+    chat_mock.expect_get_channel_info().returning(|id| Ok(mock_channel_info(id)));
     chat_mock.expect_react_to_message().returning(move |_, _, _| Ok(()));
     chat_mock.expect_send_message().withf(move |c, t, _| c == channel_id && t == thread_ts).returning(move |_, _, m| {
         let m = m.to_string();
@@ -439,9 +539,9 @@ async fn test_mcp_access() {
             tx.send(m).await.expect("Failed to send message");
         });
 
-        Ok(())
+        Ok("1234567890.999999".to_string())
     });
-    runtime.chat = ChatClient::new(Arc::new(chat_mock));
+    runtime.chats = vec![ChatClient::new(Arc::new(chat_mock))];
 
     // Create a message that would trigger MCP access
     let mcp_message = serde_json::json!({
@@ -458,9 +558,10 @@ async fn test_mcp_access() {
         mcp_message,
         channel_id.to_string(),
         thread_ts.to_string(),
+        runtime.config.clone(),
         runtime.db.clone(),
         runtime.llm.clone(),
-        runtime.chat.clone(),
+        runtime.chats[0].clone(),
         runtime.mcp.clone(),
     );
 
@@ -468,3 +569,841 @@ async fn test_mcp_access() {
     let sent_message = rx.recv().await.expect("Failed to receive message");
     assert!(sent_message.len() > 10, "Expected sent message");
 }
+
+#[tokio::test]
+async fn test_escalate_button_integration() {
+    // This test drives the interaction handler directly, so it needs neither the LLM nor a real chat client.
+    let db = setup_test_db().await.unwrap();
+
+    let channel_id = "C07ESCALATE";
+    let thread_ts = "1234567890.444444";
+    let reply_ts = "1234567890.444445";
+
+    // Set a directive that names the oncall, and store the bot's reply.
+    db.get_or_create_channel(channel_id).await.expect("Failed to create channel");
+    db.update_channel_directive(channel_id, &SurrealLlmContext::new(json!({ "text": "set the directive" }), "<@UONCALL> is the oncall.".to_string()))
+        .await
+        .expect("Failed to update directive");
+    db.add_channel_message(
+        channel_id,
+        &json!({ "type": "message", "user": "U12345", "text": "Try turning it off and on again.", "ts": reply_ts, "thread_ts": thread_ts, "classification": "Question" }),
+    )
+    .await
+    .expect("Failed to add message");
+
+    // Create an mpsc channel to get notification on when a message is sent.
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock.expect_get_user_info().returning(|id| Ok(mock_user_info(id)));
+    chat_mock.expect_send_message().withf(move |c, t, _| c == channel_id && t == thread_ts).returning(move |_, _, m| {
+        let m = m.to_string();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            tx.send(m).await.expect("Failed to send message");
+        });
+
+        Ok("1234567890.999999".to_string())
+    });
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    // Simulate the interaction payload of the "Escalate to oncall" button.
+    let payload = json!({
+        "type": "block_actions",
+        "user": { "id": "U54321" },
+        "container": { "type": "message", "message_ts": reply_ts, "channel_id": channel_id },
+        "message": { "ts": reply_ts, "thread_ts": thread_ts },
+        "actions": [{ "type": "button", "action_id": triage_bot::interaction::reply_action::ESCALATE_ACTION_ID, "value": thread_ts }],
+    });
+
+    triage_bot::interaction::reply_action::handle_reply_actions(payload, db.clone(), chat);
+
+    // The oncall should be tagged in the thread.
+    let sent_message = rx.recv().await.expect("Failed to receive message");
+    assert!(sent_message.contains("<@UONCALL>"), "Expected the oncall to be tagged");
+    assert!(sent_message.contains("<@U54321>"), "Expected the escalating user to be mentioned");
+
+    // The click should also be recorded as feedback (before the escalation is posted).
+    let feedback = db.get_channel_feedback(channel_id).await.expect("Failed to get feedback");
+    assert_eq!(feedback.len(), 1);
+    assert_eq!(feedback[0].thread_ts, thread_ts);
+    assert_eq!(feedback[0].classification.as_deref(), Some("Question"));
+}
+
+#[tokio::test]
+async fn test_bot_joined_channel_integration() {
+    // This test drives the join handler directly, so it needs neither the LLM nor a real chat client.
+    let db = setup_test_db().await.unwrap();
+
+    let channel_id = "C08JOINED";
+
+    // Create an mpsc channel to get notification on when a message is sent.
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock.expect_get_channel_info().returning(|id| Ok(mock_channel_info(id)));
+    chat_mock.expect_send_message().withf(move |c, t, _| c == channel_id && t.is_empty()).returning(move |_, _, m| {
+        let m = m.to_string();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            tx.send(m).await.expect("Failed to send message");
+        });
+
+        Ok("1234567890.999999".to_string())
+    });
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    triage_bot::interaction::channel_join::handle_bot_joined_channel(channel_id.to_string(), db.clone(), chat);
+
+    // The onboarding message should be posted to the channel (not a thread).
+    let sent_message = rx.recv().await.expect("Failed to receive message");
+    assert_eq!(sent_message, triage_bot::interaction::channel_join::ONBOARDING_MESSAGE);
+
+    // The channel should exist, with its topic and purpose stored as context.
+    let context = db.get_channel_context(channel_id).await.expect("Failed to get context");
+    assert!(context.contains("Horse questions, answered."), "Expected the topic in the channel context");
+    assert!(context.contains("@horse-oncall is the oncall"), "Expected the purpose in the channel context");
+}
+
+#[tokio::test]
+async fn test_history_backfill_integration() {
+    let db = setup_test_db().await.unwrap();
+
+    let channel_id = "C09BACKFILL";
+
+    // One message is already stored (e.g., it arrived live while the backfill was running).
+    db.get_or_create_channel(channel_id).await.expect("Failed to create channel");
+    db.add_channel_message(channel_id, &json!({ "type": "message", "user": "U1", "text": "Already here.", "ts": "1700000000.000001" }))
+        .await
+        .expect("Failed to add message");
+
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_get_channel_history().returning(|_, _| {
+        Ok(vec![
+            json!({ "type": "message", "user": "U1", "text": "Already here.", "ts": "1700000000.000001" }),
+            json!({ "type": "message", "user": "U2", "text": "The horse API is down again.", "ts": "1700000000.000002" }),
+            json!({ "type": "message", "subtype": "channel_join", "ts": "1700000000.000003" }),
+        ])
+    });
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let stored = triage_bot::interaction::history_backfill::backfill_channel_history(channel_id, 30, &db, &chat)
+        .await
+        .expect("Failed to backfill");
+    assert_eq!(stored, 1, "Expected only the new message (with text) to be stored");
+
+    // Running it again should be a no-op.
+    let stored = triage_bot::interaction::history_backfill::backfill_channel_history(channel_id, 30, &db, &chat)
+        .await
+        .expect("Failed to backfill");
+    assert_eq!(stored, 0, "Expected the backfill to be idempotent");
+
+    let message = db
+        .get_channel_message(channel_id, "1700000000.000002")
+        .await
+        .expect("Failed to get message")
+        .expect("Expected the message to be stored");
+    assert_eq!(message.raw["channel"], channel_id);
+}
+
+#[tokio::test]
+async fn test_file_attachment_integration() {
+    let db = setup_test_db().await.unwrap();
+
+    let channel_id = "C09FILES";
+
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_get_channel_history().returning(|_, _| {
+        Ok(vec![json!({
+            "type": "message",
+            "user": "U1",
+            "text": "Seeing this on every deploy.",
+            "ts": "1700000000.000001",
+            "files": [
+                { "id": "F1", "name": "trace.txt", "mimetype": "text/plain", "url_private_download": "https://files.slack.com/F1/trace.txt" },
+                { "id": "F2", "name": "screenshot.png", "mimetype": "image/png", "url_private_download": "https://files.slack.com/F2/screenshot.png" },
+            ],
+        })])
+    });
+    chat_mock
+        .expect_get_file_text()
+        .withf(|url| url == "https://files.slack.com/F1/trace.txt")
+        .times(1)
+        .returning(|_| Ok("thread 'main' panicked at src/saddle.rs:42".to_string()));
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let stored = triage_bot::interaction::history_backfill::backfill_channel_history(channel_id, 30, &db, &chat)
+        .await
+        .expect("Failed to backfill");
+    assert_eq!(stored, 1);
+
+    let message = db
+        .get_channel_message(channel_id, "1700000000.000001")
+        .await
+        .expect("Failed to get message")
+        .expect("Expected the message to be stored");
+    let text = message.raw["text"].as_str().unwrap();
+
+    assert!(text.starts_with("Seeing this on every deploy."));
+    assert!(text.contains("thread 'main' panicked at src/saddle.rs:42"), "Expected the snippet content to be appended");
+    assert!(text.contains("User attached an image named `screenshot.png`"), "Expected the image to be noted");
+}
+
+#[tokio::test]
+async fn test_image_attachment_integration() {
+    use async_openai::types::responses::Input;
+    use triage_bot::{base::types::ReasoningTracker, interaction::file_attachment::download_images, service::llm::openai::OpenAiLlmClient};
+
+    // A 1x1 transparent PNG.
+    const PIXEL_PNG: &[u8] = &[
+        0x89, 0x50, 0x4E, 0x47, 0x0D, 0x0A, 0x1A, 0x0A, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x48, 0x44, 0x52, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x01, 0x08, 0x06, 0x00, 0x00, 0x00, 0x1F, 0x15, 0xC4,
+        0x89, 0x00, 0x00, 0x00, 0x0D, 0x49, 0x44, 0x41, 0x54, 0x78, 0x9C, 0x63, 0x00, 0x01, 0x00, 0x00, 0x05, 0x00, 0x01, 0x0D, 0x0A, 0x2D, 0xB4, 0x00, 0x00, 0x00, 0x00, 0x49, 0x45, 0x4E, 0x44, 0xAE,
+        0x42, 0x60, 0x82,
+    ];
+
+    let message = json!({
+        "type": "message",
+        "user": "U1",
+        "text": "<@U12345> what does this error mean?",
+        "ts": "1700000000.000001",
+        "files": [
+            { "id": "F1", "name": "error.png", "mimetype": "image/png", "size": PIXEL_PNG.len(), "url_private_download": "https://files.slack.com/F1/error.png" },
+            { "id": "F2", "name": "trace.txt", "mimetype": "text/plain", "url_private_download": "https://files.slack.com/F2/trace.txt" },
+            { "id": "F3", "name": "huge.png", "mimetype": "image/png", "size": 50_000_000, "url_private_download": "https://files.slack.com/F3/huge.png" },
+        ],
+    });
+
+    // Only the image within the size limit is downloaded.
+    let mut chat_mock = MockChat::new();
+    chat_mock
+        .expect_get_file_bytes()
+        .withf(|url, max_bytes| url == "https://files.slack.com/F1/error.png" && *max_bytes == 5_000_000)
+        .times(1)
+        .returning(|_, _| Ok(PIXEL_PNG.to_vec()));
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let images = download_images(&message, 4, 5_000_000, &chat).await;
+    assert_eq!(images.len(), 1);
+    assert_eq!(images[0].name, "error.png");
+    assert_eq!(images[0].mime_type, "image/png");
+    assert_eq!(images[0].data, PIXEL_PNG);
+
+    // A count of zero disables downloading images.
+    let chat = ChatClient::new(Arc::new(MockChat::new()));
+    assert!(download_images(&message, 0, 5_000_000, &chat).await.is_empty());
+
+    let context = AssistantContext {
+        user_message: message.to_string(),
+        bot_user_id: "U12345".to_string(),
+        channel_id: "C09IMAGES".to_string(),
+        thread_ts: "1700000000.000001".to_string(),
+        is_direct_message: false,
+        system_addendum: String::new(),
+        channel_directive: "null".to_string(),
+        channel_context: "[]".to_string(),
+        user_context: "[]".to_string(),
+        thread_context: "[]".to_string(),
+        previous_responses: "[]".to_string(),
+        recent_messages: "[]".to_string(),
+        images,
+        language: "English".to_string(),
+        mode: ChannelMode::Full,
+        web_search_context: String::new(),
+        web_search_sources: Vec::new(),
+        message_search_context: String::new(),
+        tools: Vec::new(),
+        intent: AssistantIntent::Ordinary,
+        variables: Default::default(),
+        usage: UsageTracker::default(),
+        reasoning: ReasoningTracker::default(),
+    };
+
+    let build_input = |model: &str| {
+        let config = Config {
+            inner: Arc::new(
+                serde_json::from_value(json!({
+                    "openai_api_key": "test_key",
+                    "openai_search_agent_model": "gpt-4.1-mini",
+                    "openai_assistant_agent_model": model,
+                    "openai_search_agent_temperature": 0.1,
+                    "openai_message_search_agent_model": "gpt-4.1-mini",
+                    "openai_message_search_agent_temperature": 0.1,
+                    "openai_assistant_agent_temperature": 0.1,
+                    "openai_max_tokens": 500,
+                    "slack_app_token": "xapp-test",
+                    "slack_bot_token": "xoxb-test",
+                    "slack_signing_secret": "test_secret",
+                    "db_endpoint": "memory",
+                    "db_username": "test",
+                    "db_password": "test",
+                    "mcp_config_path": "tests/mcp.json",
+                }))
+                .unwrap(),
+            ),
+        };
+
+        let Input::Items(items) = OpenAiLlmClient::new(&config).build_assistant_agent_input(&context).unwrap() else {
+            panic!("Expected the input to be items");
+        };
+
+        serde_json::to_value(items).unwrap()
+    };
+
+    // A model that can see images is shown them (as a data URL, since the file URLs are private).
+    let items = build_input("gpt-4.1-mini");
+    let image_input = items.as_array().unwrap().last().unwrap();
+    assert_eq!(image_input["role"], "user");
+    assert_eq!(image_input["content"][1]["type"], "input_image");
+    assert!(image_input["content"][1]["image_url"].as_str().unwrap().starts_with("data:image/png;base64,iVBORw0KGgo"));
+
+    // Any other model is told that images were attached, but not analyzed.
+    let items = build_input("gpt-3.5-turbo");
+    let note = items.as_array().unwrap().last().unwrap();
+    assert_eq!(note["role"], "developer");
+    assert!(note["content"].as_str().unwrap().contains("1 image(s) (`error.png`), which were not analyzed"));
+    assert!(!items.to_string().contains("input_image"));
+}
+
+#[tokio::test]
+async fn test_outbox_retry_integration() {
+    let db = setup_test_db().await.unwrap();
+
+    let channel_id = "C09OUTBOX";
+
+    // The first send fails (e.g., Slack is having a bad minute), and the retry succeeds.
+    let attempts = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+    let callback_attempts = attempts.clone();
+
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock
+        .expect_send_message()
+        .withf(move |c, t, _| c == channel_id && t == "1700000000.000001")
+        .returning(move |_, _, _| {
+            if callback_attempts.fetch_add(1, std::sync::atomic::Ordering::SeqCst) == 0 {
+                Err(anyhow::anyhow!("Slack is unavailable."))
+            } else {
+                Ok("1700000000.999999".to_string())
+            }
+        });
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let ts = triage_bot::interaction::outbox::send_or_queue(channel_id, "1700000000.000001", "Have you tried oats?", &[], &Default::default(), &chat, &db)
+        .await
+        .expect("Failed to send or queue");
+    assert_eq!(ts, None, "Expected the failed message to be queued");
+
+    // The message is backed off, so it is not retried immediately.
+    let now = chrono::Utc::now().timestamp();
+    let max_age = std::time::Duration::from_secs(3600);
+
+    let sent = triage_bot::interaction::outbox::drain_outbox(&db, &[chat.clone()], max_age, now).await.expect("Failed to drain");
+    assert_eq!(sent, 0);
+    assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 1);
+
+    // Once the backoff has elapsed, the retry drains the queue.
+    let sent = triage_bot::interaction::outbox::drain_outbox(&db, &[chat.clone()], max_age, now + 60).await.expect("Failed to drain");
+    assert_eq!(sent, 1);
+    assert_eq!(attempts.load(std::sync::atomic::Ordering::SeqCst), 2);
+    assert!(db.get_due_pending_messages(i64::MAX).await.expect("Failed to get pending messages").is_empty());
+}
+
+#[tokio::test]
+async fn test_thread_context_integration() {
+    let db = setup_test_db().await.unwrap();
+
+    let channel_id = "C09THREAD";
+    let thread_ts = "1700000000.000001";
+    let thread = vec![
+        json!({ "type": "message", "user": "U1", "text": "The horse API is down.", "ts": "1700000000.000001" }),
+        json!({ "type": "message", "user": "U12345", "text": "Which endpoint?", "ts": "1700000000.000002", "thread_ts": thread_ts }),
+        json!({ "type": "message", "user": "U1", "text": "`/v1/saddles`", "ts": "1700000000.000003", "thread_ts": thread_ts }),
+    ];
+
+    // The chat API is only asked once: before any of the thread is stored.
+    let api_thread = serde_json::to_string(&thread).unwrap();
+    let mut chat_mock = MockChat::new();
+    chat_mock
+        .expect_get_thread_context()
+        .withf(move |c, t| c == channel_id && t == thread_ts)
+        .times(1)
+        .returning(move |_, _| Ok(api_thread.clone()));
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let api_context = triage_bot::interaction::chat_event::get_thread_context(channel_id, thread_ts, &db, &chat)
+        .await
+        .expect("Failed to get the thread context from the chat API");
+
+    // Once the thread is stored, it is read from the database instead, and matches what the chat API returned.
+    db.get_or_create_channel(channel_id).await.expect("Failed to create channel");
+    for message in thread.iter().rev() {
+        db.add_channel_message(channel_id, message).await.expect("Failed to add message");
+    }
+
+    let db_context = triage_bot::interaction::chat_event::get_thread_context(channel_id, thread_ts, &db, &chat)
+        .await
+        .expect("Failed to get the thread context from the database");
+
+    let summarize = |context: &str| {
+        serde_json::from_str::<Vec<serde_json::Value>>(context)
+            .expect("Expected the thread context to be a JSON array")
+            .iter()
+            .map(|m| (m["ts"].clone(), m["user"].clone(), m["text"].clone()))
+            .collect::<Vec<_>>()
+    };
+
+    assert_eq!(summarize(&db_context), summarize(&api_context));
+}
+
+#[tokio::test]
+async fn test_semantic_search_integration() {
+    let db = setup_test_db().await.unwrap();
+
+    let mut llm_mock = MockLlm::new();
+    llm_mock.expect_get_embedding().returning(|text| Ok(stub_embedding(text)));
+    llm_mock.expect_get_embeddings().returning(|texts| Ok(texts.iter().map(|text| stub_embedding(text)).collect()));
+    let llm = LlmClient::new(Arc::new(llm_mock));
+
+    let channel_id = "C09SEMANTIC";
+    db.get_or_create_channel(channel_id).await.expect("Failed to create channel");
+
+    let messages = [
+        json!({ "type": "message", "user": "U1", "text": "Deploys are stuck again.", "ts": "1700000000.000001" }),
+        json!({ "type": "message", "user": "U2", "text": "Anyone up for lunch?", "ts": "1700000000.000002" }),
+        json!({ "type": "message", "user": "U3", "text": "The pipeline hangs on the last step.", "ts": "1700000000.000003" }),
+    ];
+
+    for message in &messages {
+        db.add_channel_message(channel_id, message).await.expect("Failed to add message");
+        triage_bot::interaction::message_storage::embed_message(channel_id, message, &db, &llm)
+            .await
+            .expect("Failed to embed message");
+    }
+
+    // The keyword search only finds the message that shares a keyword...
+    let keyword_results = db.search_channel_messages(channel_id, "pipeline").await.expect("Failed to search messages");
+
+    // ... but the semantic search also finds its paraphrase.
+    let query = llm.get_embedding("Why is the pipeline hanging?").await.unwrap();
+
+    // Batched embeddings are the same as the single ones (in order).
+    let batch = llm.get_embeddings(&["Anyone up for lunch?".to_string(), "Why is the pipeline hanging?".to_string()]).await.unwrap();
+    assert_eq!(batch, vec![stub_embedding("Anyone up for lunch?"), query.clone()]);
+    let semantic_results = db.search_channel_messages_semantic(channel_id, &query, 2).await.expect("Failed to search messages semantically");
+
+    let merged = triage_bot::interaction::chat_event::merge_message_search_results(&keyword_results, &semantic_results);
+    let mut texts = serde_json::from_str::<Vec<serde_json::Value>>(&merged)
+        .unwrap()
+        .iter()
+        .map(|m| m["raw"]["text"].as_str().unwrap().to_string())
+        .collect::<Vec<_>>();
+
+    // The keyword hit comes first, and is not duplicated by the semantic hit.
+    assert_eq!(texts.remove(0), "The pipeline hangs on the last step.");
+    assert_eq!(texts, vec!["Deploys are stuck again."]);
+}
+
+#[tokio::test]
+async fn test_message_update_integration() {
+    let db = setup_test_db().await.unwrap();
+
+    let config = Config {
+        inner: Arc::new(
+            serde_json::from_value(json!({
+                "openai_api_key": "test",
+                "slack_app_token": "xapp-test",
+                "slack_bot_token": "xoxb-test",
+                "slack_signing_secret": "test_secret",
+                "db_endpoint": "memory",
+                "db_username": "test",
+                "db_password": "test",
+                "semantic_search_enabled": true,
+            }))
+            .unwrap(),
+        ),
+    };
+
+    let mut llm_mock = MockLlm::new();
+    llm_mock.expect_get_embedding().returning(|text| Ok(stub_embedding(text)));
+    let llm = LlmClient::new(Arc::new(llm_mock));
+
+    let mut chat_mock = get_mock_chat();
+    chat_mock
+        .expect_get_file_text()
+        .withf(|url| url == "https://files.slack.com/F1/menu.txt")
+        .times(1)
+        .returning(|_| Ok("Tacos, or burritos.".to_string()));
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let channel_id = "C12EDITED";
+    let ts = "1700000000.000001";
+    db.get_or_create_channel(channel_id).await.expect("Failed to create channel");
+
+    let message = json!({ "type": "message", "user": "U1", "text": "Deploys are stuck again.", "ts": ts });
+    db.add_channel_message(channel_id, &message).await.expect("Failed to add message");
+    triage_bot::interaction::message_storage::embed_message(channel_id, &message, &db, &llm)
+        .await
+        .expect("Failed to embed message");
+
+    // The message is edited (to be about lunch), and a file is attached.
+    let edited = json!({
+        "type": "message",
+        "user": "U1",
+        "text": "Anyone up for lunch?",
+        "ts": ts,
+        "files": [{ "id": "F1", "name": "menu.txt", "mimetype": "text/plain", "url_private_download": "https://files.slack.com/F1/menu.txt" }],
+    });
+    triage_bot::interaction::message_storage::update_message(edited, channel_id, ts, &config, &db, &llm, &chat)
+        .await
+        .expect("Failed to update message");
+
+    // The stored text has the edit, and the file's text...
+    let expected_text = "Anyone up for lunch?\n\n[User attached a file named `menu.txt`:]\n```\nTacos, or burritos.\n```";
+
+    let stored = db.get_channel_message(channel_id, ts).await.expect("Failed to get message").expect("Expected the message to be stored");
+    assert_eq!(stored.raw["text"], expected_text);
+
+    // ... and the embedding is of the edited text (so semantic search finds it by its new meaning).
+    let export = db.export_channel(channel_id).await.expect("Failed to export channel");
+    assert_eq!(export.messages.len(), 1);
+    assert_eq!(export.messages[0].embedding, Some(stub_embedding(expected_text)));
+    assert_ne!(export.messages[0].embedding, Some(stub_embedding("Deploys are stuck again.")));
+}
+
+#[tokio::test]
+async fn test_thread_summary_integration() {
+    use triage_bot::interaction::chat_event::condense_thread_context;
+
+    let mut llm_mock = MockLlm::new();
+    llm_mock
+        .expect_get_summary_agent_response()
+        .withf(|context| context.thread_context.contains("message 0") && !context.thread_context.contains("message 27"))
+        .times(1)
+        .returning(|_| Ok("*Key Facts*\n• Deploys hang.".to_string()));
+    let llm = LlmClient::new(Arc::new(llm_mock));
+    let usage = UsageTracker::default();
+
+    let messages = (0..30)
+        .map(|i| json!({ "user": "U1", "ts": format!("1700000000.{i:06}"), "text": format!("message {i}: the deploy is still stuck.") }))
+        .collect::<Vec<_>>();
+    let thread_context = serde_json::to_string(&messages).unwrap();
+
+    // Short threads (and disabled summaries) are left as they are, without calling the summary agent.
+    let short_thread = serde_json::to_string(&messages[..3]).unwrap();
+    assert_eq!(condense_thread_context(short_thread.clone(), 1000, 3, &llm, &usage).await, short_thread);
+    assert_eq!(condense_thread_context(thread_context.clone(), 0, 3, &llm, &usage).await, thread_context);
+
+    // Long threads are a summary, followed by their most recent messages.
+    let condensed = condense_thread_context(thread_context.clone(), 1000, 3, &llm, &usage).await;
+    let condensed = serde_json::from_str::<Vec<serde_json::Value>>(&condensed).unwrap();
+
+    assert_eq!(condensed.len(), 4);
+    assert_eq!(condensed[0], json!({ "summary": "*Key Facts*\n• Deploys hang.", "summarized_messages": 27 }));
+    assert_eq!(condensed[1..], messages[27..]);
+}
+
+#[tokio::test]
+async fn test_moderation_integration() {
+    use triage_bot::interaction::moderation::{FLAGGED_REPLY_TEXT, check_reply, moderate_reply};
+
+    let mut llm_mock = MockLlm::new();
+    llm_mock.expect_moderate().returning(|text| {
+        Ok(Moderation {
+            flagged: text.contains("hurt"),
+            categories: if text.contains("hurt") { vec!["violence".to_string()] } else { Vec::new() },
+        })
+    });
+    let llm = LlmClient::new(Arc::new(llm_mock));
+    let denylist = vec![regex::Regex::new(r"[a-z0-9-]+\.corp\.example\.com").unwrap()];
+
+    // Replies that pass are posted as they are.
+    assert_eq!(check_reply("Try restarting the pod.", &denylist, &llm).await, None);
+    assert_eq!(moderate_reply("Try restarting the pod.".to_string(), &denylist, &llm).await, "Try restarting the pod.");
+
+    // Replies flagged by the moderation model are withheld, and never logged as they are.
+    let violation = check_reply("This will hurt.", &denylist, &llm).await.unwrap();
+    assert_eq!(violation.reason, "moderation: violence");
+    assert!(!violation.redacted_text.contains("hurt"));
+    assert_eq!(moderate_reply("This will hurt.".to_string(), &denylist, &llm).await, FLAGGED_REPLY_TEXT);
+
+    // Replies that match the denylist are withheld, with the match redacted.
+    let violation = check_reply("SSH into build01.corp.example.com.", &denylist, &llm).await.unwrap();
+    assert_eq!(violation.reason, "denylist");
+    assert_eq!(violation.redacted_text, "SSH into [redacted].");
+
+    // Moderation failures are not fatal.
+    let mut failing_mock = MockLlm::new();
+    failing_mock.expect_moderate().returning(|_| Err(anyhow::anyhow!("The moderation API is down.")));
+    let failing_llm = LlmClient::new(Arc::new(failing_mock));
+    assert_eq!(check_reply("This will hurt.", &[], &failing_llm).await, None);
+}
+
+#[tokio::test]
+async fn test_intent_integration() {
+    use triage_bot::interaction::chat_event::get_intent;
+
+    // Messages that only mention "remember" or "directive" (or that ask for a change without those words).
+    let phrasings = [
+        ("<@U12345> I can never remember the deploy command, what is it?", AssistantIntent::Ordinary),
+        ("<@U12345> what does the security directive say about tokens?", AssistantIntent::Ordinary),
+        ("<@U12345> from now on, answer in French.", AssistantIntent::UpdateDirective),
+        ("<@U12345> FYI, @bob owns billing, keep that in mind.", AssistantIntent::UpdateContext),
+        ("<@U12345> what is 5 plus 6?", AssistantIntent::McpRequest),
+    ];
+
+    let mut llm_mock = MockLlm::new();
+    llm_mock
+        .expect_get_intent_agent_response()
+        .withf(|context| context.tools.contains("`everything__add`") && context.thread_context == "[]")
+        .returning(move |context| Ok(phrasings.iter().find(|(message, _)| *message == context.user_message).unwrap().1));
+    let llm = LlmClient::new(Arc::new(llm_mock));
+
+    let context = |message: &str| IntentContext {
+        user_message: message.to_string(),
+        bot_user_id: "U12345".to_string(),
+        channel_id: "C10INTENT".to_string(),
+        thread_context: "[]".to_string(),
+        tools: "- `everything__add`: Adds two numbers.".to_string(),
+        variables: Default::default(),
+        usage: UsageTracker::default(),
+    };
+
+    for (message, intent) in phrasings {
+        assert_eq!(get_intent(context(message), &llm).await, intent, "{message}");
+    }
+
+    // Intent failures are not fatal: the message is treated as ordinary.
+    let mut failing_mock = MockLlm::new();
+    failing_mock.expect_get_intent_agent_response().returning(|_| Err(anyhow::anyhow!("The intent agent is down.")));
+    let failing_llm = LlmClient::new(Arc::new(failing_mock));
+    assert_eq!(get_intent(context("<@U12345> from now on, answer in French."), &failing_llm).await, AssistantIntent::Ordinary);
+}
+
+#[tokio::test]
+async fn test_web_search_disabled_integration() {
+    let channel_id = "C11NOWEB";
+
+    // No API key is needed, since every LLM call is mocked.
+    let config = Config {
+        inner: Arc::new(
+            serde_json::from_value(json!({
+                "openai_api_key": "test",
+                "slack_app_token": "xapp-test",
+                "slack_bot_token": "xoxb-test",
+                "slack_signing_secret": "test_secret",
+                "db_endpoint": "memory",
+                "db_username": "test",
+                "db_password": "test",
+            }))
+            .unwrap(),
+        ),
+    };
+    assert!(config.web_search_enabled);
+
+    // The channel disables web search (overriding the config).
+    let db = setup_test_db().await.unwrap();
+    db.get_or_create_channel(channel_id).await.expect("Failed to create channel");
+    db.set_channel_web_search(channel_id, Some(false)).await.expect("Failed to disable web search");
+
+    // The web search agent must never be called, and the assistant is given the context that it would have been called with.
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let mut llm_mock = MockLlm::new();
+    llm_mock
+        .expect_get_web_search_agent_response()
+        .returning(|_| panic!("The web search agent was called, although web search is disabled in the channel."));
+    llm_mock.expect_get_intent_agent_response().returning(|_| Ok(AssistantIntent::Ordinary));
+    llm_mock.expect_get_message_search_agent_response().returning(|_| Ok(String::new()));
+    llm_mock.expect_get_assistant_agent_response().returning(move |context, _| {
+        tx.try_send(context).expect("The assistant agent was called more than once");
+        Ok(())
+    });
+    let llm = LlmClient::new(Arc::new(llm_mock));
+
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock.expect_get_user_info().returning(|id| Ok(mock_user_info(id)));
+    chat_mock.expect_get_permalink().returning(|c, ts| Ok(mock_permalink(c, ts)));
+    chat_mock.expect_get_thread_context().returning(|_, _| Ok("[]".to_string()));
+    chat_mock.expect_get_channel_info().returning(|id| Ok(mock_channel_info(id)));
+    chat_mock.expect_react_to_message().returning(|_, _, _| Ok(()));
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let mcp = McpClient::new("tests/nonexistent-mcp.json", None).await.expect("Failed to create MCP client");
+
+    let event = json!({
+        "type": "message",
+        "user": "U54321",
+        "text": "The horse API returns a 500 on every request.  Is it down?",
+        "ts": "1700000000.000001",
+        "channel": channel_id,
+    });
+
+    triage_bot::interaction::chat_event::handle_chat_event(event, channel_id.to_string(), "1700000000.000001".to_string(), config, db, llm, chat, mcp);
+
+    let context = tokio::time::timeout(std::time::Duration::from_secs(30), rx.recv())
+        .await
+        .expect("Timed out waiting for the assistant agent")
+        .expect("Failed to receive the assistant's context");
+
+    assert!(!context.web_search_enabled);
+    assert_eq!(context.web_search_context, WEB_SEARCH_DISABLED);
+    assert!(context.web_search_sources.is_empty());
+}
+
+/// Helper that handles a chat event, and returns the thread that the reply was sent to.
+async fn get_reply_thread_ts(runtime: &mut Runtime, channel_id: &'static str, thread_ts: &str, event: serde_json::Value) -> String {
+    // Create an mpsc channel to get notification on when a message is sent.
+    let (tx, mut rx) = tokio::sync::mpsc::channel(1);
+
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock.expect_get_user_info().returning(|id| Ok(mock_user_info(id)));
+    chat_mock.expect_get_permalink().returning(|c, ts| Ok(mock_permalink(c, ts)));
+    chat_mock.expect_get_thread_context().returning(|_, _| Ok("[]".to_string()));
+    chat_mock.expect_get_channel_info().returning(|id| Ok(mock_channel_info(id)));
+    chat_mock.expect_react_to_message().returning(|_, _, _| Ok(()));
+    chat_mock.expect_send_message().withf(move |c, _, _| c == channel_id).returning(move |_, t, _| {
+        let t = t.to_string();
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            tx.send(t).await.expect("Failed to send message");
+        });
+
+        Ok("1700000000.999999".to_string())
+    });
+    runtime.chats = vec![ChatClient::new(Arc::new(chat_mock))];
+
+    triage_bot::interaction::chat_event::handle_chat_event(
+        event,
+        channel_id.to_string(),
+        thread_ts.to_string(),
+        runtime.config.clone(),
+        runtime.db.clone(),
+        runtime.llm.clone(),
+        runtime.chats[0].clone(),
+        runtime.mcp.clone(),
+    );
+
+    rx.recv().await.expect("Failed to receive message")
+}
+
+#[tokio::test]
+async fn test_top_level_message_thread_integration() {
+    let mut runtime = setup_test_environment().await;
+
+    let channel_id = "C10TOPLEVEL";
+
+    // A top-level message is the root of its own thread.
+    let event = json!({
+        "type": "app_mention",
+        "user": "U54321",
+        "text": "<@U12345> The horse API returns a 500 on every request.  Is it down?",
+        "ts": "1700000000.000001",
+        "channel": channel_id,
+    });
+
+    let reply_thread_ts = get_reply_thread_ts(&mut runtime, channel_id, "1700000000.000001", event).await;
+    assert_eq!(reply_thread_ts, "1700000000.000001", "Expected the reply to start a thread on the message");
+}
+
+#[tokio::test]
+async fn test_thread_reply_thread_integration() {
+    let mut runtime = setup_test_environment().await;
+
+    let channel_id = "C11THREADREPLY";
+
+    // A reply in an existing thread has its own `ts`, but the reply must go to the thread root.
+    let event = json!({
+        "type": "app_mention",
+        "user": "U54321",
+        "text": "<@U12345> The horse API is still returning 500s.  Any ideas?",
+        "ts": "1700000000.000005",
+        "thread_ts": "1700000000.000001",
+        "channel": channel_id,
+    });
+
+    let reply_thread_ts = get_reply_thread_ts(&mut runtime, channel_id, "1700000000.000001", event).await;
+    assert_eq!(reply_thread_ts, "1700000000.000001", "Expected the reply to go to the thread root");
+}
+
+#[tokio::test]
+async fn test_reminder_fallback_integration() {
+    let db = setup_test_db().await.unwrap();
+
+    let channel_id = "C12REMINDER";
+    let thread_ts = "1700000000.000001";
+
+    // The mock has no native scheduling, so reminders go through the outbox.
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_bot_user_id().return_const("U12345".to_string());
+    chat_mock
+        .expect_send_message()
+        .withf(move |c, t, m| c == channel_id && t == thread_ts && m == "Is this still broken?")
+        .times(1)
+        .returning(|_, _, _| Ok("1700000000.999999".to_string()));
+    let chat = ChatClient::new(Arc::new(chat_mock));
+
+    let post_at = chrono::Utc::now().timestamp() + 3600;
+    let max_age = std::time::Duration::from_secs(3600);
+
+    triage_bot::interaction::reminder::schedule_reminder(channel_id, thread_ts, "Is this still broken?", post_at, &chat, &db)
+        .await
+        .expect("Failed to schedule reminder");
+
+    // Nothing is posted until the reminder is due.
+    let sent = triage_bot::interaction::outbox::drain_outbox(&db, &[chat.clone()], max_age, post_at - 1)
+        .await
+        .expect("Failed to drain");
+    assert_eq!(sent, 0);
+
+    let sent = triage_bot::interaction::outbox::drain_outbox(&db, &[chat.clone()], max_age, post_at).await.expect("Failed to drain");
+    assert_eq!(sent, 1);
+
+    // A cancelled reminder is never posted.
+    triage_bot::interaction::reminder::schedule_reminder(channel_id, thread_ts, "Any updates?", post_at, &chat, &db)
+        .await
+        .expect("Failed to schedule reminder");
+
+    let cancelled = triage_bot::interaction::reminder::cancel_thread_reminders(channel_id, thread_ts, &chat, &db)
+        .await
+        .expect("Failed to cancel");
+    // Both reminders are still in the future by the wall clock (the first one was drained early), so both records are cancelled.
+    assert_eq!(cancelled, 2);
+    assert!(db.get_due_pending_messages(i64::MAX).await.expect("Failed to get pending messages").is_empty());
+    assert!(db.get_thread_scheduled_messages(channel_id, thread_ts).await.expect("Failed to get scheduled messages").is_empty());
+}
+
+#[tokio::test]
+async fn test_runtime_health() {
+    let mut runtime = setup_test_environment().await;
+
+    // The LLM is mocked, so that the health check does not depend on the API.
+    let mut llm_mock = MockLlm::new();
+    llm_mock.expect_healthy().returning(|| Ok(()));
+    runtime.llm = LlmClient::new(Arc::new(llm_mock));
+
+    let health = runtime.health().await;
+
+    assert!(health.is_healthy(), "Unexpected health: {health:?}");
+    assert_eq!(health.services.iter().map(|s| s.service.as_str()).collect::<Vec<_>>(), vec!["db", "llm", "chat:U12345"]);
+    assert!(runtime.ensure_healthy().await.is_ok());
+
+    // A failing service is reported on its own, without failing the other checks.
+    let mut chat_mock = MockChat::new();
+    chat_mock.expect_bot_user_id().return_const("U67890".to_string());
+    chat_mock.expect_healthy().returning(|| Err(anyhow::anyhow!("invalid_auth")));
+    runtime.chats.push(ChatClient::new(Arc::new(chat_mock)));
+
+    let health = runtime.health().await;
+
+    assert!(!health.is_healthy());
+    assert!(health.services.iter().filter(|s| s.service != "chat:U67890").all(|s| s.is_healthy()));
+    assert_eq!(health.services.iter().find(|s| s.service == "chat:U67890").unwrap().error.as_deref(), Some("invalid_auth"));
+
+    // The startup readiness check fails with the unhealthy services.
+    let err = runtime.ensure_healthy().await.unwrap_err();
+    assert_eq!(err.to_string(), "Failed the health check (chat:U67890: invalid_auth).");
+}